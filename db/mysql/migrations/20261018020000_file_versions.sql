@@ -0,0 +1,11 @@
+-- 文件历史版本，内容保存在根目录下的 .versions/{id}
+CREATE TABLE IF NOT EXISTS file_versions
+(
+    id         VARCHAR(64) PRIMARY KEY,
+    path       VARCHAR(512) NOT NULL,
+    version    BIGINT       NOT NULL,
+    size       BIGINT       NOT NULL,
+    created_by VARCHAR(255) NOT NULL,
+    created_at BIGINT       NOT NULL,
+    UNIQUE KEY uk_file_versions_path_version (path, version)
+);
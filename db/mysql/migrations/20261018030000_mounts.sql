@@ -0,0 +1,13 @@
+-- 多挂载点：各表按挂载点名称区分，默认挂载点为空串
+ALTER TABLE tus_uploads ADD COLUMN mount VARCHAR(64) NOT NULL DEFAULT '';
+ALTER TABLE trash_items ADD COLUMN mount VARCHAR(64) NOT NULL DEFAULT '';
+
+ALTER TABLE file_checksums
+    ADD COLUMN mount VARCHAR(64) NOT NULL DEFAULT '' FIRST,
+    DROP PRIMARY KEY,
+    ADD PRIMARY KEY (mount, path, algorithm);
+
+ALTER TABLE file_versions
+    ADD COLUMN mount VARCHAR(64) NOT NULL DEFAULT '' AFTER id,
+    DROP INDEX uk_file_versions_path_version,
+    ADD UNIQUE KEY uk_file_versions_mount_path_version (mount, path, version);
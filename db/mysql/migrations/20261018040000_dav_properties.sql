@@ -0,0 +1,11 @@
+-- WebDAV 死属性，值为属性元素内容的 XML
+-- 完整主键超过 InnoDB 的索引长度，唯一性由写入时先删后插保证
+CREATE TABLE IF NOT EXISTS dav_properties
+(
+    mount     VARCHAR(64)  NOT NULL DEFAULT '',
+    path      VARCHAR(512) NOT NULL,
+    namespace VARCHAR(255) NOT NULL,
+    name      VARCHAR(255) NOT NULL,
+    value     TEXT         NOT NULL,
+    KEY idx_dav_properties_path (mount, path)
+);
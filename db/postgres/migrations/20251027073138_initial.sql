@@ -1 +1,11 @@
--- Add migration script here
+-- tus 断点续传上传记录
+CREATE TABLE IF NOT EXISTS tus_uploads
+(
+    id            VARCHAR(64) PRIMARY KEY,
+    target_path   TEXT   NOT NULL,
+    upload_length BIGINT NOT NULL,
+    upload_offset BIGINT NOT NULL DEFAULT 0,
+    metadata      TEXT   NOT NULL,
+    created_at    BIGINT NOT NULL,
+    updated_at    BIGINT NOT NULL
+);
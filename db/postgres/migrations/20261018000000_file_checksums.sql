@@ -0,0 +1,12 @@
+-- 文件摘要缓存，按 inode、大小和修改时间判断是否失效
+CREATE TABLE IF NOT EXISTS file_checksums
+(
+    path        TEXT        NOT NULL,
+    algorithm   VARCHAR(16) NOT NULL,
+    inode       BIGINT      NOT NULL,
+    size        BIGINT      NOT NULL,
+    mtime_ns    BIGINT      NOT NULL,
+    digest      TEXT        NOT NULL,
+    computed_at BIGINT      NOT NULL,
+    PRIMARY KEY (path, algorithm)
+);
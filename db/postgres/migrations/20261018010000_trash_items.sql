@@ -0,0 +1,11 @@
+-- 回收站条目，内容保存在根目录下的 .trash/{id}
+CREATE TABLE IF NOT EXISTS trash_items
+(
+    id            VARCHAR(64) PRIMARY KEY,
+    original_path TEXT         NOT NULL,
+    kind          VARCHAR(16)  NOT NULL,
+    size          BIGINT       NOT NULL,
+    deleted_by    VARCHAR(255) NOT NULL,
+    deleted_at    BIGINT       NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_trash_items_deleted_at ON trash_items (deleted_at);
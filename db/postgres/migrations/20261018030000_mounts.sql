@@ -0,0 +1,11 @@
+-- 多挂载点：各表按挂载点名称区分，默认挂载点为空串
+ALTER TABLE tus_uploads ADD COLUMN mount VARCHAR(64) NOT NULL DEFAULT '';
+ALTER TABLE trash_items ADD COLUMN mount VARCHAR(64) NOT NULL DEFAULT '';
+
+ALTER TABLE file_checksums ADD COLUMN mount VARCHAR(64) NOT NULL DEFAULT '';
+ALTER TABLE file_checksums DROP CONSTRAINT file_checksums_pkey;
+ALTER TABLE file_checksums ADD PRIMARY KEY (mount, path, algorithm);
+
+ALTER TABLE file_versions ADD COLUMN mount VARCHAR(64) NOT NULL DEFAULT '';
+ALTER TABLE file_versions DROP CONSTRAINT file_versions_path_version_key;
+ALTER TABLE file_versions ADD CONSTRAINT file_versions_mount_path_version_key UNIQUE (mount, path, version);
@@ -0,0 +1,10 @@
+-- WebDAV 死属性，值为属性元素内容的 XML
+CREATE TABLE IF NOT EXISTS dav_properties
+(
+    mount     TEXT NOT NULL DEFAULT '',
+    path      TEXT NOT NULL,
+    namespace TEXT NOT NULL,
+    name      TEXT NOT NULL,
+    value     TEXT NOT NULL,
+    PRIMARY KEY (mount, path, namespace, name)
+);
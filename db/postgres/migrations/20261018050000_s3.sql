@@ -0,0 +1,28 @@
+-- S3 兼容接口的访问密钥，SigV4 需要原始密钥计算签名
+CREATE TABLE IF NOT EXISTS s3_keys
+(
+    access_key  VARCHAR(128) PRIMARY KEY,
+    secret_key  TEXT   NOT NULL,
+    description TEXT   NOT NULL DEFAULT '',
+    created_at  BIGINT NOT NULL
+);
+
+-- 未完成的分段上传
+CREATE TABLE IF NOT EXISTS s3_uploads
+(
+    id         VARCHAR(64) PRIMARY KEY,
+    mount      VARCHAR(64) NOT NULL DEFAULT '',
+    object_key TEXT        NOT NULL,
+    created_at BIGINT      NOT NULL
+);
+
+-- 已上传的分段
+CREATE TABLE IF NOT EXISTS s3_parts
+(
+    upload_id   VARCHAR(64) NOT NULL,
+    part_number BIGINT      NOT NULL,
+    etag        VARCHAR(64) NOT NULL,
+    size        BIGINT      NOT NULL,
+    uploaded_at BIGINT      NOT NULL,
+    PRIMARY KEY (upload_id, part_number)
+);
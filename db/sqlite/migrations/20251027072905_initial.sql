@@ -1 +1,11 @@
--- Add migration script here
+-- tus 断点续传上传记录
+CREATE TABLE IF NOT EXISTS tus_uploads
+(
+    id            TEXT PRIMARY KEY NOT NULL,
+    target_path   TEXT    NOT NULL,
+    upload_length INTEGER NOT NULL,
+    upload_offset INTEGER NOT NULL DEFAULT 0,
+    metadata      TEXT    NOT NULL,
+    created_at    INTEGER NOT NULL,
+    updated_at    INTEGER NOT NULL
+);
@@ -0,0 +1,11 @@
+-- 回收站条目，内容保存在根目录下的 .trash/{id}
+CREATE TABLE IF NOT EXISTS trash_items
+(
+    id            TEXT PRIMARY KEY NOT NULL,
+    original_path TEXT    NOT NULL,
+    kind          TEXT    NOT NULL,
+    size          INTEGER NOT NULL,
+    deleted_by    TEXT    NOT NULL,
+    deleted_at    INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_trash_items_deleted_at ON trash_items (deleted_at);
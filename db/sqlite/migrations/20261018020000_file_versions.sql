@@ -0,0 +1,11 @@
+-- 文件历史版本，内容保存在根目录下的 .versions/{id}
+CREATE TABLE IF NOT EXISTS file_versions
+(
+    id         TEXT PRIMARY KEY NOT NULL,
+    path       TEXT    NOT NULL,
+    version    INTEGER NOT NULL,
+    size       INTEGER NOT NULL,
+    created_by TEXT    NOT NULL,
+    created_at INTEGER NOT NULL,
+    UNIQUE (path, version)
+);
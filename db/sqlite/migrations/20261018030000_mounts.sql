@@ -0,0 +1,35 @@
+-- 多挂载点：各表按挂载点名称区分，默认挂载点为空串
+ALTER TABLE tus_uploads ADD COLUMN mount TEXT NOT NULL DEFAULT '';
+ALTER TABLE trash_items ADD COLUMN mount TEXT NOT NULL DEFAULT '';
+
+-- 摘要缓存可以重建，直接换成新的主键
+DROP TABLE IF EXISTS file_checksums;
+CREATE TABLE file_checksums
+(
+    mount       TEXT    NOT NULL DEFAULT '',
+    path        TEXT    NOT NULL,
+    algorithm   TEXT    NOT NULL,
+    inode       INTEGER NOT NULL,
+    size        INTEGER NOT NULL,
+    mtime_ns    INTEGER NOT NULL,
+    digest      TEXT    NOT NULL,
+    computed_at INTEGER NOT NULL,
+    PRIMARY KEY (mount, path, algorithm)
+);
+
+-- SQLite 不能修改唯一约束，重建版本表
+CREATE TABLE file_versions_new
+(
+    id         TEXT PRIMARY KEY NOT NULL,
+    mount      TEXT    NOT NULL DEFAULT '',
+    path       TEXT    NOT NULL,
+    version    INTEGER NOT NULL,
+    size       INTEGER NOT NULL,
+    created_by TEXT    NOT NULL,
+    created_at INTEGER NOT NULL,
+    UNIQUE (mount, path, version)
+);
+INSERT INTO file_versions_new (id, mount, path, version, size, created_by, created_at)
+SELECT id, '', path, version, size, created_by, created_at FROM file_versions;
+DROP TABLE file_versions;
+ALTER TABLE file_versions_new RENAME TO file_versions;
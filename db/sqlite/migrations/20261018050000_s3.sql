@@ -0,0 +1,28 @@
+-- S3 兼容接口的访问密钥，SigV4 需要原始密钥计算签名
+CREATE TABLE IF NOT EXISTS s3_keys
+(
+    access_key  TEXT PRIMARY KEY NOT NULL,
+    secret_key  TEXT    NOT NULL,
+    description TEXT    NOT NULL DEFAULT '',
+    created_at  INTEGER NOT NULL
+);
+
+-- 未完成的分段上传
+CREATE TABLE IF NOT EXISTS s3_uploads
+(
+    id         TEXT PRIMARY KEY NOT NULL,
+    mount      TEXT    NOT NULL DEFAULT '',
+    object_key TEXT    NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+-- 已上传的分段
+CREATE TABLE IF NOT EXISTS s3_parts
+(
+    upload_id   TEXT    NOT NULL,
+    part_number INTEGER NOT NULL,
+    etag        TEXT    NOT NULL,
+    size        INTEGER NOT NULL,
+    uploaded_at INTEGER NOT NULL,
+    PRIMARY KEY (upload_id, part_number)
+);
@@ -0,0 +1,53 @@
+#!/usr/bin/env sh
+# 用 litmus 测试 WebDAV 接口：构建后在临时目录中启动一个独立实例，测试结束后停止并清理。
+#
+#   scripts/litmus.sh                 # 运行全部测试组
+#   TESTS="basic locks" scripts/litmus.sh
+#
+# 环境变量：PORT 监听端口（默认 18080），LITMUS litmus 可执行文件（默认 litmus），
+# TESTS 传给 litmus 的测试组（默认全部）。
+set -eu
+
+PORT=${PORT:-18080}
+LITMUS=${LITMUS:-litmus}
+ROOT_DIR=$(cd "$(dirname "$0")/.." && pwd)
+
+if ! command -v "$LITMUS" >/dev/null 2>&1; then
+    echo "找不到 litmus，请先安装（如 apt install litmus）或用 LITMUS 指定路径" >&2
+    exit 2
+fi
+
+cargo build --release --manifest-path "$ROOT_DIR/Cargo.toml"
+
+# 配置只从可执行文件所在目录的 .env 读取，因此把程序复制到临时目录运行
+WORK=$(mktemp -d)
+cp "$ROOT_DIR/target/release/fs-proxy" "$WORK/"
+mkdir "$WORK/files"
+cat >"$WORK/.env" <<EOF
+HOST=127.0.0.1
+PORT=$PORT
+ROOT=$WORK/files
+DATABASE_URL=sqlite:$WORK/fs-proxy.sqlite
+EOF
+
+"$WORK/fs-proxy" start >"$WORK/server.log" 2>&1 &
+SERVER=$!
+trap 'kill "$SERVER" 2>/dev/null || true; wait "$SERVER" 2>/dev/null || true; rm -rf "$WORK"' EXIT INT TERM
+
+URL="http://127.0.0.1:$PORT/dav/"
+i=0
+until curl -fs -o /dev/null -X OPTIONS "$URL"; do
+    i=$((i + 1))
+    if [ "$i" -ge 50 ] || ! kill -0 "$SERVER" 2>/dev/null; then
+        echo "服务器未能启动：" >&2
+        cat "$WORK/server.log" >&2
+        exit 1
+    fi
+    sleep 0.2
+done
+
+# litmus 在 URL 下创建 litmus/ 集合并在结束时删除
+if [ -n "${TESTS:-}" ]; then
+    export TESTS
+fi
+"$LITMUS" "$URL"
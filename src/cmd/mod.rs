@@ -1,19 +1,65 @@
 use crate::{PID_FILE, util};
 use clap::{Parser, Subcommand};
-use salvo::conn::TcpListener;
-use salvo::{Listener, Server};
+use salvo::conn::{QuinnListener, TcpListener};
+use salvo::{Listener, Router, Server, Service};
 use std::collections::HashMap;
 use std::io::Write;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio, exit};
+use std::sync::Arc;
 use std::time::Duration;
 use std::{env, fs, io, thread};
 use tokio::signal;
+use tokio::task::JoinHandle;
 
 /// 服务器配置结构
 #[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
-struct ServerConfig {
-    port: u16,
-    host: String,
+pub(crate) struct ServerConfig {
+    pub(crate) port: u16,
+    pub(crate) host: String,
+    /// 文件根目录，所有文件操作都限制在此目录内
+    pub(crate) root: String,
+    /// 其他挂载点，如 `datasets=/srv/data,ro;scratch=/tmp/scratch,max_size=1073741824`
+    pub(crate) mounts: String,
+    /// 数据库连接串，支持 sqlite / postgres / mysql
+    pub(crate) database_url: String,
+    /// 解压上传归档时的最大条目数
+    pub(crate) extract_max_entries: usize,
+    /// 解压上传归档时的最大总字节数
+    pub(crate) extract_max_bytes: u64,
+    /// 回收站保留天数，0 表示不自动清除
+    pub(crate) trash_retention_days: u64,
+    /// 版本保留规则，如 `docs/**=20;*.log=off;**=10,30d`
+    pub(crate) version_rules: String,
+    /// Redis 地址，多个节点以逗号分隔；为空时锁只在进程内有效
+    pub(crate) redis_url: String,
+    /// 以集群模式连接 Redis
+    pub(crate) redis_cluster: bool,
+    /// 启动时进入只读维护的挂载点，`true` 表示全部
+    pub(crate) read_only: String,
+    /// 管理接口的令牌，为空时管理接口关闭
+    pub(crate) admin_token: String,
+    /// S3 接口中默认挂载点的桶名，为空时不公开默认挂载点
+    pub(crate) s3_bucket: String,
+    /// 额外监听的 Unix 套接字路径，为空时只监听 TCP
+    pub(crate) unix_socket: String,
+    /// Unix 套接字文件权限（八进制）
+    pub(crate) unix_socket_mode: String,
+    /// Unix 套接字文件属主，如 `www-data:www-data`，为空时不修改
+    pub(crate) unix_socket_owner: String,
+    /// TLS 证书链（PEM），与 `tls_key` 同时配置时以 HTTPS 监听
+    pub(crate) tls_cert: String,
+    /// TLS 私钥（PEM）
+    pub(crate) tls_key: String,
+    /// 校验客户端证书的 CA（PEM），为空时不要求客户端证书
+    pub(crate) tls_client_ca: String,
+    /// 客户端证书校验方式：`required` 或 `optional`
+    pub(crate) tls_client_auth: String,
+    /// 可调用管理接口的客户端证书 CN，以逗号分隔
+    pub(crate) tls_admin_clients: String,
+    /// 在同一端口的 UDP 上提供 HTTP/3，需要配置证书
+    pub(crate) http3: bool,
 }
 
 /// 命令行参数结构
@@ -252,6 +298,69 @@ fn load_config() -> ServerConfig {
         if let Some(h) = map.get("HOST") {
             default_config.host = h.to_string();
         }
+        if let Some(r) = map.get("ROOT") {
+            default_config.root = r.to_string();
+        }
+        if let Some(m) = map.get("MOUNTS") {
+            default_config.mounts = m.to_string();
+        }
+        if let Some(d) = map.get("DATABASE_URL") {
+            default_config.database_url = d.to_string();
+        }
+        if let Some(n) = map.get("EXTRACT_MAX_ENTRIES") {
+            default_config.extract_max_entries = n.parse::<usize>().unwrap_or(10_000);
+        }
+        if let Some(n) = map.get("EXTRACT_MAX_BYTES") {
+            default_config.extract_max_bytes = n.parse::<u64>().unwrap_or(1 << 32);
+        }
+        if let Some(n) = map.get("TRASH_RETENTION_DAYS") {
+            default_config.trash_retention_days = n.parse::<u64>().unwrap_or(30);
+        }
+        if let Some(r) = map.get("VERSION_RULES") {
+            default_config.version_rules = r.to_string();
+        }
+        if let Some(r) = map.get("REDIS_URL") {
+            default_config.redis_url = r.to_string();
+        }
+        if let Some(c) = map.get("REDIS_CLUSTER") {
+            default_config.redis_cluster = c.parse::<bool>().unwrap_or(false);
+        }
+        if let Some(r) = map.get("READ_ONLY") {
+            default_config.read_only = r.to_string();
+        }
+        if let Some(t) = map.get("ADMIN_TOKEN") {
+            default_config.admin_token = t.to_string();
+        }
+        if let Some(b) = map.get("S3_BUCKET") {
+            default_config.s3_bucket = b.trim().to_string();
+        }
+        if let Some(u) = map.get("UNIX_SOCKET") {
+            default_config.unix_socket = u.trim().to_string();
+        }
+        if let Some(m) = map.get("UNIX_SOCKET_MODE") {
+            default_config.unix_socket_mode = m.trim().to_string();
+        }
+        if let Some(o) = map.get("UNIX_SOCKET_OWNER") {
+            default_config.unix_socket_owner = o.trim().to_string();
+        }
+        if let Some(c) = map.get("TLS_CERT") {
+            default_config.tls_cert = c.trim().to_string();
+        }
+        if let Some(k) = map.get("TLS_KEY") {
+            default_config.tls_key = k.trim().to_string();
+        }
+        if let Some(c) = map.get("TLS_CLIENT_CA") {
+            default_config.tls_client_ca = c.trim().to_string();
+        }
+        if let Some(a) = map.get("TLS_CLIENT_AUTH") {
+            default_config.tls_client_auth = a.trim().to_string();
+        }
+        if let Some(c) = map.get("TLS_ADMIN_CLIENTS") {
+            default_config.tls_admin_clients = c.trim().to_string();
+        }
+        if let Some(h) = map.get("HTTP3") {
+            default_config.http3 = h.parse::<bool>().unwrap_or(false);
+        }
         log::info!(
             "加载配置文件: {}",
             util::EXECUTABLE_DIRECTORY.clone() + std::path::MAIN_SEPARATOR_STR + ".env"
@@ -261,6 +370,75 @@ fn load_config() -> ServerConfig {
     let mut map = HashMap::new();
     map.insert("PORT".to_string(), default_config.port.to_string());
     map.insert("HOST".to_string(), default_config.host.to_string());
+    map.insert("ROOT".to_string(), default_config.root.to_string());
+    map.insert("MOUNTS".to_string(), default_config.mounts.to_string());
+    map.insert(
+        "DATABASE_URL".to_string(),
+        default_config.database_url.to_string(),
+    );
+    map.insert(
+        "EXTRACT_MAX_ENTRIES".to_string(),
+        default_config.extract_max_entries.to_string(),
+    );
+    map.insert(
+        "EXTRACT_MAX_BYTES".to_string(),
+        default_config.extract_max_bytes.to_string(),
+    );
+    map.insert(
+        "TRASH_RETENTION_DAYS".to_string(),
+        default_config.trash_retention_days.to_string(),
+    );
+    map.insert(
+        "VERSION_RULES".to_string(),
+        default_config.version_rules.to_string(),
+    );
+    map.insert(
+        "REDIS_URL".to_string(),
+        default_config.redis_url.to_string(),
+    );
+    map.insert(
+        "REDIS_CLUSTER".to_string(),
+        default_config.redis_cluster.to_string(),
+    );
+    map.insert(
+        "READ_ONLY".to_string(),
+        default_config.read_only.to_string(),
+    );
+    map.insert(
+        "ADMIN_TOKEN".to_string(),
+        default_config.admin_token.to_string(),
+    );
+    map.insert(
+        "S3_BUCKET".to_string(),
+        default_config.s3_bucket.to_string(),
+    );
+    map.insert(
+        "UNIX_SOCKET".to_string(),
+        default_config.unix_socket.to_string(),
+    );
+    map.insert(
+        "UNIX_SOCKET_MODE".to_string(),
+        default_config.unix_socket_mode.to_string(),
+    );
+    map.insert(
+        "UNIX_SOCKET_OWNER".to_string(),
+        default_config.unix_socket_owner.to_string(),
+    );
+    map.insert("TLS_CERT".to_string(), default_config.tls_cert.to_string());
+    map.insert("TLS_KEY".to_string(), default_config.tls_key.to_string());
+    map.insert(
+        "TLS_CLIENT_CA".to_string(),
+        default_config.tls_client_ca.to_string(),
+    );
+    map.insert(
+        "TLS_CLIENT_AUTH".to_string(),
+        default_config.tls_client_auth.to_string(),
+    );
+    map.insert(
+        "TLS_ADMIN_CLIENTS".to_string(),
+        default_config.tls_admin_clients.to_string(),
+    );
+    map.insert("HTTP3".to_string(), default_config.http3.to_string());
     log::debug!("{:?}", map.clone());
     match util::write_to_default_env_file(map) {
         Ok(_) => {
@@ -284,6 +462,27 @@ impl Default for ServerConfig {
         ServerConfig {
             port: 8080,
             host: "127.0.0.1".to_string(),
+            root: "files".to_string(),
+            mounts: String::new(),
+            database_url: "sqlite:data/fs-proxy.sqlite".to_string(),
+            extract_max_entries: 10_000,
+            extract_max_bytes: 1 << 32,
+            trash_retention_days: 30,
+            version_rules: String::new(),
+            redis_url: String::new(),
+            redis_cluster: false,
+            read_only: String::new(),
+            admin_token: String::new(),
+            s3_bucket: "root".to_string(),
+            unix_socket: String::new(),
+            unix_socket_mode: "660".to_string(),
+            unix_socket_owner: String::new(),
+            tls_cert: String::new(),
+            tls_key: String::new(),
+            tls_client_ca: String::new(),
+            tls_client_auth: "required".to_string(),
+            tls_admin_clients: String::new(),
+            http3: false,
         }
     }
 }
@@ -295,31 +494,53 @@ async fn start_server() -> anyhow::Result<(), String> {
     let os = env::consts::OS;
 
     log::info!("启动FS Proxy ({}版本)...", os);
-    log::info!("监听地址: http://{}:{}", config.host, config.port);
+    let tls = crate::tls::TlsSettings::from_config(&config)?
+        .map(crate::tls::Tls::load)
+        .transpose()?
+        .map(Arc::new);
+    if config.http3 && tls.is_none() {
+        return Err("HTTP3 需要配置 TLS_CERT 与 TLS_KEY".to_string());
+    }
+    log::info!(
+        "监听地址: {}://{}:{}",
+        if tls.is_some() { "https" } else { "http" },
+        config.host,
+        config.port
+    );
     log::info!("PID文件: {}", PID_FILE.clone());
 
-    // 保存PID
-    save_pid()?;
-
     // 创建路由
-    let router = crate::web::create_router();
+    let router = crate::web::create_router(&config)
+        .await
+        .map_err(|e| format!("{}", e))?;
 
-    // 创建TCP监听器
-    let addr = format!("{}:{}", config.host, config.port);
-    log::info!("正在监听 {}", addr);
-    let acceptor = TcpListener::new(addr).bind().await;
+    // 保存PID
+    save_pid()?;
 
-    // 启动服务器
-    let server = Server::new(acceptor).serve(router);
+    // 将服务器 Future 放到独立任务中运行，并使用 select 等待任务完成或 Ctrl+C
+    let running = listen(&config, tls, router).await?;
 
     log::info!("服务器已启动，按 Ctrl+C 停止");
 
-    // 将服务器 Future 放到独立任务中运行，并使用 select 等待任务完成或 Ctrl+C
-    let server_handle = tokio::spawn(server);
+    // `stop` 命令发送的 SIGTERM 与 Ctrl+C 一样走正常退出流程
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut term) => {
+                term.recv().await;
+            }
+            Err(e) => {
+                log::warn!("无法监听 SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
     tokio::select! {
         // 等待服务器任务完成
-        res = server_handle => {
+        res = running.server => {
             match res {
                 Ok(_) => log::info!("服务器正常退出"),
                 Err(e) => log::error!("服务器任务被取消或 panic: {}", e),
@@ -329,15 +550,224 @@ async fn start_server() -> anyhow::Result<(), String> {
         _ = signal::ctrl_c() => {
             log::info!("\n接收到中断信号，正在关闭服务器...");
         }
+        // 等待终止信号
+        _ = terminate => {
+            log::info!("接收到终止信号，正在关闭服务器...");
+        }
     }
 
     // 删除PID文件
     let _ = fs::remove_file(PID_FILE.clone());
+    // 删除 Unix 套接字文件
+    #[cfg(unix)]
+    if !config.unix_socket.is_empty() {
+        let _ = fs::remove_file(&config.unix_socket);
+    }
 
     log::info!("服务器已停止");
     Ok(())
 }
 
+/// 运行中的服务器
+struct Running {
+    /// TCP 上的服务器任务
+    server: JoinHandle<()>,
+    /// HTTP/3 服务器任务
+    _h3: Option<JoinHandle<()>>,
+    /// 证书重新加载的触发器，丢弃时停止
+    _tls_watcher: Option<crate::tls::Watcher>,
+}
+
+/// 在 `HOST:PORT` 上启动服务器
+///
+/// 配置了证书时 TCP 上使用 TLS；启用 HTTP/3 时另起一个 QUIC 服务器共用路由，并在 TCP 的响应中以 Alt-Svc 通告同一端口号。
+async fn listen(
+    config: &ServerConfig,
+    tls: Option<Arc<crate::tls::Tls>>,
+    router: Router,
+) -> anyhow::Result<Running, String> {
+    let addr = format!("{}:{}", config.host, config.port);
+    log::info!("正在监听 {}", addr);
+    let listener = TcpListener::new(addr.clone());
+    let router = Arc::new(router);
+    let Some(tls) = tls else {
+        return Ok(Running {
+            server: serve(listener, config, Service::new(router)).await?,
+            _h3: None,
+            _tls_watcher: None,
+        });
+    };
+    let watcher = tls.watch()?;
+    let mut service = Service::new(router.clone());
+    let h3 = if config.http3 {
+        service = service.hoop(crate::tls::AltSvc::new(config.port));
+        Some(serve_h3(&tls, &addr, router.clone()).await?)
+    } else {
+        None
+    };
+    Ok(Running {
+        server: serve_tls(listener, tls, config, service, router).await?,
+        _h3: h3,
+        _tls_watcher: Some(watcher),
+    })
+}
+
+/// 绑定监听器并在独立任务中运行服务器，配置了 Unix 套接字时与之一起监听
+async fn serve<L>(
+    listener: L,
+    config: &ServerConfig,
+    service: Service,
+) -> anyhow::Result<JoinHandle<()>, String>
+where
+    L: Listener + Unpin + 'static,
+    L::Acceptor: Unpin + 'static,
+{
+    #[cfg(unix)]
+    if !config.unix_socket.is_empty() {
+        let unix = unix_listener(config)?;
+        log::info!("正在监听 unix://{}", config.unix_socket);
+        let acceptor = listener
+            .join(unix)
+            .try_bind()
+            .await
+            .map_err(|e| format!("监听失败: {}", e))?;
+        return Ok(tokio::spawn(Server::new(acceptor).serve(service)));
+    }
+    #[cfg(not(unix))]
+    if !config.unix_socket.is_empty() {
+        log::warn!("当前平台不支持 Unix 套接字，忽略 UNIX_SOCKET");
+    }
+    let acceptor = listener
+        .try_bind()
+        .await
+        .map_err(|e| format!("监听失败: {}", e))?;
+    Ok(tokio::spawn(Server::new(acceptor).serve(service)))
+}
+
+/// 在 TCP 上以 TLS 提供服务，配置了 Unix 套接字时另起一个服务器监听它
+///
+/// TLS 连接由 [`crate::tls::TlsAcceptor::serve`] 处理，以便把客户端证书身份随连接带到请求中。
+async fn serve_tls(
+    listener: TcpListener<String>,
+    tls: Arc<crate::tls::Tls>,
+    config: &ServerConfig,
+    service: Service,
+    router: Arc<Router>,
+) -> anyhow::Result<JoinHandle<()>, String> {
+    #[cfg(unix)]
+    if !config.unix_socket.is_empty() {
+        let unix = unix_listener(config)?
+            .try_bind()
+            .await
+            .map_err(|e| format!("监听失败: {}", e))?;
+        log::info!("正在监听 unix://{}", config.unix_socket);
+        tokio::spawn(Server::new(unix).serve(router));
+    }
+    #[cfg(not(unix))]
+    if !config.unix_socket.is_empty() {
+        log::warn!("当前平台不支持 Unix 套接字，忽略 UNIX_SOCKET");
+    }
+    let acceptor = crate::tls::TlsListener::new(listener, tls)
+        .bind()
+        .await
+        .map_err(|e| format!("监听失败: {}", e))?;
+    Ok(tokio::spawn(acceptor.serve(service)))
+}
+
+/// 在与 TCP 相同端口号的 UDP 上以 HTTP/3 提供服务
+async fn serve_h3(
+    tls: &crate::tls::Tls,
+    addr: &str,
+    router: Arc<Router>,
+) -> anyhow::Result<JoinHandle<()>, String> {
+    // QUIC 端点在第一次接受连接时才绑定，绑定失败会反复报错，先在这里检查端口
+    drop(std::net::UdpSocket::bind(addr).map_err(|e| format!("监听 UDP {} 失败: {}", addr, e))?);
+    let acceptor = QuinnListener::new(tls.quic_config()?, addr.to_string())
+        .try_bind()
+        .await
+        .map_err(|e| format!("监听失败: {}", e))?;
+    log::info!("正在监听 HTTP/3 (UDP) {}", addr);
+    Ok(tokio::spawn(Server::new(acceptor).serve(router)))
+}
+
+/// 按配置创建 Unix 套接字监听器，绑定前清理上次遗留的套接字文件
+#[cfg(unix)]
+fn unix_listener(
+    config: &ServerConfig,
+) -> anyhow::Result<salvo::conn::UnixListener<PathBuf>, String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = PathBuf::from(&config.unix_socket);
+    remove_stale_socket(&path)?;
+    let mode = parse_socket_mode(&config.unix_socket_mode)?;
+    let listener =
+        salvo::conn::UnixListener::new(path).permissions(fs::Permissions::from_mode(mode));
+    if config.unix_socket_owner.is_empty() {
+        return Ok(listener);
+    }
+    let (uid, gid) = parse_socket_owner(&config.unix_socket_owner)?;
+    Ok(listener.owner(uid, gid))
+}
+
+/// 解析八进制的套接字文件权限，如 `660`、`0770`
+#[cfg(unix)]
+fn parse_socket_mode(mode: &str) -> anyhow::Result<u32, String> {
+    match u32::from_str_radix(mode, 8) {
+        Ok(m) if m <= 0o777 => Ok(m),
+        _ => Err(format!("无效的 UNIX_SOCKET_MODE: {}", mode)),
+    }
+}
+
+/// 解析 `user[:group]` 形式的套接字文件属主，用户和组可以是名称或数字 ID
+#[cfg(unix)]
+fn parse_socket_owner(owner: &str) -> anyhow::Result<(Option<u32>, Option<u32>), String> {
+    use nix::unistd::{Group, User};
+
+    let (user, group) = owner.split_once(':').unwrap_or((owner, ""));
+    let uid = match user {
+        "" => None,
+        u => match u.parse::<u32>() {
+            Ok(id) => Some(id),
+            Err(_) => match User::from_name(u) {
+                Ok(Some(user)) => Some(user.uid.as_raw()),
+                _ => return Err(format!("未知用户: {}", u)),
+            },
+        },
+    };
+    let gid = match group {
+        "" => None,
+        g => match g.parse::<u32>() {
+            Ok(id) => Some(id),
+            Err(_) => match Group::from_name(g) {
+                Ok(Some(group)) => Some(group.gid.as_raw()),
+                _ => return Err(format!("未知用户组: {}", g)),
+            },
+        },
+    };
+    Ok((uid, gid))
+}
+
+/// 删除上次异常退出遗留的套接字文件；仍有进程在监听或不是套接字时报错
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> anyhow::Result<(), String> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("无法访问 {}: {}", path.display(), e)),
+    };
+    if !meta.file_type().is_socket() {
+        return Err(format!("{} 已存在且不是套接字", path.display()));
+    }
+    if UnixStream::connect(path).is_ok() {
+        return Err(format!("{} 已有进程在监听", path.display()));
+    }
+    log::info!("删除遗留的套接字文件: {}", path.display());
+    fs::remove_file(path).map_err(|e| format!("删除 {} 失败: {}", path.display(), e))
+}
+
 /// 重启服务器
 fn restart_server() -> anyhow::Result<(), String> {
     log::info!("正在重启服务器...");
@@ -461,3 +891,159 @@ pub fn handle_command(command: Commands) -> anyhow::Result<(), String> {
         }
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::tls::tests::{Ca, connect, connector, get, h3_client};
+    use crate::tls::{ClientAuth, Tls, TlsSettings};
+    use bytes::Buf;
+    use salvo::http::StatusCode;
+    use salvo::prelude::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[handler]
+    async fn hello() -> &'static str {
+        "hello"
+    }
+
+    #[test]
+    fn test_parse_socket_options() {
+        assert_eq!(parse_socket_mode("660"), Ok(0o660));
+        assert_eq!(parse_socket_mode("0770"), Ok(0o770));
+        assert!(parse_socket_mode("888").is_err());
+        assert!(parse_socket_mode("7777").is_err());
+
+        assert_eq!(parse_socket_owner("1000"), Ok((Some(1000), None)));
+        assert_eq!(parse_socket_owner(":33"), Ok((None, Some(33))));
+        assert_eq!(parse_socket_owner("root:0"), Ok((Some(0), Some(0))));
+        assert!(parse_socket_owner("no-such-user-fsp").is_err());
+    }
+
+    #[test]
+    fn test_remove_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fsp.sock");
+        assert!(remove_stale_socket(&path).is_ok());
+
+        let live = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        assert!(remove_stale_socket(&path).is_err());
+        drop(live);
+        assert!(remove_stale_socket(&path).is_ok());
+        assert!(!path.exists());
+
+        fs::write(&path, "x").unwrap();
+        assert!(remove_stale_socket(&path).is_err());
+        assert!(path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_unix_listener() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fsp.sock");
+        // 遗留的套接字文件会在绑定前被清理
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let config = ServerConfig {
+            unix_socket: path.to_string_lossy().to_string(),
+            unix_socket_mode: "600".to_string(),
+            ..ServerConfig::default()
+        };
+        let acceptor = TcpListener::new("127.0.0.1:0")
+            .join(unix_listener(&config).unwrap())
+            .bind()
+            .await;
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        tokio::spawn(Server::new(acceptor).serve(Router::new().get(hello)));
+
+        let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("hello"));
+    }
+
+    /// TCP 和 UDP 上都空闲的端口
+    fn free_port() -> u16 {
+        loop {
+            let port = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            if std::net::UdpSocket::bind(("127.0.0.1", port)).is_ok() {
+                return port;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http3_download() {
+        let dir = tempfile::tempdir().unwrap();
+        let ca = Ca::new();
+        let (cert, key) = ca.issue("server", &["localhost"]);
+        std::fs::write(dir.path().join("cert.pem"), cert).unwrap();
+        std::fs::write(dir.path().join("key.pem"), key.serialize_pem()).unwrap();
+        let tls = Arc::new(
+            Tls::load(TlsSettings {
+                cert: dir.path().join("cert.pem"),
+                key: dir.path().join("key.pem"),
+                client_ca: None,
+                client_auth: ClientAuth::Required,
+            })
+            .unwrap(),
+        );
+        std::fs::create_dir_all(dir.path().join("files")).unwrap();
+        let data: Vec<u8> = (0..24u32 << 20)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        std::fs::write(dir.path().join("files/big.bin"), &data).unwrap();
+        let port = free_port();
+        let config = ServerConfig {
+            port,
+            root: dir.path().join("files").display().to_string(),
+            database_url: "sqlite::memory:".to_string(),
+            http3: true,
+            ..Default::default()
+        };
+        let router = || crate::web::create_router(&config);
+
+        // UDP 端口被占用时启动即失败，而不是等到第一个 QUIC 连接
+        let busy = std::net::UdpSocket::bind(("127.0.0.1", port)).unwrap();
+        let err = listen(&config, Some(tls.clone()), router().await.unwrap())
+            .await
+            .err()
+            .unwrap();
+        assert!(err.contains("UDP"), "{}", err);
+        drop(busy);
+
+        let _running = listen(&config, Some(tls), router().await.unwrap())
+            .await
+            .unwrap();
+        let addr: std::net::SocketAddr = ([127, 0, 0, 1], port).into();
+        let mut stream = connect(addr, &connector(&ca, None)).await.unwrap();
+        let (head, _) = get(&mut stream, "/health").await.unwrap();
+        assert!(head.contains(&format!("alt-svc: h3=\":{}\"", port)));
+
+        let mut send_request = h3_client(&ca, "127.0.0.1:0".parse().unwrap(), addr).await;
+        let uri = format!("https://localhost:{}/fs/big.bin", port);
+        let request = salvo::hyper::Request::get(uri).body(()).unwrap();
+        let mut stream = send_request.send_request(request).await.unwrap();
+        stream.finish().await.unwrap();
+        let response = stream.recv_response().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.version(), salvo::http::Version::HTTP_3);
+        let mut body = Vec::with_capacity(data.len());
+        while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+            body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+        }
+        assert_eq!(body.len(), data.len());
+        assert!(body == data);
+    }
+}
@@ -0,0 +1,172 @@
+//! WebDAV 的 `If` 请求头（RFC 4918 第 10.4 节）
+
+/// 单个条件：锁令牌或实体标签，可以取反
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Token { not: bool, token: String },
+    ETag { not: bool, etag: String },
+}
+
+/// 一个条件列表，全部成立时列表成立；`resource` 为空时作用于请求的资源
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct List {
+    pub resource: Option<String>,
+    pub conditions: Vec<Condition>,
+}
+
+/// 资源的当前状态
+#[derive(Debug, Clone, Default)]
+pub struct State {
+    pub etag: Option<String>,
+    /// 作用于资源的锁的令牌
+    pub tokens: Vec<String>,
+}
+
+/// 解析后的 `If` 头，任意一个列表成立时整个条件成立
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfHeader {
+    pub lists: Vec<List>,
+}
+
+/// 去掉弱标签前缀后比较实体标签
+fn same_etag(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+impl Condition {
+    fn holds(&self, state: &State) -> bool {
+        match self {
+            Condition::Token { not, token } => state.tokens.contains(token) != *not,
+            Condition::ETag { not, etag } => {
+                state.etag.as_deref().is_some_and(|e| same_etag(e, etag)) != *not
+            }
+        }
+    }
+}
+
+impl IfHeader {
+    /// 解析头的值
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut lists = Vec::new();
+        let mut resource = None;
+        let mut rest = value.trim_start();
+        while let Some(c) = rest.chars().next() {
+            match c {
+                '<' => {
+                    let end = rest.find('>').ok_or("资源标签未闭合")?;
+                    resource = Some(rest[1..end].to_string());
+                    rest = &rest[end + 1..];
+                }
+                '(' => {
+                    let end = rest.find(')').ok_or("条件列表未闭合")?;
+                    let conditions = parse_conditions(&rest[1..end])?;
+                    if conditions.is_empty() {
+                        return Err("条件列表为空".to_string());
+                    }
+                    lists.push(List {
+                        resource: resource.clone(),
+                        conditions,
+                    });
+                    rest = &rest[end + 1..];
+                }
+                _ => return Err(format!("无法解析的 If 头: {}", value)),
+            }
+            rest = rest.trim_start();
+        }
+        if lists.is_empty() {
+            return Err("If 头为空".to_string());
+        }
+        Ok(IfHeader { lists })
+    }
+
+    /// 列表中提到的资源标签，不含请求的资源
+    pub fn resources(&self) -> Vec<&str> {
+        let mut resources: Vec<&str> = self
+            .lists
+            .iter()
+            .filter_map(|l| l.resource.as_deref())
+            .collect();
+        resources.sort();
+        resources.dedup();
+        resources
+    }
+
+    /// 求值，`state` 给出资源标签（`None` 为请求的资源）对应的状态
+    pub fn evaluate<'a>(&self, state: impl Fn(Option<&str>) -> &'a State) -> bool {
+        self.lists.iter().any(|list| {
+            let state = state(list.resource.as_deref());
+            list.conditions.iter().all(|c| c.holds(state))
+        })
+    }
+}
+
+fn parse_conditions(mut rest: &str) -> Result<Vec<Condition>, String> {
+    let mut conditions = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(conditions);
+        }
+        let not = match rest.get(..3) {
+            Some(word) if word.eq_ignore_ascii_case("not") => {
+                rest = rest[3..].trim_start();
+                true
+            }
+            _ => false,
+        };
+        let (open, close) = match rest.chars().next() {
+            Some('<') => ('<', '>'),
+            Some('[') => ('[', ']'),
+            _ => return Err(format!("无法解析的条件: {}", rest)),
+        };
+        let end = rest.find(close).ok_or("条件未闭合")?;
+        let value = rest[1..end].to_string();
+        conditions.push(match open {
+            '<' => Condition::Token { not, token: value },
+            _ => Condition::ETag { not, etag: value },
+        });
+        rest = &rest[end + 1..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_header() {
+        let header = IfHeader::parse(
+            r#"</dav/a> (<opaquelocktoken:1> ["e1"]) (Not <DAV:no-lock>) </dav/b> (["e2"])"#,
+        )
+        .unwrap();
+        assert_eq!(header.lists.len(), 3);
+        assert_eq!(header.resources(), vec!["/dav/a", "/dav/b"]);
+        assert_eq!(
+            header.lists[1].conditions,
+            vec![Condition::Token {
+                not: true,
+                token: "DAV:no-lock".to_string()
+            }]
+        );
+
+        let locked = State {
+            etag: Some("\"e1\"".to_string()),
+            tokens: vec!["opaquelocktoken:1".to_string()],
+        };
+        let other = State::default();
+        let header = IfHeader::parse(r#"(<opaquelocktoken:1> ["e2"])"#).unwrap();
+        assert!(!header.evaluate(|_| &locked));
+        let header = IfHeader::parse(r#"(<opaquelocktoken:1> [W/"e1"])"#).unwrap();
+        assert!(header.evaluate(|_| &locked));
+        assert!(!header.evaluate(|_| &other));
+        let header = IfHeader::parse("(<opaquelocktoken:2>) (Not <DAV:no-lock>)").unwrap();
+        assert!(header.evaluate(|_| &other));
+        let header = IfHeader::parse("(Not <opaquelocktoken:1>)").unwrap();
+        assert!(!header.evaluate(|_| &locked));
+
+        assert!(IfHeader::parse("").is_err());
+        assert!(IfHeader::parse("(<a>").is_err());
+        assert!(IfHeader::parse("()").is_err());
+        assert!(IfHeader::parse("<a> x").is_err());
+    }
+}
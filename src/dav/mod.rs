@@ -0,0 +1,245 @@
+//! WebDAV（RFC 4918）协议部分：请求体解析、多状态响应和 `If` 头，与存储和路由无关
+
+use std::fmt;
+use xml::name::OwnedName;
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+
+pub mod condition;
+pub mod request;
+pub mod response;
+
+/// DAV 命名空间
+pub const DAV_NS: &str = "DAV:";
+
+/// 带命名空间的元素名，命名空间可以为空
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PropName {
+    pub namespace: String,
+    pub name: String,
+}
+
+impl PropName {
+    pub fn new(namespace: &str, name: &str) -> Self {
+        PropName {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    /// DAV 命名空间中的元素
+    pub fn dav(name: &str) -> Self {
+        PropName::new(DAV_NS, name)
+    }
+
+    pub fn is_dav(&self, name: &str) -> bool {
+        self.namespace == DAV_NS && self.name == name
+    }
+
+    fn from_owned(name: &OwnedName) -> Self {
+        PropName {
+            namespace: name.namespace.clone().unwrap_or_default(),
+            name: name.local_name.clone(),
+        }
+    }
+
+    /// 开始标签，`empty` 为真时为空元素；DAV 元素使用 `D:` 前缀，其他命名空间就地声明
+    pub fn start_tag(&self, empty: bool) -> String {
+        let end = if empty { "/>" } else { ">" };
+        if self.namespace == DAV_NS {
+            format!("<D:{}{}", self.name, end)
+        } else if self.namespace.is_empty() {
+            format!("<{} xmlns=\"\"{}", self.name, end)
+        } else {
+            format!(
+                "<P:{} xmlns:P=\"{}\"{}",
+                self.name,
+                escape(&self.namespace),
+                end
+            )
+        }
+    }
+
+    /// 结束标签，与 `start_tag` 对应
+    pub fn end_tag(&self) -> String {
+        if self.namespace == DAV_NS {
+            format!("</D:{}>", self.name)
+        } else if self.namespace.is_empty() {
+            format!("</{}>", self.name)
+        } else {
+            format!("</P:{}>", self.name)
+        }
+    }
+}
+
+impl fmt::Display for PropName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{{}}}{}", self.namespace, self.name)
+    }
+}
+
+/// 解析后的 XML 元素
+#[derive(Debug, Clone)]
+pub struct Element {
+    pub name: PropName,
+    attributes: Vec<(OwnedName, String)>,
+    pub children: Vec<Node>,
+}
+
+/// 元素的子节点
+#[derive(Debug, Clone)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    /// 子元素，忽略文本
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None,
+        })
+    }
+
+    /// 第一个 DAV 命名空间中名为 `name` 的子元素
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name.is_dav(name))
+    }
+
+    /// 全部文本内容
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for node in &self.children {
+            match node {
+                Node::Text(t) => text.push_str(t),
+                Node::Element(e) => text.push_str(&e.text()),
+            }
+        }
+        text
+    }
+
+    /// 子节点序列化为 XML，每个元素自带命名空间声明，可以脱离原文档单独使用
+    pub fn inner_xml(&self) -> String {
+        let mut out = String::new();
+        for node in &self.children {
+            match node {
+                Node::Text(t) => out.push_str(&escape(t)),
+                Node::Element(e) => e.write(&mut out),
+            }
+        }
+        out
+    }
+
+    fn write(&self, out: &mut String) {
+        out.push('<');
+        out.push_str(&self.name.name);
+        out.push_str(&format!(" xmlns=\"{}\"", escape(&self.name.namespace)));
+        for (i, (name, value)) in self.attributes.iter().enumerate() {
+            match (&name.namespace, &name.prefix) {
+                (Some(_), Some(prefix)) if prefix == "xml" => {
+                    out.push_str(&format!(" xml:{}", name.local_name))
+                }
+                (Some(ns), _) => out.push_str(&format!(
+                    " xmlns:a{i}=\"{}\" a{i}:{}",
+                    escape(ns),
+                    name.local_name
+                )),
+                (None, _) => out.push_str(&format!(" {}", name.local_name)),
+            }
+            out.push_str(&format!("=\"{}\"", escape(value)));
+        }
+        if self.children.is_empty() {
+            out.push_str("/>");
+            return;
+        }
+        out.push('>');
+        out.push_str(&self.inner_xml());
+        out.push_str(&format!("</{}>", self.name.name));
+    }
+}
+
+/// 解析 XML 文档，返回根元素
+pub fn parse(body: &[u8]) -> Result<Element, String> {
+    let config = ParserConfig::new()
+        .trim_whitespace(false)
+        .cdata_to_characters(true)
+        .ignore_comments(true);
+    let mut stack: Vec<Element> = Vec::new();
+    for event in EventReader::new_with_config(body, config) {
+        match event.map_err(|e| e.to_string())? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => stack.push(Element {
+                name: PropName::from_owned(&name),
+                attributes: attributes.into_iter().map(|a| (a.name, a.value)).collect(),
+                children: Vec::new(),
+            }),
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().ok_or("XML 结构错误")?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(Node::Element(element)),
+                    None => return Ok(element),
+                }
+            }
+            XmlEvent::Characters(text) | XmlEvent::Whitespace(text) => {
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(Node::Text(text));
+                }
+            }
+            _ => {}
+        }
+    }
+    Err("XML 文档不完整".to_string())
+}
+
+/// 转义 XML 文本和属性值
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_serialize() {
+        let root = parse(
+            br#"<?xml version="1.0"?>
+<D:propertyupdate xmlns:D="DAV:" xmlns:t="urn:t">
+  <D:set><D:prop><t:p xml:lang="en">a &amp; <b xmlns="urn:b" t:x="1"><c/></b><d/></t:p></D:prop></D:set>
+</D:propertyupdate>"#,
+        )
+        .unwrap();
+        assert!(root.name.is_dav("propertyupdate"));
+        let prop = root.child("set").unwrap().child("prop").unwrap();
+        let p = prop.elements().next().unwrap();
+        assert_eq!(p.name, PropName::new("urn:t", "p"));
+        assert_eq!(
+            p.inner_xml(),
+            r#"a &amp; <b xmlns="urn:b" xmlns:a0="urn:t" a0:x="1"><c xmlns="urn:b"/></b><d xmlns=""/>"#
+        );
+        assert_eq!(p.text(), "a & ");
+
+        // 序列化的内容可以重新解析
+        let wrapped = format!("<v>{}</v>", p.inner_xml());
+        assert_eq!(
+            parse(wrapped.as_bytes()).unwrap().inner_xml(),
+            p.inner_xml()
+        );
+
+        assert!(parse(b"<a><b></a>").is_err());
+        assert!(parse(b"<a>").is_err());
+        assert!(parse(br#"<a><x:b xmlns:x=""/></a>"#).is_err());
+    }
+}
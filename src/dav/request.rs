@@ -0,0 +1,164 @@
+use crate::dav::{Element, PropName, parse};
+use crate::lock::Scope;
+
+/// PROPFIND 请求的内容
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropFind {
+    /// 全部属性，附加 `include` 中列出的属性
+    AllProp(Vec<PropName>),
+    /// 只要属性名
+    PropName,
+    /// 指定的属性
+    Prop(Vec<PropName>),
+}
+
+/// PROPPATCH 中的一项修改，按请求中的顺序执行
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropUpdate {
+    /// 设置属性，值为元素内容的 XML
+    Set(PropName, String),
+    Remove(PropName),
+}
+
+/// LOCK 请求的内容
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockInfo {
+    pub scope: Scope,
+    /// owner 元素的文本
+    pub owner: String,
+    /// owner 元素内容的 XML
+    pub owner_xml: Option<String>,
+}
+
+fn names(prop: &Element) -> Vec<PropName> {
+    prop.elements().map(|e| e.name.clone()).collect()
+}
+
+/// 解析 PROPFIND 请求体，空请求体等同于 `allprop`
+pub fn propfind(body: &[u8]) -> Result<PropFind, String> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(PropFind::AllProp(Vec::new()));
+    }
+    let root = parse(body)?;
+    if !root.name.is_dav("propfind") {
+        return Err(format!("根元素应为 DAV:propfind: {}", root.name));
+    }
+    if root.child("allprop").is_some() {
+        return Ok(PropFind::AllProp(
+            root.child("include").map(names).unwrap_or_default(),
+        ));
+    }
+    if root.child("propname").is_some() {
+        return Ok(PropFind::PropName);
+    }
+    match root.child("prop") {
+        Some(prop) => Ok(PropFind::Prop(names(prop))),
+        None => Err("缺少 prop、allprop 或 propname".to_string()),
+    }
+}
+
+/// 解析 PROPPATCH 请求体
+pub fn proppatch(body: &[u8]) -> Result<Vec<PropUpdate>, String> {
+    let root = parse(body)?;
+    if !root.name.is_dav("propertyupdate") {
+        return Err(format!("根元素应为 DAV:propertyupdate: {}", root.name));
+    }
+    let mut updates = Vec::new();
+    for action in root.elements() {
+        let set = if action.name.is_dav("set") {
+            true
+        } else if action.name.is_dav("remove") {
+            false
+        } else {
+            continue;
+        };
+        for prop in action.elements().filter(|e| e.name.is_dav("prop")) {
+            for element in prop.elements() {
+                updates.push(match set {
+                    true => PropUpdate::Set(element.name.clone(), element.inner_xml()),
+                    false => PropUpdate::Remove(element.name.clone()),
+                });
+            }
+        }
+    }
+    if updates.is_empty() {
+        return Err("没有要修改的属性".to_string());
+    }
+    Ok(updates)
+}
+
+/// 解析 LOCK 请求体，空请求体表示刷新已有的锁，返回 `None`
+pub fn lockinfo(body: &[u8]) -> Result<Option<LockInfo>, String> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    let root = parse(body)?;
+    if !root.name.is_dav("lockinfo") {
+        return Err(format!("根元素应为 DAV:lockinfo: {}", root.name));
+    }
+    let scope = match root.child("lockscope").and_then(|s| s.elements().next()) {
+        Some(s) if s.name.is_dav("exclusive") => Scope::Exclusive,
+        Some(s) if s.name.is_dav("shared") => Scope::Shared,
+        _ => return Err("缺少或无效的 lockscope".to_string()),
+    };
+    match root.child("locktype").and_then(|t| t.elements().next()) {
+        Some(t) if t.name.is_dav("write") => {}
+        _ => return Err("只支持 write 锁".to_string()),
+    }
+    let owner = root.child("owner");
+    Ok(Some(LockInfo {
+        scope,
+        owner: owner
+            .map(|o| o.text().trim().to_string())
+            .unwrap_or_default(),
+        owner_xml: owner.map(Element::inner_xml),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_bodies() {
+        assert_eq!(propfind(b"").unwrap(), PropFind::AllProp(Vec::new()));
+        assert_eq!(
+            propfind(
+                br#"<propfind xmlns="DAV:"><prop><getetag/><x xmlns="urn:x"/></prop></propfind>"#
+            )
+            .unwrap(),
+            PropFind::Prop(vec![PropName::dav("getetag"), PropName::new("urn:x", "x")])
+        );
+        assert_eq!(
+            propfind(br#"<propfind xmlns="DAV:"><propname/></propfind>"#).unwrap(),
+            PropFind::PropName
+        );
+        assert!(propfind(b"<propfind xmlns=\"DAV:\"><prop>").is_err());
+        assert!(propfind(br#"<x xmlns="DAV:"><allprop/></x>"#).is_err());
+
+        let updates = proppatch(
+            br#"<D:propertyupdate xmlns:D="DAV:"><D:set><D:prop><a xmlns="">1</a></D:prop></D:set><D:remove><D:prop><b xmlns="urn:b"/></D:prop></D:remove></D:propertyupdate>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            updates,
+            vec![
+                PropUpdate::Set(PropName::new("", "a"), "1".to_string()),
+                PropUpdate::Remove(PropName::new("urn:b", "b"))
+            ]
+        );
+
+        let info = lockinfo(
+            br#"<D:lockinfo xmlns:D="DAV:"><D:lockscope><D:shared/></D:lockscope><D:locktype><D:write/></D:locktype><D:owner><D:href>mailto:a@b</D:href></D:owner></D:lockinfo>"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(info.scope, Scope::Shared);
+        assert_eq!(info.owner, "mailto:a@b");
+        assert_eq!(
+            info.owner_xml.as_deref(),
+            Some(r#"<href xmlns="DAV:">mailto:a@b</href>"#)
+        );
+        assert_eq!(lockinfo(b"  ").unwrap(), None);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::dav::{PropName, escape};
+use salvo::http::StatusCode;
+
+/// XML 声明
+pub const XML_DECLARATION: &str = r#"<?xml version="1.0" encoding="utf-8"?>"#;
+
+/// 状态行，如 `HTTP/1.1 404 Not Found`
+pub fn status_line(status: StatusCode) -> String {
+    format!(
+        "HTTP/1.1 {} {}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or_default()
+    )
+}
+
+/// 一个属性及其值（已序列化的 XML，`None` 为空元素）
+pub fn property(name: &PropName, value: Option<&str>) -> String {
+    match value {
+        Some(value) if !value.is_empty() => {
+            format!("{}{}{}", name.start_tag(false), value, name.end_tag())
+        }
+        _ => name.start_tag(true),
+    }
+}
+
+/// 207 Multi-Status 响应体
+#[derive(Debug, Default)]
+pub struct Multistatus {
+    body: String,
+}
+
+impl Multistatus {
+    pub fn new() -> Self {
+        Multistatus::default()
+    }
+
+    /// 一个资源的属性，按状态分组
+    pub fn propstat(&mut self, href: &str, groups: &[(StatusCode, Vec<String>)]) {
+        self.body.push_str("<D:response><D:href>");
+        self.body.push_str(&escape(href));
+        self.body.push_str("</D:href>");
+        for (status, props) in groups.iter().filter(|(_, props)| !props.is_empty()) {
+            self.body.push_str("<D:propstat><D:prop>");
+            for prop in props {
+                self.body.push_str(prop);
+            }
+            self.body.push_str("</D:prop><D:status>");
+            self.body.push_str(&status_line(*status));
+            self.body.push_str("</D:status></D:propstat>");
+        }
+        self.body.push_str("</D:response>");
+    }
+
+    /// 一个资源的状态
+    pub fn status(&mut self, href: &str, status: StatusCode, description: Option<&str>) {
+        self.body.push_str("<D:response><D:href>");
+        self.body.push_str(&escape(href));
+        self.body.push_str("</D:href><D:status>");
+        self.body.push_str(&status_line(status));
+        self.body.push_str("</D:status>");
+        if let Some(description) = description {
+            self.body.push_str("<D:responsedescription>");
+            self.body.push_str(&escape(description));
+            self.body.push_str("</D:responsedescription>");
+        }
+        self.body.push_str("</D:response>");
+    }
+
+    pub fn finish(self) -> String {
+        format!(
+            "{}<D:multistatus xmlns:D=\"DAV:\">{}</D:multistatus>",
+            XML_DECLARATION, self.body
+        )
+    }
+}
+
+/// 只含一个 `prop` 元素的响应体，用于 LOCK
+pub fn prop(content: &str) -> String {
+    format!(
+        "{}<D:prop xmlns:D=\"DAV:\">{}</D:prop>",
+        XML_DECLARATION, content
+    )
+}
+
+/// 前置条件或后置条件错误的响应体，如 `lock-token-matches-request-uri`
+pub fn error(condition: &str) -> String {
+    format!(
+        "{}<D:error xmlns:D=\"DAV:\"><D:{}/></D:error>",
+        XML_DECLARATION, condition
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multistatus() {
+        let mut ms = Multistatus::new();
+        ms.propstat(
+            "/dav/a&b",
+            &[
+                (
+                    StatusCode::OK,
+                    vec![property(&PropName::dav("getetag"), Some("\"1\""))],
+                ),
+                (StatusCode::NOT_FOUND, vec![]),
+            ],
+        );
+        ms.status("/dav/c", StatusCode::LOCKED, None);
+        let body = ms.finish();
+        assert!(body.contains("<D:href>/dav/a&amp;b</D:href><D:propstat><D:prop><D:getetag>\"1\"</D:getetag></D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>"));
+        assert!(body.contains("<D:status>HTTP/1.1 423 Locked</D:status>"));
+        assert!(crate::dav::parse(body.as_bytes()).is_ok());
+        assert_eq!(property(&PropName::new("", "x"), None), "<x xmlns=\"\"/>");
+    }
+}
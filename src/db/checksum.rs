@@ -0,0 +1,153 @@
+use crate::db::{Db, now};
+use crate::digest::{self, Algorithm};
+use sqlx::Row;
+use std::fs::Metadata;
+use std::time::UNIX_EPOCH;
+
+/// 判断缓存是否失效的文件特征
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub inode: i64,
+    pub size: i64,
+    /// 修改时间（纳秒）
+    pub mtime_ns: i64,
+}
+
+impl Fingerprint {
+    pub fn new(metadata: &Metadata) -> Self {
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(metadata) as i64;
+        #[cfg(not(unix))]
+        let inode = 0;
+        let mtime_ns = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos() as i64)
+            .unwrap_or_default();
+        Fingerprint {
+            inode,
+            size: metadata.len() as i64,
+            mtime_ns,
+        }
+    }
+}
+
+impl Db {
+    /// 查询缓存的摘要，文件特征不一致时视为未命中
+    pub async fn get_checksum(
+        &self,
+        mount: &str,
+        path: &str,
+        algorithm: Algorithm,
+        fingerprint: Fingerprint,
+    ) -> sqlx::Result<Option<Vec<u8>>> {
+        let row = sqlx::query(&self.sql(
+            "SELECT digest FROM file_checksums \
+             WHERE mount = ? AND path = ? AND algorithm = ? AND inode = ? AND size = ? AND mtime_ns = ?",
+        ))
+        .bind(mount)
+        .bind(path)
+        .bind(algorithm.name())
+        .bind(fingerprint.inode)
+        .bind(fingerprint.size)
+        .bind(fingerprint.mtime_ns)
+        .fetch_optional(self.pool())
+        .await?;
+        Ok(row
+            .map(|row| row.try_get::<String, _>("digest"))
+            .transpose()?
+            .and_then(|hex| digest::from_hex(&hex)))
+    }
+
+    /// 保存摘要，替换同一路径和算法的旧记录
+    pub async fn put_checksum(
+        &self,
+        mount: &str,
+        path: &str,
+        algorithm: Algorithm,
+        fingerprint: Fingerprint,
+        value: &[u8],
+    ) -> sqlx::Result<()> {
+        let mut tx = self.pool().begin().await?;
+        sqlx::query(
+            &self.sql("DELETE FROM file_checksums WHERE mount = ? AND path = ? AND algorithm = ?"),
+        )
+        .bind(mount)
+        .bind(path)
+        .bind(algorithm.name())
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(&self.sql(
+            "INSERT INTO file_checksums (mount, path, algorithm, inode, size, mtime_ns, digest, computed_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        ))
+        .bind(mount)
+        .bind(path)
+        .bind(algorithm.name())
+        .bind(fingerprint.inode)
+        .bind(fingerprint.size)
+        .bind(fingerprint.mtime_ns)
+        .bind(digest::hex(value))
+        .bind(now())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::checksum::Fingerprint;
+    use crate::db::test_db;
+    use crate::digest::Algorithm;
+
+    #[tokio::test]
+    async fn test_checksum_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = test_db(dir.path()).await;
+        let fp = Fingerprint {
+            inode: 1,
+            size: 5,
+            mtime_ns: 1_000,
+        };
+        assert_eq!(
+            db.get_checksum("", "a", Algorithm::Sha256, fp)
+                .await
+                .unwrap(),
+            None
+        );
+        db.put_checksum("", "a", Algorithm::Sha256, fp, &[1, 2])
+            .await
+            .unwrap();
+        db.put_checksum("", "a", Algorithm::Sha256, fp, &[3, 4])
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_checksum("", "a", Algorithm::Sha256, fp)
+                .await
+                .unwrap(),
+            Some(vec![3, 4])
+        );
+        let changed = Fingerprint {
+            mtime_ns: 2_000,
+            ..fp
+        };
+        assert_eq!(
+            db.get_checksum("", "a", Algorithm::Sha256, changed)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            db.get_checksum("", "a", Algorithm::Md5, fp).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            db.get_checksum("data", "a", Algorithm::Sha256, fp)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
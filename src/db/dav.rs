@@ -0,0 +1,227 @@
+use crate::db::Db;
+use sqlx::Row;
+use sqlx::any::AnyRow;
+
+/// WebDAV 死属性：客户端通过 PROPPATCH 设置的任意属性
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadProperty {
+    /// 路径（相对挂载点根目录）
+    pub path: String,
+    /// 命名空间URI，可以为空
+    pub namespace: String,
+    pub name: String,
+    /// 属性元素内容的 XML
+    pub value: String,
+}
+
+/// 属性修改：`value` 为 `None` 时删除
+pub type PropertyChange = (String, String, Option<String>);
+
+fn from_row(row: AnyRow) -> sqlx::Result<DeadProperty> {
+    Ok(DeadProperty {
+        path: row.try_get("path")?,
+        namespace: row.try_get("namespace")?,
+        name: row.try_get("name")?,
+        value: row.try_get("value")?,
+    })
+}
+
+/// `path` 是否为 `root` 本身或位于其下
+fn in_tree(path: &str, root: &str) -> bool {
+    root.is_empty()
+        || path == root
+        || path
+            .strip_prefix(root)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl Db {
+    /// 路径上的全部死属性
+    pub async fn dav_properties(&self, mount: &str, path: &str) -> sqlx::Result<Vec<DeadProperty>> {
+        sqlx::query(&self.sql(
+            "SELECT path, namespace, name, value FROM dav_properties \
+             WHERE mount = ? AND path = ? ORDER BY namespace, name",
+        ))
+        .bind(mount)
+        .bind(path)
+        .fetch_all(self.pool())
+        .await?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    /// `root` 及其子树上的死属性，按路径排序，供一次查出整棵树的属性
+    pub async fn dav_property_tree(
+        &self,
+        mount: &str,
+        root: &str,
+    ) -> sqlx::Result<Vec<DeadProperty>> {
+        let rows = sqlx::query(&self.sql(
+            "SELECT path, namespace, name, value FROM dav_properties \
+             WHERE mount = ? ORDER BY path, namespace, name",
+        ))
+        .bind(mount)
+        .fetch_all(self.pool())
+        .await?;
+        let mut properties = Vec::new();
+        for row in rows {
+            let property = from_row(row)?;
+            // 路径中可能含有 `%` / `_`，不用 LIKE 匹配
+            if in_tree(&property.path, root) {
+                properties.push(property);
+            }
+        }
+        Ok(properties)
+    }
+
+    /// 在一个事务中设置和删除同一路径上的多个属性
+    pub async fn update_dav_properties(
+        &self,
+        mount: &str,
+        path: &str,
+        changes: &[PropertyChange],
+    ) -> sqlx::Result<()> {
+        let mut tx = self.pool().begin().await?;
+        for (namespace, name, value) in changes {
+            sqlx::query(&self.sql(
+                "DELETE FROM dav_properties WHERE mount = ? AND path = ? AND namespace = ? AND name = ?",
+            ))
+            .bind(mount)
+            .bind(path)
+            .bind(namespace)
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+            if let Some(value) = value {
+                sqlx::query(&self.sql(
+                    "INSERT INTO dav_properties (mount, path, namespace, name, value) VALUES (?, ?, ?, ?, ?)",
+                ))
+                .bind(mount)
+                .bind(path)
+                .bind(namespace)
+                .bind(name)
+                .bind(value)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        tx.commit().await
+    }
+
+    /// 把 `from` 子树上的属性复制到 `to`，先清除 `to` 子树上原有的属性；`keep` 为假时删除源属性（移动）
+    pub async fn copy_dav_properties(
+        &self,
+        mount: &str,
+        from: &str,
+        to: &str,
+        keep: bool,
+    ) -> sqlx::Result<()> {
+        let properties = self.dav_property_tree(mount, from).await?;
+        let replaced = self.dav_property_tree(mount, to).await?;
+        let mut tx = self.pool().begin().await?;
+        let mut removed = replaced.iter().map(|p| &p.path).collect::<Vec<_>>();
+        if !keep {
+            removed.extend(properties.iter().map(|p| &p.path));
+        }
+        removed.sort();
+        removed.dedup();
+        for path in removed {
+            sqlx::query(&self.sql("DELETE FROM dav_properties WHERE mount = ? AND path = ?"))
+                .bind(mount)
+                .bind(path)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for property in properties {
+            let path = format!("{}{}", to, &property.path[from.len()..]);
+            sqlx::query(&self.sql(
+                "INSERT INTO dav_properties (mount, path, namespace, name, value) VALUES (?, ?, ?, ?, ?)",
+            ))
+            .bind(mount)
+            .bind(path)
+            .bind(&property.namespace)
+            .bind(&property.name)
+            .bind(&property.value)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// 删除 `root` 及其子树上的属性
+    pub async fn delete_dav_properties(&self, mount: &str, root: &str) -> sqlx::Result<()> {
+        let mut paths: Vec<String> = self
+            .dav_property_tree(mount, root)
+            .await?
+            .into_iter()
+            .map(|p| p.path)
+            .collect();
+        paths.dedup();
+        for path in paths {
+            sqlx::query(&self.sql("DELETE FROM dav_properties WHERE mount = ? AND path = ?"))
+                .bind(mount)
+                .bind(path)
+                .execute(self.pool())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::memory_db;
+
+    #[tokio::test]
+    async fn test_dav_properties() {
+        let db = memory_db().await;
+        let set = |name: &str, value: &str| {
+            (
+                "urn:x".to_string(),
+                name.to_string(),
+                Some(value.to_string()),
+            )
+        };
+        db.update_dav_properties("", "a", &[set("p", "1"), set("q", "<b/>")])
+            .await
+            .unwrap();
+        db.update_dav_properties("", "a/b", &[set("p", "2")])
+            .await
+            .unwrap();
+        db.update_dav_properties("", "ab", &[set("p", "3")])
+            .await
+            .unwrap();
+        db.update_dav_properties(
+            "",
+            "a",
+            &[set("p", "4"), ("urn:x".into(), "q".into(), None)],
+        )
+        .await
+        .unwrap();
+        let props = db.dav_properties("", "a").await.unwrap();
+        assert_eq!(props.len(), 1);
+        assert_eq!(props[0].value, "4");
+        let tree = db.dav_property_tree("", "a").await.unwrap();
+        let values: Vec<_> = tree
+            .iter()
+            .map(|p| (p.path.as_str(), p.value.as_str()))
+            .collect();
+        assert_eq!(values, [("a", "4"), ("a/b", "2")]);
+
+        // 移动子树，不影响同前缀的兄弟路径和其他挂载点
+        db.copy_dav_properties("", "a", "c", false).await.unwrap();
+        assert!(db.dav_properties("", "a").await.unwrap().is_empty());
+        assert_eq!(db.dav_properties("", "c/b").await.unwrap()[0].value, "2");
+        assert_eq!(db.dav_properties("", "ab").await.unwrap()[0].value, "3");
+        assert!(db.dav_properties("data", "c").await.unwrap().is_empty());
+
+        db.copy_dav_properties("", "c", "d", true).await.unwrap();
+        assert_eq!(db.dav_properties("", "c").await.unwrap()[0].value, "4");
+        assert_eq!(db.dav_properties("", "d").await.unwrap()[0].value, "4");
+
+        db.delete_dav_properties("", "c").await.unwrap();
+        assert!(db.dav_properties("", "c/b").await.unwrap().is_empty());
+        assert_eq!(db.dav_properties("", "d/b").await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,156 @@
+use sqlx::AnyPool;
+use sqlx::any::AnyPoolOptions;
+use sqlx::migrate::Migrator;
+use std::borrow::Cow;
+use std::path::Path;
+
+pub mod checksum;
+pub mod dav;
+pub mod s3;
+pub mod trash;
+pub mod tus;
+pub mod versions;
+
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("db/sqlite/migrations");
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("db/postgres/migrations");
+static MYSQL_MIGRATOR: Migrator = sqlx::migrate!("db/mysql/migrations");
+
+/// 数据库类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbKind {
+    Sqlite,
+    Postgres,
+    MySql,
+}
+
+impl DbKind {
+    fn from_url(url: &str) -> anyhow::Result<Self> {
+        if url.starts_with("sqlite:") {
+            Ok(DbKind::Sqlite)
+        } else if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+            Ok(DbKind::Postgres)
+        } else if url.starts_with("mysql:") || url.starts_with("mariadb:") {
+            Ok(DbKind::MySql)
+        } else {
+            Err(anyhow::anyhow!("不支持的数据库: {}", url))
+        }
+    }
+}
+
+/// 数据库连接池
+#[derive(Debug, Clone)]
+pub struct Db {
+    pool: AnyPool,
+    kind: DbKind,
+}
+
+impl Db {
+    /// 连接数据库并执行迁移
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        sqlx::any::install_default_drivers();
+        let kind = DbKind::from_url(url)?;
+
+        let url = if kind == DbKind::Sqlite {
+            prepare_sqlite(url)?
+        } else {
+            url.to_string()
+        };
+        let pool = AnyPoolOptions::new()
+            .max_connections(if kind == DbKind::Sqlite { 1 } else { 10 })
+            .connect(&url)
+            .await?;
+
+        let migrator = match kind {
+            DbKind::Sqlite => &SQLITE_MIGRATOR,
+            DbKind::Postgres => &POSTGRES_MIGRATOR,
+            DbKind::MySql => &MYSQL_MIGRATOR,
+        };
+        migrator.run(&pool).await?;
+        Ok(Db { pool, kind })
+    }
+
+    pub fn pool(&self) -> &AnyPool {
+        &self.pool
+    }
+
+    /// 将 `?` 占位符转换为当前数据库的写法（PostgreSQL 使用 `$1`）
+    pub fn sql<'a>(&self, query: &'a str) -> Cow<'a, str> {
+        if self.kind != DbKind::Postgres {
+            return Cow::Borrowed(query);
+        }
+        let mut out = String::with_capacity(query.len() + 8);
+        let mut n = 0;
+        for c in query.chars() {
+            if c == '?' {
+                n += 1;
+                out.push('$');
+                out.push_str(&n.to_string());
+            } else {
+                out.push(c);
+            }
+        }
+        Cow::Owned(out)
+    }
+}
+
+/// SQLite 数据库文件不存在时自动创建
+fn prepare_sqlite(url: &str) -> anyhow::Result<String> {
+    let path = url
+        .trim_start_matches("sqlite:")
+        .trim_start_matches("//")
+        .split('?')
+        .next()
+        .unwrap_or_default();
+    if path.is_empty() || path == ":memory:" {
+        return Ok(url.to_string());
+    }
+    if let Some(parent) = Path::new(path).parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    if url.contains("mode=") {
+        Ok(url.to_string())
+    } else if url.contains('?') {
+        Ok(format!("{}&mode=rwc", url))
+    } else {
+        Ok(format!("{}?mode=rwc", url))
+    }
+}
+
+/// 当前Unix时间戳（秒）
+pub fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+#[cfg(test)]
+pub(crate) async fn test_db(dir: &Path) -> Db {
+    let url = format!("sqlite:{}", dir.join("test.sqlite").display());
+    Db::connect(&url).await.unwrap()
+}
+
+/// 内存数据库，供不关心数据库文件的测试使用
+#[cfg(test)]
+pub(crate) async fn memory_db() -> Db {
+    Db::connect("sqlite::memory:").await.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sql_placeholders() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = test_db(dir.path()).await;
+        assert_eq!(db.sql("SELECT ? , ?"), "SELECT ? , ?");
+        let pg = Db {
+            kind: DbKind::Postgres,
+            ..db
+        };
+        assert_eq!(
+            pg.sql("UPDATE t SET a = ? WHERE b = ?"),
+            "UPDATE t SET a = $1 WHERE b = $2"
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use crate::db::{Db, now};
+use serde::Serialize;
+use sqlx::Row;
+
+/// S3 访问密钥
+#[derive(Serialize, Debug, Clone)]
+pub struct S3Key {
+    pub access_key: String,
+    /// 只在创建时返回
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub secret_key: String,
+    pub description: String,
+    pub created_at: i64,
+}
+
+impl S3Key {
+    /// 新的访问密钥
+    pub fn new(access_key: String, secret_key: String, description: &str) -> Self {
+        S3Key {
+            access_key,
+            secret_key,
+            description: description.to_string(),
+            created_at: now(),
+        }
+    }
+}
+
+/// 未完成的分段上传
+#[derive(Debug, Clone)]
+pub struct S3Upload {
+    pub id: String,
+    pub mount: String,
+    /// 目标对象的键，即相对挂载点根目录的路径
+    pub key: String,
+    pub created_at: i64,
+}
+
+/// 已上传的分段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3Part {
+    pub part_number: i64,
+    /// 分段内容的 MD5（十六进制）
+    pub etag: String,
+    pub size: i64,
+    pub uploaded_at: i64,
+}
+
+impl Db {
+    /// 保存访问密钥
+    pub async fn insert_s3_key(&self, key: &S3Key) -> sqlx::Result<()> {
+        sqlx::query(&self.sql(
+            "INSERT INTO s3_keys (access_key, secret_key, description, created_at) VALUES (?, ?, ?, ?)",
+        ))
+        .bind(&key.access_key)
+        .bind(&key.secret_key)
+        .bind(&key.description)
+        .bind(key.created_at)
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// 访问密钥对应的私钥
+    pub async fn s3_secret(&self, access_key: &str) -> sqlx::Result<Option<String>> {
+        let row = sqlx::query(&self.sql("SELECT secret_key FROM s3_keys WHERE access_key = ?"))
+            .bind(access_key)
+            .fetch_optional(self.pool())
+            .await?;
+        row.map(|row| row.try_get("secret_key")).transpose()
+    }
+
+    /// 全部访问密钥，不含私钥
+    pub async fn list_s3_keys(&self) -> sqlx::Result<Vec<S3Key>> {
+        sqlx::query(&self.sql(
+            "SELECT access_key, description, created_at FROM s3_keys ORDER BY created_at, access_key",
+        ))
+        .fetch_all(self.pool())
+        .await?
+        .into_iter()
+        .map(|row| {
+            Ok(S3Key {
+                access_key: row.try_get("access_key")?,
+                secret_key: String::new(),
+                description: row.try_get("description")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
+    }
+
+    /// 删除访问密钥，返回是否存在
+    pub async fn delete_s3_key(&self, access_key: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query(&self.sql("DELETE FROM s3_keys WHERE access_key = ?"))
+            .bind(access_key)
+            .execute(self.pool())
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 新建分段上传
+    pub async fn insert_s3_upload(&self, upload: &S3Upload) -> sqlx::Result<()> {
+        sqlx::query(
+            &self.sql(
+                "INSERT INTO s3_uploads (id, mount, object_key, created_at) VALUES (?, ?, ?, ?)",
+            ),
+        )
+        .bind(&upload.id)
+        .bind(&upload.mount)
+        .bind(&upload.key)
+        .bind(upload.created_at)
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// 查询挂载点中的分段上传
+    pub async fn get_s3_upload(&self, mount: &str, id: &str) -> sqlx::Result<Option<S3Upload>> {
+        let row = sqlx::query(&self.sql(
+            "SELECT id, mount, object_key, created_at FROM s3_uploads WHERE mount = ? AND id = ?",
+        ))
+        .bind(mount)
+        .bind(id)
+        .fetch_optional(self.pool())
+        .await?;
+        row.map(|row| {
+            Ok(S3Upload {
+                id: row.try_get("id")?,
+                mount: row.try_get("mount")?,
+                key: row.try_get("object_key")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    /// 删除分段上传及其分段记录
+    pub async fn delete_s3_upload(&self, id: &str) -> sqlx::Result<()> {
+        let mut tx = self.pool().begin().await?;
+        sqlx::query(&self.sql("DELETE FROM s3_parts WHERE upload_id = ?"))
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&self.sql("DELETE FROM s3_uploads WHERE id = ?"))
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+
+    /// 记录分段，同一编号重复上传时替换
+    pub async fn put_s3_part(&self, upload_id: &str, part: &S3Part) -> sqlx::Result<()> {
+        let mut tx = self.pool().begin().await?;
+        sqlx::query(&self.sql("DELETE FROM s3_parts WHERE upload_id = ? AND part_number = ?"))
+            .bind(upload_id)
+            .bind(part.part_number)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&self.sql(
+            "INSERT INTO s3_parts (upload_id, part_number, etag, size, uploaded_at) VALUES (?, ?, ?, ?, ?)",
+        ))
+        .bind(upload_id)
+        .bind(part.part_number)
+        .bind(&part.etag)
+        .bind(part.size)
+        .bind(part.uploaded_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    /// 分段上传中已上传的分段，按编号排序
+    pub async fn list_s3_parts(&self, upload_id: &str) -> sqlx::Result<Vec<S3Part>> {
+        sqlx::query(&self.sql(
+            "SELECT part_number, etag, size, uploaded_at FROM s3_parts \
+             WHERE upload_id = ? ORDER BY part_number",
+        ))
+        .bind(upload_id)
+        .fetch_all(self.pool())
+        .await?
+        .into_iter()
+        .map(|row| {
+            Ok(S3Part {
+                part_number: row.try_get("part_number")?,
+                etag: row.try_get("etag")?,
+                size: row.try_get("size")?,
+                uploaded_at: row.try_get("uploaded_at")?,
+            })
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory_db;
+
+    #[tokio::test]
+    async fn test_s3_records() {
+        let db = memory_db().await;
+        db.insert_s3_key(&S3Key::new("AK".into(), "secret".into(), "ci"))
+            .await
+            .unwrap();
+        assert_eq!(db.s3_secret("AK").await.unwrap().as_deref(), Some("secret"));
+        assert_eq!(db.s3_secret("nope").await.unwrap(), None);
+        let keys = db.list_s3_keys().await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert!(keys[0].secret_key.is_empty());
+        assert!(db.delete_s3_key("AK").await.unwrap());
+        assert!(!db.delete_s3_key("AK").await.unwrap());
+
+        let upload = S3Upload {
+            id: "u1".into(),
+            mount: "data".into(),
+            key: "a/b.bin".into(),
+            created_at: now(),
+        };
+        db.insert_s3_upload(&upload).await.unwrap();
+        assert!(db.get_s3_upload("", "u1").await.unwrap().is_none());
+        assert_eq!(
+            db.get_s3_upload("data", "u1").await.unwrap().unwrap().key,
+            "a/b.bin"
+        );
+        let part = |n: i64, etag: &str| S3Part {
+            part_number: n,
+            etag: etag.into(),
+            size: 5,
+            uploaded_at: 0,
+        };
+        db.put_s3_part("u1", &part(2, "b")).await.unwrap();
+        db.put_s3_part("u1", &part(1, "a")).await.unwrap();
+        db.put_s3_part("u1", &part(2, "c")).await.unwrap();
+        assert_eq!(
+            db.list_s3_parts("u1").await.unwrap(),
+            vec![part(1, "a"), part(2, "c")]
+        );
+        db.delete_s3_upload("u1").await.unwrap();
+        assert!(db.get_s3_upload("data", "u1").await.unwrap().is_none());
+        assert!(db.list_s3_parts("u1").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::db::Db;
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::any::AnyRow;
+
+/// 回收站条目
+#[derive(Debug, Clone, Serialize)]
+pub struct TrashItem {
+    pub id: String,
+    /// 所在挂载点
+    #[serde(skip)]
+    pub mount: String,
+    /// 删除前的路径（相对根目录）
+    pub original_path: String,
+    /// file / dir / symlink
+    pub kind: String,
+    /// 删除时的总字节数
+    pub size: i64,
+    /// 删除者
+    pub deleted_by: String,
+    pub deleted_at: i64,
+}
+
+const COLUMNS: &str = "id, mount, original_path, kind, size, deleted_by, deleted_at";
+
+fn from_row(row: AnyRow) -> sqlx::Result<TrashItem> {
+    Ok(TrashItem {
+        id: row.try_get("id")?,
+        mount: row.try_get("mount")?,
+        original_path: row.try_get("original_path")?,
+        kind: row.try_get("kind")?,
+        size: row.try_get("size")?,
+        deleted_by: row.try_get("deleted_by")?,
+        deleted_at: row.try_get("deleted_at")?,
+    })
+}
+
+impl Db {
+    /// 新建回收站记录
+    pub async fn insert_trash_item(&self, item: &TrashItem) -> sqlx::Result<()> {
+        sqlx::query(&self.sql(&format!(
+            "INSERT INTO trash_items ({}) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            COLUMNS
+        )))
+        .bind(&item.id)
+        .bind(&item.mount)
+        .bind(&item.original_path)
+        .bind(&item.kind)
+        .bind(item.size)
+        .bind(&item.deleted_by)
+        .bind(item.deleted_at)
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// 查询挂载点中的回收站记录
+    pub async fn get_trash_item(&self, mount: &str, id: &str) -> sqlx::Result<Option<TrashItem>> {
+        sqlx::query(&self.sql(&format!(
+            "SELECT {} FROM trash_items WHERE mount = ? AND id = ?",
+            COLUMNS
+        )))
+        .bind(mount)
+        .bind(id)
+        .fetch_optional(self.pool())
+        .await?
+        .map(from_row)
+        .transpose()
+    }
+
+    /// 按删除时间倒序列出回收站，`prefix` 非空时只列出该路径及其子路径
+    pub async fn list_trash_items(
+        &self,
+        mount: &str,
+        prefix: &str,
+    ) -> sqlx::Result<Vec<TrashItem>> {
+        let rows = sqlx::query(&self.sql(&format!(
+            "SELECT {} FROM trash_items WHERE mount = ? ORDER BY deleted_at DESC, id DESC",
+            COLUMNS
+        )))
+        .bind(mount)
+        .fetch_all(self.pool())
+        .await?;
+        let mut items = Vec::with_capacity(rows.len());
+        for row in rows {
+            let item = from_row(row)?;
+            // 路径中可能含有 `%` / `_`，不用 LIKE 匹配
+            if prefix.is_empty()
+                || item.original_path == prefix
+                || item
+                    .original_path
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('/'))
+            {
+                items.push(item);
+            }
+        }
+        Ok(items)
+    }
+
+    /// 挂载点中删除时间早于 `before` 的记录
+    pub async fn expired_trash_items(
+        &self,
+        mount: &str,
+        before: i64,
+    ) -> sqlx::Result<Vec<TrashItem>> {
+        sqlx::query(&self.sql(&format!(
+            "SELECT {} FROM trash_items WHERE mount = ? AND deleted_at < ?",
+            COLUMNS
+        )))
+        .bind(mount)
+        .bind(before)
+        .fetch_all(self.pool())
+        .await?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    /// 删除回收站记录
+    pub async fn delete_trash_item(&self, id: &str) -> sqlx::Result<()> {
+        sqlx::query(&self.sql("DELETE FROM trash_items WHERE id = ?"))
+            .bind(id)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+}
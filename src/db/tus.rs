@@ -0,0 +1,86 @@
+use crate::db::{Db, now};
+use sqlx::Row;
+
+/// tus 上传记录
+#[derive(Debug, Clone)]
+pub struct TusUpload {
+    pub id: String,
+    /// 所在挂载点
+    pub mount: String,
+    /// 上传完成后的目标路径（相对根目录）
+    pub target_path: String,
+    pub upload_length: i64,
+    pub upload_offset: i64,
+    /// 原始 Upload-Metadata 头
+    pub metadata: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Db {
+    /// 新建上传记录
+    pub async fn insert_tus_upload(&self, upload: &TusUpload) -> sqlx::Result<()> {
+        sqlx::query(&self.sql(
+            "INSERT INTO tus_uploads (id, mount, target_path, upload_length, upload_offset, metadata, created_at, updated_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        ))
+        .bind(&upload.id)
+        .bind(&upload.mount)
+        .bind(&upload.target_path)
+        .bind(upload.upload_length)
+        .bind(upload.upload_offset)
+        .bind(&upload.metadata)
+        .bind(upload.created_at)
+        .bind(upload.updated_at)
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// 查询挂载点中的上传记录
+    pub async fn get_tus_upload(&self, mount: &str, id: &str) -> sqlx::Result<Option<TusUpload>> {
+        let row = sqlx::query(&self.sql(
+            "SELECT id, mount, target_path, upload_length, upload_offset, metadata, created_at, updated_at \
+             FROM tus_uploads WHERE mount = ? AND id = ?",
+        ))
+        .bind(mount)
+        .bind(id)
+        .fetch_optional(self.pool())
+        .await?;
+        row.map(|row| {
+            Ok(TusUpload {
+                id: row.try_get("id")?,
+                mount: row.try_get("mount")?,
+                target_path: row.try_get("target_path")?,
+                upload_length: row.try_get("upload_length")?,
+                upload_offset: row.try_get("upload_offset")?,
+                metadata: row.try_get("metadata")?,
+                created_at: row.try_get("created_at")?,
+                updated_at: row.try_get("updated_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    /// 更新已接收的偏移量
+    pub async fn update_tus_offset(&self, id: &str, offset: i64) -> sqlx::Result<()> {
+        sqlx::query(
+            &self.sql("UPDATE tus_uploads SET upload_offset = ?, updated_at = ? WHERE id = ?"),
+        )
+        .bind(offset)
+        .bind(now())
+        .bind(id)
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// 删除上传记录
+    pub async fn delete_tus_upload(&self, id: &str) -> sqlx::Result<()> {
+        sqlx::query(&self.sql("DELETE FROM tus_uploads WHERE id = ?"))
+            .bind(id)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use crate::db::Db;
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::any::AnyRow;
+
+/// 文件的一个历史版本
+#[derive(Debug, Clone, Serialize)]
+pub struct FileVersion {
+    #[serde(skip)]
+    pub id: String,
+    /// 所在挂载点
+    #[serde(skip)]
+    pub mount: String,
+    /// 文件路径（相对根目录）
+    pub path: String,
+    /// 版本号，同一路径从1递增
+    pub version: i64,
+    pub size: i64,
+    /// 覆盖这一版本的客户端
+    pub created_by: String,
+    pub created_at: i64,
+}
+
+const COLUMNS: &str = "id, mount, path, version, size, created_by, created_at";
+
+fn from_row(row: AnyRow) -> sqlx::Result<FileVersion> {
+    Ok(FileVersion {
+        id: row.try_get("id")?,
+        mount: row.try_get("mount")?,
+        path: row.try_get("path")?,
+        version: row.try_get("version")?,
+        size: row.try_get("size")?,
+        created_by: row.try_get("created_by")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+impl Db {
+    /// 下一个版本号
+    pub async fn next_file_version(&self, mount: &str, path: &str) -> sqlx::Result<i64> {
+        let row = sqlx::query(&self.sql(
+            "SELECT COALESCE(MAX(version), 0) AS latest FROM file_versions WHERE mount = ? AND path = ?",
+        ))
+        .bind(mount)
+        .bind(path)
+        .fetch_one(self.pool())
+        .await?;
+        Ok(row.try_get::<i64, _>("latest")? + 1)
+    }
+
+    /// 新建版本记录
+    pub async fn insert_file_version(&self, version: &FileVersion) -> sqlx::Result<()> {
+        sqlx::query(&self.sql(&format!(
+            "INSERT INTO file_versions ({}) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            COLUMNS
+        )))
+        .bind(&version.id)
+        .bind(&version.mount)
+        .bind(&version.path)
+        .bind(version.version)
+        .bind(version.size)
+        .bind(&version.created_by)
+        .bind(version.created_at)
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// 按版本号倒序列出文件的历史版本
+    pub async fn list_file_versions(
+        &self,
+        mount: &str,
+        path: &str,
+    ) -> sqlx::Result<Vec<FileVersion>> {
+        sqlx::query(&self.sql(&format!(
+            "SELECT {} FROM file_versions WHERE mount = ? AND path = ? ORDER BY version DESC",
+            COLUMNS
+        )))
+        .bind(mount)
+        .bind(path)
+        .fetch_all(self.pool())
+        .await?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    /// 查询指定版本
+    pub async fn get_file_version(
+        &self,
+        mount: &str,
+        path: &str,
+        version: i64,
+    ) -> sqlx::Result<Option<FileVersion>> {
+        sqlx::query(&self.sql(&format!(
+            "SELECT {} FROM file_versions WHERE mount = ? AND path = ? AND version = ?",
+            COLUMNS
+        )))
+        .bind(mount)
+        .bind(path)
+        .bind(version)
+        .fetch_optional(self.pool())
+        .await?
+        .map(from_row)
+        .transpose()
+    }
+
+    /// 挂载点中所有有历史版本的路径
+    pub async fn versioned_paths(&self, mount: &str) -> sqlx::Result<Vec<String>> {
+        sqlx::query(&self.sql("SELECT DISTINCT path FROM file_versions WHERE mount = ?"))
+            .bind(mount)
+            .fetch_all(self.pool())
+            .await?
+            .into_iter()
+            .map(|row| row.try_get("path"))
+            .collect()
+    }
+
+    /// 删除版本记录
+    pub async fn delete_file_version(&self, id: &str) -> sqlx::Result<()> {
+        sqlx::query(&self.sql("DELETE FROM file_versions WHERE id = ?"))
+            .bind(id)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// 计算文件摘要时的读取缓冲区大小
+const BUFFER_SIZE: usize = 256 * 1024;
+
+/// 摘要算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Md5,
+    Blake3,
+    Crc32c,
+}
+
+impl Algorithm {
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            Algorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Md5 => Hasher::Md5(Md5::new()),
+            Algorithm::Blake3 => Hasher::Blake3(Box::default()),
+            Algorithm::Crc32c => Hasher::Crc32c(0),
+        }
+    }
+
+    /// 接口和数据库中使用的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Md5 => "md5",
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Crc32c => "crc32c",
+        }
+    }
+
+    /// RFC 9530 摘要字段中的名称
+    pub fn http_name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "sha",
+            Algorithm::Sha256 => "sha-256",
+            Algorithm::Md5 => "md5",
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Crc32c => "crc32c",
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "sha1" | "sha" => Ok(Algorithm::Sha1),
+            "sha256" | "sha-256" => Ok(Algorithm::Sha256),
+            "md5" => Ok(Algorithm::Md5),
+            "blake3" => Ok(Algorithm::Blake3),
+            "crc32c" => Ok(Algorithm::Crc32c),
+            _ => Err(format!("不支持的摘要算法: {}", s)),
+        }
+    }
+}
+
+/// 增量计算摘要
+pub enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Md5(Md5),
+    Blake3(Box<blake3::Hasher>),
+    Crc32c(u32),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Md5(h) => h.update(data),
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+            Hasher::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+        }
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha1(h) => h.finalize().to_vec(),
+            Hasher::Sha256(h) => h.finalize().to_vec(),
+            Hasher::Md5(h) => h.finalize().to_vec(),
+            Hasher::Blake3(h) => h.finalize().as_bytes().to_vec(),
+            Hasher::Crc32c(crc) => crc.to_be_bytes().to_vec(),
+        }
+    }
+}
+
+/// 同时计算多个摘要
+pub struct MultiHasher(Vec<(Algorithm, Hasher)>);
+
+impl MultiHasher {
+    pub fn new(algorithms: &[Algorithm]) -> Self {
+        MultiHasher(algorithms.iter().map(|a| (*a, a.hasher())).collect())
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for (_, hasher) in &mut self.0 {
+            hasher.update(data);
+        }
+    }
+
+    pub fn finalize(self) -> Vec<(Algorithm, Vec<u8>)> {
+        self.0.into_iter().map(|(a, h)| (a, h.finalize())).collect()
+    }
+}
+
+/// 读取整个文件计算摘要（阻塞）
+pub fn file_digests(
+    path: &Path,
+    algorithms: &[Algorithm],
+) -> io::Result<Vec<(Algorithm, Vec<u8>)>> {
+    let mut file = File::open(path)?;
+    let mut hasher = MultiHasher::new(algorithms);
+    let mut buf = vec![0u8; BUFFER_SIZE];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize())
+}
+
+/// 小写十六进制编码
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 解码十六进制字符串
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// 解析 `Content-Digest` / `Repr-Digest`: `sha-256=:base64:, md5=:base64:`
+///
+/// 不支持的算法被忽略，格式错误时返回 `Err`。
+pub fn parse_digest_fields(value: &str) -> Result<Vec<(Algorithm, Vec<u8>)>, String> {
+    let mut digests = Vec::new();
+    for member in value.split(',') {
+        let member = member.trim();
+        if member.is_empty() {
+            continue;
+        }
+        let (key, value) = member
+            .split_once('=')
+            .ok_or_else(|| format!("无效的摘要字段: {}", member))?;
+        let encoded = value
+            .trim()
+            .strip_prefix(':')
+            .and_then(|v| v.strip_suffix(':'))
+            .ok_or_else(|| format!("无效的摘要字段: {}", member))?;
+        let Ok(algorithm) = key.parse::<Algorithm>() else {
+            continue;
+        };
+        let digest = STANDARD
+            .decode(encoded)
+            .map_err(|_| format!("无效的摘要字段: {}", member))?;
+        digests.push((algorithm, digest));
+    }
+    Ok(digests)
+}
+
+/// 生成 `Content-Digest` / `Repr-Digest` 的值
+pub fn format_digest_fields(digests: &[(Algorithm, Vec<u8>)]) -> String {
+    digests
+        .iter()
+        .map(|(a, d)| format!("{}=:{}:", a.http_name(), STANDARD.encode(d)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 解析 `Want-Content-Digest` / `Want-Repr-Digest`，按偏好从高到低返回支持的算法
+pub fn parse_want_digest(value: &str) -> Vec<Algorithm> {
+    let mut wanted: Vec<(Algorithm, u8)> = value
+        .split(',')
+        .filter_map(|member| {
+            let (key, weight) = member.split_once('=')?;
+            let algorithm = key.parse::<Algorithm>().ok()?;
+            let weight = weight.trim().parse::<u8>().ok().filter(|w| *w > 0)?;
+            Some((algorithm, weight))
+        })
+        .collect();
+    wanted.sort_by_key(|w| std::cmp::Reverse(w.1));
+    let mut algorithms: Vec<Algorithm> = Vec::new();
+    for (algorithm, _) in wanted {
+        if !algorithms.contains(&algorithm) {
+            algorithms.push(algorithm);
+        }
+    }
+    algorithms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_algorithms() {
+        let digest = |a: Algorithm| {
+            let mut hasher = a.hasher();
+            hasher.update(b"hello ");
+            hasher.update(b"world");
+            hex(&hasher.finalize())
+        };
+        assert_eq!(
+            digest(Algorithm::Sha256),
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert_eq!(
+            digest(Algorithm::Blake3),
+            "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24"
+        );
+        assert_eq!(digest(Algorithm::Md5), "5eb63bbbe01eeed093cb22bb8f5acdc3");
+        assert_eq!(digest(Algorithm::Crc32c), "c99465aa");
+        assert_eq!(from_hex("c99465aa"), Some(vec![0xc9, 0x94, 0x65, 0xaa]));
+        assert_eq!(from_hex("c9x"), None);
+    }
+
+    #[test]
+    fn test_digest_fields() {
+        let digests = parse_digest_fields("sha-256=:AAEC:, unixsum=:AA==:,md5=:/w==:").unwrap();
+        assert_eq!(
+            digests,
+            vec![
+                (Algorithm::Sha256, vec![0, 1, 2]),
+                (Algorithm::Md5, vec![255])
+            ]
+        );
+        assert_eq!(format_digest_fields(&digests), "sha-256=:AAEC:, md5=:/w==:");
+        assert!(parse_digest_fields("sha-256=AAEC").is_err());
+        assert_eq!(
+            parse_want_digest("md5=1, sha-256=9, crc32c=0, foo=3"),
+            vec![Algorithm::Sha256, Algorithm::Md5]
+        );
+    }
+}
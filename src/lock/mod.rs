@@ -0,0 +1,571 @@
+use crate::db::now;
+use redis::Script;
+use redis::aio::ConnectionManager;
+use redis::cluster_async::ClusterConnection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// 锁令牌前缀，与 WebDAV 的写法一致
+pub const TOKEN_PREFIX: &str = "opaquelocktoken:";
+/// Redis 键，用同一个 hash tag 保证集群下落在同一个槽
+const VERSION_KEY: &str = "fs-proxy:{locks}:version";
+const LOCKS_KEY: &str = "fs-proxy:{locks}:locks";
+/// 并发修改冲突时的最大重试次数
+const MAX_RETRIES: usize = 16;
+
+/// 读取版本号和全部锁
+const LOAD_SCRIPT: &str = r"
+return {redis.call('GET', KEYS[1]) or '0', redis.call('HGETALL', KEYS[2])}
+";
+
+/// 版本号未变时删除和写入锁，ARGV: 版本号, 删除数, 删除的令牌..., 令牌, 内容...
+const STORE_SCRIPT: &str = r"
+if (redis.call('GET', KEYS[1]) or '0') ~= ARGV[1] then
+    return 0
+end
+redis.call('INCR', KEYS[1])
+local n = tonumber(ARGV[2])
+for i = 3, 2 + n do
+    redis.call('HDEL', KEYS[2], ARGV[i])
+end
+for i = 3 + n, #ARGV, 2 do
+    redis.call('HSET', KEYS[2], ARGV[i], ARGV[i + 1])
+end
+return 1
+";
+
+/// 锁的范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Depth {
+    /// 只锁定路径本身
+    #[serde(rename = "0")]
+    Zero,
+    /// 锁定路径及其下的整个子树
+    #[serde(rename = "infinity")]
+    Infinity,
+}
+
+/// 锁的共享方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// 与任何重叠的锁冲突
+    #[default]
+    Exclusive,
+    /// 可以与其他共享锁重叠，出示其中任意一把的令牌即可修改
+    Shared,
+}
+
+/// 一把锁
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lock {
+    pub token: String,
+    /// 所在挂载点，默认挂载点为空串
+    #[serde(default)]
+    pub mount: String,
+    /// 锁定的路径（相对挂载点根目录，空串为根目录）
+    pub path: String,
+    pub depth: Depth,
+    #[serde(default)]
+    pub scope: Scope,
+    /// 持有者
+    pub owner: String,
+    /// WebDAV 客户端提交的 owner 元素内容（XML），原样返回给客户端
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_xml: Option<String>,
+    /// 有效期（秒），刷新时重新计时
+    pub timeout: u64,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+/// `path` 是否位于 `ancestor` 之下（不含自身）
+fn is_under(path: &str, ancestor: &str) -> bool {
+    if ancestor.is_empty() {
+        return !path.is_empty();
+    }
+    path.strip_prefix(ancestor)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl Lock {
+    /// 锁是否作用于 `path`
+    pub fn covers(&self, path: &str) -> bool {
+        self.path == path || (self.depth == Depth::Infinity && is_under(path, &self.path))
+    }
+
+    /// 锁定的路径是否位于 `path` 之下
+    pub fn inside(&self, path: &str) -> bool {
+        is_under(&self.path, path)
+    }
+
+    fn expired(&self, now: i64) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// 新锁的属性
+#[derive(Debug, Clone)]
+pub struct Grant {
+    pub depth: Depth,
+    pub scope: Scope,
+    pub owner: String,
+    pub owner_xml: Option<String>,
+    /// 有效期（秒）
+    pub timeout: u64,
+}
+
+impl Grant {
+    /// REST 接口使用的排他锁
+    pub fn exclusive(depth: Depth, owner: &str, timeout: u64) -> Self {
+        Grant {
+            depth,
+            scope: Scope::Exclusive,
+            owner: owner.to_string(),
+            owner_xml: None,
+            timeout,
+        }
+    }
+}
+
+/// 锁操作错误
+#[derive(Debug)]
+pub enum LockError {
+    /// 与已有的锁冲突
+    Locked(Box<Lock>),
+    /// 令牌不存在或已过期
+    NotFound(String),
+    /// 锁存储不可用
+    Backend(String),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Locked(lock) => write!(f, "路径已被锁定: /{} ({})", lock.path, lock.owner),
+            LockError::NotFound(token) => write!(f, "锁不存在或已过期: {}", token),
+            LockError::Backend(e) => write!(f, "锁存储错误: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+impl From<redis::RedisError> for LockError {
+    fn from(e: redis::RedisError) -> Self {
+        LockError::Backend(e.to_string())
+    }
+}
+
+/// 令牌到锁的映射，两种存储共用的冲突判断
+#[derive(Debug, Clone, Default, PartialEq)]
+struct LockTable(HashMap<String, Lock>);
+
+impl LockTable {
+    fn purge(&mut self, now: i64) {
+        self.0.retain(|_, lock| !lock.expired(now));
+    }
+
+    /// 在挂载点 `mount` 的 `path` 上加锁会冲突的已有锁，共享锁之间不冲突
+    fn conflict(&self, mount: &str, path: &str, depth: Depth, scope: Scope) -> Option<&Lock> {
+        self.0.values().find(|lock| {
+            lock.mount == mount
+                && (lock.covers(path) || (depth == Depth::Infinity && lock.inside(path)))
+                && (scope == Scope::Exclusive || lock.scope == Scope::Exclusive)
+        })
+    }
+
+    /// 修改 `path`（`tree` 为真时包括其子树）时未提供令牌的锁
+    ///
+    /// 排他锁的令牌都必须出示；共享锁只要出示了其中任意一把即可。
+    fn blocking(&self, mount: &str, path: &str, tree: bool, tokens: &[String]) -> Option<&Lock> {
+        let relevant = || {
+            self.0.values().filter(move |lock| {
+                lock.mount == mount && (lock.covers(path) || (tree && lock.inside(path)))
+            })
+        };
+        let shared_held =
+            relevant().any(|lock| lock.scope == Scope::Shared && tokens.contains(&lock.token));
+        relevant().find(|lock| {
+            let held = tokens.contains(&lock.token) || (lock.scope == Scope::Shared && shared_held);
+            !held
+        })
+    }
+
+    fn acquire(
+        &mut self,
+        mount: &str,
+        path: &str,
+        depth: Depth,
+        owner: &str,
+        timeout: u64,
+        now: i64,
+    ) -> Result<Lock, LockError> {
+        self.grant(mount, path, &Grant::exclusive(depth, owner, timeout), now)
+    }
+
+    fn grant(
+        &mut self,
+        mount: &str,
+        path: &str,
+        grant: &Grant,
+        now: i64,
+    ) -> Result<Lock, LockError> {
+        if let Some(lock) = self.conflict(mount, path, grant.depth, grant.scope) {
+            return Err(LockError::Locked(Box::new(lock.clone())));
+        }
+        let lock = Lock {
+            token: format!("{}{}", TOKEN_PREFIX, uuid::Uuid::now_v7()),
+            mount: mount.to_string(),
+            path: path.to_string(),
+            depth: grant.depth,
+            scope: grant.scope,
+            owner: grant.owner.clone(),
+            owner_xml: grant.owner_xml.clone(),
+            timeout: grant.timeout,
+            created_at: now,
+            expires_at: now + grant.timeout as i64,
+        };
+        self.0.insert(lock.token.clone(), lock.clone());
+        Ok(lock)
+    }
+
+    fn refresh(&mut self, token: &str, timeout: u64, now: i64) -> Result<Lock, LockError> {
+        let lock = self
+            .0
+            .get_mut(token)
+            .ok_or_else(|| LockError::NotFound(token.to_string()))?;
+        lock.timeout = timeout;
+        lock.expires_at = now + timeout as i64;
+        Ok(lock.clone())
+    }
+
+    fn release(&mut self, token: &str) -> Result<Lock, LockError> {
+        self.0
+            .remove(token)
+            .ok_or_else(|| LockError::NotFound(token.to_string()))
+    }
+}
+
+#[derive(Clone)]
+enum RedisConnection {
+    Single(ConnectionManager),
+    Cluster(ClusterConnection),
+}
+
+impl RedisConnection {
+    async fn invoke<T: redis::FromRedisValue>(
+        &self,
+        script: &Script,
+        args: &[String],
+    ) -> redis::RedisResult<T> {
+        let mut invocation = script.prepare_invoke();
+        invocation.key(VERSION_KEY).key(LOCKS_KEY);
+        for arg in args {
+            invocation.arg(arg);
+        }
+        match self {
+            RedisConnection::Single(conn) => invocation.invoke_async(&mut conn.clone()).await,
+            RedisConnection::Cluster(conn) => invocation.invoke_async(&mut conn.clone()).await,
+        }
+    }
+}
+
+/// Redis 存储：锁以 JSON 存在一个哈希里，修改时比较版本号，冲突则重读重试
+struct RedisStore {
+    conn: RedisConnection,
+    load: Script,
+    store: Script,
+}
+
+impl RedisStore {
+    async fn load(&self) -> Result<(String, LockTable), LockError> {
+        let (version, fields): (String, Vec<String>) = self.conn.invoke(&self.load, &[]).await?;
+        let mut table = LockTable::default();
+        for pair in fields.chunks(2) {
+            if let [token, value] = pair {
+                match serde_json::from_str::<Lock>(value) {
+                    Ok(lock) => {
+                        table.0.insert(token.clone(), lock);
+                    }
+                    Err(e) => log::warn!("忽略无法解析的锁 {}: {}", token, e),
+                }
+            }
+        }
+        Ok((version, table))
+    }
+
+    /// 写回与 `before` 相比的变化，版本号已变时返回 false
+    async fn store(
+        &self,
+        version: String,
+        before: &LockTable,
+        after: &LockTable,
+    ) -> Result<bool, LockError> {
+        let deleted: Vec<String> = before
+            .0
+            .keys()
+            .filter(|token| !after.0.contains_key(*token))
+            .cloned()
+            .collect();
+        let mut args = vec![version, deleted.len().to_string()];
+        args.extend(deleted);
+        for (token, lock) in &after.0 {
+            if before.0.get(token) != Some(lock) {
+                args.push(token.clone());
+                args.push(
+                    serde_json::to_string(lock).map_err(|e| LockError::Backend(e.to_string()))?,
+                );
+            }
+        }
+        let stored: i64 = self.conn.invoke(&self.store, &args).await?;
+        Ok(stored == 1)
+    }
+}
+
+enum Store {
+    Memory(Mutex<LockTable>),
+    Redis(RedisStore),
+}
+
+/// 锁管理器，配置 Redis 时多个节点共享同一个锁空间，否则只在进程内有效
+pub struct LockManager {
+    store: Store,
+}
+
+impl LockManager {
+    /// 进程内的锁表
+    pub fn memory() -> Self {
+        LockManager {
+            store: Store::Memory(Mutex::new(LockTable::default())),
+        }
+    }
+
+    /// 连接 Redis，`urls` 以逗号分隔多个节点时或 `cluster` 为真时使用集群模式
+    pub async fn redis(urls: &str, cluster: bool) -> anyhow::Result<Self> {
+        let nodes: Vec<&str> = urls
+            .split(',')
+            .map(str::trim)
+            .filter(|u| !u.is_empty())
+            .collect();
+        let conn = if cluster || nodes.len() > 1 {
+            let client = redis::cluster::ClusterClient::new(nodes)?;
+            RedisConnection::Cluster(client.get_async_connection().await?)
+        } else {
+            let url = nodes
+                .first()
+                .ok_or_else(|| anyhow::anyhow!("Redis 地址为空"))?;
+            let client = redis::Client::open(*url)?;
+            RedisConnection::Single(client.get_connection_manager().await?)
+        };
+        Ok(LockManager {
+            store: Store::Redis(RedisStore {
+                conn,
+                load: Script::new(LOAD_SCRIPT),
+                store: Script::new(STORE_SCRIPT),
+            }),
+        })
+    }
+
+    /// 是否为多节点共享的存储
+    pub fn is_shared(&self) -> bool {
+        matches!(self.store, Store::Redis(_))
+    }
+
+    /// 读取当前有效的锁
+    async fn snapshot(&self) -> Result<LockTable, LockError> {
+        let mut table = match &self.store {
+            Store::Memory(table) => table.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+            Store::Redis(redis) => redis.load().await?.1,
+        };
+        table.purge(now());
+        Ok(table)
+    }
+
+    /// 在锁表上执行修改，顺带清除过期的锁
+    async fn update<T>(
+        &self,
+        f: impl Fn(&mut LockTable, i64) -> Result<T, LockError>,
+    ) -> Result<T, LockError> {
+        match &self.store {
+            Store::Memory(table) => {
+                let mut table = table.lock().unwrap_or_else(|e| e.into_inner());
+                let now = now();
+                table.purge(now);
+                f(&mut table, now)
+            }
+            Store::Redis(redis) => {
+                for _ in 0..MAX_RETRIES {
+                    let (version, before) = redis.load().await?;
+                    let mut after = before.clone();
+                    let now = now();
+                    after.purge(now);
+                    let result = f(&mut after, now)?;
+                    if after == before || redis.store(version, &before, &after).await? {
+                        return Ok(result);
+                    }
+                }
+                Err(LockError::Backend("并发修改过多，请重试".to_string()))
+            }
+        }
+    }
+
+    /// 在挂载点 `mount` 的 `path` 上加排他锁，`timeout` 秒后过期
+    pub async fn acquire(
+        &self,
+        mount: &str,
+        path: &str,
+        depth: Depth,
+        owner: &str,
+        timeout: u64,
+    ) -> Result<Lock, LockError> {
+        self.update(|table, now| table.acquire(mount, path, depth, owner, timeout, now))
+            .await
+    }
+
+    /// 按 `grant` 在挂载点 `mount` 的 `path` 上加锁，可以是共享锁
+    pub async fn grant(&self, mount: &str, path: &str, grant: &Grant) -> Result<Lock, LockError> {
+        self.update(|table, now| table.grant(mount, path, grant, now))
+            .await
+    }
+
+    /// 延长锁的有效期，从现在起重新计时
+    pub async fn refresh(&self, token: &str, timeout: u64) -> Result<Lock, LockError> {
+        self.update(|table, now| table.refresh(token, timeout, now))
+            .await
+    }
+
+    /// 释放锁
+    pub async fn release(&self, token: &str) -> Result<Lock, LockError> {
+        self.update(|table, _| table.release(token)).await
+    }
+
+    /// 查询令牌对应的锁
+    pub async fn get(&self, token: &str) -> Result<Lock, LockError> {
+        self.snapshot()
+            .await?
+            .0
+            .remove(token)
+            .ok_or_else(|| LockError::NotFound(token.to_string()))
+    }
+
+    /// 作用于 `path`、其祖先（子树锁）或其子路径的锁，按路径排序；`path` 为空时返回全部
+    pub async fn list(&self, mount: &str, path: &str) -> Result<Vec<Lock>, LockError> {
+        let mut locks: Vec<Lock> = self
+            .snapshot()
+            .await?
+            .0
+            .into_values()
+            .filter(|lock| lock.mount == mount && (lock.covers(path) || lock.inside(path)))
+            .collect();
+        locks.sort_by(|a, b| a.path.cmp(&b.path).then(a.created_at.cmp(&b.created_at)));
+        Ok(locks)
+    }
+
+    /// 检查能否修改 `path`：作用于它的锁（`tree` 为真时还包括子树中的锁）都必须在 `tokens` 中
+    pub async fn check(
+        &self,
+        mount: &str,
+        path: &str,
+        tree: bool,
+        tokens: &[String],
+    ) -> Result<(), LockError> {
+        match self.snapshot().await?.blocking(mount, path, tree, tokens) {
+            Some(lock) => Err(LockError::Locked(Box::new(lock.clone()))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_scope() {
+        let mut table = LockTable::default();
+        let deep = table
+            .acquire("", "a/b", Depth::Infinity, "x", 60, 0)
+            .unwrap();
+        assert!(deep.token.starts_with(TOKEN_PREFIX));
+        assert!(deep.covers("a/b") && deep.covers("a/b/c/d"));
+        assert!(!deep.covers("a/bc") && !deep.covers("a"));
+
+        assert!(table.acquire("", "a/b/c", Depth::Zero, "y", 60, 0).is_err());
+        assert!(table.acquire("", "a", Depth::Infinity, "y", 60, 0).is_err());
+        let shallow = table.acquire("", "a", Depth::Zero, "y", 60, 0).unwrap();
+        let sibling = table.acquire("", "a/bc", Depth::Zero, "y", 60, 0).unwrap();
+
+        assert!(table.blocking("", "a/b/c", false, &[]).is_some());
+        let tokens = vec![deep.token.clone(), sibling.token];
+        assert!(table.blocking("", "a/b/c", false, &tokens).is_none());
+        assert!(table.blocking("", "x", true, &[]).is_none());
+        assert_eq!(table.blocking("", "", true, &tokens).unwrap().path, "a");
+        assert!(table.blocking("", "a", false, &[shallow.token]).is_none());
+        assert!(table.blocking("", "a/new", false, &[]).is_none());
+        // 其他挂载点的同名路径互不影响
+        assert!(table.blocking("data", "a/b", true, &[]).is_none());
+        assert!(
+            table
+                .acquire("data", "a", Depth::Infinity, "z", 60, 0)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_shared_locks() {
+        let mut table = LockTable::default();
+        let shared = |owner: &str| Grant {
+            scope: Scope::Shared,
+            ..Grant::exclusive(Depth::Zero, owner, 60)
+        };
+        let a = table.grant("", "f", &shared("a"), 0).unwrap();
+        let b = table.grant("", "f", &shared("b"), 0).unwrap();
+        assert!(table.acquire("", "f", Depth::Zero, "c", 60, 0).is_err());
+        assert!(table.blocking("", "f", false, &[]).is_some());
+        assert!(table.blocking("", "f", false, &[a.token]).is_none());
+        assert!(table.blocking("", "f", false, &[b.token]).is_none());
+
+        table.acquire("", "g", Depth::Zero, "c", 60, 0).unwrap();
+        assert!(table.grant("", "g", &shared("a"), 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_manager() {
+        let locks = LockManager::memory();
+        let lock = locks
+            .acquire("", "f.txt", Depth::Zero, "x", 60)
+            .await
+            .unwrap();
+        assert!(matches!(
+            locks.check("", "f.txt", false, &[]).await,
+            Err(LockError::Locked(_))
+        ));
+        locks
+            .check("", "f.txt", false, std::slice::from_ref(&lock.token))
+            .await
+            .unwrap();
+        assert_eq!(locks.list("", "").await.unwrap().len(), 1);
+
+        let refreshed = locks.refresh(&lock.token, 600).await.unwrap();
+        assert_eq!(refreshed.timeout, 600);
+        assert!(refreshed.expires_at >= lock.expires_at + 540);
+
+        locks.release(&lock.token).await.unwrap();
+        assert!(matches!(
+            locks.release(&lock.token).await,
+            Err(LockError::NotFound(_))
+        ));
+        locks.check("", "f.txt", false, &[]).await.unwrap();
+
+        // 过期的锁不再生效
+        let lock = locks
+            .acquire("", "g.txt", Depth::Zero, "x", 0)
+            .await
+            .unwrap();
+        locks.check("", "g.txt", false, &[]).await.unwrap();
+        assert!(locks.get(&lock.token).await.is_err());
+    }
+}
@@ -6,6 +6,13 @@ use std::path::PathBuf;
 use std::process::exit;
 
 mod cmd;
+mod dav;
+mod db;
+mod digest;
+mod lock;
+mod s3;
+mod sandbox;
+mod tls;
 mod util;
 mod web;
 
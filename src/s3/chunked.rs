@@ -0,0 +1,246 @@
+//! aws-chunked 请求体：`大小[;chunk-signature=签名]\r\n数据\r\n ... 0\r\n[尾部字段]\r\n`
+
+use crate::digest::hex;
+use crate::s3::sigv4::{chunk_string_to_sign, sign};
+use crate::util::token_eq;
+use bytes::{Buf, Bytes, BytesMut};
+use sha2::{Digest, Sha256};
+
+/// 分块头或尾部字段一行的最大字节数
+const MAX_LINE: usize = 4096;
+
+/// 校验分块签名所需的上下文
+#[derive(Debug, Clone)]
+pub struct ChunkSigner {
+    pub key: Vec<u8>,
+    pub amz_date: String,
+    pub scope: String,
+    /// 上一个分块的签名，第一个分块为请求本身的签名
+    pub previous: String,
+}
+
+#[derive(Debug)]
+enum State {
+    /// 等待分块头
+    Header,
+    /// 分块数据，还剩 `remaining` 字节
+    Data {
+        remaining: usize,
+        signature: Option<String>,
+        hasher: Sha256,
+    },
+    /// 分块数据后的 `\r\n`
+    DataEnd,
+    /// 最后一个分块之后的尾部字段，以空行结束
+    Trailer,
+    Done,
+}
+
+/// 增量解码 aws-chunked 请求体
+///
+/// 带 `signer` 时校验每个分块的签名，缺少或不匹配时报错；尾部的校验和字段只解析不校验。
+#[derive(Debug)]
+pub struct Decoder {
+    buf: BytesMut,
+    state: State,
+    signer: Option<ChunkSigner>,
+}
+
+impl Decoder {
+    pub fn new(signer: Option<ChunkSigner>) -> Self {
+        Decoder {
+            buf: BytesMut::new(),
+            state: State::Header,
+            signer,
+        }
+    }
+
+    /// 输入一段原始数据，返回解码出的内容
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<Bytes>, String> {
+        self.buf.extend_from_slice(data);
+        let mut out = Vec::new();
+        loop {
+            match &mut self.state {
+                State::Header => {
+                    let Some(line) = self.line()? else {
+                        break;
+                    };
+                    let (size, signature) = match line.split_once(';') {
+                        Some((size, ext)) => (
+                            size,
+                            ext.trim()
+                                .strip_prefix("chunk-signature=")
+                                .map(str::to_string),
+                        ),
+                        None => (line.as_str(), None),
+                    };
+                    let size = usize::from_str_radix(size.trim(), 16)
+                        .map_err(|_| format!("无效的分块大小: {}", size))?;
+                    if self.signer.is_some() && signature.is_none() {
+                        return Err("分块缺少签名".to_string());
+                    }
+                    if size == 0 {
+                        self.verify(signature.as_deref(), Sha256::new())?;
+                        self.state = State::Trailer;
+                    } else {
+                        self.state = State::Data {
+                            remaining: size,
+                            signature,
+                            hasher: Sha256::new(),
+                        };
+                    }
+                }
+                State::Data {
+                    remaining,
+                    signature,
+                    hasher,
+                } => {
+                    if self.buf.is_empty() {
+                        break;
+                    }
+                    let n = (*remaining).min(self.buf.len());
+                    let chunk = self.buf.split_to(n).freeze();
+                    hasher.update(&chunk);
+                    out.push(chunk);
+                    *remaining -= n;
+                    if *remaining == 0 {
+                        let signature = signature.take();
+                        let hasher = std::mem::take(hasher);
+                        self.verify(signature.as_deref(), hasher)?;
+                        self.state = State::DataEnd;
+                    }
+                }
+                State::DataEnd => {
+                    if self.buf.len() < 2 {
+                        break;
+                    }
+                    if &self.buf[..2] != b"\r\n" {
+                        return Err("分块数据后缺少 CRLF".to_string());
+                    }
+                    self.buf.advance(2);
+                    self.state = State::Header;
+                }
+                State::Trailer => {
+                    let Some(line) = self.line()? else {
+                        break;
+                    };
+                    if line.is_empty() {
+                        self.state = State::Done;
+                    }
+                }
+                State::Done => {
+                    if self.buf.iter().any(|b| !b.is_ascii_whitespace()) {
+                        return Err("最后一个分块之后还有数据".to_string());
+                    }
+                    self.buf.clear();
+                    break;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// 请求体结束：必须已经读到最后一个分块
+    pub fn finish(&self) -> Result<(), String> {
+        match self.state {
+            State::Done => Ok(()),
+            // 没有尾部字段时有的客户端省略最后的空行
+            State::Trailer if self.buf.is_empty() => Ok(()),
+            _ => Err("aws-chunked 请求体不完整".to_string()),
+        }
+    }
+
+    /// 取出一行（不含 `\r\n`），数据不足时返回 `None`
+    fn line(&mut self) -> Result<Option<String>, String> {
+        match self.buf.windows(2).position(|w| w == b"\r\n") {
+            Some(end) => {
+                let line = self.buf.split_to(end + 2);
+                String::from_utf8(line[..end].to_vec())
+                    .map(Some)
+                    .map_err(|_| "分块头不是有效的 UTF-8".to_string())
+            }
+            None if self.buf.len() > MAX_LINE => Err("分块头过长".to_string()),
+            None => Ok(None),
+        }
+    }
+
+    /// 校验一个分块的签名
+    fn verify(&mut self, signature: Option<&str>, hasher: Sha256) -> Result<(), String> {
+        let (Some(signer), Some(signature)) = (self.signer.as_mut(), signature) else {
+            return Ok(());
+        };
+        let to_sign = chunk_string_to_sign(
+            &signer.amz_date,
+            &signer.scope,
+            &signer.previous,
+            &hex(&hasher.finalize()),
+        );
+        let expected = sign(&signer.key, &to_sign);
+        if !token_eq(expected.as_bytes(), signature.as_bytes()) {
+            return Err("分块签名不匹配".to_string());
+        }
+        signer.previous = expected;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::s3::sigv4::{Scope, signing_key};
+
+    fn decode(decoder: &mut Decoder, input: &[u8], step: usize) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        for piece in input.chunks(step) {
+            for bytes in decoder.push(piece)? {
+                out.extend_from_slice(&bytes);
+            }
+        }
+        decoder.finish()?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_unsigned() {
+        let body = b"5\r\nhello\r\n6\r\n world\r\n0\r\nx-amz-checksum-crc32:AAAAAA==\r\n\r\n";
+        for step in [1, 3, body.len()] {
+            let mut decoder = Decoder::new(None);
+            assert_eq!(decode(&mut decoder, body, step).unwrap(), b"hello world");
+        }
+        assert!(decode(&mut Decoder::new(None), b"5\r\nhel", 2).is_err());
+        assert!(decode(&mut Decoder::new(None), b"zz\r\n", 2).is_err());
+        assert!(decode(&mut Decoder::new(None), b"1\r\nab\r\n", 2).is_err());
+    }
+
+    /// AWS 文档中的分块上传示例
+    #[test]
+    fn test_signed() {
+        let scope = Scope {
+            date: "20130524".into(),
+            region: "us-east-1".into(),
+            service: "s3".into(),
+        };
+        let signer = ChunkSigner {
+            key: signing_key("wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY", &scope),
+            amz_date: "20130524T000000Z".into(),
+            scope: scope.to_string(),
+            previous: "4f232c4386841ef735655705268965c44a0e4690baa4adea153f7db9fa80a0a9".into(),
+        };
+        let mut body = Vec::new();
+        body.extend_from_slice(b"10000;chunk-signature=ad80c730a21e5b8d04586a2213dd63b9a0e99e0e2307b0ade35a65485a288648\r\n");
+        body.extend_from_slice(&[b'a'; 65536]);
+        body.extend_from_slice(b"\r\n400;chunk-signature=0055627c9e194cb4542bae2aa5492e3c1575bbb81b612b7d234b86a503ef5497\r\n");
+        body.extend_from_slice(&[b'a'; 1024]);
+        body.extend_from_slice(b"\r\n0;chunk-signature=b6c6ea8a5354eaf15b3cb7646744f4275b71ea724fed81ceb9323e279d449df9\r\n\r\n");
+        let mut decoder = Decoder::new(Some(signer.clone()));
+        assert_eq!(decode(&mut decoder, &body, 7000).unwrap().len(), 66560);
+
+        // 篡改数据
+        body[200] = b'b';
+        let mut decoder = Decoder::new(Some(signer.clone()));
+        assert!(decode(&mut decoder, &body, 7000).is_err());
+        // 缺少签名
+        let mut decoder = Decoder::new(Some(signer));
+        assert!(decode(&mut decoder, b"1\r\na\r\n0\r\n\r\n", 4).is_err());
+    }
+}
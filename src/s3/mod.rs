@@ -0,0 +1,52 @@
+//! S3 兼容接口的协议部分：SigV4 签名、aws-chunked 请求体和 XML，与存储和路由无关
+
+use chrono::{DateTime, Utc};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+
+pub mod chunked;
+pub mod request;
+pub mod response;
+pub mod sigv4;
+
+/// S3 XML 命名空间
+pub const S3_NS: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+/// SigV4 不编码的字符
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+/// 路径中还保留 `/`
+const PATH: &AsciiSet = &UNRESERVED.remove(b'/');
+
+/// 按 SigV4 的规则编码，`keep_slash` 为真时保留 `/`
+pub fn uri_encode(s: &str, keep_slash: bool) -> String {
+    let set = if keep_slash { PATH } else { UNRESERVED };
+    utf8_percent_encode(s, set).to_string()
+}
+
+/// 解码百分号编码，`+` 不视为空格
+pub fn uri_decode(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().into_owned()
+}
+
+/// XML 中的时间格式，如 `2009-10-12T17:50:30.000Z`
+pub fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(uri_encode("a b/c~d+e", true), "a%20b/c~d%2Be");
+        assert_eq!(uri_encode("a/b=", false), "a%2Fb%3D");
+        assert_eq!(uri_encode("中", true), "%E4%B8%AD");
+        assert_eq!(uri_decode("a+b%2Fc%E4%B8%AD"), "a+b/c中");
+        let time = DateTime::from_timestamp(1_255_369_830, 0).unwrap();
+        assert_eq!(timestamp(time), "2009-10-12T17:50:30.000Z");
+    }
+}
@@ -0,0 +1,86 @@
+use crate::dav::{Element, parse};
+
+/// DeleteObjects 请求
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delete {
+    pub keys: Vec<String>,
+    /// 为真时只报告失败的键
+    pub quiet: bool,
+}
+
+/// 按本地名称查找子元素，S3 请求体的命名空间可有可无
+fn child<'a>(element: &'a Element, name: &str) -> Option<&'a Element> {
+    element.elements().find(|e| e.name.name == name)
+}
+
+fn children<'a>(element: &'a Element, name: &'a str) -> impl Iterator<Item = &'a Element> {
+    element.elements().filter(move |e| e.name.name == name)
+}
+
+/// 解析 CompleteMultipartUpload 请求体，返回分段编号和 ETag（去掉引号）
+pub fn complete_multipart_upload(body: &[u8]) -> Result<Vec<(i64, String)>, String> {
+    let root = parse(body)?;
+    if root.name.name != "CompleteMultipartUpload" {
+        return Err(format!("根元素应为 CompleteMultipartUpload: {}", root.name));
+    }
+    let mut parts = Vec::new();
+    for part in children(&root, "Part") {
+        let number = child(part, "PartNumber")
+            .and_then(|n| n.text().trim().parse::<i64>().ok())
+            .ok_or("缺少或无效的 PartNumber")?;
+        let etag = child(part, "ETag").ok_or("缺少 ETag")?.text();
+        parts.push((number, etag.trim().trim_matches('"').to_string()));
+    }
+    if parts.is_empty() {
+        return Err("没有分段".to_string());
+    }
+    Ok(parts)
+}
+
+/// 解析 DeleteObjects 请求体
+pub fn delete(body: &[u8]) -> Result<Delete, String> {
+    let root = parse(body)?;
+    if root.name.name != "Delete" {
+        return Err(format!("根元素应为 Delete: {}", root.name));
+    }
+    let keys = children(&root, "Object")
+        .map(|object| {
+            child(object, "Key")
+                .map(Element::text)
+                .ok_or_else(|| "Object 缺少 Key".to_string())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let quiet = child(&root, "Quiet").is_some_and(|q| q.text().trim() == "true");
+    Ok(Delete { keys, quiet })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_bodies() {
+        let parts = complete_multipart_upload(
+            br#"<CompleteMultipartUpload xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <Part><ETag>"a1"</ETag><PartNumber>1</PartNumber></Part>
+  <Part><PartNumber>2</PartNumber><ETag>b2</ETag></Part>
+</CompleteMultipartUpload>"#,
+        )
+        .unwrap();
+        assert_eq!(parts, vec![(1, "a1".to_string()), (2, "b2".to_string())]);
+        assert!(complete_multipart_upload(b"<CompleteMultipartUpload/>").is_err());
+        assert!(
+            complete_multipart_upload(
+                b"<CompleteMultipartUpload><Part><ETag>x</ETag></Part></CompleteMultipartUpload>"
+            )
+            .is_err()
+        );
+
+        let delete = delete(
+            b"<Delete><Object><Key>a b.txt</Key></Object><Object><Key>c/&amp;</Key></Object><Quiet>true</Quiet></Delete>",
+        )
+        .unwrap();
+        assert_eq!(delete.keys, vec!["a b.txt", "c/&"]);
+        assert!(delete.quiet);
+    }
+}
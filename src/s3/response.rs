@@ -0,0 +1,255 @@
+use crate::dav::escape;
+use crate::s3::{S3_NS, timestamp, uri_encode};
+use chrono::{DateTime, Utc};
+
+/// XML 声明
+const XML_DECLARATION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+
+/// 写入一个只含文本的元素
+fn field(out: &mut String, name: &str, value: &str) {
+    out.push_str(&format!("<{}>{}</{}>", name, escape(value), name));
+}
+
+/// 根元素带命名空间的文档
+fn document(root: &str, content: &str) -> String {
+    format!(
+        "{}<{} xmlns=\"{}\">{}</{}>",
+        XML_DECLARATION, root, S3_NS, content, root
+    )
+}
+
+/// 错误响应体
+pub fn error(code: &str, message: &str, resource: &str, request_id: &str) -> String {
+    let mut out = String::new();
+    field(&mut out, "Code", code);
+    field(&mut out, "Message", message);
+    field(&mut out, "Resource", resource);
+    field(&mut out, "RequestId", request_id);
+    format!("{}<Error>{}</Error>", XML_DECLARATION, out)
+}
+
+/// ListBuckets 响应体，桶为名称和创建时间
+pub fn list_buckets(owner: &str, buckets: &[(String, DateTime<Utc>)]) -> String {
+    let mut out = String::from("<Owner>");
+    field(&mut out, "ID", owner);
+    field(&mut out, "DisplayName", owner);
+    out.push_str("</Owner><Buckets>");
+    for (name, created) in buckets {
+        out.push_str("<Bucket>");
+        field(&mut out, "Name", name);
+        field(&mut out, "CreationDate", &timestamp(*created));
+        out.push_str("</Bucket>");
+    }
+    out.push_str("</Buckets>");
+    document("ListAllMyBucketsResult", &out)
+}
+
+/// 列表中的一个对象
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Object {
+    pub key: String,
+    pub last_modified: DateTime<Utc>,
+    /// 带引号
+    pub etag: String,
+    pub size: u64,
+}
+
+/// ListObjects（V1）和 ListObjectsV2 的响应
+#[derive(Debug, Default)]
+pub struct Listing {
+    pub bucket: String,
+    pub prefix: String,
+    pub delimiter: Option<String>,
+    pub max_keys: usize,
+    /// `encoding-type=url`：键和前缀按 URL 编码返回
+    pub encode: bool,
+    pub v2: bool,
+    /// V1 的 `marker` 或 V2 的 `start-after`
+    pub start_after: Option<String>,
+    /// V2 的 `continuation-token`
+    pub continuation_token: Option<String>,
+    /// 截断时下一页从这个键之后开始
+    pub next: Option<String>,
+    pub objects: Vec<Object>,
+    pub prefixes: Vec<String>,
+}
+
+impl Listing {
+    fn encoded(&self, value: &str) -> String {
+        match self.encode {
+            true => uri_encode(value, true),
+            false => value.to_string(),
+        }
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut out = String::new();
+        field(&mut out, "Name", &self.bucket);
+        field(&mut out, "Prefix", &self.encoded(&self.prefix));
+        if let Some(delimiter) = &self.delimiter {
+            field(&mut out, "Delimiter", &self.encoded(delimiter));
+        }
+        field(&mut out, "MaxKeys", &self.max_keys.to_string());
+        if self.encode {
+            field(&mut out, "EncodingType", "url");
+        }
+        field(&mut out, "IsTruncated", &self.next.is_some().to_string());
+        if self.v2 {
+            field(
+                &mut out,
+                "KeyCount",
+                &(self.objects.len() + self.prefixes.len()).to_string(),
+            );
+            if let Some(token) = &self.continuation_token {
+                field(&mut out, "ContinuationToken", token);
+            }
+            if let Some(next) = &self.next {
+                field(&mut out, "NextContinuationToken", next);
+            }
+            if let Some(start_after) = &self.start_after {
+                field(&mut out, "StartAfter", &self.encoded(start_after));
+            }
+        } else {
+            field(
+                &mut out,
+                "Marker",
+                &self.encoded(self.start_after.as_deref().unwrap_or_default()),
+            );
+            if let Some(next) = &self.next {
+                field(&mut out, "NextMarker", &self.encoded(next));
+            }
+        }
+        for object in &self.objects {
+            out.push_str("<Contents>");
+            field(&mut out, "Key", &self.encoded(&object.key));
+            field(&mut out, "LastModified", &timestamp(object.last_modified));
+            field(&mut out, "ETag", &object.etag);
+            field(&mut out, "Size", &object.size.to_string());
+            field(&mut out, "StorageClass", "STANDARD");
+            out.push_str("</Contents>");
+        }
+        for prefix in &self.prefixes {
+            out.push_str("<CommonPrefixes>");
+            field(&mut out, "Prefix", &self.encoded(prefix));
+            out.push_str("</CommonPrefixes>");
+        }
+        document("ListBucketResult", &out)
+    }
+}
+
+/// GetBucketLocation 响应体，区域总是默认的 us-east-1（空值）
+pub fn location() -> String {
+    document("LocationConstraint", "")
+}
+
+/// CopyObject 响应体
+pub fn copy_object(etag: &str, last_modified: DateTime<Utc>) -> String {
+    let mut out = String::new();
+    field(&mut out, "LastModified", &timestamp(last_modified));
+    field(&mut out, "ETag", etag);
+    document("CopyObjectResult", &out)
+}
+
+/// DeleteObjects 响应体，失败的键附带错误码和说明
+pub fn delete_result(deleted: &[String], errors: &[(String, String, String)]) -> String {
+    let mut out = String::new();
+    for key in deleted {
+        out.push_str("<Deleted>");
+        field(&mut out, "Key", key);
+        out.push_str("</Deleted>");
+    }
+    for (key, code, message) in errors {
+        out.push_str("<Error>");
+        field(&mut out, "Key", key);
+        field(&mut out, "Code", code);
+        field(&mut out, "Message", message);
+        out.push_str("</Error>");
+    }
+    document("DeleteResult", &out)
+}
+
+/// CreateMultipartUpload 响应体
+pub fn initiate_multipart_upload(bucket: &str, key: &str, upload_id: &str) -> String {
+    let mut out = String::new();
+    field(&mut out, "Bucket", bucket);
+    field(&mut out, "Key", key);
+    field(&mut out, "UploadId", upload_id);
+    document("InitiateMultipartUploadResult", &out)
+}
+
+/// CompleteMultipartUpload 响应体
+pub fn complete_multipart_upload(location: &str, bucket: &str, key: &str, etag: &str) -> String {
+    let mut out = String::new();
+    field(&mut out, "Location", location);
+    field(&mut out, "Bucket", bucket);
+    field(&mut out, "Key", key);
+    field(&mut out, "ETag", etag);
+    document("CompleteMultipartUploadResult", &out)
+}
+
+/// 已上传的一个分段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub number: i64,
+    /// 带引号
+    pub etag: String,
+    pub size: i64,
+    pub last_modified: DateTime<Utc>,
+}
+
+/// ListParts 响应体
+pub fn list_parts(bucket: &str, key: &str, upload_id: &str, parts: &[Part]) -> String {
+    let mut out = String::new();
+    field(&mut out, "Bucket", bucket);
+    field(&mut out, "Key", key);
+    field(&mut out, "UploadId", upload_id);
+    field(&mut out, "StorageClass", "STANDARD");
+    field(&mut out, "IsTruncated", "false");
+    for part in parts {
+        out.push_str("<Part>");
+        field(&mut out, "PartNumber", &part.number.to_string());
+        field(&mut out, "LastModified", &timestamp(part.last_modified));
+        field(&mut out, "ETag", &part.etag);
+        field(&mut out, "Size", &part.size.to_string());
+        out.push_str("</Part>");
+    }
+    document("ListPartsResult", &out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing() {
+        let time = DateTime::from_timestamp(0, 0).unwrap();
+        let listing = Listing {
+            bucket: "data".into(),
+            prefix: "a b/".into(),
+            delimiter: Some("/".into()),
+            max_keys: 2,
+            encode: true,
+            v2: true,
+            next: Some("token".into()),
+            objects: vec![Object {
+                key: "a b/c&d.txt".into(),
+                last_modified: time,
+                etag: "\"1-2-3\"".into(),
+                size: 5,
+            }],
+            prefixes: vec!["a b/e/".into()],
+            ..Default::default()
+        };
+        let xml = listing.to_xml();
+        assert!(xml.contains("<Prefix>a%20b/</Prefix>"));
+        assert!(xml.contains(
+            "<KeyCount>2</KeyCount><NextContinuationToken>token</NextContinuationToken>"
+        ));
+        assert!(xml.contains("<Key>a%20b/c%26d.txt</Key><LastModified>1970-01-01T00:00:00.000Z</LastModified><ETag>&quot;1-2-3&quot;</ETag><Size>5</Size>"));
+        assert!(xml.contains("<CommonPrefixes><Prefix>a%20b/e/</Prefix></CommonPrefixes>"));
+        assert!(crate::dav::parse(xml.as_bytes()).is_ok());
+
+        let xml = error("NoSuchKey", "<missing>", "/s3/data/x", "r1");
+        assert!(xml.ends_with("<Error><Code>NoSuchKey</Code><Message>&lt;missing&gt;</Message><Resource>/s3/data/x</Resource><RequestId>r1</RequestId></Error>"));
+    }
+}
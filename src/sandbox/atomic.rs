@@ -0,0 +1,143 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+
+/// 同目录临时文件的名称，只由 UUID 构成
+pub(crate) fn temp_name() -> String {
+    format!(".{}.tmp", uuid::Uuid::now_v7().simple())
+}
+
+/// 原子写入文件
+///
+/// 内容先写入同目录下的临时文件，提交时 fsync 后重命名到目标位置，
+/// 崩溃或出错时目标文件要么是旧内容，要么是完整的新内容。
+/// 未提交就被丢弃时自动删除临时文件。
+pub struct AtomicFile {
+    target: PathBuf,
+    temp: PathBuf,
+    file: Option<File>,
+    written: u64,
+}
+
+impl AtomicFile {
+    /// 在目标文件所在目录创建临时文件
+    pub async fn create(target: &Path) -> io::Result<Self> {
+        let dir = target
+            .parent()
+            .ok_or_else(|| io::Error::other("目标路径没有父目录"))?;
+        // 临时文件名不含目标名，目标名接近 NAME_MAX 时也不会超长
+        let temp = dir.join(temp_name());
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .await?;
+        Ok(AtomicFile {
+            target: target.to_path_buf(),
+            temp,
+            file: Some(file),
+            written: 0,
+        })
+    }
+
+    /// 追加写入数据
+    pub async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let file = self
+            .file
+            .as_mut()
+            .ok_or_else(|| io::Error::other("临时文件已关闭"))?;
+        file.write_all(data).await?;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    /// 提交：fsync 后重命名覆盖目标文件
+    pub async fn commit(mut self) -> io::Result<u64> {
+        self.flush().await?;
+        tokio::fs::rename(&self.temp, &self.target).await?;
+        sync_dir(&self.target).await;
+        Ok(self.written)
+    }
+
+    /// 仅在目标不存在时提交，目标已存在返回 `AlreadyExists`
+    pub async fn commit_new(self) -> io::Result<u64> {
+        match self.try_commit_new().await? {
+            Ok(written) => Ok(written),
+            Err(_) => Err(io::Error::new(io::ErrorKind::AlreadyExists, "目标已存在")),
+        }
+    }
+
+    /// 仅在目标不存在时提交，目标已存在时交还临时文件以便换个目标重试
+    pub async fn try_commit_new(mut self) -> io::Result<Result<u64, AtomicFile>> {
+        self.flush().await?;
+        match tokio::fs::hard_link(&self.temp, &self.target).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(Err(self)),
+            Err(e) => return Err(e),
+        }
+        tokio::fs::remove_file(&self.temp).await?;
+        sync_dir(&self.target).await;
+        Ok(Ok(self.written))
+    }
+
+    /// 更换提交目标，新目标须与原目标在同一目录
+    pub fn set_target(&mut self, target: PathBuf) {
+        self.target = target;
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush().await?;
+            file.sync_all().await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.temp.exists() {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// fsync 目标所在目录，保证重命名落盘
+async fn sync_dir(target: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = target.parent() {
+        let dir = dir.to_path_buf();
+        let _ = tokio::task::spawn_blocking(move || std::fs::File::open(dir)?.sync_all()).await;
+    }
+    #[cfg(not(unix))]
+    let _ = target;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_atomic_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("a.txt");
+
+        let mut file = AtomicFile::create(&target).await.unwrap();
+        file.write(b"hello").await.unwrap();
+        assert!(!target.exists());
+        assert_eq!(file.commit().await.unwrap(), 5);
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "hello");
+
+        let mut file = AtomicFile::create(&target).await.unwrap();
+        file.write(b"world").await.unwrap();
+        let err = file.commit_new().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "hello");
+
+        let mut file = AtomicFile::create(&target).await.unwrap();
+        file.write(b"discarded").await.unwrap();
+        drop(file);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
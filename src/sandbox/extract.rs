@@ -0,0 +1,773 @@
+use crate::sandbox::ops::Conflict;
+use crate::sandbox::{Sandbox, SymlinkPolicy};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// 可解压的归档格式
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar")]
+    Tar,
+    #[serde(rename = "tar.gz", alias = "tgz")]
+    TarGz,
+    #[serde(rename = "tar.zst")]
+    TarZst,
+}
+
+impl Format {
+    /// 根据文件头的魔数识别格式
+    pub fn detect(header: &[u8]) -> Option<Format> {
+        if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            Some(Format::Zip)
+        } else if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Format::TarGz)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Format::TarZst)
+        } else if header.get(257..262) == Some(b"ustar") {
+            Some(Format::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// 解压限制，防御解压炸弹
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// 最大条目数
+    pub max_entries: usize,
+    /// 解压后的最大总字节数
+    pub max_bytes: u64,
+    /// 单个文件的最大字节数，超过时该条目失败
+    pub max_file_size: Option<u64>,
+}
+
+/// 条目类型
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryType {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+/// 条目处理结果
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryStatus {
+    Extracted,
+    Skipped,
+    Failed,
+}
+
+/// 单个条目的报告
+#[derive(Debug)]
+pub struct EntryReport {
+    /// 归档中的名称
+    pub name: String,
+    /// 解压到的路径
+    pub path: Option<PathBuf>,
+    pub kind: EntryType,
+    pub size: u64,
+    pub status: EntryStatus,
+    pub error: Option<String>,
+}
+
+/// 解压错误，发生时已解压的新文件会被删除
+#[derive(Debug)]
+pub enum ExtractError {
+    /// 无法识别的格式
+    Unsupported,
+    /// 归档损坏
+    Corrupt(String),
+    /// 条目数超过限制
+    TooManyEntries(usize),
+    /// 解压后大小超过限制
+    TooLarge(u64),
+    Io(io::Error),
+}
+
+impl std::fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExtractError::Unsupported => write!(f, "不支持的归档格式"),
+            ExtractError::Corrupt(e) => write!(f, "归档损坏: {}", e),
+            ExtractError::TooManyEntries(n) => write!(f, "条目数超过限制: {}", n),
+            ExtractError::TooLarge(n) => write!(f, "解压大小超过限制: {} 字节", n),
+            ExtractError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ExtractError {
+    fn from(e: io::Error) -> Self {
+        ExtractError::Io(e)
+    }
+}
+
+/// 解压上下文
+struct Extractor<'a> {
+    sandbox: &'a Sandbox,
+    /// 目标目录的相对路径
+    dest: String,
+    /// 目标目录的绝对路径
+    dest_dir: PathBuf,
+    limits: Limits,
+    conflict: Conflict,
+    entries: usize,
+    bytes: u64,
+    /// 新建的路径，出错时按逆序删除
+    created: Vec<PathBuf>,
+    report: Vec<EntryReport>,
+}
+
+/// 把归档中的条目名拆分为安全的路径段，拒绝绝对路径和 `..`
+fn entry_parts(name: &str) -> Option<Vec<&str>> {
+    if name.starts_with(['/', '\\']) || name.contains('\0') {
+        return None;
+    }
+    let mut parts = Vec::new();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => continue,
+            ".." => return None,
+            // Windows 盘符
+            p if p.ends_with(':') => return None,
+            p => parts.push(p),
+        }
+    }
+    if parts.is_empty() { None } else { Some(parts) }
+}
+
+/// 符号链接目标必须是相对路径，且按链接所在目录计算后仍在解压目录内
+///
+/// 只检查文本；途经已有符号链接的目标由 [`through_symlink`] 拒绝。
+fn link_target_allowed(parts: &[&str], target: &Path) -> bool {
+    if target.is_absolute() {
+        return false;
+    }
+    let mut depth = parts.len() as isize - 1;
+    for component in target.components() {
+        match component {
+            std::path::Component::Normal(_) => depth += 1,
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                depth -= 1;
+                if depth < 0 {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// 从 `base` 出发按 `path` 逐级前进，途中已存在的路径有符号链接时返回真
+///
+/// 归档可以先创建符号链接再借它写入或指向别处，只按文本检查无法发现这种链式逃逸。
+fn through_symlink(base: &Path, path: &Path) -> bool {
+    let mut current = base.to_path_buf();
+    for component in path.components() {
+        match component {
+            std::path::Component::Normal(part) => {
+                current.push(part);
+                if fs::symlink_metadata(&current).is_ok_and(|m| m.is_symlink()) {
+                    return true;
+                }
+            }
+            std::path::Component::ParentDir => {
+                current.pop();
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+impl Extractor<'_> {
+    fn count(&mut self) -> Result<(), ExtractError> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(ExtractError::TooManyEntries(self.limits.max_entries));
+        }
+        Ok(())
+    }
+
+    fn push(
+        &mut self,
+        name: &str,
+        path: Option<PathBuf>,
+        kind: EntryType,
+        size: u64,
+        result: Result<EntryStatus, String>,
+    ) {
+        let (status, error) = match result {
+            Ok(status) => (status, None),
+            Err(e) => {
+                log::warn!("解压条目失败 {}: {}", name, e);
+                (EntryStatus::Failed, Some(e))
+            }
+        };
+        self.report.push(EntryReport {
+            name: name.to_string(),
+            path,
+            kind,
+            size,
+            status,
+            error,
+        });
+    }
+
+    /// 解压一个条目；单个条目的错误记入报告，超出限制则中止
+    fn entry(
+        &mut self,
+        name: &str,
+        kind: EntryType,
+        mode: Option<u32>,
+        link: Option<PathBuf>,
+        reader: &mut dyn Read,
+    ) -> Result<(), ExtractError> {
+        self.count()?;
+        let Some(parts) = entry_parts(name) else {
+            self.push(name, None, kind, 0, Err("非法路径".to_string()));
+            return Ok(());
+        };
+        // 不经由解压目录内已有的符号链接写入，包括本归档先前创建的链接
+        let parents: PathBuf = parts[..parts.len() - 1].iter().collect();
+        if through_symlink(&self.dest_dir, &parents) {
+            self.push(name, None, kind, 0, Err("路径经过符号链接".to_string()));
+            return Ok(());
+        }
+        let path =
+            match self
+                .sandbox
+                .resolve_nofollow(&format!("{}/{}", self.dest, parts.join("/")))
+            {
+                Ok(path) => path,
+                Err(e) => {
+                    self.push(name, None, kind, 0, Err(e.to_string()));
+                    return Ok(());
+                }
+            };
+        let result = match kind {
+            EntryType::Dir => self.dir(&path),
+            EntryType::File => return self.file(name, path, mode, reader),
+            EntryType::Symlink if self.sandbox.symlinks() == SymlinkPolicy::Deny => {
+                Err("不允许符号链接".to_string())
+            }
+            EntryType::Symlink => match link {
+                Some(target)
+                    if link_target_allowed(&parts, &target)
+                        && !through_symlink(&self.dest_dir.join(&parents), &target) =>
+                {
+                    self.symlink(&path, &target)
+                }
+                _ => Err("符号链接指向解压目录之外".to_string()),
+            },
+            EntryType::Other => Err("不支持的条目类型".to_string()),
+        };
+        self.push(name, Some(path), kind, 0, result);
+        Ok(())
+    }
+
+    /// 按冲突策略检查目标，返回是否继续写入
+    fn check_conflict(&self, path: &Path) -> Result<bool, String> {
+        match fs::symlink_metadata(path) {
+            Err(_) => Ok(true),
+            Ok(m) if m.is_dir() => Err("目标是目录".to_string()),
+            Ok(_) => match self.conflict {
+                Conflict::Overwrite => Ok(true),
+                Conflict::Skip => Ok(false),
+                Conflict::Fail => Err("目标已存在".to_string()),
+            },
+        }
+    }
+
+    fn create_parents(&mut self, path: &Path) -> io::Result<()> {
+        let Some(parent) = path.parent() else {
+            return Ok(());
+        };
+        let mut missing = Vec::new();
+        let mut dir = parent;
+        while fs::symlink_metadata(dir).is_err() {
+            missing.push(dir.to_path_buf());
+            match dir.parent() {
+                Some(p) => dir = p,
+                None => break,
+            }
+        }
+        fs::create_dir_all(parent)?;
+        self.created.extend(missing.into_iter().rev());
+        Ok(())
+    }
+
+    fn dir(&mut self, path: &Path) -> Result<EntryStatus, String> {
+        match fs::symlink_metadata(path) {
+            Ok(m) if m.is_dir() => Ok(EntryStatus::Skipped),
+            Ok(_) => Err("目标已存在且不是目录".to_string()),
+            Err(_) => {
+                self.create_parents(path).map_err(|e| e.to_string())?;
+                fs::create_dir(path).map_err(|e| e.to_string())?;
+                self.created.push(path.to_path_buf());
+                Ok(EntryStatus::Extracted)
+            }
+        }
+    }
+
+    fn symlink(&mut self, path: &Path, target: &Path) -> Result<EntryStatus, String> {
+        if !self.check_conflict(path)? {
+            return Ok(EntryStatus::Skipped);
+        }
+        self.create_parents(path).map_err(|e| e.to_string())?;
+        #[cfg(unix)]
+        {
+            let existed = fs::symlink_metadata(path).is_ok();
+            if existed {
+                fs::remove_file(path).map_err(|e| e.to_string())?;
+            }
+            std::os::unix::fs::symlink(target, path).map_err(|e| e.to_string())?;
+            if !existed {
+                self.created.push(path.to_path_buf());
+            }
+            Ok(EntryStatus::Extracted)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = target;
+            Err("不支持符号链接".to_string())
+        }
+    }
+
+    /// 写入文件：先写同目录临时文件，再重命名，不会写穿已存在的符号链接
+    fn file(
+        &mut self,
+        name: &str,
+        path: PathBuf,
+        mode: Option<u32>,
+        reader: &mut dyn Read,
+    ) -> Result<(), ExtractError> {
+        match self.check_conflict(&path) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.push(
+                    name,
+                    Some(path),
+                    EntryType::File,
+                    0,
+                    Ok(EntryStatus::Skipped),
+                );
+                return Ok(());
+            }
+            Err(e) => {
+                self.push(name, Some(path), EntryType::File, 0, Err(e));
+                return Ok(());
+            }
+        }
+        if let Err(e) = self.create_parents(&path) {
+            self.push(name, Some(path), EntryType::File, 0, Err(e.to_string()));
+            return Ok(());
+        }
+
+        let temp = path.with_file_name(super::temp_name());
+        let remaining = self.limits.max_bytes - self.bytes;
+        let max_file = self.limits.max_file_size.unwrap_or(u64::MAX);
+        let result = File::create(&temp).and_then(|mut file| {
+            let n = io::copy(&mut reader.take(remaining.min(max_file) + 1), &mut file)?;
+            file.sync_all()?;
+            Ok(n)
+        });
+        let size = match result {
+            Ok(n) if n > remaining => {
+                let _ = fs::remove_file(&temp);
+                return Err(ExtractError::TooLarge(self.limits.max_bytes));
+            }
+            Ok(n) if n > max_file => {
+                let _ = fs::remove_file(&temp);
+                let error = format!("文件超过大小限制: {} 字节", max_file);
+                self.push(name, Some(path), EntryType::File, 0, Err(error));
+                return Ok(());
+            }
+            Ok(n) => n,
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                // 读取归档出错说明数据损坏，后续条目也无法读取
+                if e.kind() == io::ErrorKind::InvalidData
+                    || e.kind() == io::ErrorKind::UnexpectedEof
+                {
+                    return Err(ExtractError::Corrupt(e.to_string()));
+                }
+                self.push(name, Some(path), EntryType::File, 0, Err(e.to_string()));
+                return Ok(());
+            }
+        };
+        self.bytes += size;
+
+        #[cfg(unix)]
+        if let Some(mode) = mode {
+            use std::os::unix::fs::PermissionsExt;
+            // 去掉 setuid/setgid/sticky 位，并保证属主可读写
+            let mode = (mode & 0o777) | 0o600;
+            let _ = fs::set_permissions(&temp, fs::Permissions::from_mode(mode));
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        let existed = fs::symlink_metadata(&path).is_ok();
+        match fs::rename(&temp, &path) {
+            Ok(()) => {
+                if !existed {
+                    self.created.push(path.clone());
+                }
+                self.push(
+                    name,
+                    Some(path),
+                    EntryType::File,
+                    size,
+                    Ok(EntryStatus::Extracted),
+                );
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                self.push(name, Some(path), EntryType::File, size, Err(e.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 删除本次新建的文件和目录
+    fn rollback(&self) {
+        for path in self.created.iter().rev() {
+            let result = match fs::symlink_metadata(path) {
+                Ok(m) if m.is_dir() => fs::remove_dir(path),
+                Ok(_) => fs::remove_file(path),
+                Err(_) => continue,
+            };
+            if let Err(e) = result {
+                log::warn!("回滚解压失败 {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    fn tar<R: Read>(&mut self, reader: R) -> Result<(), ExtractError> {
+        let mut archive = tar::Archive::new(reader);
+        let entries = archive
+            .entries()
+            .map_err(|e| ExtractError::Corrupt(e.to_string()))?;
+        for entry in entries {
+            let mut entry = entry.map_err(|e| ExtractError::Corrupt(e.to_string()))?;
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_pax_global_extensions() || entry_type.is_pax_local_extensions() {
+                continue;
+            }
+            let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+            let kind = if entry_type.is_dir() {
+                EntryType::Dir
+            } else if entry_type.is_file() || entry_type.is_contiguous() {
+                EntryType::File
+            } else if entry_type.is_symlink() {
+                EntryType::Symlink
+            } else {
+                EntryType::Other
+            };
+            let link = entry.link_name().ok().flatten().map(|l| l.into_owned());
+            let mode = entry.header().mode().ok();
+            self.entry(&name, kind, mode, link, &mut entry)?;
+        }
+        Ok(())
+    }
+
+    fn zip(&mut self, file: File) -> Result<(), ExtractError> {
+        let mut archive =
+            zip::ZipArchive::new(file).map_err(|e| ExtractError::Corrupt(e.to_string()))?;
+        // 先按中央目录检查，避免解压到一半才发现超限
+        if archive.len() > self.limits.max_entries {
+            return Err(ExtractError::TooManyEntries(self.limits.max_entries));
+        }
+        let declared = (0..archive.len())
+            .filter_map(|i| archive.by_index_raw(i).ok().map(|f| f.size()))
+            .fold(0u64, u64::saturating_add);
+        if declared > self.limits.max_bytes {
+            return Err(ExtractError::TooLarge(self.limits.max_bytes));
+        }
+        for i in 0..archive.len() {
+            let name = archive.name_for_index(i).unwrap_or_default().to_string();
+            let mut entry = match archive.by_index(i) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.count()?;
+                    self.push(&name, None, EntryType::Other, 0, Err(e.to_string()));
+                    continue;
+                }
+            };
+            let mode = entry.unix_mode();
+            if entry.is_symlink() {
+                let mut target = String::new();
+                if let Err(e) = (&mut entry).take(4096).read_to_string(&mut target) {
+                    self.count()?;
+                    self.push(&name, None, EntryType::Symlink, 0, Err(e.to_string()));
+                    continue;
+                }
+                let target = PathBuf::from(target);
+                self.entry(
+                    &name,
+                    EntryType::Symlink,
+                    mode,
+                    Some(target),
+                    &mut io::empty(),
+                )?;
+            } else if entry.is_dir() {
+                self.entry(&name, EntryType::Dir, mode, None, &mut io::empty())?;
+            } else {
+                self.entry(&name, EntryType::File, mode, None, &mut entry)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 把归档解压到沙箱内的目录
+///
+/// `dest` 是目标目录的相对路径。条目路径经过与请求路径相同的沙箱检查，
+/// 拒绝 `..`、绝对路径以及借助符号链接逃逸；超出限制或归档损坏时中止，
+/// 并删除本次新建的文件。
+pub fn extract(
+    sandbox: &Sandbox,
+    archive: &Path,
+    format: Option<Format>,
+    dest: &str,
+    limits: Limits,
+    conflict: Conflict,
+) -> Result<Vec<EntryReport>, ExtractError> {
+    let mut file = File::open(archive)?;
+    let format = match format {
+        Some(format) => format,
+        None => {
+            let mut header = Vec::with_capacity(512);
+            (&mut file).take(512).read_to_end(&mut header)?;
+            file.seek(SeekFrom::Start(0))?;
+            Format::detect(&header).ok_or(ExtractError::Unsupported)?
+        }
+    };
+
+    let mut extractor = Extractor {
+        sandbox,
+        dest: dest.to_string(),
+        dest_dir: sandbox
+            .resolve(dest)
+            .map_err(|e| io::Error::new(io::ErrorKind::PermissionDenied, e.to_string()))?,
+        limits,
+        conflict,
+        entries: 0,
+        bytes: 0,
+        created: Vec::new(),
+        report: Vec::new(),
+    };
+    let result = match format {
+        Format::Zip => extractor.zip(file),
+        Format::Tar => extractor.tar(io::BufReader::new(file)),
+        Format::TarGz => extractor.tar(flate2::read::GzDecoder::new(io::BufReader::new(file))),
+        Format::TarZst => zstd::Decoder::new(file)
+            .map_err(ExtractError::from)
+            .and_then(|decoder| extractor.tar(decoder)),
+    };
+    match result {
+        Ok(()) => Ok(extractor.report),
+        Err(e) => {
+            extractor.rollback();
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: Limits = Limits {
+        max_entries: 100,
+        max_bytes: 1 << 20,
+        max_file_size: None,
+    };
+
+    fn tar_with(entries: &[(&str, &[u8])], links: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            // 绕过 tar crate 对 `..` 的检查，构造恶意条目
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        for (name, target) in links {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, name, target).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_entry_parts() {
+        assert_eq!(entry_parts("a/./b//c.txt").unwrap(), ["a", "b", "c.txt"]);
+        assert!(entry_parts("../x").is_none());
+        assert!(entry_parts("a/../../x").is_none());
+        assert!(entry_parts("/etc/passwd").is_none());
+        assert!(entry_parts("C:/x").is_none());
+        assert!(link_target_allowed(&["a", "l"], Path::new("../b")));
+        assert!(!link_target_allowed(&["l"], Path::new("../b")));
+        assert!(!link_target_allowed(&["l"], Path::new("/etc")));
+    }
+
+    #[test]
+    fn test_extract_tar() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path().join("root")).unwrap();
+        fs::create_dir(sandbox.root().join("out")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path(), sandbox.root().join("out/outside")).unwrap();
+        let archive = dir.path().join("a.tar");
+        fs::write(
+            &archive,
+            tar_with(
+                &[
+                    ("ok/a.txt", b"a"),
+                    ("../evil.txt", b"e"),
+                    ("esc/x", b"x"),
+                    ("outside/evil.txt", b"e"),
+                ],
+                &[("esc", "../.."), ("ok/l", "a.txt")],
+            ),
+        )
+        .unwrap();
+
+        let report = extract(&sandbox, &archive, None, "out", LIMITS, Conflict::Fail).unwrap();
+        let status: Vec<_> = report.iter().map(|e| (e.name.as_str(), e.status)).collect();
+        assert_eq!(
+            status,
+            [
+                ("ok/a.txt", EntryStatus::Extracted),
+                ("../evil.txt", EntryStatus::Failed),
+                ("esc/x", EntryStatus::Extracted),
+                ("outside/evil.txt", EntryStatus::Failed),
+                ("esc", EntryStatus::Failed),
+                ("ok/l", EntryStatus::Extracted),
+            ]
+        );
+        assert_eq!(fs::read(sandbox.root().join("out/ok/a.txt")).unwrap(), b"a");
+        assert!(!dir.path().join("evil.txt").exists());
+
+        // 再次解压时已存在的文件按策略处理
+        let report = extract(&sandbox, &archive, None, "out", LIMITS, Conflict::Skip).unwrap();
+        assert_eq!(report[0].status, EntryStatus::Skipped);
+
+        // 超出大小限制时中止并删除新建的文件
+        let limits = Limits {
+            max_entries: 100,
+            max_bytes: 1,
+            max_file_size: None,
+        };
+        let result = extract(&sandbox, &archive, None, "new", limits, Conflict::Fail);
+        assert!(matches!(result, Err(ExtractError::TooLarge(1))));
+        assert!(!sandbox.root().join("new").exists());
+
+        let limits = Limits {
+            max_entries: 1,
+            max_bytes: 1 << 20,
+            max_file_size: None,
+        };
+        let result = extract(&sandbox, &archive, None, "new", limits, Conflict::Fail);
+        assert!(matches!(result, Err(ExtractError::TooManyEntries(1))));
+        assert!(!sandbox.root().join("new").exists());
+
+        // 单个文件超限和挂载点禁止的符号链接只让对应条目失败
+        let limits = Limits {
+            max_file_size: Some(0),
+            ..LIMITS
+        };
+        let sandbox = sandbox.with_symlinks(SymlinkPolicy::Deny);
+        let report = extract(&sandbox, &archive, None, "strict", limits, Conflict::Fail).unwrap();
+        assert!(report.iter().all(|e| e.status == EntryStatus::Failed));
+        assert!(fs::symlink_metadata(sandbox.root().join("strict/ok/l")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_chained_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path().join("root")).unwrap();
+        fs::create_dir(sandbox.root().join("out")).unwrap();
+        fs::write(sandbox.root().join("victim"), "keep").unwrap();
+        // 每个链接单看都在解压目录内，串起来却指向解压目录的上级
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, target) in [("s/x", ".."), ("p", "s/x/.."), ("q", "s")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, name, target).unwrap();
+        }
+        for name in ["p/victim", "q/y"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(3);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, &b"own"[..]).unwrap();
+        }
+        let archive = dir.path().join("chain.tar");
+        fs::write(&archive, builder.into_inner().unwrap()).unwrap();
+
+        let report = extract(&sandbox, &archive, None, "out", LIMITS, Conflict::Overwrite).unwrap();
+        let status: Vec<_> = report.iter().map(|e| (e.name.as_str(), e.status)).collect();
+        assert_eq!(
+            status,
+            [
+                ("s/x", EntryStatus::Extracted),
+                ("p", EntryStatus::Failed),
+                ("q", EntryStatus::Extracted),
+                ("p/victim", EntryStatus::Extracted),
+                ("q/y", EntryStatus::Failed),
+            ]
+        );
+        // p 未建成链接，p/victim 落在解压目录内的普通目录中
+        assert!(!sandbox.root().join("out/p").is_symlink());
+        assert_eq!(
+            fs::read_to_string(sandbox.root().join("out/p/victim")).unwrap(),
+            "own"
+        );
+        assert_eq!(
+            fs::read_to_string(sandbox.root().join("victim")).unwrap(),
+            "keep"
+        );
+        assert!(!sandbox.root().join("out/s/y").exists());
+    }
+
+    #[test]
+    fn test_extract_zip() {
+        use std::io::Write;
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path().join("root")).unwrap();
+        let archive = dir.path().join("a.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_directory("d", options).unwrap();
+        zip.start_file("d/f.txt", options).unwrap();
+        zip.write_all(b"hello").unwrap();
+        zip.start_file("../../slip.txt", options).unwrap();
+        zip.write_all(b"bad").unwrap();
+        zip.finish().unwrap();
+
+        let report = extract(&sandbox, &archive, None, "", LIMITS, Conflict::Fail).unwrap();
+        assert_eq!(report.len(), 3);
+        assert_eq!(report[1].size, 5);
+        assert_eq!(report[2].status, EntryStatus::Failed);
+        assert_eq!(fs::read(sandbox.root().join("d/f.txt")).unwrap(), b"hello");
+        assert!(!dir.path().join("slip.txt").exists());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::sandbox::ops::PathError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// 日志文件名
+const JOURNAL_FILE: &str = "journal.json";
+/// 日志目录前缀
+const PREFIX: &str = "batch-";
+
+/// 一个已执行步骤的撤销动作
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "undo", rename_all = "snake_case")]
+pub enum Undo {
+    /// 删除新建的文件或目录树
+    Remove { path: PathBuf },
+    /// 删除新建的目录，目录非空时保留
+    RemoveDir { path: PathBuf },
+    /// 把 `from` 移回 `to`
+    Rename { from: PathBuf, to: PathBuf },
+}
+
+impl Undo {
+    /// 执行撤销，目标已不存在时视为完成
+    fn apply(&self) -> io::Result<()> {
+        let result = match self {
+            Undo::Remove { path } => match fs::symlink_metadata(path) {
+                Ok(m) if m.is_dir() => fs::remove_dir_all(path),
+                Ok(_) => fs::remove_file(path),
+                Err(e) => Err(e),
+            },
+            Undo::RemoveDir { path } => match fs::remove_dir(path) {
+                Err(e) if e.kind() == io::ErrorKind::DirectoryNotEmpty => Ok(()),
+                other => other,
+            },
+            Undo::Rename { from, to } => {
+                if fs::symlink_metadata(from).is_err() {
+                    return Ok(());
+                }
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::rename(from, to)
+            }
+        };
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            other => other,
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Undo::Remove { path } | Undo::RemoveDir { path } => path,
+            Undo::Rename { to, .. } => to,
+        }
+    }
+}
+
+/// 批量操作的回滚日志
+///
+/// 每个撤销动作在对应的修改之前写入磁盘，被覆盖或删除的内容先移入日志目录，
+/// 因此进程中断后也能按日志倒序撤销。
+#[derive(Debug)]
+pub struct Journal {
+    dir: PathBuf,
+    entries: Vec<Undo>,
+}
+
+impl Journal {
+    /// 在 `staging` 下新建日志目录
+    pub fn begin(staging: &Path) -> io::Result<Self> {
+        let dir = staging.join(format!("{}{}", PREFIX, uuid::Uuid::now_v7().simple()));
+        fs::create_dir_all(&dir)?;
+        let journal = Journal {
+            dir,
+            entries: Vec::new(),
+        };
+        journal.persist()?;
+        Ok(journal)
+    }
+
+    /// 原子地写入日志文件
+    fn persist(&self) -> io::Result<()> {
+        let temp = self.dir.join(format!("{}.tmp", JOURNAL_FILE));
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(&serde_json::to_vec(&self.entries).map_err(io::Error::other)?)?;
+        file.sync_all()?;
+        fs::rename(&temp, self.dir.join(JOURNAL_FILE))
+    }
+
+    /// 记录撤销动作，必须在执行对应修改之前调用
+    pub fn record(&mut self, undo: Undo) -> io::Result<()> {
+        self.entries.push(undo);
+        self.persist()
+    }
+
+    /// 记录 `path` 上尚不存在的各级目录，创建目录前调用
+    pub fn record_missing_dirs(&mut self, path: &Path) -> io::Result<()> {
+        let mut missing = Vec::new();
+        let mut current = Some(path);
+        while let Some(dir) = current
+            && fs::symlink_metadata(dir).is_err()
+        {
+            missing.push(dir.to_path_buf());
+            current = dir.parent();
+        }
+        for dir in missing.into_iter().rev() {
+            self.record(Undo::RemoveDir { path: dir })?;
+        }
+        Ok(())
+    }
+
+    /// 把已存在的 `path` 移入日志目录保存，回滚时移回
+    pub fn set_aside(&mut self, path: &Path) -> io::Result<bool> {
+        if fs::symlink_metadata(path).is_err() {
+            return Ok(false);
+        }
+        let saved = self.dir.join(self.entries.len().to_string());
+        self.record(Undo::Rename {
+            from: saved.clone(),
+            to: path.to_path_buf(),
+        })?;
+        fs::rename(path, &saved)?;
+        Ok(true)
+    }
+
+    /// 提交：丢弃保存的旧内容
+    pub fn commit(self) -> io::Result<()> {
+        fs::remove_dir_all(&self.dir)
+    }
+
+    /// 按倒序撤销所有步骤，返回撤销失败的路径；全部成功时删除日志目录
+    pub fn rollback(self) -> Vec<PathError> {
+        let mut errors = Vec::new();
+        for undo in self.entries.iter().rev() {
+            if let Err(e) = undo.apply() {
+                log::error!("回滚失败 {:?}: {}", undo, e);
+                errors.push(PathError {
+                    path: undo.path().to_path_buf(),
+                    error: e.to_string(),
+                });
+            }
+        }
+        if errors.is_empty()
+            && let Err(e) = fs::remove_dir_all(&self.dir)
+        {
+            log::warn!("删除回滚日志失败 {}: {}", self.dir.display(), e);
+        }
+        errors
+    }
+}
+
+/// 回滚 `staging` 下进程中断时遗留的日志，返回处理的数量
+pub fn recover(staging: &Path) -> usize {
+    let Ok(entries) = fs::read_dir(staging) else {
+        return 0;
+    };
+    let mut recovered = 0;
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with(PREFIX) {
+            continue;
+        }
+        let dir = entry.path();
+        let entries = match fs::read(dir.join(JOURNAL_FILE))
+            .map_err(|e| e.to_string())
+            .and_then(|data| serde_json::from_slice(&data).map_err(|e| e.to_string()))
+        {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::error!("无法读取回滚日志 {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+        let errors = Journal { dir, entries }.rollback();
+        if errors.is_empty() {
+            recovered += 1;
+        }
+    }
+    recovered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let staging = dir.path().join("staging");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("old.txt"), "old").unwrap();
+
+        let mut journal = Journal::begin(&staging).unwrap();
+        journal.record_missing_dirs(&root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        assert!(journal.set_aside(&root.join("old.txt")).unwrap());
+        journal
+            .record(Undo::Remove {
+                path: root.join("old.txt"),
+            })
+            .unwrap();
+        fs::write(root.join("old.txt"), "new").unwrap();
+        assert!(!journal.set_aside(&root.join("missing")).unwrap());
+
+        // 模拟进程中断，从磁盘上的日志恢复
+        drop(journal);
+        assert_eq!(recover(&staging), 1);
+        assert_eq!(fs::read_to_string(root.join("old.txt")).unwrap(), "old");
+        assert!(!root.join("a").exists());
+        assert_eq!(fs::read_dir(&staging).unwrap().count(), 0);
+
+        let mut journal = Journal::begin(&staging).unwrap();
+        journal.set_aside(&root.join("old.txt")).unwrap();
+        journal.commit().unwrap();
+        assert!(!root.join("old.txt").exists());
+        assert_eq!(fs::read_dir(&staging).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,441 @@
+mod atomic;
+pub mod extract;
+pub mod journal;
+pub mod ops;
+
+pub use atomic::AtomicFile;
+pub(crate) use atomic::temp_name;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// 沙箱路径错误
+#[derive(Debug)]
+pub enum SandboxError {
+    /// 路径试图逃逸出根目录
+    Forbidden(String),
+    /// 路径不存在
+    NotFound(String),
+    /// 其他IO错误
+    Io(io::Error),
+}
+
+impl fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxError::Forbidden(p) => write!(f, "禁止访问: {}", p),
+            SandboxError::NotFound(p) => write!(f, "路径不存在: {}", p),
+            SandboxError::Io(e) => write!(f, "IO错误: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SandboxError {}
+
+impl From<io::Error> for SandboxError {
+    fn from(e: io::Error) -> Self {
+        SandboxError::Io(e)
+    }
+}
+
+/// 服务器内部使用的根目录下的目录，不允许通过请求路径访问
+pub const RESERVED_DIRS: &[&str] = &[".tus", ".staging", ".trash", ".versions", ".s3"];
+
+/// 符号链接策略
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// 跟随指向根目录之内的符号链接
+    #[default]
+    Follow,
+    /// 不跟随任何符号链接，链接本身仍可查看、移动和删除
+    Deny,
+}
+
+/// 隐藏规则：不含 `/` 的通配符匹配任意一级的名称，含 `/` 的匹配相对路径
+#[derive(Debug, Clone)]
+struct Hidden {
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl Hidden {
+    fn new(patterns: &[String]) -> Result<Self, String> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = GlobBuilder::new(pattern.trim_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map_err(|e| format!("无效的通配符 {}: {}", pattern, e))?;
+            match pattern.contains('/') {
+                true => paths.add(glob),
+                false => names.add(glob),
+            };
+        }
+        Ok(Hidden {
+            names: names.build().map_err(|e| e.to_string())?,
+            paths: paths.build().map_err(|e| e.to_string())?,
+        })
+    }
+
+    /// 相对路径本身或其任一上级是否被隐藏
+    fn matches(&self, parts: &[String]) -> bool {
+        (1..=parts.len()).any(|n| {
+            self.names.is_match(&parts[n - 1]) || self.paths.is_match(parts[..n].join("/"))
+        })
+    }
+}
+
+/// 文件系统沙箱，所有请求路径都被限制在根目录之内
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    root: PathBuf,
+    /// 挂载点名称，默认挂载点为空串
+    mount: String,
+    hidden: Option<Hidden>,
+    symlinks: SymlinkPolicy,
+}
+
+impl Sandbox {
+    /// 创建沙箱，根目录不存在时自动创建
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = root.as_ref();
+        if !root.exists() {
+            std::fs::create_dir_all(root)?;
+        }
+        let root = std::fs::canonicalize(root)?;
+        if !root.is_dir() {
+            return Err(io::Error::other(format!(
+                "根路径不是目录: {}",
+                root.display()
+            )));
+        }
+        Ok(Sandbox {
+            root,
+            mount: String::new(),
+            hidden: None,
+            symlinks: SymlinkPolicy::default(),
+        })
+    }
+
+    /// 设置挂载点名称，数据库记录和锁按名称区分
+    pub fn with_mount(mut self, name: &str) -> Self {
+        self.mount = name.to_string();
+        self
+    }
+
+    /// 设置隐藏规则，匹配的路径既不列出也不能访问
+    pub fn with_hidden(mut self, patterns: &[String]) -> Result<Self, String> {
+        self.hidden = match patterns.is_empty() {
+            true => None,
+            false => Some(Hidden::new(patterns)?),
+        };
+        Ok(self)
+    }
+
+    /// 设置符号链接策略
+    pub fn with_symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// 挂载点名称
+    pub fn mount(&self) -> &str {
+        &self.mount
+    }
+
+    /// 符号链接策略
+    pub fn symlinks(&self) -> SymlinkPolicy {
+        self.symlinks
+    }
+
+    /// 规范化后的根目录
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 将请求路径解析为根目录下的绝对路径
+    ///
+    /// 目标可以不存在（用于写入），但其最近的已存在祖先必须位于根目录内，
+    /// 因此 `..`、绝对路径以及指向外部的符号链接都无法逃逸。
+    /// 隐藏的路径和 `SymlinkPolicy::Deny` 下经过符号链接的路径都被拒绝，
+    /// 内部目录和隐藏规则按请求路径和跟随链接后的实际路径各检查一次。
+    pub fn resolve(&self, request_path: &str) -> Result<PathBuf, SandboxError> {
+        let parts = self.parts(request_path)?;
+        let mut existing = self.root.clone();
+        let mut rest = parts.as_slice();
+        while let Some((first, tail)) = rest.split_first() {
+            let next = existing.join(first);
+            match std::fs::symlink_metadata(&next) {
+                Ok(m) if m.is_symlink() && self.symlinks == SymlinkPolicy::Deny => {
+                    return Err(SandboxError::Forbidden(request_path.to_string()));
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+            existing = next;
+            rest = tail;
+        }
+
+        let mut resolved = std::fs::canonicalize(&existing)?;
+        if !resolved.starts_with(&self.root) {
+            return Err(SandboxError::Forbidden(request_path.to_string()));
+        }
+        for part in rest {
+            resolved.push(part);
+        }
+        // 跟随符号链接后的实际位置同样不能落在内部目录或隐藏的路径中
+        if self
+            .relative(&resolved)
+            .is_none_or(|rel| self.parts(&rel).is_err())
+        {
+            return Err(SandboxError::Forbidden(request_path.to_string()));
+        }
+        Ok(resolved)
+    }
+
+    /// 解析路径但不跟随最后一级符号链接，用于查看或操作链接本身
+    pub fn resolve_nofollow(&self, request_path: &str) -> Result<PathBuf, SandboxError> {
+        let parts = self.parts(request_path)?;
+        match parts.split_last() {
+            Some((last, parents)) => Ok(self.resolve(&parents.join("/"))?.join(last)),
+            None => Ok(self.root.clone()),
+        }
+    }
+
+    /// 解析必须已存在的路径
+    pub fn resolve_existing(&self, request_path: &str) -> Result<PathBuf, SandboxError> {
+        let path = self.resolve(request_path)?;
+        if std::fs::symlink_metadata(&path).is_err() {
+            return Err(SandboxError::NotFound(request_path.to_string()));
+        }
+        Ok(path)
+    }
+
+    /// 将根目录下的绝对路径转换为以 `/` 分隔的相对路径
+    pub fn relative(&self, path: &Path) -> Option<String> {
+        let rel = path.strip_prefix(&self.root).ok()?;
+        let parts: Vec<String> = rel
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        Some(parts.join("/"))
+    }
+
+    /// 服务器内部目录，不存在时创建
+    pub fn internal_dir(&self, name: &str) -> io::Result<PathBuf> {
+        let dir = self.root.join(name);
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// 是否为服务器内部目录
+    pub fn is_internal(&self, path: &Path) -> bool {
+        path.parent() == Some(self.root.as_path())
+            && path
+                .file_name()
+                .is_some_and(|n| RESERVED_DIRS.iter().any(|r| n == *r))
+    }
+
+    /// 是否被排除：服务器内部目录或匹配隐藏规则的路径
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        if self.is_internal(path) {
+            return true;
+        }
+        let Some(hidden) = &self.hidden else {
+            return false;
+        };
+        match self.relative(path) {
+            Some(rel) if !rel.is_empty() => {
+                let parts: Vec<String> = rel.split('/').map(str::to_string).collect();
+                hidden.matches(&parts)
+            }
+            _ => false,
+        }
+    }
+
+    /// 是否为隐藏文件
+    pub fn is_hidden(&self, name: &str) -> bool {
+        name.starts_with('.')
+    }
+
+    /// 规范化请求路径并拒绝内部目录和隐藏的路径
+    fn parts(&self, request_path: &str) -> Result<Vec<String>, SandboxError> {
+        let parts = parts(request_path)?;
+        if self.hidden.as_ref().is_some_and(|h| h.matches(&parts)) {
+            return Err(SandboxError::Forbidden(request_path.to_string()));
+        }
+        Ok(parts)
+    }
+}
+
+/// 规范化请求路径并拒绝内部目录
+fn parts(request_path: &str) -> Result<Vec<String>, SandboxError> {
+    normalize(request_path)
+        .filter(|parts| {
+            parts
+                .first()
+                .is_none_or(|first| !RESERVED_DIRS.contains(&first.as_str()))
+        })
+        .ok_or_else(|| SandboxError::Forbidden(request_path.to_string()))
+}
+
+/// 对请求路径做词法规范化，`..` 越过根目录时返回 `None`
+fn normalize(request_path: &str) -> Option<Vec<String>> {
+    if request_path.contains('\0') {
+        return None;
+    }
+    let mut parts: Vec<String> = Vec::new();
+    for part in request_path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            _ => {
+                // 拒绝Windows盘符等带前缀的组件
+                if Path::new(part)
+                    .components()
+                    .any(|c| !matches!(c, Component::Normal(_)))
+                {
+                    return None;
+                }
+                parts.push(part.to_string());
+            }
+        }
+    }
+    Some(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("a/./b//c").unwrap(), vec!["a", "b", "c"]);
+        assert_eq!(normalize("/etc/passwd").unwrap(), vec!["etc", "passwd"]);
+        assert_eq!(normalize("a/../b").unwrap(), vec!["b"]);
+        assert!(normalize("../x").is_none());
+        assert!(normalize("a/../../x").is_none());
+        assert!(normalize("a\\..\\..\\x").is_none());
+        assert!(normalize("a\0b").is_none());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        std::fs::create_dir(sandbox.root().join("sub")).unwrap();
+        std::fs::write(sandbox.root().join("sub/a.txt"), "a").unwrap();
+
+        let p = sandbox.resolve("sub/a.txt").unwrap();
+        assert_eq!(p, sandbox.root().join("sub/a.txt"));
+        let p = sandbox.resolve("sub/new/b.txt").unwrap();
+        assert_eq!(p, sandbox.root().join("sub/new/b.txt"));
+        assert_eq!(sandbox.relative(&p).unwrap(), "sub/new/b.txt");
+        assert!(matches!(
+            sandbox.resolve("../outside"),
+            Err(SandboxError::Forbidden(_))
+        ));
+        assert!(matches!(
+            sandbox.resolve(".tus/x"),
+            Err(SandboxError::Forbidden(_))
+        ));
+        assert!(sandbox.is_internal(&sandbox.internal_dir(".tus").unwrap()));
+        assert!(matches!(
+            sandbox.resolve_existing("sub/missing"),
+            Err(SandboxError::NotFound(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mount_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path().join("root"))
+            .unwrap()
+            .with_hidden(&["*.bak".to_string(), "private/**".to_string()])
+            .unwrap()
+            .with_symlinks(SymlinkPolicy::Deny);
+        let root = sandbox.root().to_path_buf();
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::os::unix::fs::symlink(root.join("sub"), root.join("link")).unwrap();
+
+        assert!(sandbox.resolve("sub/a.txt").is_ok());
+        assert!(sandbox.resolve("sub/a.bak").is_err());
+        assert!(sandbox.resolve("x.bak/a.txt").is_err());
+        assert!(sandbox.resolve("private/a.txt").is_err());
+        assert!(sandbox.resolve("sub/private/a.txt").is_ok());
+        assert!(sandbox.is_excluded(&root.join("sub/old.bak")));
+        assert!(!sandbox.is_excluded(&root.join("sub")));
+
+        assert!(matches!(
+            sandbox.resolve("link/a.txt"),
+            Err(SandboxError::Forbidden(_))
+        ));
+        assert_eq!(sandbox.resolve_nofollow("link").unwrap(), root.join("link"));
+        let follow = sandbox.with_symlinks(SymlinkPolicy::Follow);
+        assert_eq!(
+            follow.resolve("link/a.txt").unwrap(),
+            root.join("sub/a.txt")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_symlink_escape() {
+        let outside = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        std::os::unix::fs::symlink(outside.path(), sandbox.root().join("link")).unwrap();
+
+        assert!(matches!(
+            sandbox.resolve("link/secret"),
+            Err(SandboxError::Forbidden(_))
+        ));
+        assert!(matches!(
+            sandbox.resolve("link"),
+            Err(SandboxError::Forbidden(_))
+        ));
+        assert_eq!(
+            sandbox.resolve_nofollow("link").unwrap(),
+            sandbox.root().join("link")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_symlink_to_excluded() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path())
+            .unwrap()
+            .with_hidden(&["*.bak".to_string(), "private/**".to_string()])
+            .unwrap();
+        let root = sandbox.root().to_path_buf();
+        let trash = sandbox.internal_dir(".trash").unwrap();
+        std::fs::write(trash.join("item"), "deleted").unwrap();
+        std::fs::create_dir_all(root.join("private")).unwrap();
+        std::fs::write(root.join("private/key"), "key").unwrap();
+        std::fs::write(root.join("old.bak"), "old").unwrap();
+        std::os::unix::fs::symlink(&trash, root.join("bin")).unwrap();
+        std::os::unix::fs::symlink(root.join("private"), root.join("open")).unwrap();
+        std::os::unix::fs::symlink(root.join("old.bak"), root.join("old.txt")).unwrap();
+
+        for path in ["bin", "bin/item", "bin/new", "open/key", "old.txt"] {
+            assert!(
+                matches!(sandbox.resolve(path), Err(SandboxError::Forbidden(_))),
+                "{}",
+                path
+            );
+        }
+        // 链接本身仍可查看和删除
+        assert_eq!(sandbox.resolve_nofollow("bin").unwrap(), root.join("bin"));
+    }
+}
@@ -0,0 +1,349 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 目标已存在时的处理策略
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Conflict {
+    /// 删除已存在的目标后继续
+    Overwrite,
+    /// 跳过，不做任何修改
+    Skip,
+    /// 报错
+    #[default]
+    Fail,
+}
+
+/// 单个路径的错误
+#[derive(Serialize, Debug, Clone)]
+pub struct PathError {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// 文件操作结果
+#[derive(Debug, Default)]
+pub struct Report {
+    /// 因 `Conflict::Skip` 而未执行
+    pub skipped: bool,
+    /// 部分失败的路径
+    pub errors: Vec<PathError>,
+}
+
+impl Report {
+    fn error(&mut self, path: &Path, e: io::Error) {
+        log::warn!("文件操作失败 {}: {}", path.display(), e);
+        self.errors.push(PathError {
+            path: path.to_path_buf(),
+            error: e.to_string(),
+        });
+    }
+}
+
+/// 文件操作错误
+#[derive(Debug)]
+pub enum OpError {
+    /// 目标已存在
+    Exists,
+    /// 目录非空且未指定递归
+    NotEmpty,
+    /// 不能把目录复制或移动到自身内部
+    IntoItself,
+    Io(io::Error),
+}
+
+impl std::fmt::Display for OpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpError::Exists => write!(f, "目标已存在"),
+            OpError::NotEmpty => write!(f, "目录非空"),
+            OpError::IntoItself => write!(f, "不能复制或移动到自身内部"),
+            OpError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for OpError {
+    fn from(e: io::Error) -> Self {
+        OpError::Io(e)
+    }
+}
+
+/// 删除文件或目录，`recursive` 为假时只能删除空目录
+pub fn remove(path: &Path, recursive: bool) -> Result<Report, OpError> {
+    let metadata = fs::symlink_metadata(path)?;
+    let mut report = Report::default();
+    if !metadata.is_dir() {
+        fs::remove_file(path)?;
+    } else if recursive {
+        remove_tree(path, &mut report);
+    } else {
+        fs::remove_dir(path).map_err(|e| {
+            if fs::read_dir(path).is_ok_and(|mut d| d.next().is_some()) {
+                OpError::NotEmpty
+            } else {
+                OpError::Io(e)
+            }
+        })?;
+    }
+    Ok(report)
+}
+
+/// 递归删除，单个失败不影响其余路径
+fn remove_tree(dir: &Path, report: &mut Report) {
+    match fs::read_dir(dir) {
+        Ok(entries) => {
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        report.error(dir, e);
+                        continue;
+                    }
+                };
+                let path = entry.path();
+                match entry.file_type() {
+                    Ok(t) if t.is_dir() => remove_tree(&path, report),
+                    Ok(_) => {
+                        if let Err(e) = fs::remove_file(&path) {
+                            report.error(&path, e);
+                        }
+                    }
+                    Err(e) => report.error(&path, e),
+                }
+            }
+        }
+        Err(e) => {
+            report.error(dir, e);
+            return;
+        }
+    }
+    if let Err(e) = fs::remove_dir(dir) {
+        report.error(dir, e);
+    }
+}
+
+/// 按策略处理已存在的目标后执行 `op`
+///
+/// 覆盖时旧目标先改名到同目录的临时名，`op` 成功后才删除；`op` 失败时清掉残留并放回原处。
+fn with_conflict(
+    dst: &Path,
+    conflict: Conflict,
+    op: impl FnOnce() -> Result<Report, OpError>,
+) -> Result<Report, OpError> {
+    if fs::symlink_metadata(dst).is_err() {
+        return op();
+    }
+    match conflict {
+        Conflict::Fail => Err(OpError::Exists),
+        Conflict::Skip => Ok(Report {
+            skipped: true,
+            ..Default::default()
+        }),
+        Conflict::Overwrite => {
+            let aside = dst.with_file_name(super::temp_name());
+            fs::rename(dst, &aside)?;
+            match op() {
+                Ok(mut report) => {
+                    match remove(&aside, true) {
+                        Ok(removed) => report.errors.extend(removed.errors),
+                        Err(e) => report.error(&aside, io::Error::other(e.to_string())),
+                    }
+                    Ok(report)
+                }
+                Err(e) => {
+                    if fs::symlink_metadata(dst).is_ok() {
+                        let _ = remove(dst, true);
+                    }
+                    if let Err(e) = fs::rename(&aside, dst) {
+                        log::error!("放回被覆盖的目标失败 {}: {}", aside.display(), e);
+                    }
+                    Err(e)
+                }
+            }
+        }
+    }
+}
+
+/// 创建目录及其所有父目录
+pub fn mkdir(path: &Path, conflict: Conflict) -> Result<Report, OpError> {
+    match fs::symlink_metadata(path) {
+        Ok(m) if m.is_dir() && conflict != Conflict::Fail => Ok(Report {
+            skipped: true,
+            ..Default::default()
+        }),
+        Ok(_) => Err(OpError::Exists),
+        Err(_) => {
+            fs::create_dir_all(path)?;
+            Ok(Report::default())
+        }
+    }
+}
+
+/// 复制文件或目录树
+pub fn copy(src: &Path, dst: &Path, conflict: Conflict) -> Result<Report, OpError> {
+    let metadata = fs::symlink_metadata(src)?;
+    if src == dst || (metadata.is_dir() && dst.starts_with(src)) {
+        return Err(OpError::IntoItself);
+    }
+    with_conflict(dst, conflict, || {
+        let mut report = Report::default();
+        if metadata.is_dir() {
+            fs::create_dir(dst)?;
+            copy_tree(src, dst, &mut report);
+        } else {
+            copy_entry(src, dst, &metadata.file_type())?;
+        }
+        Ok(report)
+    })
+}
+
+fn copy_tree(src: &Path, dst: &Path, report: &mut Report) {
+    let entries = match fs::read_dir(src) {
+        Ok(entries) => entries,
+        Err(e) => {
+            report.error(src, e);
+            return;
+        }
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                report.error(src, e);
+                continue;
+            }
+        };
+        let from = entry.path();
+        let to = dst.join(entry.file_name());
+        let result = entry.file_type().and_then(|t| {
+            if t.is_dir() {
+                fs::create_dir(&to).map(|_| copy_tree(&from, &to, report))
+            } else {
+                copy_entry(&from, &to, &t)
+            }
+        });
+        if let Err(e) = result {
+            report.error(&from, e);
+        }
+    }
+}
+
+/// 复制单个文件或符号链接，先写临时文件再重命名
+fn copy_entry(from: &Path, to: &Path, file_type: &fs::FileType) -> io::Result<()> {
+    if file_type.is_symlink() {
+        #[cfg(unix)]
+        return std::os::unix::fs::symlink(fs::read_link(from)?, to);
+        #[cfg(not(unix))]
+        return Err(io::Error::other("不支持复制符号链接"));
+    }
+    let temp = to.with_file_name(super::temp_name());
+    let result = fs::copy(from, &temp)
+        .and_then(|_| fs::File::open(&temp)?.sync_all())
+        .and_then(|_| fs::rename(&temp, to));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// 移动或重命名，跨设备时退化为复制后删除
+pub fn rename(src: &Path, dst: &Path, conflict: Conflict) -> Result<Report, OpError> {
+    let metadata = fs::symlink_metadata(src)?;
+    if src == dst || (metadata.is_dir() && dst.starts_with(src)) {
+        return Err(OpError::IntoItself);
+    }
+    with_conflict(dst, conflict, || match fs::rename(src, dst) {
+        Ok(()) => Ok(Report::default()),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let mut report = copy(src, dst, Conflict::Fail)?;
+            if report.errors.is_empty() {
+                report.errors = remove(src, true)?.errors;
+            }
+            Ok(report)
+        }
+        Err(e) => Err(OpError::Io(e)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_move_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/f.txt"), "f").unwrap();
+        fs::write(root.join("a/g.txt"), "g").unwrap();
+
+        let report = copy(&root.join("a"), &root.join("c"), Conflict::Fail).unwrap();
+        assert!(report.errors.is_empty());
+        assert_eq!(fs::read_to_string(root.join("c/b/f.txt")).unwrap(), "f");
+        assert!(matches!(
+            copy(&root.join("a"), &root.join("c"), Conflict::Fail),
+            Err(OpError::Exists)
+        ));
+        assert!(
+            copy(&root.join("a"), &root.join("c"), Conflict::Skip)
+                .unwrap()
+                .skipped
+        );
+        assert!(matches!(
+            copy(&root.join("a"), &root.join("a/b/x"), Conflict::Fail),
+            Err(OpError::IntoItself)
+        ));
+
+        fs::write(root.join("h.txt"), "h").unwrap();
+        rename(
+            &root.join("h.txt"),
+            &root.join("a/g.txt"),
+            Conflict::Overwrite,
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(root.join("a/g.txt")).unwrap(), "h");
+        assert!(!root.join("h.txt").exists());
+        assert_eq!(fs::read_dir(root.join("a")).unwrap().count(), 2);
+
+        assert!(matches!(
+            remove(&root.join("c"), false),
+            Err(OpError::NotEmpty)
+        ));
+        assert!(remove(&root.join("c"), true).unwrap().errors.is_empty());
+        assert!(!root.join("c").exists());
+
+        assert!(!mkdir(&root.join("x/y"), Conflict::Fail).unwrap().skipped);
+        assert!(mkdir(&root.join("x/y"), Conflict::Skip).unwrap().skipped);
+        assert!(matches!(
+            mkdir(&root.join("x/y"), Conflict::Fail),
+            Err(OpError::Exists)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_overwrite_keeps_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        // 套接字文件无法打开读取，复制失败
+        let _socket = std::os::unix::net::UnixListener::bind(root.join("sock")).unwrap();
+        fs::write(root.join("dst.txt"), "original").unwrap();
+
+        assert!(
+            copy(
+                &root.join("sock"),
+                &root.join("dst.txt"),
+                Conflict::Overwrite
+            )
+            .is_err()
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("dst.txt")).unwrap(),
+            "original"
+        );
+        assert_eq!(fs::read_dir(root).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,961 @@
+//! TLS 与双向 TLS 监听
+//!
+//! 证书在收到 SIGHUP 或文件变化时重新加载，只影响之后建立的连接；已有连接继续使用握手时的证书。
+//! HTTP/3 的 QUIC 配置与 TCP 共用同一份证书。
+//! 启用客户端证书校验时，证书主题会映射为调用方身份 [`Principal`]，处理函数通过 [`principal`] 读取。
+
+use crate::cmd::ServerConfig;
+use futures_util::FutureExt;
+use futures_util::future::BoxFuture;
+use notify_debouncer_full::notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{DebounceEventResult, Debouncer, RecommendedCache, new_debouncer};
+use quinn::crypto::rustls::QuicServerConfig;
+use salvo::conn::{Acceptor, HttpBuilder, Listener};
+use salvo::http::header::{ALT_SVC, HeaderValue};
+use salvo::http::uri::Scheme;
+use salvo::hyper;
+use salvo::hyper::service::Service as HyperService;
+use salvo::{Depot, FlowCtrl, Handler, Request, Response, Service, async_trait};
+use std::io::{self, IoSlice};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::rustls::client::danger::HandshakeSignatureValid;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, UnixTime};
+use tokio_rustls::rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::{
+    self, DigitallySignedStruct, DistinguishedName, RootCertStore, SignatureScheme,
+};
+use tokio_rustls::server::TlsStream;
+
+/// TLS 握手超时
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// 证书文件变化的去抖时间，避免证书和私钥先后写入时加载到不匹配的一对
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// 客户端证书对应的调用方身份
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// 证书主题，如 `CN=backup, O=ops`
+    pub subject: String,
+    /// 主题中的 CN
+    pub common_name: Option<String>,
+}
+
+impl Principal {
+    /// 从 DER 编码的证书中读取主题
+    fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        let subject = cert.subject();
+        Some(Principal {
+            subject: subject.to_string(),
+            common_name: subject
+                .iter_common_name()
+                .next()
+                .and_then(|cn| cn.as_str().ok())
+                .map(str::to_string),
+        })
+    }
+
+    /// 授权时使用的名称：证书的 CN，没有 CN 时为完整主题
+    pub fn name(&self) -> &str {
+        self.common_name.as_deref().unwrap_or(&self.subject)
+    }
+}
+
+/// 请求所在连接的客户端证书身份；非 TLS 连接、HTTP/3 请求或客户端未出示证书时为 `None`
+///
+/// 身份由 [`TlsAcceptor::serve`] 随连接放入每个请求的扩展中，不按地址查找。
+pub fn principal(req: &Request) -> Option<Principal> {
+    req.extensions().get::<Principal>().cloned()
+}
+
+/// 客户端证书校验方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// 必须出示由 CA 签发的证书
+    Required,
+    /// 可以不出示证书；出示时必须由 CA 签发
+    Optional,
+}
+
+impl ClientAuth {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "required" => Ok(ClientAuth::Required),
+            "optional" => Ok(ClientAuth::Optional),
+            other => Err(format!("无效的 TLS_CLIENT_AUTH: {}", other)),
+        }
+    }
+}
+
+/// 证书、私钥与可选的客户端 CA 路径
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub client_ca: Option<PathBuf>,
+    pub client_auth: ClientAuth,
+}
+
+impl TlsSettings {
+    /// 读取 `TLS_CERT`、`TLS_KEY`、`TLS_CLIENT_CA` 与 `TLS_CLIENT_AUTH`，未配置证书时为 `None`
+    pub fn from_config(config: &ServerConfig) -> Result<Option<Self>, String> {
+        match (config.tls_cert.is_empty(), config.tls_key.is_empty()) {
+            (true, true) => {
+                if !config.tls_client_ca.is_empty() {
+                    return Err("配置 TLS_CLIENT_CA 时需同时配置 TLS_CERT 与 TLS_KEY".to_string());
+                }
+                Ok(None)
+            }
+            (false, false) => Ok(Some(TlsSettings {
+                cert: PathBuf::from(&config.tls_cert),
+                key: PathBuf::from(&config.tls_key),
+                client_ca: (!config.tls_client_ca.is_empty())
+                    .then(|| PathBuf::from(&config.tls_client_ca)),
+                client_auth: ClientAuth::parse(&config.tls_client_auth)?,
+            })),
+            _ => Err("TLS_CERT 与 TLS_KEY 需同时配置".to_string()),
+        }
+    }
+
+    /// 监听的文件：证书、私钥和客户端 CA
+    fn files(&self) -> Vec<&Path> {
+        let mut files = vec![self.cert.as_path(), self.key.as_path()];
+        files.extend(self.client_ca.as_deref());
+        files
+    }
+
+    /// 读取证书、私钥和客户端 CA
+    fn load(&self) -> Result<Loaded, String> {
+        let provider = Arc::new(ring::default_provider());
+        let read = |path: &Path| {
+            std::fs::read(path).map_err(|e| format!("读取 {} 失败: {}", path.display(), e))
+        };
+        let chain = CertificateDer::pem_slice_iter(&read(&self.cert)?)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("解析证书 {} 失败: {}", self.cert.display(), e))?;
+        if chain.is_empty() {
+            return Err(format!("{} 中没有证书", self.cert.display()));
+        }
+        let key = PrivateKeyDer::from_pem_slice(&read(&self.key)?)
+            .map_err(|e| format!("解析私钥 {} 失败: {}", self.key.display(), e))?;
+        let key = CertifiedKey::from_der(chain, key, &provider)
+            .map_err(|e| format!("证书与私钥不匹配 {}: {}", self.key.display(), e))?;
+        let verifier = match &self.client_ca {
+            None => None,
+            Some(ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in CertificateDer::pem_slice_iter(&read(ca)?) {
+                    let cert = cert.map_err(|e| format!("解析 CA {} 失败: {}", ca.display(), e))?;
+                    roots
+                        .add(cert)
+                        .map_err(|e| format!("解析 CA {} 失败: {}", ca.display(), e))?;
+                }
+                let builder = WebPkiClientVerifier::builder_with_provider(roots.into(), provider);
+                let builder = match self.client_auth {
+                    ClientAuth::Required => builder,
+                    ClientAuth::Optional => builder.allow_unauthenticated(),
+                };
+                Some(
+                    builder
+                        .build()
+                        .map_err(|e| format!("加载 CA {} 失败: {}", ca.display(), e))?,
+                )
+            }
+        };
+        Ok(Loaded {
+            key: Arc::new(key),
+            verifier,
+        })
+    }
+}
+
+/// 一次加载的证书与客户端证书校验器
+struct Loaded {
+    key: Arc<CertifiedKey>,
+    verifier: Option<Arc<dyn ClientCertVerifier>>,
+}
+
+/// 握手时读取最近一次加载成功的证书与 CA，TCP 与 QUIC 共用
+#[derive(Clone)]
+struct Current(Arc<RwLock<Arc<Loaded>>>);
+
+impl Current {
+    fn get(&self) -> Arc<Loaded> {
+        match self.0.read() {
+            Ok(loaded) => loaded.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn set(&self, loaded: Loaded) {
+        match self.0.write() {
+            Ok(mut current) => *current = Arc::new(loaded),
+            Err(poisoned) => *poisoned.into_inner() = Arc::new(loaded),
+        }
+    }
+
+    fn verifier(&self) -> Result<Arc<dyn ClientCertVerifier>, rustls::Error> {
+        self.get()
+            .verifier
+            .clone()
+            .ok_or_else(|| rustls::Error::General("未配置客户端 CA".to_string()))
+    }
+}
+
+impl std::fmt::Debug for Current {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Current").finish()
+    }
+}
+
+impl ResolvesServerCert for Current {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.get().key.clone())
+    }
+}
+
+impl ClientCertVerifier for Current {
+    fn offer_client_auth(&self) -> bool {
+        self.get()
+            .verifier
+            .as_ref()
+            .is_some_and(|v| v.offer_client_auth())
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        self.get()
+            .verifier
+            .as_ref()
+            .is_some_and(|v| v.client_auth_mandatory())
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        // CA 可能被重新加载，无法借出当前列表；不发送提示时客户端自行选择证书
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        self.verifier()?
+            .verify_client_cert(end_entity, intermediates, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier()?.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier()?.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        match &self.get().verifier {
+            Some(v) => v.supported_verify_schemes(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// 生成 rustls 配置，证书和客户端证书校验都委托给 `current`
+fn server_config(current: &Current, alpn: Vec<Vec<u8>>) -> Result<rustls::ServerConfig, String> {
+    let builder = rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    let builder = match current.get().verifier {
+        Some(_) => builder.with_client_cert_verifier(Arc::new(current.clone())),
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder.with_cert_resolver(Arc::new(current.clone()));
+    config.alpn_protocols = alpn;
+    Ok(config)
+}
+
+/// 可热加载的 TLS 配置，新连接使用最近一次加载成功的证书
+pub struct Tls {
+    settings: TlsSettings,
+    current: Current,
+    acceptor: tokio_rustls::TlsAcceptor,
+}
+
+impl Tls {
+    /// 加载证书，失败时返回错误
+    pub fn load(settings: TlsSettings) -> Result<Self, String> {
+        let current = Current(Arc::new(RwLock::new(Arc::new(settings.load()?))));
+        let config = server_config(&current, vec![b"h2".to_vec(), b"http/1.1".to_vec()])?;
+        Ok(Tls {
+            settings,
+            current,
+            acceptor: Arc::new(config).into(),
+        })
+    }
+
+    /// 重新加载证书；失败时保留当前证书
+    pub fn reload(&self) -> Result<(), String> {
+        let loaded = self
+            .settings
+            .load()
+            .map_err(|e| format!("重新加载证书失败: {}", e))?;
+        self.current.set(loaded);
+        log::info!("已重新加载证书: {}", self.settings.cert.display());
+        Ok(())
+    }
+
+    fn acceptor(&self) -> tokio_rustls::TlsAcceptor {
+        self.acceptor.clone()
+    }
+
+    /// HTTP/3 使用的 QUIC 配置，与 TCP 共用证书且一同重新加载
+    pub fn quic_config(&self) -> Result<quinn::ServerConfig, String> {
+        let config = server_config(&self.current, vec![b"h3".to_vec()])?;
+        let crypto =
+            QuicServerConfig::try_from(config).map_err(|e| format!("生成 QUIC 配置失败: {}", e))?;
+        Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+    }
+
+    fn reload_logged(&self) {
+        if let Err(e) = self.reload() {
+            log::error!("{}", e);
+        }
+    }
+
+    /// 在证书文件变化或收到 SIGHUP 时重新加载，返回值在服务期间需保持存活
+    pub fn watch(self: &Arc<Self>) -> Result<Watcher, String> {
+        let tls = self.clone();
+        let names: Vec<_> = self
+            .settings
+            .files()
+            .iter()
+            .filter_map(|f| f.file_name().map(|n| n.to_os_string()))
+            .collect();
+        let handler = move |result: DebounceEventResult| {
+            let Ok(events) = result else { return };
+            let changed = events.iter().any(|e| {
+                !matches!(e.kind, EventKind::Access(_))
+                    && e.paths.iter().any(|p| {
+                        p.file_name()
+                            .is_some_and(|n| names.iter().any(|name| name == n))
+                    })
+            });
+            if changed {
+                tls.reload_logged();
+            }
+        };
+        let mut debouncer = new_debouncer(RELOAD_DEBOUNCE, None, handler)
+            .map_err(|e| format!("监听证书文件失败: {}", e))?;
+        // 监听所在目录，证书轮换时常以改名替换文件
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for file in self.settings.files() {
+            let dir = match file.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            if !dirs.contains(&dir) {
+                debouncer
+                    .watch(&dir, RecursiveMode::NonRecursive)
+                    .map_err(|e| format!("监听 {} 失败: {}", dir.display(), e))?;
+                dirs.push(dir);
+            }
+        }
+
+        #[cfg(unix)]
+        let signal = {
+            use tokio::signal::unix::{SignalKind, signal};
+            let mut hup =
+                signal(SignalKind::hangup()).map_err(|e| format!("无法监听 SIGHUP: {}", e))?;
+            let tls = self.clone();
+            Some(tokio::spawn(async move {
+                while hup.recv().await.is_some() {
+                    log::info!("接收到 SIGHUP，重新加载证书");
+                    tls.reload_logged();
+                }
+            }))
+        };
+        #[cfg(not(unix))]
+        let signal = None;
+
+        Ok(Watcher {
+            _debouncer: debouncer,
+            signal,
+        })
+    }
+}
+
+/// 为响应加上 `Alt-Svc`，告知客户端同一端口可用 HTTP/3
+pub struct AltSvc(HeaderValue);
+
+impl AltSvc {
+    pub fn new(port: u16) -> Self {
+        AltSvc(HeaderValue::from_str(&format!("h3=\":{}\"; ma=86400", port)).unwrap())
+    }
+}
+
+#[async_trait]
+impl Handler for AltSvc {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        res.headers_mut().insert(ALT_SVC, self.0.clone());
+    }
+}
+
+/// 证书重新加载的触发器，丢弃时停止监听
+pub struct Watcher {
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+    signal: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        if let Some(signal) = &self.signal {
+            signal.abort();
+        }
+    }
+}
+
+/// 在内层监听器上加一层 TLS
+pub struct TlsListener<L> {
+    inner: L,
+    tls: Arc<Tls>,
+}
+
+impl<L> TlsListener<L>
+where
+    L: Listener + Send + 'static,
+    <L::Acceptor as Acceptor>::Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    pub fn new(inner: L, tls: Arc<Tls>) -> Self {
+        TlsListener { inner, tls }
+    }
+
+    /// 绑定内层监听器
+    pub async fn bind(self) -> salvo::Result<TlsAcceptor<L::Acceptor>> {
+        Ok(TlsAcceptor {
+            inner: self.inner.try_bind().await?,
+            tls: self.tls,
+        })
+    }
+}
+
+/// 接受连接时取当前证书，握手在连接任务中进行
+///
+/// 不经过 salvo 的 `Server`：每个连接单独生成处理器，握手得到的客户端身份随连接放入该连接上每个请求的扩展中，
+/// 处理函数通过 [`principal`] 读取，与对端地址无关。
+pub struct TlsAcceptor<A> {
+    inner: A,
+    tls: Arc<Tls>,
+}
+
+impl<A> TlsAcceptor<A>
+where
+    A: Acceptor + Send + 'static,
+    A::Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    /// 实际监听的地址
+    #[cfg(test)]
+    pub fn local_addr(&self) -> Option<std::net::SocketAddr> {
+        self.inner.holdings().first()?.local_addr.clone().into_std()
+    }
+
+    /// 接受连接并提供服务，直到任务被取消
+    pub async fn serve(mut self, service: Service) {
+        let builder = Arc::new(HttpBuilder::new());
+        loop {
+            let accepted = match self.inner.accept(None).await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::error!("接受连接失败: {}", e);
+                    continue;
+                }
+            };
+            let conn = TlsConn::new(self.tls.acceptor(), accepted.stream);
+            let handler = WithPrincipal {
+                inner: service.hyper_handler(
+                    accepted.local_addr,
+                    accepted.remote_addr,
+                    Scheme::HTTPS,
+                    None,
+                    None,
+                ),
+                principal: conn.principal.clone(),
+            };
+            let builder = builder.clone();
+            tokio::spawn(async move {
+                if let Err(e) = builder.serve_connection(conn, handler, None, None).await {
+                    log::debug!("连接结束: {}", e);
+                }
+            });
+        }
+    }
+}
+
+/// 给请求附上连接的客户端身份后交给 salvo 处理
+struct WithPrincipal<H> {
+    inner: H,
+    principal: Arc<OnceLock<Principal>>,
+}
+
+impl<H, B> HyperService<hyper::Request<B>> for WithPrincipal<H>
+where
+    H: HyperService<hyper::Request<B>>,
+{
+    type Response = H::Response;
+    type Error = H::Error;
+    type Future = H::Future;
+
+    fn call(&self, mut req: hyper::Request<B>) -> Self::Future {
+        // 请求在握手完成后才会到达，此时身份已确定
+        if let Some(principal) = self.principal.get() {
+            req.extensions_mut().insert(principal.clone());
+        }
+        self.inner.call(req)
+    }
+}
+
+enum State<S> {
+    Handshaking(BoxFuture<'static, io::Result<TlsStream<S>>>),
+    Ready(Box<TlsStream<S>>),
+    Failed,
+}
+
+/// TLS 连接；握手完成时记下客户端证书身份
+pub struct TlsConn<S> {
+    state: State<S>,
+    principal: Arc<OnceLock<Principal>>,
+}
+
+impl<S> TlsConn<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    fn new(acceptor: tokio_rustls::TlsAcceptor, stream: S) -> Self {
+        let principal = Arc::new(OnceLock::new());
+        let slot = principal.clone();
+        let handshake = async move {
+            let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS 握手超时"))??;
+            let principal = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| Principal::from_der(cert));
+            if let Some(principal) = principal {
+                slot.set(principal).ok();
+            }
+            Ok(stream)
+        };
+        TlsConn {
+            state: State::Handshaking(handshake.boxed()),
+            principal,
+        }
+    }
+
+    /// 推进握手，完成后返回 TLS 流
+    fn poll_stream(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<&mut TlsStream<S>>> {
+        if let State::Handshaking(handshake) = &mut self.state {
+            match handshake.poll_unpin(cx) {
+                Poll::Ready(Ok(stream)) => self.state = State::Ready(Box::new(stream)),
+                Poll::Ready(Err(e)) => {
+                    self.state = State::Failed;
+                    return Poll::Ready(Err(e));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        match &mut self.state {
+            State::Ready(stream) => Poll::Ready(Ok(stream)),
+            _ => Poll::Ready(Err(io::Error::other("TLS 握手失败"))),
+        }
+    }
+}
+
+impl<S> AsyncRead for TlsConn<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut().poll_stream(cx) {
+            Poll::Ready(Ok(stream)) => Pin::new(stream).poll_read(cx, buf),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S> AsyncWrite for TlsConn<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut().poll_stream(cx) {
+            Poll::Ready(Ok(stream)) => Pin::new(stream).poll_write(cx, buf),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut().poll_stream(cx) {
+            Poll::Ready(Ok(stream)) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match &self.state {
+            State::Ready(stream) => stream.is_write_vectored(),
+            _ => false,
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut().poll_stream(cx) {
+            Poll::Ready(Ok(stream)) => Pin::new(stream).poll_flush(cx),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut().poll_stream(cx) {
+            Poll::Ready(Ok(stream)) => Pin::new(stream).poll_shutdown(cx),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, Issuer, KeyPair};
+    use salvo::conn::TcpListener;
+    use salvo::prelude::*;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio_rustls::TlsConnector;
+    use tokio_rustls::client::TlsStream as ClientStream;
+    use tokio_rustls::rustls::pki_types::{PrivateKeyDer, ServerName};
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+    #[handler]
+    async fn whoami(req: &mut Request) -> String {
+        principal(req)
+            .map(|p| p.name().to_string())
+            .unwrap_or_default()
+    }
+
+    pub(crate) struct Ca {
+        issuer: Issuer<'static, KeyPair>,
+        pub(crate) pem: String,
+    }
+
+    impl Ca {
+        pub(crate) fn new() -> Self {
+            let mut params = CertificateParams::new(Vec::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            params
+                .distinguished_name
+                .push(DnType::CommonName, "fsp test ca");
+            let key = KeyPair::generate().unwrap();
+            let pem = params.self_signed(&key).unwrap().pem();
+            Ca {
+                issuer: Issuer::new(params, key),
+                pem,
+            }
+        }
+
+        /// 签发证书，返回 (证书 PEM, 私钥)
+        pub(crate) fn issue(&self, cn: &str, names: &[&str]) -> (String, KeyPair) {
+            let names = names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+            let mut params = CertificateParams::new(names).unwrap();
+            params.distinguished_name.push(DnType::CommonName, cn);
+            params
+                .distinguished_name
+                .push(DnType::OrganizationName, "ops");
+            let key = KeyPair::generate().unwrap();
+            let cert = params.signed_by(&key, &self.issuer).unwrap();
+            (cert.pem(), key)
+        }
+    }
+
+    pub(crate) fn connector(ca: &Ca, client: Option<&(String, KeyPair)>) -> TlsConnector {
+        let mut roots = RootCertStore::empty();
+        for cert in pem_certs(&ca.pem) {
+            roots.add(cert).unwrap();
+        }
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+        let config = match client {
+            Some((pem, key)) => builder
+                .with_client_auth_cert(
+                    pem_certs(pem),
+                    PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+        TlsConnector::from(Arc::new(config))
+    }
+
+    fn pem_certs(pem: &str) -> Vec<tokio_rustls::rustls::pki_types::CertificateDer<'static>> {
+        let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes()).unwrap();
+        vec![pem.contents.into()]
+    }
+
+    pub(crate) async fn connect(
+        addr: SocketAddr,
+        connector: &TlsConnector,
+    ) -> io::Result<ClientStream<TcpStream>> {
+        let tcp = TcpStream::connect(addr).await?;
+        connector
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+    }
+
+    /// 服务端证书的 CN
+    fn server_name(stream: &ClientStream<TcpStream>) -> Option<String> {
+        let cert = stream.get_ref().1.peer_certificates()?.first()?;
+        Principal::from_der(cert)?.common_name
+    }
+
+    /// 在保持连接上发送 GET，返回响应头和响应体
+    pub(crate) async fn get(
+        stream: &mut ClientStream<TcpStream>,
+        path: &str,
+    ) -> io::Result<(String, String)> {
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await?;
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 1024];
+        loop {
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            buf.extend_from_slice(&chunk[..n]);
+            let text = String::from_utf8_lossy(&buf).to_string();
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .find_map(|l| {
+                        l.to_ascii_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|v| v.trim().to_string())
+                    })
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .unwrap_or(0);
+                if buf.len() >= end + 4 + length {
+                    let head = text[..end].to_ascii_lowercase();
+                    return Ok((head, text[end + 4..end + 4 + length].to_string()));
+                }
+            }
+        }
+    }
+
+    /// 从 `bind` 发起 HTTP/3 连接，不出示客户端证书
+    pub(crate) async fn h3_client(
+        ca: &Ca,
+        bind: SocketAddr,
+        addr: SocketAddr,
+    ) -> h3::client::SendRequest<h3_quinn::OpenStreams, bytes::Bytes> {
+        use quinn::crypto::rustls::QuicClientConfig;
+
+        let mut roots = RootCertStore::empty();
+        for cert in pem_certs(&ca.pem) {
+            roots.add(cert).unwrap();
+        }
+        let mut crypto = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        crypto.alpn_protocols = vec![b"h3".to_vec()];
+        let mut endpoint = quinn::Endpoint::client(bind).unwrap();
+        endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(
+            QuicClientConfig::try_from(crypto).unwrap(),
+        )));
+        let conn = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+        let (mut driver, send_request) = h3::client::new(h3_quinn::Connection::new(conn))
+            .await
+            .unwrap();
+        tokio::spawn(async move { std::future::poll_fn(|cx| driver.poll_close(cx)).await });
+        send_request
+    }
+
+    #[test]
+    fn test_settings() {
+        let mut config = ServerConfig::default();
+        assert!(TlsSettings::from_config(&config).unwrap().is_none());
+        config.tls_cert = "cert.pem".to_string();
+        assert!(TlsSettings::from_config(&config).is_err());
+        config.tls_key = "key.pem".to_string();
+        config.tls_client_ca = "ca.pem".to_string();
+        config.tls_client_auth = "optional".to_string();
+        let settings = TlsSettings::from_config(&config).unwrap().unwrap();
+        assert_eq!(settings.client_auth, ClientAuth::Optional);
+        assert_eq!(settings.files().len(), 3);
+        config.tls_client_auth = "sometimes".to_string();
+        assert!(TlsSettings::from_config(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mtls_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let ca = Ca::new();
+        let write_server = |cn: &str| {
+            let (cert, key) = ca.issue(cn, &["localhost"]);
+            std::fs::write(dir.path().join("cert.pem"), cert).unwrap();
+            std::fs::write(dir.path().join("key.pem"), key.serialize_pem()).unwrap();
+        };
+        write_server("server-a");
+        std::fs::write(dir.path().join("ca.pem"), &ca.pem).unwrap();
+        let tls = Arc::new(
+            Tls::load(TlsSettings {
+                cert: dir.path().join("cert.pem"),
+                key: dir.path().join("key.pem"),
+                client_ca: Some(dir.path().join("ca.pem")),
+                client_auth: ClientAuth::Required,
+            })
+            .unwrap(),
+        );
+        let _watcher = tls.watch().unwrap();
+        let acceptor = TlsListener::new(TcpListener::new("127.0.0.1:0"), tls.clone())
+            .bind()
+            .await
+            .unwrap();
+        let addr = acceptor.local_addr().unwrap();
+        let router = Router::with_path("whoami").get(whoami);
+        tokio::spawn(acceptor.serve(Service::new(router)));
+
+        // 客户端证书的 CN 映射为调用方身份
+        let client = ca.issue("ops-admin", &[]);
+        let with_cert = connector(&ca, Some(&client));
+        let mut old = connect(addr, &with_cert).await.unwrap();
+        assert_eq!(server_name(&old).as_deref(), Some("server-a"));
+        assert_eq!(get(&mut old, "/whoami").await.unwrap().1, "ops-admin");
+
+        // 未出示证书的客户端被拒绝
+        let rejected = match connect(addr, &connector(&ca, None)).await {
+            Ok(mut stream) => get(&mut stream, "/whoami").await.is_err(),
+            Err(_) => true,
+        };
+        assert!(rejected);
+
+        // 证书文件变化后新连接使用新证书，已有连接不受影响
+        write_server("server-b");
+        let mut renewed = false;
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let stream = connect(addr, &with_cert).await.unwrap();
+            if server_name(&stream).as_deref() == Some("server-b") {
+                renewed = true;
+                break;
+            }
+        }
+        assert!(renewed);
+        assert_eq!(get(&mut old, "/whoami").await.unwrap().1, "ops-admin");
+
+        // 加载失败时保留当前证书
+        std::fs::write(dir.path().join("key.pem"), "broken").unwrap();
+        assert!(tls.reload().is_err());
+        let stream = connect(addr, &with_cert).await.unwrap();
+        assert_eq!(server_name(&stream).as_deref(), Some("server-b"));
+    }
+
+    #[tokio::test]
+    async fn test_http3_has_no_principal() {
+        use salvo::conn::QuinnListener;
+
+        let dir = tempfile::tempdir().unwrap();
+        let ca = Ca::new();
+        let (cert, key) = ca.issue("server", &["localhost"]);
+        std::fs::write(dir.path().join("cert.pem"), cert).unwrap();
+        std::fs::write(dir.path().join("key.pem"), key.serialize_pem()).unwrap();
+        std::fs::write(dir.path().join("ca.pem"), &ca.pem).unwrap();
+        let tls = Arc::new(
+            Tls::load(TlsSettings {
+                cert: dir.path().join("cert.pem"),
+                key: dir.path().join("key.pem"),
+                client_ca: Some(dir.path().join("ca.pem")),
+                client_auth: ClientAuth::Optional,
+            })
+            .unwrap(),
+        );
+        let config = ServerConfig {
+            root: dir.path().join("files").display().to_string(),
+            database_url: "sqlite::memory:".to_string(),
+            tls_admin_clients: "ops-admin".to_string(),
+            ..Default::default()
+        };
+        let router = Arc::new(crate::web::create_router(&config).await.unwrap());
+        let acceptor = TlsListener::new(TcpListener::new("127.0.0.1:0"), tls.clone())
+            .bind()
+            .await
+            .unwrap();
+        let addr = acceptor.local_addr().unwrap();
+        tokio::spawn(acceptor.serve(Service::new(router.clone())));
+        let h3 = QuinnListener::new(tls.quic_config().unwrap(), addr)
+            .try_bind()
+            .await
+            .unwrap();
+        tokio::spawn(Server::new(h3).serve(router));
+
+        // 管理员证书的 TCP 连接保持打开
+        let client = ca.issue("ops-admin", &[]);
+        let mut admin = connect(addr, &connector(&ca, Some(&client))).await.unwrap();
+        let (head, _) = get(&mut admin, "/admin/read-only").await.unwrap();
+        assert!(head.starts_with("http/1.1 200"));
+        let mapped = admin.get_ref().0.local_addr().unwrap();
+
+        // 从同一地址和端口发出、不带证书的 HTTP/3 请求不能借用该身份
+        let mut send_request = h3_client(&ca, mapped, addr).await;
+        let uri = format!("https://localhost:{}/admin/read-only", addr.port());
+        let request = salvo::hyper::Request::get(uri).body(()).unwrap();
+        let mut stream = send_request.send_request(request).await.unwrap();
+        stream.finish().await.unwrap();
+        let response = stream.recv_response().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let (head, _) = get(&mut admin, "/admin/read-only").await.unwrap();
+        assert!(head.starts_with("http/1.1 200"));
+    }
+}
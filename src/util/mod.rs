@@ -2,8 +2,7 @@ use lazy_static::lazy_static;
 use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::fs::{File, OpenOptions};
-use std::io::BufReader;
+use std::fs::OpenOptions;
 use std::io::prelude::*;
 use std::path::PathBuf;
 
@@ -81,33 +80,9 @@ pub fn read_env_to_hashmap() -> anyhow::Result<HashMap<String, String>> {
     Ok(env_map)
 }
 
-pub fn parse_env_file() -> anyhow::Result<HashMap<String, String>> {
-    let env_file = PathBuf::from(&*EXECUTABLE_DIRECTORY)
-        .join(".env")
-        .to_str()
-        .unwrap_or(".env")
-        .to_string();
-    let file = File::open(env_file)?;
-    let reader = BufReader::new(file);
-
-    Ok(reader
-        .lines()
-        .map_while(Result::ok)
-        .map(|line| line.trim().to_string())
-        .filter(|line| !line.is_empty() && !line.starts_with('#'))
-        .filter_map(|line| {
-            line.find('=').map(|pos| {
-                (
-                    line[..pos].trim().to_string(),
-                    line[pos + 1..]
-                        .trim()
-                        .trim_matches('"')
-                        .trim_matches('\'')
-                        .to_string(),
-                )
-            })
-        })
-        .collect())
+/// 逐字节比较，耗时与内容无关，用于比较令牌和签名
+pub fn token_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
 #[cfg(test)]
@@ -135,14 +110,4 @@ mod tests {
             Err(e) => panic!("{:?}", e),
         }
     }
-
-    #[test]
-    fn test_parse_env_file() {
-        match parse_env_file() {
-            Ok(hashmap) => {
-                assert_eq!(hashmap, HashMap::new());
-            }
-            Err(e) => panic!("{}", e),
-        }
-    }
 }
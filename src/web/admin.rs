@@ -0,0 +1,340 @@
+use crate::db::s3::S3Key;
+use crate::tls;
+use crate::util::token_eq;
+use crate::web::fs::{db, db_status};
+use crate::web::mounts::{DEFAULT_RETRY_AFTER, MountTable, ReadOnly, Window};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use salvo::http::header::{AUTHORIZATION, HeaderValue, WWW_AUTHENTICATE};
+use salvo::http::{StatusCode, StatusError};
+use salvo::prelude::Json;
+use salvo::{Depot, FlowCtrl, Request, Response, Router, handler};
+use std::sync::Arc;
+
+/// 管理令牌，为空时管理接口关闭
+#[derive(Debug, Clone)]
+pub struct AdminToken(pub String);
+
+/// 可调用管理接口的客户端证书 CN（`TLS_ADMIN_CLIENTS`）
+#[derive(Debug, Clone, Default)]
+pub struct AdminClients(pub Vec<String>);
+
+impl AdminClients {
+    pub fn parse(s: &str) -> Self {
+        AdminClients(
+            s.split(',')
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+}
+
+/// 校验 `Authorization: Bearer <ADMIN_TOKEN>`，或 `TLS_ADMIN_CLIENTS` 中的客户端证书
+#[handler]
+async fn authorize(req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    let token = depot
+        .obtain::<AdminToken>()
+        .map(|t| t.0.clone())
+        .unwrap_or_default();
+    let clients = depot
+        .obtain::<AdminClients>()
+        .map(|c| c.0.clone())
+        .unwrap_or_default();
+    if !clients.is_empty()
+        && let Some(principal) = tls::principal(req)
+        && clients.iter().any(|c| c == principal.name())
+    {
+        return;
+    }
+    if token.is_empty() && clients.is_empty() {
+        res.render(StatusError::forbidden().brief("管理接口未启用"));
+        ctrl.skip_rest();
+        return;
+    }
+    let presented = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if token.is_empty() || !token_eq(presented.trim().as_bytes(), token.as_bytes()) {
+        res.headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        res.render(StatusError::unauthorized().brief("管理令牌无效"));
+        ctrl.skip_rest();
+    }
+}
+
+fn state(depot: &Depot) -> Result<(Arc<ReadOnly>, Arc<MountTable>), StatusError> {
+    match (
+        depot.obtain::<Arc<ReadOnly>>(),
+        depot.obtain::<Arc<MountTable>>(),
+    ) {
+        (Ok(state), Ok(mounts)) => Ok((state.clone(), mounts.clone())),
+        _ => Err(StatusError::internal_server_error().brief("只读状态未配置")),
+    }
+}
+
+/// `?mount=` 指定的挂载点名称，未指定时为 `None`（全部挂载点）
+fn target(req: &Request, mounts: &MountTable) -> Result<Option<String>, StatusError> {
+    match req.query::<String>("mount") {
+        None => Ok(None),
+        Some(key) => mounts
+            .find(&key)
+            .map(|m| Some(m.name.clone()))
+            .ok_or_else(|| StatusError::not_found().brief(format!("挂载点不存在: {}", key))),
+    }
+}
+
+/// 挂载点在报告和日志中的写法
+fn scope(mount: &Option<String>) -> String {
+    match mount {
+        Some(name) => format!("/{}", name),
+        None => "全部挂载点".to_string(),
+    }
+}
+
+/// GET /admin/read-only: 当前的只读状态
+#[handler]
+async fn show(depot: &mut Depot, res: &mut Response) -> Result<(), StatusError> {
+    let (state, mounts) = state(depot)?;
+    res.render(Json(state.report(&mounts)));
+    Ok(())
+}
+
+/// PUT /admin/read-only: 开始维护期
+///
+/// `?mount=` 只作用于一个挂载点（名称或前缀，`/` 为默认挂载点），否则作用于全部；
+/// `?retry_after=秒` 为返回给客户端的 `Retry-After`，`?reason=` 记录原因。
+#[handler]
+async fn enable(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let (state, mounts) = state(depot)?;
+    let mount = target(req, &mounts)?;
+    let reason = req
+        .query::<String>("reason")
+        .filter(|r| !r.is_empty())
+        .unwrap_or_else(|| "维护".to_string());
+    let retry_after = req
+        .query::<u64>("retry_after")
+        .unwrap_or(DEFAULT_RETRY_AFTER);
+    state.set(mount.as_deref(), Some(Window::new(&reason, retry_after)));
+    log::warn!("进入只读维护: {} ({})", scope(&mount), reason);
+    res.render(Json(state.report(&mounts)));
+    Ok(())
+}
+
+/// DELETE /admin/read-only: 结束维护期，`?mount=` 含义同上
+#[handler]
+async fn disable(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let (state, mounts) = state(depot)?;
+    let mount = target(req, &mounts)?;
+    state.set(mount.as_deref(), None);
+    log::warn!("结束只读维护: {}", scope(&mount));
+    res.render(Json(state.report(&mounts)));
+    Ok(())
+}
+
+/// 随机的 S3 访问密钥：`FSP` 加17位大写字母数字的 ID，40个字符的私钥
+fn generate_s3_key(description: &str) -> S3Key {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let random: Vec<u8> = (0..2)
+        .flat_map(|_| uuid::Uuid::new_v4().into_bytes())
+        .collect();
+    let id: String = random[..17]
+        .iter()
+        .map(|b| ALPHABET[(*b & 31) as usize] as char)
+        .collect();
+    let secret = STANDARD.encode(
+        (0..3)
+            .flat_map(|_| uuid::Uuid::new_v4().into_bytes())
+            .take(30)
+            .collect::<Vec<u8>>(),
+    );
+    S3Key::new(format!("FSP{}", id), secret, description)
+}
+
+/// GET /admin/s3-keys: 全部 S3 访问密钥，不含私钥
+#[handler]
+async fn list_s3_keys(depot: &mut Depot, res: &mut Response) -> Result<(), StatusError> {
+    let keys = db(depot)?.list_s3_keys().await.map_err(db_status)?;
+    res.render(Json(keys));
+    Ok(())
+}
+
+/// POST /admin/s3-keys: 生成新的访问密钥，私钥只在这次响应中返回；`?description=` 记录用途
+#[handler]
+async fn create_s3_key(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let description = req.query::<String>("description").unwrap_or_default();
+    let key = generate_s3_key(&description);
+    db(depot)?.insert_s3_key(&key).await.map_err(db_status)?;
+    log::warn!("创建S3访问密钥: {} ({})", key.access_key, key.description);
+    res.status_code(StatusCode::CREATED);
+    res.render(Json(key));
+    Ok(())
+}
+
+/// DELETE /admin/s3-keys/{access_key}: 吊销访问密钥
+#[handler]
+async fn delete_s3_key(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let access_key = req.param::<String>("access_key").unwrap_or_default();
+    if !db(depot)?
+        .delete_s3_key(&access_key)
+        .await
+        .map_err(db_status)?
+    {
+        return Err(StatusError::not_found().brief(format!("访问密钥不存在: {}", access_key)));
+    }
+    log::warn!("吊销S3访问密钥: {}", access_key);
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// 管理路由
+pub fn router() -> Router {
+    Router::with_path("admin")
+        .hoop(authorize)
+        .push(
+            Router::with_path("read-only")
+                .get(show)
+                .put(enable)
+                .delete(disable),
+        )
+        .push(
+            Router::with_path("s3-keys")
+                .get(list_s3_keys)
+                .post(create_s3_key)
+                .push(Router::with_path("{access_key}").delete(delete_s3_key)),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::ServerConfig;
+    use salvo::http::StatusCode;
+    use salvo::http::header::RETRY_AFTER;
+    use salvo::prelude::Service;
+    use salvo::test::{ResponseExt, TestClient};
+
+    #[test]
+    fn test_token_eq() {
+        assert!(token_eq(b"secret", b"secret"));
+        assert!(!token_eq(b"secret", b"secreT"));
+        assert!(!token_eq(b"secret", b"secret2"));
+        assert!(!token_eq(b"", b"secret"));
+    }
+
+    #[tokio::test]
+    async fn test_read_only_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).display().to_string();
+        std::fs::create_dir_all(dir.path().join("main")).unwrap();
+        std::fs::write(dir.path().join("main/x.txt"), "x").unwrap();
+        let config = ServerConfig {
+            root: path("main"),
+            mounts: format!("scratch={}", path("scratch")),
+            database_url: "sqlite::memory:".to_string(),
+            read_only: "scratch".to_string(),
+            admin_token: "secret".to_string(),
+            ..Default::default()
+        };
+        let service = Service::new(crate::web::create_router(&config).await.unwrap());
+        let admin = "http://127.0.0.1/admin/read-only";
+
+        // 配置中的挂载点启动即处于维护期
+        let res = TestClient::put("http://127.0.0.1/scratch/fs/a.txt")
+            .body("a")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(res.headers().get(RETRY_AFTER).unwrap(), "300");
+
+        // 管理接口需要令牌
+        let res = TestClient::put(admin).send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::UNAUTHORIZED));
+        let res = TestClient::put(admin)
+            .add_header(AUTHORIZATION, "Bearer wrong", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::UNAUTHORIZED));
+        let res = TestClient::put(format!("{}?mount=nope", admin))
+            .add_header(AUTHORIZATION, "Bearer secret", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+
+        // 全局维护：写入返回503，读取照常
+        let res = TestClient::put(format!("{}?retry_after=60&reason=backup", admin))
+            .add_header(AUTHORIZATION, "Bearer secret", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let res = TestClient::delete("http://127.0.0.1/fs/x.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(res.headers().get(RETRY_AFTER).unwrap(), "60");
+        let mut res = TestClient::get("http://127.0.0.1/fs/x.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "x");
+
+        let mut res = TestClient::get("http://127.0.0.1/health")
+            .send(&service)
+            .await;
+        let health: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(health["read_only"]["global"]["reason"], "backup");
+        assert_eq!(health["read_only"]["mounts"][1]["prefix"], "/scratch");
+        assert_eq!(
+            health["read_only"]["mounts"][1]["maintenance"]["reason"],
+            "配置"
+        );
+
+        // 结束全局维护后，只有 scratch 仍为只读
+        let res = TestClient::delete(admin)
+            .add_header(AUTHORIZATION, "Bearer secret", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let res = TestClient::put("http://127.0.0.1/fs/y.txt")
+            .body("y")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        let res = TestClient::put("http://127.0.0.1/scratch/fs/a.txt")
+            .body("a")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::SERVICE_UNAVAILABLE));
+        let mut res = TestClient::delete(format!("{}?mount=/scratch", admin))
+            .add_header(AUTHORIZATION, "Bearer secret", true)
+            .send(&service)
+            .await;
+        let report: serde_json::Value = res.take_json().await.unwrap();
+        assert!(report["global"].is_null());
+        assert!(report["mounts"][1]["maintenance"].is_null());
+        let res = TestClient::put("http://127.0.0.1/scratch/fs/a.txt?mkdirs=true")
+            .body("a")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+    }
+}
@@ -0,0 +1,421 @@
+use crate::sandbox::Sandbox;
+use crate::web::fs::{io_status, request_path, sandbox};
+use bytes::Bytes;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use salvo::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE, HeaderValue};
+use salvo::http::{StatusCode, StatusError};
+use salvo::{Depot, Request, Response, handler};
+use serde::Deserialize;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// 每次发送给客户端的数据块大小
+const CHUNK_SIZE: usize = 64 * 1024;
+/// 待发送数据块的最大数量，和 `CHUNK_SIZE` 一起限制内存占用
+const CHANNEL_CAPACITY: usize = 8;
+/// zstd 压缩级别
+const ZSTD_LEVEL: i32 = 3;
+
+/// 打包格式
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    #[serde(rename = "tar")]
+    Tar,
+    #[serde(rename = "tar.zst")]
+    TarZst,
+    #[serde(rename = "zip")]
+    Zip,
+}
+
+impl ArchiveFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarZst => "application/zstd",
+            ArchiveFormat::Zip => "application/zip",
+        }
+    }
+}
+
+/// 把写入的数据按块发送到响应流，客户端断开时返回 `BrokenPipe`
+struct ChannelWriter {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn new(tx: mpsc::Sender<io::Result<Bytes>>) -> Self {
+        ChannelWriter {
+            tx,
+            buf: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+        self.tx
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "客户端已断开"))
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(CHUNK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+/// 目录树中的一项
+enum Item {
+    Dir,
+    File(File, u64),
+    Symlink(PathBuf),
+}
+
+/// 打包器，按格式写入目录、文件和符号链接
+trait Packer {
+    fn add(&mut self, name: &str, metadata: &Metadata, item: Item) -> io::Result<()>;
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+/// 读取文件内容，长度固定为打包时记录的大小
+///
+/// 打包过程中文件被截断时补零，变长时截断，保证归档格式正确。
+fn fixed_reader(file: File, size: u64) -> impl Read {
+    file.take(size).chain(io::repeat(0)).take(size)
+}
+
+fn mtime_secs(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+struct TarPacker<W: Write> {
+    builder: tar::Builder<W>,
+}
+
+impl<W: Write> TarPacker<W> {
+    fn header(metadata: &Metadata, entry_type: tar::EntryType, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mode(crate::web::listing::file_mode(metadata));
+        header.set_mtime(mtime_secs(metadata));
+        header
+    }
+}
+
+impl<W: Write> Packer for TarPacker<W> {
+    fn add(&mut self, name: &str, metadata: &Metadata, item: Item) -> io::Result<()> {
+        match item {
+            Item::Dir => {
+                let mut header = Self::header(metadata, tar::EntryType::Directory, 0);
+                self.builder
+                    .append_data(&mut header, format!("{}/", name), io::empty())
+            }
+            Item::File(file, size) => {
+                let mut header = Self::header(metadata, tar::EntryType::Regular, size);
+                self.builder
+                    .append_data(&mut header, name, fixed_reader(file, size))
+            }
+            Item::Symlink(target) => {
+                let mut header = Self::header(metadata, tar::EntryType::Symlink, 0);
+                self.builder.append_link(&mut header, name, target)
+            }
+        }
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        let mut inner = self.builder.into_inner()?;
+        inner.flush()
+    }
+}
+
+struct TarZstPacker<W: Write> {
+    tar: TarPacker<zstd::Encoder<'static, W>>,
+}
+
+impl<W: Write> Packer for TarZstPacker<W> {
+    fn add(&mut self, name: &str, metadata: &Metadata, item: Item) -> io::Result<()> {
+        self.tar.add(name, metadata, item)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        let encoder = self.tar.builder.into_inner()?;
+        encoder.finish()?.flush()
+    }
+}
+
+struct ZipPacker<W: Write> {
+    writer: zip::ZipWriter<zip::write::StreamWriter<W>>,
+}
+
+impl<W: Write> ZipPacker<W> {
+    fn options(metadata: &Metadata) -> zip::write::SimpleFileOptions {
+        let mut options = zip::write::SimpleFileOptions::default()
+            .unix_permissions(crate::web::listing::file_mode(metadata));
+        let mtime = metadata.modified().ok().map(DateTime::<Utc>::from);
+        if let Some(time) = mtime.and_then(|t| {
+            zip::DateTime::from_date_and_time(
+                t.year() as u16,
+                t.month() as u8,
+                t.day() as u8,
+                t.hour() as u8,
+                t.minute() as u8,
+                t.second() as u8,
+            )
+            .ok()
+        }) {
+            options = options.last_modified_time(time);
+        }
+        options
+    }
+}
+
+impl<W: Write> Packer for ZipPacker<W> {
+    fn add(&mut self, name: &str, metadata: &Metadata, item: Item) -> io::Result<()> {
+        let options = Self::options(metadata);
+        match item {
+            Item::Dir => self.writer.add_directory(name, options)?,
+            Item::File(file, size) => {
+                // 压缩后可能略大于原文件，提前为接近 4GB 的文件启用 zip64
+                let options = options.large_file(size >= u32::MAX as u64 / 2);
+                self.writer.start_file(name, options)?;
+                io::copy(&mut fixed_reader(file, size), &mut self.writer)?;
+            }
+            Item::Symlink(target) => {
+                self.writer
+                    .add_symlink(name, target.to_string_lossy(), options)?
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.writer.finish()?.into_inner().flush()
+    }
+}
+
+fn packer(format: ArchiveFormat, writer: ChannelWriter) -> io::Result<Box<dyn Packer>> {
+    Ok(match format {
+        ArchiveFormat::Tar => Box::new(TarPacker {
+            builder: tar::Builder::new(writer),
+        }),
+        ArchiveFormat::TarZst => Box::new(TarZstPacker {
+            tar: TarPacker {
+                builder: tar::Builder::new(zstd::Encoder::new(writer, ZSTD_LEVEL)?),
+            },
+        }),
+        ArchiveFormat::Zip => Box::new(ZipPacker {
+            writer: zip::ZipWriter::new_stream(writer),
+        }),
+    })
+}
+
+/// 递归打包目录，规则与目录列表相同：跳过内部目录，默认跳过隐藏文件，不跟随符号链接
+fn pack_dir(
+    sandbox: &Sandbox,
+    dir: &Path,
+    prefix: &str,
+    hidden: bool,
+    packer: &mut dyn Packer,
+) -> io::Result<()> {
+    let mut entries = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(Result::ok).collect::<Vec<_>>(),
+        Err(e) => {
+            log::warn!("读取目录失败 {}: {}", dir.display(), e);
+            return Ok(());
+        }
+    };
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        if (!hidden && sandbox.is_hidden(&name)) || sandbox.is_excluded(&path) {
+            continue;
+        }
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                log::warn!("读取元数据失败 {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let archive_name = format!("{}/{}", prefix, name);
+        let item = if metadata.is_dir() {
+            Item::Dir
+        } else if metadata.file_type().is_symlink() {
+            match fs::read_link(&path) {
+                Ok(target) => Item::Symlink(target),
+                Err(e) => {
+                    log::warn!("读取符号链接失败 {}: {}", path.display(), e);
+                    continue;
+                }
+            }
+        } else if metadata.is_file() {
+            match File::open(&path) {
+                Ok(file) => Item::File(file, metadata.len()),
+                Err(e) => {
+                    log::warn!("打包跳过 {}: {}", path.display(), e);
+                    continue;
+                }
+            }
+        } else {
+            continue;
+        };
+        let is_dir = matches!(item, Item::Dir);
+        // 写出失败（如客户端断开）时终止整个打包
+        packer.add(&archive_name, &metadata, item)?;
+        if is_dir {
+            pack_dir(sandbox, &path, &archive_name, hidden, packer)?;
+        }
+    }
+    Ok(())
+}
+
+/// GET ?archive=zip|tar|tar.zst: 边打包边发送目录，不落盘
+#[handler]
+pub async fn download_dir(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let format = req
+        .query::<ArchiveFormat>("archive")
+        .ok_or_else(|| StatusError::bad_request().brief("不支持的打包格式"))?;
+    let hidden = req.query::<bool>("hidden").unwrap_or(false);
+    let dir = sandbox.resolve_existing(&rel)?;
+    let metadata = tokio::fs::metadata(&dir).await.map_err(io_status)?;
+    if !metadata.is_dir() {
+        return Err(StatusError::bad_request().brief(format!("不是目录: {}", rel)));
+    }
+
+    let name = match dir.file_name() {
+        Some(name) if dir != sandbox.root() => name.to_string_lossy().into_owned(),
+        _ => "root".to_string(),
+    };
+    let filename = format!("{}.{}", name, format.extension());
+    let disposition = format!(
+        "attachment; filename*=UTF-8''{}",
+        utf8_percent_encode(&filename, NON_ALPHANUMERIC)
+    );
+    if let Ok(v) = HeaderValue::from_str(&disposition) {
+        res.headers_mut().insert(CONTENT_DISPOSITION, v);
+    }
+    res.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    res.status_code(StatusCode::OK);
+    log::info!("打包下载: {} ({})", dir.display(), format.extension());
+
+    let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(CHANNEL_CAPACITY);
+    let sandbox = Arc::clone(&sandbox);
+    tokio::task::spawn_blocking(move || {
+        let writer = ChannelWriter::new(tx.clone());
+        let result = packer(format, writer).and_then(|mut packer| {
+            pack_dir(&sandbox, &dir, &name, hidden, packer.as_mut())?;
+            packer.finish()
+        });
+        if let Err(e) = result {
+            log::warn!("打包中断 {}: {}", dir.display(), e);
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    res.stream(futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(format: ArchiveFormat, root: &Path, hidden: bool) -> Vec<u8> {
+        let sandbox = Sandbox::new(root).unwrap();
+        let (tx, mut rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let dir = sandbox.root().to_path_buf();
+        let handle = std::thread::spawn(move || {
+            let mut packer = packer(format, ChannelWriter::new(tx)).unwrap();
+            pack_dir(&sandbox, &dir, "root", hidden, packer.as_mut()).unwrap();
+            packer.finish().unwrap();
+        });
+        let mut data = Vec::new();
+        while let Some(chunk) = rx.blocking_recv() {
+            data.extend_from_slice(&chunk.unwrap());
+        }
+        handle.join().unwrap();
+        data
+    }
+
+    fn tar_names(data: impl Read) -> Vec<String> {
+        tar::Archive::new(data)
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_pack_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("sub/b.txt"), vec![7u8; 200_000]).unwrap();
+        fs::write(root.join(".secret"), "s").unwrap();
+        fs::create_dir_all(root.join(".tus")).unwrap();
+        fs::write(root.join(".tus/x"), "x").unwrap();
+
+        let names = tar_names(collect(ArchiveFormat::Tar, root, false).as_slice());
+        assert_eq!(names, ["root/a.txt", "root/sub/", "root/sub/b.txt"]);
+
+        let data = collect(ArchiveFormat::TarZst, root, true);
+        let names = tar_names(zstd::Decoder::new(data.as_slice()).unwrap());
+        assert_eq!(
+            names,
+            ["root/.secret", "root/a.txt", "root/sub/", "root/sub/b.txt"]
+        );
+
+        let data = collect(ArchiveFormat::Zip, root, false);
+        let mut zip = zip::ZipArchive::new(io::Cursor::new(data)).unwrap();
+        assert_eq!(zip.len(), 3);
+        let mut content = Vec::new();
+        zip.by_name("root/sub/b.txt")
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content.len(), 200_000);
+    }
+}
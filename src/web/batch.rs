@@ -0,0 +1,531 @@
+use crate::db::versions::FileVersion;
+use crate::sandbox::journal::{Journal, Undo};
+use crate::sandbox::ops::{self, Conflict, OpError, Report};
+use crate::sandbox::{AtomicFile, Sandbox};
+use crate::web::extract::STAGING_DIR;
+use crate::web::fs::{client_addr, db, db_status, io_status, prepare_parent, sandbox};
+use crate::web::ops::{OpPathError, OpResult, replace, resolve_target, run};
+use crate::web::patch::{file_lock, release_lock};
+use crate::web::{locks, mounts, trash, versions};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use salvo::http::{StatusCode, StatusError};
+use salvo::prelude::Json;
+use salvo::{Depot, Request, Response, handler};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// 请求体的最大字节数
+const MAX_BODY: usize = 16 << 20;
+/// 单个批次的最大操作数
+const MAX_OPERATIONS: usize = 1000;
+/// `write` 操作的最大内容长度
+const MAX_WRITE: usize = 1 << 20;
+
+/// 批次中的一个操作
+#[derive(Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    Mkdir {
+        path: String,
+        #[serde(default)]
+        conflict: Conflict,
+    },
+    Move {
+        from: String,
+        to: String,
+        #[serde(default)]
+        conflict: Conflict,
+        #[serde(default)]
+        mkdirs: bool,
+    },
+    Copy {
+        from: String,
+        to: String,
+        #[serde(default)]
+        conflict: Conflict,
+        #[serde(default)]
+        mkdirs: bool,
+    },
+    Delete {
+        path: String,
+        #[serde(default)]
+        recursive: bool,
+        #[serde(default)]
+        permanent: bool,
+    },
+    /// 写入小文件，`base64` 为真时内容按 base64 解码
+    #[serde(alias = "write-small")]
+    Write {
+        path: String,
+        content: String,
+        #[serde(default)]
+        base64: bool,
+        #[serde(default)]
+        mkdirs: bool,
+    },
+}
+
+impl Operation {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Mkdir { .. } => "mkdir",
+            Operation::Move { .. } => "move",
+            Operation::Copy { .. } => "copy",
+            Operation::Delete { .. } => "delete",
+            Operation::Write { .. } => "write",
+        }
+    }
+
+    /// 结果中报告的路径，移动和复制为目标路径
+    fn path(&self) -> &str {
+        match self {
+            Operation::Mkdir { path, .. }
+            | Operation::Delete { path, .. }
+            | Operation::Write { path, .. } => path,
+            Operation::Move { to, .. } | Operation::Copy { to, .. } => to,
+        }
+    }
+}
+
+/// 单个操作的结果
+#[derive(Serialize, Debug)]
+pub struct StepResult {
+    pub op: &'static str,
+    pub path: String,
+    /// 与单独请求时相同的状态码；原子模式下因前面失败而未执行的为424
+    pub status: u16,
+    pub skipped: bool,
+    pub errors: Vec<OpPathError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trash_id: Option<String>,
+    /// 已执行但被回滚
+    pub rolled_back: bool,
+}
+
+impl StepResult {
+    fn new(op: &Operation, outcome: Result<OpResult, StatusError>) -> Self {
+        let mut result = StepResult {
+            op: op.name(),
+            path: op.path().trim_matches('/').to_string(),
+            status: StatusCode::OK.as_u16(),
+            skipped: false,
+            errors: Vec::new(),
+            error: None,
+            trash_id: None,
+            rolled_back: false,
+        };
+        match outcome {
+            Ok(r) => {
+                if !r.errors.is_empty() {
+                    result.status = StatusCode::MULTI_STATUS.as_u16();
+                }
+                result.path = r.path;
+                result.skipped = r.skipped;
+                result.errors = r.errors;
+                result.trash_id = r.trash_id;
+            }
+            Err(e) => {
+                result.status = e.code.as_u16();
+                result.error = e.brief.into();
+            }
+        }
+        result
+    }
+
+    fn ok(&self) -> bool {
+        self.status == StatusCode::OK.as_u16()
+    }
+}
+
+/// 批次的执行状态，原子模式下带回滚日志
+struct Batch<'a> {
+    depot: &'a Depot,
+    req: &'a Request,
+    sandbox: &'a Sandbox,
+    client: String,
+    journal: Option<Journal>,
+    /// 回滚时要删除的回收站记录
+    trashed: Vec<String>,
+    /// 回滚时要丢弃的版本
+    versions: Vec<FileVersion>,
+}
+
+impl Batch<'_> {
+    /// 确保父目录存在，原子模式下先记录将要创建的目录
+    async fn prepare_parent(&mut self, path: &Path, mkdirs: bool) -> Result<(), StatusError> {
+        if mkdirs && let (Some(journal), Some(parent)) = (self.journal.as_mut(), path.parent()) {
+            journal.record_missing_dirs(parent).map_err(io_status)?;
+        }
+        prepare_parent(path, mkdirs).await
+    }
+
+    /// 按冲突策略处理已存在的目标，返回是否继续；原子模式下被覆盖的目标先移入日志
+    fn take_destination(&mut self, dst: &Path, conflict: Conflict) -> Result<bool, StatusError> {
+        let Some(journal) = self.journal.as_mut() else {
+            return Ok(true);
+        };
+        if std::fs::symlink_metadata(dst).is_err() {
+            return Ok(true);
+        }
+        match conflict {
+            Conflict::Fail => Err(OpError::Exists.into()),
+            Conflict::Skip => Ok(false),
+            Conflict::Overwrite => {
+                journal.set_aside(dst).map_err(io_status)?;
+                Ok(true)
+            }
+        }
+    }
+
+    async fn execute(&mut self, op: &Operation) -> Result<OpResult, StatusError> {
+        match op {
+            Operation::Mkdir { path, conflict } => self.mkdir(path, *conflict).await,
+            Operation::Move {
+                from,
+                to,
+                conflict,
+                mkdirs,
+            } => self.transfer(from, to, *conflict, *mkdirs, false).await,
+            Operation::Copy {
+                from,
+                to,
+                conflict,
+                mkdirs,
+            } => self.transfer(from, to, *conflict, *mkdirs, true).await,
+            Operation::Delete {
+                path,
+                recursive,
+                permanent,
+            } => self.delete(path, *recursive, *permanent).await,
+            Operation::Write {
+                path,
+                content,
+                base64,
+                mkdirs,
+            } => {
+                let data = match base64 {
+                    true => STANDARD
+                        .decode(content)
+                        .map_err(|_| StatusError::bad_request().brief("无效的 base64 内容"))?,
+                    false => content.as_bytes().to_vec(),
+                };
+                self.write(path, &data, *mkdirs).await
+            }
+        }
+    }
+
+    async fn mkdir(&mut self, rel: &str, conflict: Conflict) -> Result<OpResult, StatusError> {
+        let path = resolve_target(self.sandbox, rel)?;
+        locks::require(self.depot, self.req, self.sandbox, &path, false).await?;
+        if let Some(journal) = self.journal.as_mut() {
+            journal.record_missing_dirs(&path).map_err(io_status)?;
+        }
+        let target = path.clone();
+        let report = run(move || ops::mkdir(&target, conflict)).await?;
+        Ok(OpResult::new(self.sandbox, &path, report))
+    }
+
+    async fn transfer(
+        &mut self,
+        from: &str,
+        to: &str,
+        conflict: Conflict,
+        mkdirs: bool,
+        copy: bool,
+    ) -> Result<OpResult, StatusError> {
+        let src = resolve_target(self.sandbox, from)?;
+        if tokio::fs::symlink_metadata(&src).await.is_err() {
+            return Err(StatusError::not_found().brief(format!("路径不存在: {}", from)));
+        }
+        let dst = resolve_target(self.sandbox, to)?;
+        if !copy {
+            locks::require(self.depot, self.req, self.sandbox, &src, true).await?;
+        }
+        locks::require(self.depot, self.req, self.sandbox, &dst, true).await?;
+        self.prepare_parent(&dst, mkdirs).await?;
+        if !self.take_destination(&dst, conflict)? {
+            return Ok(OpResult::new(
+                self.sandbox,
+                &dst,
+                Report {
+                    skipped: true,
+                    ..Default::default()
+                },
+            ));
+        }
+
+        let conflict = match self.journal.as_mut() {
+            Some(journal) => {
+                let undo = match copy {
+                    true => Undo::Remove { path: dst.clone() },
+                    false => Undo::Rename {
+                        from: dst.clone(),
+                        to: src.clone(),
+                    },
+                };
+                journal.record(undo).map_err(io_status)?;
+                Conflict::Fail
+            }
+            None => conflict,
+        };
+        // 非原子模式下被覆盖的目标移入回收站，操作失败时放回
+        let (source, target) = (src.clone(), dst.clone());
+        let report = replace(
+            self.depot,
+            self.sandbox,
+            &dst,
+            conflict,
+            self.client.clone(),
+            move |conflict| match copy {
+                true => ops::copy(&source, &target, conflict),
+                false => ops::rename(&source, &target, conflict),
+            },
+        )
+        .await?;
+        Ok(OpResult::new(self.sandbox, &dst, report))
+    }
+
+    async fn delete(
+        &mut self,
+        rel: &str,
+        recursive: bool,
+        permanent: bool,
+    ) -> Result<OpResult, StatusError> {
+        let path = resolve_target(self.sandbox, rel)?;
+        locks::require(self.depot, self.req, self.sandbox, &path, true).await?;
+        let metadata = tokio::fs::symlink_metadata(&path)
+            .await
+            .map_err(io_status)?;
+
+        if !permanent {
+            let db = db(self.depot)?;
+            let (item, report) =
+                trash::move_to_trash(self.sandbox, &db, &path, recursive, self.client.clone())
+                    .await?;
+            let from = trash::trash_path(self.sandbox, &item.id)?;
+            if let Some(journal) = self.journal.as_mut() {
+                self.trashed.push(item.id.clone());
+                journal
+                    .record(Undo::Rename {
+                        from,
+                        to: path.clone(),
+                    })
+                    .map_err(io_status)?;
+            }
+            return Ok(OpResult {
+                trash_id: Some(item.id),
+                ..OpResult::new(self.sandbox, &path, report)
+            });
+        }
+
+        let Some(journal) = self.journal.as_mut() else {
+            let target = path.clone();
+            let report = run(move || ops::remove(&target, recursive)).await?;
+            return Ok(OpResult::new(self.sandbox, &path, report));
+        };
+        // 原子模式下只移入日志目录，提交时才真正删除
+        if metadata.is_dir()
+            && !recursive
+            && std::fs::read_dir(&path)
+                .map_err(io_status)?
+                .next()
+                .is_some()
+        {
+            return Err(OpError::NotEmpty.into());
+        }
+        journal.set_aside(&path).map_err(io_status)?;
+        Ok(OpResult::new(self.sandbox, &path, Report::default()))
+    }
+
+    async fn write(
+        &mut self,
+        rel: &str,
+        data: &[u8],
+        mkdirs: bool,
+    ) -> Result<OpResult, StatusError> {
+        if data.len() > MAX_WRITE {
+            return Err(
+                StatusError::payload_too_large().brief(format!("写入内容超过 {} 字节", MAX_WRITE))
+            );
+        }
+        mounts::check_file_size(self.depot, data.len() as u64)?;
+        let path = self.sandbox.resolve(rel)?;
+        if path == self.sandbox.root() {
+            return Err(StatusError::method_not_allowed().brief("不能写入根目录"));
+        }
+        if tokio::fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+            return Err(StatusError::conflict().brief(format!("目标是目录: {}", rel)));
+        }
+        locks::require(self.depot, self.req, self.sandbox, &path, false).await?;
+        self.prepare_parent(&path, mkdirs).await?;
+
+        let lock = file_lock(&path);
+        let result = async {
+            let _guard = lock.lock().await;
+            let saved = match tokio::fs::symlink_metadata(&path).await {
+                Ok(_) => {
+                    versions::snapshot(self.depot, self.sandbox, &path, true, self.client.clone())
+                        .await?
+                }
+                Err(_) => None,
+            };
+            if let Some(journal) = self.journal.as_mut() {
+                if let Some(saved) = saved.clone() {
+                    self.versions.push(saved);
+                }
+                journal.set_aside(&path).map_err(io_status)?;
+                journal
+                    .record(Undo::Remove { path: path.clone() })
+                    .map_err(io_status)?;
+            }
+            let written = async {
+                let mut file = AtomicFile::create(&path).await?;
+                file.write(data).await?;
+                file.commit().await
+            }
+            .await
+            .map_err(io_status);
+            if written.is_err()
+                && self.journal.is_none()
+                && let Some(saved) = saved
+            {
+                versions::discard(self.depot, self.sandbox, &saved).await;
+            }
+            written
+        }
+        .await;
+        release_lock(&path, lock);
+        result?;
+        Ok(OpResult::new(self.sandbox, &path, Report::default()))
+    }
+
+    /// 回滚已执行的步骤，返回无法撤销的路径
+    async fn rollback(&mut self, journal: Journal) -> Vec<PathBuf> {
+        let errors = tokio::task::spawn_blocking(move || journal.rollback())
+            .await
+            .unwrap_or_default();
+        if let Ok(db) = db(self.depot) {
+            for id in &self.trashed {
+                if let Err(e) = db.delete_trash_item(id).await.map_err(db_status) {
+                    log::warn!("删除回收站记录失败 {}: {:?}", id, e.brief);
+                }
+            }
+        }
+        for version in &self.versions {
+            versions::discard(self.depot, self.sandbox, version).await;
+        }
+        errors.into_iter().map(|e| e.path).collect()
+    }
+}
+
+/// 批量操作的响应
+#[derive(Serialize, Debug)]
+pub struct BatchResult {
+    pub atomic: bool,
+    /// 原子模式下是否全部生效；非原子模式下是否全部成功
+    pub committed: bool,
+    pub results: Vec<StepResult>,
+    /// 回滚失败、可能处于中间状态的路径
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rollback_errors: Vec<String>,
+}
+
+/// POST /batch: 按顺序执行一组操作
+///
+/// 请求体为操作数组，如 `[{"op":"mkdir","path":"a"},{"op":"write","path":"a/x","content":"..."}]`，
+/// 支持 mkdir、move、copy、delete 和 write（小文件）。默认失败后继续执行其余操作；
+/// `?atomic=true` 时遇到失败即停止，并按回滚日志撤销已完成的步骤。
+/// 全部成功返回200，否则返回207，每个操作的结果按顺序列出。
+#[handler]
+pub async fn batch(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let atomic = req.query::<bool>("atomic").unwrap_or(false);
+    let operations = req
+        .parse_json_with_max_size::<Vec<Operation>>(MAX_BODY)
+        .await
+        .map_err(|e| StatusError::bad_request().brief(format!("无效的操作列表: {}", e)))?;
+    if operations.len() > MAX_OPERATIONS {
+        return Err(
+            StatusError::payload_too_large().brief(format!("操作数超过 {}", MAX_OPERATIONS))
+        );
+    }
+
+    let journal = match atomic {
+        true => {
+            let staging = sandbox.internal_dir(STAGING_DIR).map_err(io_status)?;
+            Some(Journal::begin(&staging).map_err(io_status)?)
+        }
+        false => None,
+    };
+    let mut state = Batch {
+        depot,
+        req,
+        sandbox: &sandbox,
+        client: client_addr(req),
+        journal,
+        trashed: Vec::new(),
+        versions: Vec::new(),
+    };
+    let mut results: Vec<StepResult> = Vec::with_capacity(operations.len());
+    let mut failed = false;
+    for op in &operations {
+        if failed && atomic {
+            results.push(StepResult::new(
+                op,
+                Err(StatusError::failed_dependency().brief("前面的操作失败，未执行")),
+            ));
+            continue;
+        }
+        let result = StepResult::new(op, state.execute(op).await);
+        failed |= !result.ok();
+        results.push(result);
+    }
+
+    let mut rollback_errors = Vec::new();
+    if let Some(journal) = state.journal.take() {
+        if failed {
+            rollback_errors = state
+                .rollback(journal)
+                .await
+                .iter()
+                .map(|p| {
+                    sandbox
+                        .relative(p)
+                        .unwrap_or_else(|| p.display().to_string())
+                })
+                .collect();
+            let mut rolled_back = 0;
+            for result in results.iter_mut().filter(|r| r.ok()) {
+                result.rolled_back = true;
+                rolled_back += 1;
+            }
+            log::warn!("批量操作失败，已回滚 {} 个操作", rolled_back);
+        } else if let Err(e) = journal.commit() {
+            log::warn!("删除回滚日志失败: {}", e);
+        }
+    }
+    log::info!(
+        "批量操作: {} 个, {}",
+        operations.len(),
+        if failed { "有失败" } else { "全部成功" }
+    );
+
+    res.status_code(match failed {
+        true => StatusCode::MULTI_STATUS,
+        false => StatusCode::OK,
+    });
+    res.render(Json(BatchResult {
+        atomic,
+        committed: !failed,
+        results,
+        rollback_errors,
+    }));
+    Ok(())
+}
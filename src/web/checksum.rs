@@ -0,0 +1,423 @@
+use crate::db::Db;
+use crate::db::checksum::Fingerprint;
+use crate::digest::{self, Algorithm, MultiHasher};
+use crate::sandbox::Sandbox;
+use crate::web::fs::{io_status, request_path, sandbox, set_etag};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use lazy_static::lazy_static;
+use salvo::http::header::{HeaderMap, HeaderName, HeaderValue};
+use salvo::http::{StatusCode, StatusError};
+use salvo::prelude::Json;
+use salvo::{Depot, Request, Response, handler};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const CONTENT_DIGEST: HeaderName = HeaderName::from_static("content-digest");
+pub const REPR_DIGEST: HeaderName = HeaderName::from_static("repr-digest");
+const WANT_CONTENT_DIGEST: &str = "want-content-digest";
+const WANT_REPR_DIGEST: &str = "want-repr-digest";
+
+/// 未指定算法时使用的摘要
+const DEFAULT_ALGORITHM: Algorithm = Algorithm::Sha256;
+/// 下载时在后台计算摘要的文件大小上限，更大的文件只返回已缓存的摘要
+const AUTO_DIGEST_MAX: u64 = 64 * 1024 * 1024;
+
+lazy_static! {
+    /// 正在后台计算摘要的文件
+    static ref PENDING: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+/// 一个算法的摘要
+#[derive(Serialize, Debug)]
+pub struct DigestValue {
+    pub algorithm: &'static str,
+    pub hex: String,
+    pub base64: String,
+    /// 是否来自缓存
+    pub cached: bool,
+}
+
+/// GET ?checksum 的响应
+#[derive(Serialize, Debug)]
+pub struct ChecksumResult {
+    pub path: String,
+    pub size: u64,
+    pub checksums: Vec<DigestValue>,
+}
+
+/// 已取得的摘要
+struct Computed {
+    algorithm: Algorithm,
+    digest: Vec<u8>,
+    cached: bool,
+}
+
+fn pairs(computed: &[Computed]) -> Vec<(Algorithm, Vec<u8>)> {
+    computed
+        .iter()
+        .map(|c| (c.algorithm, c.digest.clone()))
+        .collect()
+}
+
+/// 取得文件摘要
+///
+/// 先按 路径 + inode + 大小 + 修改时间 查缓存；`compute` 为真时把未命中的算法一次读完文件算出并写回缓存，
+/// 否则只返回命中的部分。计算期间文件发生变化时不写缓存。
+async fn file_checksums(
+    db: Option<&Db>,
+    sandbox: &Sandbox,
+    path: &Path,
+    metadata: &Metadata,
+    algorithms: &[Algorithm],
+    compute: bool,
+) -> Result<Vec<Computed>, StatusError> {
+    let rel = sandbox.relative(path).unwrap_or_default();
+    let fingerprint = Fingerprint::new(metadata);
+    let mut computed = Vec::with_capacity(algorithms.len());
+    let mut missing = Vec::new();
+    for &algorithm in algorithms {
+        let cached = match db {
+            Some(db) => db
+                .get_checksum(sandbox.mount(), &rel, algorithm, fingerprint)
+                .await
+                .unwrap_or_else(|e| {
+                    log::warn!("读取摘要缓存失败 {}: {}", rel, e);
+                    None
+                }),
+            None => None,
+        };
+        match cached {
+            Some(digest) => computed.push(Computed {
+                algorithm,
+                digest,
+                cached: true,
+            }),
+            None => missing.push(algorithm),
+        }
+    }
+    if missing.is_empty() || !compute {
+        return Ok(computed);
+    }
+
+    let task_path = path.to_path_buf();
+    let task_algorithms = missing.clone();
+    let digests =
+        tokio::task::spawn_blocking(move || digest::file_digests(&task_path, &task_algorithms))
+            .await
+            .map_err(|e| StatusError::internal_server_error().brief(e.to_string()))?
+            .map_err(io_status)?;
+    log::debug!("计算摘要: {} {:?}", path.display(), missing);
+
+    let unchanged = tokio::fs::metadata(path)
+        .await
+        .is_ok_and(|m| Fingerprint::new(&m) == fingerprint);
+    for (algorithm, digest) in digests {
+        if let Some(db) = db
+            && unchanged
+        {
+            store(db, sandbox.mount(), &rel, algorithm, fingerprint, &digest).await;
+        }
+        computed.push(Computed {
+            algorithm,
+            digest,
+            cached: false,
+        });
+    }
+    computed.sort_by_key(|c| algorithms.iter().position(|a| *a == c.algorithm));
+    Ok(computed)
+}
+
+async fn store(
+    db: &Db,
+    mount: &str,
+    rel: &str,
+    algorithm: Algorithm,
+    fingerprint: Fingerprint,
+    digest: &[u8],
+) {
+    if let Err(e) = db
+        .put_checksum(mount, rel, algorithm, fingerprint, digest)
+        .await
+    {
+        log::warn!("写入摘要缓存失败 {}: {}", rel, e);
+    }
+}
+
+fn set_digest(res: &mut Response, name: HeaderName, digests: &[(Algorithm, Vec<u8>)]) {
+    if digests.is_empty() {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(&digest::format_digest_fields(digests)) {
+        res.headers_mut().insert(name, value);
+    }
+}
+
+/// 解析 `?checksum=sha256,blake3`，为空时使用默认算法
+fn parse_algorithms(value: &str) -> Result<Vec<Algorithm>, StatusError> {
+    let mut algorithms: Vec<Algorithm> = Vec::new();
+    for name in value.split(',').filter(|s| !s.trim().is_empty()) {
+        let algorithm = name
+            .parse::<Algorithm>()
+            .map_err(|e| StatusError::bad_request().brief(e))?;
+        if !algorithms.contains(&algorithm) {
+            algorithms.push(algorithm);
+        }
+    }
+    if algorithms.is_empty() {
+        algorithms.push(DEFAULT_ALGORITHM);
+    }
+    Ok(algorithms)
+}
+
+/// GET ?checksum=算法: 计算文件摘要
+///
+/// 支持 sha256、blake3、md5、crc32c（以及 sha1），多个算法用逗号分隔，默认 sha256。
+/// 结果按文件特征缓存在数据库中，文件未变时直接返回。
+#[handler]
+pub async fn checksum(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = depot.obtain::<Db>().ok().cloned();
+    let rel = request_path(req);
+    let path = sandbox.resolve_existing(&rel)?;
+    let algorithms = parse_algorithms(&req.query::<String>("checksum").unwrap_or_default())?;
+    let metadata = tokio::fs::metadata(&path).await.map_err(io_status)?;
+    if !metadata.is_file() {
+        return Err(StatusError::bad_request().brief(format!("不是文件: {}", rel)));
+    }
+
+    let computed =
+        file_checksums(db.as_ref(), &sandbox, &path, &metadata, &algorithms, true).await?;
+    set_digest(res, REPR_DIGEST, &pairs(&computed));
+    set_etag(&path, res).await;
+    res.render(Json(ChecksumResult {
+        path: sandbox.relative(&path).unwrap_or_default(),
+        size: metadata.len(),
+        checksums: computed
+            .into_iter()
+            .map(|c| DigestValue {
+                algorithm: c.algorithm.name(),
+                hex: digest::hex(&c.digest),
+                base64: STANDARD.encode(&c.digest),
+                cached: c.cached,
+            })
+            .collect(),
+    }));
+    Ok(())
+}
+
+/// 为下载响应加上 `Repr-Digest`，完整内容时同时加上 `Content-Digest`
+///
+/// 算法取自 `Want-Repr-Digest` / `Want-Content-Digest`，未指定时使用 sha-256。
+/// 下载路径上只使用已缓存的摘要，不为响应读一遍文件；缺少的摘要在不超过大小上限时交给后台计算并写入缓存，
+/// 之后的下载即可带上，需要立即取得时用 `?checksum`。
+pub async fn digest_headers(
+    req: &Request,
+    depot: &Depot,
+    res: &mut Response,
+    sandbox: &Arc<Sandbox>,
+    path: &Path,
+    metadata: &Metadata,
+) {
+    let full = match res.status_code {
+        Some(StatusCode::OK) | None => true,
+        Some(StatusCode::PARTIAL_CONTENT) => false,
+        Some(_) => return,
+    };
+    let want = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(digest::parse_want_digest)
+            .unwrap_or_default()
+    };
+    let mut algorithms = want(WANT_REPR_DIGEST);
+    for algorithm in want(WANT_CONTENT_DIGEST) {
+        if !algorithms.contains(&algorithm) {
+            algorithms.push(algorithm);
+        }
+    }
+    if algorithms.is_empty() {
+        algorithms.push(DEFAULT_ALGORITHM);
+    }
+
+    // 没有数据库就没有缓存，也不必在后台计算
+    let Ok(db) = depot.obtain::<Db>() else {
+        return;
+    };
+    let computed = match file_checksums(Some(db), sandbox, path, metadata, &algorithms, false).await
+    {
+        Ok(computed) => computed,
+        Err(e) => {
+            log::warn!("读取摘要失败 {}: {:?}", path.display(), e.brief);
+            return;
+        }
+    };
+    if computed.len() < algorithms.len() && metadata.len() <= AUTO_DIGEST_MAX {
+        compute_later(db.clone(), sandbox.clone(), path, metadata, algorithms);
+    }
+    let digests = pairs(&computed);
+    set_digest(res, REPR_DIGEST, &digests);
+    // 没有内容编码，完整响应的内容摘要与表示摘要相同
+    if full {
+        set_digest(res, CONTENT_DIGEST, &digests);
+    }
+}
+
+/// 在后台计算并缓存摘要，同一文件同时只算一次
+fn compute_later(
+    db: Db,
+    sandbox: Arc<Sandbox>,
+    path: &Path,
+    metadata: &Metadata,
+    algorithms: Vec<Algorithm>,
+) {
+    let path = path.to_path_buf();
+    if !PENDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(path.clone())
+    {
+        return;
+    }
+    let metadata = metadata.clone();
+    tokio::spawn(async move {
+        if let Err(e) =
+            file_checksums(Some(&db), &sandbox, &path, &metadata, &algorithms, true).await
+        {
+            log::warn!("计算摘要失败 {}: {:?}", path.display(), e.brief);
+        }
+        PENDING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&path);
+    });
+}
+
+/// 上传时客户端给出的摘要（`Content-Digest` 和 `Repr-Digest`）
+pub fn expected_digests(headers: &HeaderMap) -> Result<Vec<(Algorithm, Vec<u8>)>, StatusError> {
+    let mut expected = Vec::new();
+    for name in [CONTENT_DIGEST, REPR_DIGEST] {
+        for value in headers.get_all(name) {
+            let value = value
+                .to_str()
+                .map_err(|_| StatusError::bad_request().brief("无效的摘要字段"))?;
+            expected.extend(
+                digest::parse_digest_fields(value)
+                    .map_err(|e| StatusError::bad_request().brief(e))?,
+            );
+        }
+    }
+    Ok(expected)
+}
+
+/// 边写入边计算客户端给出的摘要，提交前校验
+pub struct UploadVerifier {
+    expected: Vec<(Algorithm, Vec<u8>)>,
+    hasher: Option<MultiHasher>,
+}
+
+impl UploadVerifier {
+    pub fn new(expected: Vec<(Algorithm, Vec<u8>)>) -> Self {
+        Self::with_extra(expected, &[])
+    }
+
+    /// 除了校验 `expected`，还计算 `extra` 中的摘要
+    pub fn with_extra(expected: Vec<(Algorithm, Vec<u8>)>, extra: &[Algorithm]) -> Self {
+        let mut algorithms: Vec<Algorithm> = extra.to_vec();
+        for (algorithm, _) in &expected {
+            if !algorithms.contains(algorithm) {
+                algorithms.push(*algorithm);
+            }
+        }
+        let hasher = (!algorithms.is_empty()).then(|| MultiHasher::new(&algorithms));
+        UploadVerifier { expected, hasher }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(data);
+        }
+    }
+
+    /// 校验摘要，成功时返回算出的摘要
+    pub fn verify(self) -> Result<Vec<(Algorithm, Vec<u8>)>, String> {
+        let Some(hasher) = self.hasher else {
+            return Ok(Vec::new());
+        };
+        let actual = hasher.finalize();
+        for (algorithm, expected) in &self.expected {
+            if actual
+                .iter()
+                .any(|(a, digest)| a == algorithm && digest != expected)
+            {
+                return Err(format!("{} 摘要不匹配", algorithm.http_name()));
+            }
+        }
+        Ok(actual)
+    }
+}
+
+/// 上传提交后缓存已校验的摘要并在响应中返回
+pub async fn remember_upload(
+    depot: &Depot,
+    res: Option<&mut Response>,
+    sandbox: &Sandbox,
+    path: &Path,
+    digests: &[(Algorithm, Vec<u8>)],
+) {
+    if digests.is_empty() {
+        return;
+    }
+    if let Some(res) = res {
+        set_digest(res, REPR_DIGEST, digests);
+    }
+    let (Ok(db), Ok(metadata)) = (depot.obtain::<Db>(), tokio::fs::metadata(path).await) else {
+        return;
+    };
+    let rel = sandbox.relative(path).unwrap_or_default();
+    let fingerprint = Fingerprint::new(&metadata);
+    for (algorithm, digest) in digests {
+        store(db, sandbox.mount(), &rel, *algorithm, fingerprint, digest).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_verifier() {
+        let sha = |data: &[u8]| {
+            let mut hasher = Algorithm::Sha256.hasher();
+            hasher.update(data);
+            hasher.finalize()
+        };
+        let mut verifier = UploadVerifier::new(vec![(Algorithm::Sha256, sha(b"hello"))]);
+        verifier.update(b"hel");
+        verifier.update(b"lo");
+        assert_eq!(
+            verifier.verify().unwrap(),
+            vec![(Algorithm::Sha256, sha(b"hello"))]
+        );
+
+        let mut verifier = UploadVerifier::new(vec![(Algorithm::Sha256, sha(b"hello"))]);
+        verifier.update(b"world");
+        assert!(verifier.verify().is_err());
+
+        assert!(UploadVerifier::new(Vec::new()).verify().unwrap().is_empty());
+        assert_eq!(
+            parse_algorithms("blake3, crc32c,blake3").unwrap(),
+            vec![Algorithm::Blake3, Algorithm::Crc32c]
+        );
+        assert_eq!(parse_algorithms("").unwrap(), vec![Algorithm::Sha256]);
+        assert!(parse_algorithms("sha512").is_err());
+    }
+}
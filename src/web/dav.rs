@@ -0,0 +1,1057 @@
+use crate::dav::condition::{IfHeader, State};
+use crate::dav::request::{self, PropFind, PropUpdate};
+use crate::dav::response::{self, Multistatus, property};
+use crate::dav::{PropName, escape};
+use crate::db::Db;
+use crate::db::dav::{DeadProperty, PropertyChange};
+use crate::lock::{Depth, Grant, Lock, LockManager, Scope, TOKEN_PREFIX};
+use crate::sandbox::Sandbox;
+use crate::sandbox::ops::{self, Conflict, OpError, Report};
+use crate::web::fs::{
+    client_addr, db, db_status, io_status, prepare_parent, request_path, sandbox,
+};
+use crate::web::listing::SEGMENT;
+use crate::web::locks::{DEFAULT_TIMEOUT, LOCK_TOKEN, MAX_TIMEOUT, lock_status, locks};
+use crate::web::ops::{replace, resolve_target};
+use crate::web::{fs, mounts, precondition, trash};
+use chrono::{DateTime, SecondsFormat, Utc};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use salvo::http::header::{ALLOW, CONTENT_TYPE, HeaderName, HeaderValue};
+use salvo::http::{StatusCode, StatusError};
+use salvo::{Depot, FlowCtrl, Handler, Request, Response, Router, handler};
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+
+/// 支持的方法
+const METHODS: &str =
+    "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, PROPPATCH, MKCOL, COPY, MOVE, LOCK, UNLOCK";
+/// XML 请求体的最大字节数
+const MAX_BODY: usize = 1 << 20;
+/// 一次 PROPFIND 最多返回的资源数，超过时拒绝 `Depth: infinity`
+const MAX_RESOURCES: usize = 10_000;
+/// 由服务器维护、不能通过 PROPPATCH 修改的属性
+const LIVE_PROPERTIES: [&str; 9] = [
+    "creationdate",
+    "displayname",
+    "getcontentlength",
+    "getcontenttype",
+    "getetag",
+    "getlastmodified",
+    "lockdiscovery",
+    "resourcetype",
+    "supportedlock",
+];
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+const DAV: HeaderName = HeaderName::from_static("dav");
+const DEPTH: HeaderName = HeaderName::from_static("depth");
+const DESTINATION: HeaderName = HeaderName::from_static("destination");
+const OVERWRITE: HeaderName = HeaderName::from_static("overwrite");
+const TIMEOUT: HeaderName = HeaderName::from_static("timeout");
+const IF: HeaderName = HeaderName::from_static("if");
+
+/// 挂载点下 WebDAV 资源的URL前缀，如 `/dav/` 或 `/scratch/dav/`
+fn base(depot: &Depot) -> String {
+    let prefix = mounts::mount(depot)
+        .map(|m| m.prefix())
+        .unwrap_or_else(|| "/".to_string());
+    format!("{}/dav/", prefix.trim_end_matches('/'))
+}
+
+/// 相对路径对应的 href，集合以 `/` 结尾
+fn href(base: &str, rel: &str, collection: bool) -> String {
+    let mut href = base.to_string();
+    let segments: Vec<String> = rel
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| utf8_percent_encode(s, SEGMENT).to_string())
+        .collect();
+    href.push_str(&segments.join("/"));
+    if collection && !segments.is_empty() {
+        href.push('/');
+    }
+    href
+}
+
+/// 把 `Destination` 或 `If` 中的URL转换为挂载点内的相对路径，不属于 `base` 时返回 `None`
+fn local_path(base: &str, uri: &str) -> Option<String> {
+    let path = match uri.find("://") {
+        Some(i) => {
+            let rest = &uri[i + 3..];
+            rest.find('/').map(|j| &rest[j..]).unwrap_or("/")
+        }
+        None => uri,
+    };
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let path = percent_decode_str(path).decode_utf8().ok()?;
+    let rel = match path.strip_prefix(base) {
+        Some(rel) => rel,
+        None if path == base.trim_end_matches('/') => "",
+        None => return None,
+    };
+    Some(rel.trim_matches('/').to_string())
+}
+
+fn header<'a>(req: &'a Request, name: &HeaderName) -> Option<&'a str> {
+    req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+/// `Depth` 头：`0`、`1` 或 `infinity`（默认），返回最大深度
+fn depth(req: &Request) -> Result<usize, StatusError> {
+    match header(req, &DEPTH).map(str::trim) {
+        None => Ok(usize::MAX),
+        Some("0") => Ok(0),
+        Some("1") => Ok(1),
+        Some(d) if d.eq_ignore_ascii_case("infinity") => Ok(usize::MAX),
+        Some(d) => Err(StatusError::bad_request().brief(format!("无效的 Depth: {}", d))),
+    }
+}
+
+/// `Timeout` 头：`Second-N` 或 `Infinite`，取第一个可识别的值
+fn timeout(req: &Request) -> u64 {
+    header(req, &TIMEOUT)
+        .into_iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .find_map(|t| {
+            if t.eq_ignore_ascii_case("infinite") {
+                Some(MAX_TIMEOUT)
+            } else {
+                t.strip_prefix("Second-")?.parse::<u64>().ok()
+            }
+        })
+        .unwrap_or(DEFAULT_TIMEOUT)
+        .clamp(1, MAX_TIMEOUT)
+}
+
+/// 读取 XML 请求体
+async fn body(req: &mut Request) -> Result<Vec<u8>, StatusError> {
+    req.payload_with_max_size(MAX_BODY)
+        .await
+        .map(|b| b.to_vec())
+        .map_err(|e| StatusError::payload_too_large().brief(e.to_string()))
+}
+
+fn render_xml(res: &mut Response, status: StatusCode, body: String) {
+    res.status_code(status);
+    res.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(XML_CONTENT_TYPE));
+    res.write_body(body).ok();
+}
+
+fn bad_xml(e: String) -> StatusError {
+    StatusError::bad_request().brief(format!("无效的 XML 请求体: {}", e))
+}
+
+/// 作用于 `rel` 的锁
+fn covering<'a>(locks: &'a [Lock], rel: &'a str) -> impl Iterator<Item = &'a Lock> {
+    locks.iter().filter(move |lock| lock.covers(rel))
+}
+
+/// 资源当前的状态，用于 `If` 头求值
+async fn state(sandbox: &Sandbox, locks: &LockManager, rel: &str) -> Result<State, StatusError> {
+    let etag = match sandbox.resolve(rel) {
+        Ok(path) => tokio::fs::metadata(&path)
+            .await
+            .ok()
+            .map(|m| precondition::etag(&m)),
+        Err(_) => None,
+    };
+    let all = locks
+        .list(sandbox.mount(), rel)
+        .await
+        .map_err(lock_status)?;
+    Ok(State {
+        etag,
+        tokens: covering(&all, rel).map(|l| l.token.clone()).collect(),
+    })
+}
+
+/// 按 `If` 头求值，不成立时返回412
+async fn check_if(req: &Request, depot: &Depot, rel: &str) -> Result<(), StatusError> {
+    let Some(value) = header(req, &IF) else {
+        return Ok(());
+    };
+    let condition = IfHeader::parse(value).map_err(|e| StatusError::bad_request().brief(e))?;
+    let sandbox = sandbox(depot)?;
+    let locks = locks(depot)?;
+    let base = base(depot);
+    let mut states = HashMap::new();
+    states.insert(None, state(&sandbox, &locks, rel).await?);
+    for resource in condition.resources() {
+        let state = match local_path(&base, resource) {
+            Some(rel) => state(&sandbox, &locks, &rel).await?,
+            None => State::default(),
+        };
+        states.insert(Some(resource.to_string()), state);
+    }
+    if condition.evaluate(|resource| &states[&resource.map(str::to_string)]) {
+        Ok(())
+    } else {
+        Err(StatusError::precondition_failed().brief("If 条件不成立"))
+    }
+}
+
+/// 修改前检查 `If` 头和锁
+async fn require(
+    req: &Request,
+    depot: &Depot,
+    sandbox: &Sandbox,
+    path: &Path,
+    tree: bool,
+) -> Result<(), StatusError> {
+    check_if(req, depot, &sandbox.relative(path).unwrap_or_default()).await?;
+    crate::web::locks::require(depot, req, sandbox, path, tree).await
+}
+
+/// 释放 `rel` 及其子树上的锁，资源被删除或移走后调用
+async fn release_tree(depot: &Depot, sandbox: &Sandbox, rel: &str) {
+    let Ok(locks) = locks(depot) else { return };
+    let Ok(all) = locks.list(sandbox.mount(), rel).await else {
+        return;
+    };
+    for lock in all.iter().filter(|l| l.path == rel || l.inside(rel)) {
+        if let Err(e) = locks.release(&lock.token).await {
+            log::warn!("释放锁失败 {}: {}", lock.token, e);
+        }
+    }
+}
+
+/// 部分路径失败时以207列出失败的路径
+fn render_report(
+    sandbox: &Sandbox,
+    base: &str,
+    report: Report,
+    status: StatusCode,
+    res: &mut Response,
+) {
+    if report.errors.is_empty() {
+        res.status_code(status);
+        return;
+    }
+    let mut ms = Multistatus::new();
+    for e in &report.errors {
+        let rel = sandbox.relative(&e.path).unwrap_or_default();
+        ms.status(
+            &href(base, &rel, false),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Some(&e.error),
+        );
+    }
+    render_xml(res, StatusCode::MULTI_STATUS, ms.finish());
+}
+
+/// PROPFIND 中的一个资源
+struct Resource {
+    path: PathBuf,
+    rel: String,
+    metadata: Metadata,
+}
+
+impl Resource {
+    fn is_collection(&self) -> bool {
+        self.metadata.is_dir()
+    }
+
+    /// 活属性的值（已转义的 XML），不适用于该资源时返回 `None`
+    fn live(&self, name: &str, base: &str, locks: &[Lock]) -> Option<String> {
+        let collection = self.is_collection();
+        match name {
+            "creationdate" => {
+                let created = self.metadata.created().or(self.metadata.modified()).ok()?;
+                Some(DateTime::<Utc>::from(created).to_rfc3339_opts(SecondsFormat::Secs, true))
+            }
+            "displayname" => Some(escape(self.rel.rsplit('/').next().unwrap_or_default())),
+            "getcontentlength" if !collection => Some(self.metadata.len().to_string()),
+            "getcontenttype" if !collection => Some(escape(
+                mime_guess::from_path(&self.path)
+                    .first_or_octet_stream()
+                    .as_ref(),
+            )),
+            "getetag" => Some(escape(&precondition::etag(&self.metadata))),
+            "getlastmodified" => Some(httpdate::fmt_http_date(self.metadata.modified().ok()?)),
+            "resourcetype" => Some(if collection {
+                "<D:collection/>".to_string()
+            } else {
+                String::new()
+            }),
+            "lockdiscovery" => Some(
+                covering(locks, &self.rel)
+                    .map(|lock| active_lock(base, lock))
+                    .collect(),
+            ),
+            "supportedlock" => Some(
+                ["exclusive", "shared"]
+                    .iter()
+                    .map(|scope| {
+                        format!(
+                            "<D:lockentry><D:lockscope><D:{}/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockentry>",
+                            scope
+                        )
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// `activelock` 元素
+fn active_lock(base: &str, lock: &Lock) -> String {
+    let scope = match lock.scope {
+        Scope::Exclusive => "exclusive",
+        Scope::Shared => "shared",
+    };
+    let depth = match lock.depth {
+        Depth::Zero => "0",
+        Depth::Infinity => "infinity",
+    };
+    let owner = match &lock.owner_xml {
+        Some(xml) => format!("<D:owner>{}</D:owner>", xml),
+        None if lock.owner.is_empty() => String::new(),
+        None => format!("<D:owner>{}</D:owner>", escape(&lock.owner)),
+    };
+    let remaining = (lock.expires_at - crate::db::now()).max(0);
+    format!(
+        "<D:activelock><D:locktype><D:write/></D:locktype><D:lockscope><D:{}/></D:lockscope>\
+         <D:depth>{}</D:depth>{}<D:timeout>Second-{}</D:timeout>\
+         <D:locktoken><D:href>{}</D:href></D:locktoken>\
+         <D:lockroot><D:href>{}</D:href></D:lockroot></D:activelock>",
+        scope,
+        depth,
+        owner,
+        remaining,
+        escape(&lock.token),
+        escape(&href(base, &lock.path, false))
+    )
+}
+
+/// 收集 `root` 下深度不超过 `depth` 的资源，跳过被排除的路径
+async fn collect(
+    sandbox: &Sandbox,
+    root: PathBuf,
+    depth: usize,
+) -> Result<Vec<Resource>, StatusError> {
+    let metadata = tokio::fs::metadata(&root).await.map_err(io_status)?;
+    let mut resources = vec![Resource {
+        rel: sandbox.relative(&root).unwrap_or_default(),
+        path: root,
+        metadata,
+    }];
+    let mut level = vec![0];
+    for _ in 0..depth {
+        let parents = std::mem::take(&mut level);
+        for index in parents {
+            if !resources[index].is_collection() {
+                continue;
+            }
+            let mut read_dir = match tokio::fs::read_dir(&resources[index].path).await {
+                Ok(read_dir) => read_dir,
+                Err(e) => {
+                    log::warn!("读取目录失败 {}: {}", resources[index].path.display(), e);
+                    continue;
+                }
+            };
+            let mut children = Vec::new();
+            while let Ok(Some(item)) = read_dir.next_entry().await {
+                let path = item.path();
+                if sandbox.is_excluded(&path) {
+                    continue;
+                }
+                // 按沙箱规则解析，拒绝越界和被禁止的符号链接
+                let Some(rel) = sandbox.relative(&path) else {
+                    continue;
+                };
+                if sandbox.resolve(&rel).is_err() {
+                    continue;
+                }
+                if let Ok(metadata) = tokio::fs::metadata(&path).await {
+                    children.push(Resource {
+                        path,
+                        rel,
+                        metadata,
+                    });
+                }
+            }
+            children.sort_by(|a, b| a.rel.cmp(&b.rel));
+            for child in children {
+                if resources.len() >= MAX_RESOURCES {
+                    return Err(StatusError::forbidden().brief("资源过多，请使用较小的 Depth"));
+                }
+                level.push(resources.len());
+                resources.push(child);
+            }
+        }
+        if level.is_empty() {
+            break;
+        }
+    }
+    Ok(resources)
+}
+
+/// PROPFIND: 查询属性，`Depth` 为0、1或 infinity
+async fn propfind(req: &mut Request, depot: &Depot, res: &mut Response) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let base = base(depot);
+    let path = sandbox.resolve_existing(&request_path(req))?;
+    let depth = depth(req)?;
+    let find = request::propfind(&body(req).await?).map_err(bad_xml)?;
+    let resources = collect(&sandbox, path, depth).await?;
+    let all_locks = locks(depot)?
+        .list(sandbox.mount(), "")
+        .await
+        .map_err(lock_status)?;
+
+    // 一次查出所有资源的死属性，Depth: infinity 时不必逐个查询
+    let properties = match resources.len() {
+        1 => db.dav_properties(sandbox.mount(), &resources[0].rel).await,
+        _ => {
+            db.dav_property_tree(sandbox.mount(), &resources[0].rel)
+                .await
+        }
+    }
+    .map_err(db_status)?;
+    let mut dead_properties: HashMap<String, Vec<DeadProperty>> = HashMap::new();
+    for p in properties {
+        dead_properties.entry(p.path.clone()).or_default().push(p);
+    }
+
+    let mut ms = Multistatus::new();
+    for resource in &resources {
+        let dead = dead_properties.remove(&resource.rel).unwrap_or_default();
+        let mut found = Vec::new();
+        let mut missing = Vec::new();
+        match &find {
+            PropFind::PropName => {
+                for name in LIVE_PROPERTIES {
+                    if resource.live(name, &base, &all_locks).is_some() {
+                        found.push(property(&PropName::dav(name), None));
+                    }
+                }
+                for p in &dead {
+                    found.push(property(&PropName::new(&p.namespace, &p.name), None));
+                }
+            }
+            PropFind::AllProp(include) => {
+                for name in LIVE_PROPERTIES {
+                    if let Some(value) = resource.live(name, &base, &all_locks) {
+                        found.push(property(&PropName::dav(name), Some(&value)));
+                    }
+                }
+                for p in &dead {
+                    found.push(property(
+                        &PropName::new(&p.namespace, &p.name),
+                        Some(&p.value),
+                    ));
+                }
+                // allprop 已包含全部活属性，include 中的其他属性按 prop 处理
+                for name in include.iter().filter(|n| n.namespace != crate::dav::DAV_NS) {
+                    if !dead
+                        .iter()
+                        .any(|p| p.namespace == name.namespace && p.name == name.name)
+                    {
+                        missing.push(property(name, None));
+                    }
+                }
+            }
+            PropFind::Prop(names) => {
+                for name in names {
+                    let value = if name.namespace == crate::dav::DAV_NS
+                        && LIVE_PROPERTIES.contains(&name.name.as_str())
+                    {
+                        resource.live(&name.name, &base, &all_locks)
+                    } else {
+                        dead.iter()
+                            .find(|p| p.namespace == name.namespace && p.name == name.name)
+                            .map(|p| p.value.clone())
+                    };
+                    match value {
+                        Some(value) => found.push(property(name, Some(&value))),
+                        None => missing.push(property(name, None)),
+                    }
+                }
+            }
+        }
+        ms.propstat(
+            &href(&base, &resource.rel, resource.is_collection()),
+            &[(StatusCode::OK, found), (StatusCode::NOT_FOUND, missing)],
+        );
+    }
+    render_xml(res, StatusCode::MULTI_STATUS, ms.finish());
+    Ok(())
+}
+
+/// PROPPATCH: 设置或删除死属性，全部成功或全部失败
+async fn proppatch(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let base = base(depot);
+    let path = sandbox.resolve_existing(&request_path(req))?;
+    require(req, depot, &sandbox, &path, false).await?;
+    let updates = request::proppatch(&body(req).await?).map_err(bad_xml)?;
+    let rel = sandbox.relative(&path).unwrap_or_default();
+
+    let protected = |name: &PropName| {
+        name.namespace == crate::dav::DAV_NS && LIVE_PROPERTIES.contains(&name.name.as_str())
+    };
+    let names: Vec<&PropName> = updates
+        .iter()
+        .map(|u| match u {
+            PropUpdate::Set(name, _) | PropUpdate::Remove(name) => name,
+        })
+        .collect();
+    let groups = if names.iter().any(|n| protected(n)) {
+        let (forbidden, rest): (Vec<&PropName>, Vec<&PropName>) =
+            names.iter().copied().partition(|n| protected(n));
+        vec![
+            (
+                StatusCode::FORBIDDEN,
+                forbidden.iter().map(|n| property(n, None)).collect(),
+            ),
+            (
+                StatusCode::FAILED_DEPENDENCY,
+                rest.iter().map(|n| property(n, None)).collect(),
+            ),
+        ]
+    } else {
+        let changes: Vec<PropertyChange> = updates
+            .iter()
+            .map(|u| match u {
+                PropUpdate::Set(n, value) => {
+                    (n.namespace.clone(), n.name.clone(), Some(value.clone()))
+                }
+                PropUpdate::Remove(n) => (n.namespace.clone(), n.name.clone(), None),
+            })
+            .collect();
+        db.update_dav_properties(sandbox.mount(), &rel, &changes)
+            .await
+            .map_err(db_status)?;
+        vec![(
+            StatusCode::OK,
+            names.iter().map(|n| property(n, None)).collect(),
+        )]
+    };
+    let mut ms = Multistatus::new();
+    ms.propstat(&href(&base, &rel, path.is_dir()), &groups);
+    render_xml(res, StatusCode::MULTI_STATUS, ms.finish());
+    Ok(())
+}
+
+/// MKCOL: 创建集合，父集合必须存在
+async fn mkcol(req: &mut Request, depot: &Depot, res: &mut Response) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let path = sandbox.resolve_nofollow(&rel)?;
+    if !body(req).await?.is_empty() {
+        return Err(StatusError::unsupported_media_type().brief("MKCOL 不支持请求体"));
+    }
+    if tokio::fs::symlink_metadata(&path).await.is_ok() {
+        res.headers_mut()
+            .insert(ALLOW, HeaderValue::from_static(METHODS));
+        return Err(StatusError::method_not_allowed().brief(format!("路径已存在: {}", rel)));
+    }
+    require(req, depot, &sandbox, &path, false).await?;
+    prepare_parent(&path, false).await?;
+    tokio::fs::create_dir(&path).await.map_err(io_status)?;
+    log::info!("WebDAV 创建集合: {}", path.display());
+    res.status_code(StatusCode::CREATED);
+    Ok(())
+}
+
+/// DELETE: 把资源移入回收站，集合连同其成员一起删除
+async fn delete(req: &mut Request, depot: &Depot, res: &mut Response) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let path = resolve_target(&sandbox, &request_path(req))?;
+    tokio::fs::symlink_metadata(&path)
+        .await
+        .map_err(io_status)?;
+    require(req, depot, &sandbox, &path, true).await?;
+    let rel = sandbox.relative(&path).unwrap_or_default();
+
+    let (item, report) = trash::move_to_trash(&sandbox, &db, &path, true, client_addr(req)).await?;
+    log::info!("WebDAV 移入回收站: {} -> {}", path.display(), item.id);
+    forget(&db, &sandbox, &rel).await;
+    release_tree(depot, &sandbox, &rel).await;
+    render_report(&sandbox, &base(depot), report, StatusCode::NO_CONTENT, res);
+    Ok(())
+}
+
+/// 删除资源的死属性，失败只记录日志
+async fn forget(db: &Db, sandbox: &Sandbox, rel: &str) {
+    if let Err(e) = db.delete_dav_properties(sandbox.mount(), rel).await {
+        log::warn!("删除属性失败 {}: {}", rel, e);
+    }
+}
+
+/// COPY / MOVE: 目标来自 `Destination`，`Overwrite: F` 时不覆盖已存在的目标
+async fn transfer(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+    copy: bool,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let base = base(depot);
+    let src = resolve_target(&sandbox, &request_path(req))?;
+    let metadata = tokio::fs::symlink_metadata(&src).await.map_err(io_status)?;
+    let destination = header(req, &DESTINATION)
+        .ok_or_else(|| StatusError::bad_request().brief("缺少 Destination"))?;
+    let dst_rel = local_path(&base, destination).ok_or_else(|| {
+        StatusError::bad_gateway().brief(format!("目标不在同一挂载点: {}", destination))
+    })?;
+    let dst = resolve_target(&sandbox, &dst_rel)?;
+    if dst.starts_with(&src) {
+        return Err(StatusError::forbidden().brief("目标与源相同或位于源之内"));
+    }
+    let depth = depth(req)?;
+    let shallow = match depth {
+        0 if copy => true,
+        usize::MAX => false,
+        _ => return Err(StatusError::bad_request().brief("无效的 Depth")),
+    };
+    let overwrite = match header(req, &OVERWRITE).map(str::trim) {
+        None | Some("T") | Some("t") => true,
+        Some("F") | Some("f") => false,
+        Some(o) => return Err(StatusError::bad_request().brief(format!("无效的 Overwrite: {}", o))),
+    };
+    if !copy {
+        require(req, depot, &sandbox, &src, true).await?;
+    } else {
+        check_if(req, depot, &sandbox.relative(&src).unwrap_or_default()).await?;
+    }
+    crate::web::locks::require(depot, req, &sandbox, &dst, true).await?;
+    prepare_parent(&dst, false).await?;
+
+    let src_rel = sandbox.relative(&src).unwrap_or_default();
+    let dst_rel = sandbox.relative(&dst).unwrap_or_default();
+    let existed = tokio::fs::symlink_metadata(&dst).await.is_ok();
+    if existed && !overwrite {
+        return Err(StatusError::precondition_failed().brief("目标已存在"));
+    }
+
+    // 被覆盖的目标移入回收站，失败时放回
+    let shallow_dir = shallow && metadata.is_dir();
+    let (from, to) = (src.clone(), dst.clone());
+    let report = replace(
+        depot,
+        &sandbox,
+        &dst,
+        match existed {
+            true => Conflict::Overwrite,
+            false => Conflict::Fail,
+        },
+        client_addr(req),
+        move |conflict| match (shallow_dir, copy) {
+            (true, _) => std::fs::create_dir(&to)
+                .map(|_| Report::default())
+                .map_err(OpError::Io),
+            (false, true) => ops::copy(&from, &to, conflict),
+            (false, false) => ops::rename(&from, &to, conflict),
+        },
+    )
+    .await?;
+    if existed {
+        forget(&db, &sandbox, &dst_rel).await;
+    }
+    if shallow_dir {
+        let properties: Vec<PropertyChange> = db
+            .dav_properties(sandbox.mount(), &src_rel)
+            .await
+            .map_err(db_status)?
+            .into_iter()
+            .map(|p| (p.namespace, p.name, Some(p.value)))
+            .collect();
+        db.update_dav_properties(sandbox.mount(), &dst_rel, &properties)
+            .await
+            .map_err(db_status)?;
+    } else if let Err(e) = db
+        .copy_dav_properties(sandbox.mount(), &src_rel, &dst_rel, copy)
+        .await
+    {
+        log::warn!("复制属性失败 {} -> {}: {}", src_rel, dst_rel, e);
+    }
+    if !copy {
+        release_tree(depot, &sandbox, &src_rel).await;
+    }
+    log::info!(
+        "WebDAV {}: {} -> {}",
+        if copy { "复制" } else { "移动" },
+        src.display(),
+        dst.display()
+    );
+    let status = if existed {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::CREATED
+    };
+    render_report(&sandbox, &base, report, status, res);
+    Ok(())
+}
+
+/// LOCK: 加锁或刷新锁
+///
+/// 请求体为空时刷新 `If` 头中出示的锁；否则按 `lockinfo` 加锁，路径不存在时创建空文件。
+async fn lock(req: &mut Request, depot: &Depot, res: &mut Response) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let locks = locks(depot)?;
+    let base = base(depot);
+    let path = sandbox.resolve_nofollow(&request_path(req))?;
+    let rel = sandbox.relative(&path).unwrap_or_default();
+    let info = request::lockinfo(&body(req).await?).map_err(bad_xml)?;
+    let timeout = timeout(req);
+
+    let Some(info) = info else {
+        // 刷新：If 头中出示的、作用于该资源的锁
+        let tokens = crate::web::locks::presented_tokens(req.headers());
+        for token in tokens {
+            let Ok(lock) = locks.get(&token).await else {
+                continue;
+            };
+            if lock.mount == sandbox.mount() && lock.covers(&rel) {
+                let lock = locks.refresh(&token, timeout).await.map_err(lock_status)?;
+                let discovery = property(
+                    &PropName::dav("lockdiscovery"),
+                    Some(&active_lock(&base, &lock)),
+                );
+                render_xml(res, StatusCode::OK, response::prop(&discovery));
+                return Ok(());
+            }
+        }
+        return Err(StatusError::precondition_failed().brief("没有可刷新的锁"));
+    };
+
+    let depth = match depth(req)? {
+        0 => Depth::Zero,
+        usize::MAX => Depth::Infinity,
+        _ => return Err(StatusError::bad_request().brief("LOCK 的 Depth 只能为 0 或 infinity")),
+    };
+    check_if(req, depot, &rel).await?;
+    let exists = tokio::fs::symlink_metadata(&path).await.is_ok();
+    if !exists {
+        prepare_parent(&path, false).await?;
+    }
+    let owner = match info.owner.is_empty() {
+        true => client_addr(req),
+        false => info.owner,
+    };
+    let grant = Grant {
+        depth,
+        scope: info.scope,
+        owner,
+        owner_xml: info.owner_xml,
+        timeout,
+    };
+    let lock = locks
+        .grant(sandbox.mount(), &rel, &grant)
+        .await
+        .map_err(lock_status)?;
+    if !exists
+        && let Err(e) = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+    {
+        locks.release(&lock.token).await.ok();
+        return Err(io_status(e));
+    }
+    log::info!(
+        "WebDAV 加锁: /{} {:?} {:?} {} ({})",
+        lock.path,
+        lock.scope,
+        lock.depth,
+        lock.token,
+        lock.owner
+    );
+
+    if let Ok(value) = HeaderValue::from_str(&format!("<{}>", lock.token)) {
+        res.headers_mut().insert(LOCK_TOKEN, value);
+    }
+    let discovery = property(
+        &PropName::dav("lockdiscovery"),
+        Some(&active_lock(&base, &lock)),
+    );
+    let status = if exists {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    render_xml(res, status, response::prop(&discovery));
+    Ok(())
+}
+
+/// UNLOCK: 释放 `Lock-Token` 指定的、作用于该资源的锁
+async fn unlock(req: &mut Request, depot: &Depot, res: &mut Response) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let locks = locks(depot)?;
+    let path = sandbox.resolve_nofollow(&request_path(req))?;
+    let rel = sandbox.relative(&path).unwrap_or_default();
+    let token = header(req, &LOCK_TOKEN)
+        .map(|t| t.trim().trim_start_matches('<').trim_end_matches('>'))
+        .filter(|t| t.starts_with(TOKEN_PREFIX))
+        .ok_or_else(|| StatusError::bad_request().brief("缺少 Lock-Token"))?
+        .to_string();
+    match locks.get(&token).await {
+        Ok(lock) if lock.mount == sandbox.mount() && lock.covers(&rel) => {}
+        Ok(_) | Err(crate::lock::LockError::NotFound(_)) => {
+            render_xml(
+                res,
+                StatusCode::CONFLICT,
+                response::error("lock-token-matches-request-uri"),
+            );
+            return Ok(());
+        }
+        Err(e) => return Err(lock_status(e)),
+    }
+    let lock = locks.release(&token).await.map_err(lock_status)?;
+    log::info!("WebDAV 解锁: /{} {}", lock.path, lock.token);
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// WebDAV 入口，按方法分派；GET、HEAD 和 PUT 与 `/fs` 的处理相同
+#[handler]
+pub async fn dav(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) -> Result<(), StatusError> {
+    match req.method().as_str() {
+        "OPTIONS" => {
+            res.headers_mut()
+                .insert(DAV, HeaderValue::from_static("1, 2"));
+            res.headers_mut()
+                .insert(ALLOW, HeaderValue::from_static(METHODS));
+            res.headers_mut().insert(
+                HeaderName::from_static("ms-author-via"),
+                HeaderValue::from_static("DAV"),
+            );
+            res.status_code(StatusCode::OK);
+            Ok(())
+        }
+        "GET" | "HEAD" => {
+            fs::read_file.handle(req, depot, res, ctrl).await;
+            Ok(())
+        }
+        "PUT" => {
+            let sandbox = sandbox(depot)?;
+            let rel = request_path(req);
+            let path = sandbox.resolve(&rel)?;
+            if tokio::fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+                return Err(StatusError::method_not_allowed().brief("不能写入集合"));
+            }
+            check_if(req, depot, &sandbox.relative(&path).unwrap_or_default()).await?;
+            fs::write_file.handle(req, depot, res, ctrl).await;
+            Ok(())
+        }
+        "DELETE" => delete(req, depot, res).await,
+        "PROPFIND" => propfind(req, depot, res).await,
+        "PROPPATCH" => proppatch(req, depot, res).await,
+        "MKCOL" => mkcol(req, depot, res).await,
+        "COPY" => transfer(req, depot, res, true).await,
+        "MOVE" => transfer(req, depot, res, false).await,
+        "LOCK" => lock(req, depot, res).await,
+        "UNLOCK" => unlock(req, depot, res).await,
+        _ => {
+            res.headers_mut()
+                .insert(ALLOW, HeaderValue::from_static(METHODS));
+            Err(StatusError::method_not_allowed())
+        }
+    }
+}
+
+/// WebDAV 路由
+pub fn router() -> Router {
+    Router::with_path("dav/{**path}").goal(dav)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::ServerConfig;
+    use salvo::http::Method;
+    use salvo::prelude::Service;
+    use salvo::test::{RequestBuilder, ResponseExt, TestClient};
+
+    fn request(method: &str, path: &str) -> RequestBuilder {
+        RequestBuilder::new(
+            format!("http://127.0.0.1{}", path),
+            Method::from_bytes(method.as_bytes()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_dav() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            root: dir.path().display().to_string(),
+            database_url: "sqlite::memory:".to_string(),
+            ..Default::default()
+        };
+        let service = Service::new(crate::web::create_router(&config).await.unwrap());
+
+        let res = request("OPTIONS", "/dav/").send(&service).await;
+        assert_eq!(res.headers().get(DAV).unwrap(), "1, 2");
+
+        let res = request("MKCOL", "/dav/a").send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        let res = request("MKCOL", "/dav/a").send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::METHOD_NOT_ALLOWED));
+        let res = request("MKCOL", "/dav/x/y").send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+        let res = request("PUT", "/dav/a/f%20g.txt")
+            .body("hello")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+
+        // 死属性
+        let res = request("PROPPATCH", "/dav/a/f%20g.txt")
+            .body(r#"<D:propertyupdate xmlns:D="DAV:" xmlns:Z="urn:z"><D:set><D:prop><Z:color>red</Z:color></D:prop></D:set></D:propertyupdate>"#)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::MULTI_STATUS));
+        let mut res = request("PROPFIND", "/dav/a")
+            .add_header(DEPTH, "1", true)
+            .body(r#"<D:propfind xmlns:D="DAV:"><D:prop><D:getcontentlength/><Z:color xmlns:Z="urn:z"/></D:prop></D:propfind>"#)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::MULTI_STATUS));
+        let body = res.take_string().await.unwrap();
+        assert!(body.contains("<D:href>/dav/a/</D:href>"));
+        assert!(body.contains("<D:href>/dav/a/f%20g.txt</D:href>"));
+        assert!(body.contains("<D:getcontentlength>5</D:getcontentlength>"));
+        assert!(body.contains(r#"<P:color xmlns:P="urn:z">red</P:color>"#));
+        assert!(body.contains("HTTP/1.1 404 Not Found"));
+
+        // 加锁后，不出示令牌的写入返回423
+        let mut res = request("LOCK", "/dav/a/f%20g.txt")
+            .body(r#"<D:lockinfo xmlns:D="DAV:"><D:lockscope><D:exclusive/></D:lockscope><D:locktype><D:write/></D:locktype><D:owner>tester</D:owner></D:lockinfo>"#)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let token = res
+            .headers()
+            .get(LOCK_TOKEN)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(res.take_string().await.unwrap().contains("<D:exclusive/>"));
+        let res = request("PUT", "/dav/a/f%20g.txt")
+            .body("x")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::LOCKED));
+        let res = request("PUT", "/dav/a/f%20g.txt")
+            .add_header(IF, "(<opaquelocktoken:nope>)", true)
+            .body("x")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::PRECONDITION_FAILED));
+        let res = request("PUT", "/dav/a/f%20g.txt")
+            .add_header(IF, format!("({})", token), true)
+            .body("world")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+
+        // 复制带上属性，移动释放源上的锁
+        let res = request("COPY", "/dav/a")
+            .add_header(DESTINATION, "http://127.0.0.1/dav/b", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        let res = request("COPY", "/dav/a")
+            .add_header(DESTINATION, "/dav/b", true)
+            .add_header(OVERWRITE, "F", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::PRECONDITION_FAILED));
+        let mut res = request("PROPFIND", "/dav/b/f%20g.txt")
+            .add_header(DEPTH, "0", true)
+            .send(&service)
+            .await;
+        assert!(res.take_string().await.unwrap().contains(">red<"));
+        let res = request("MOVE", "/dav/a/f%20g.txt")
+            .add_header(DESTINATION, "/dav/c.txt", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::LOCKED));
+        let res = request("MOVE", "/dav/a/f%20g.txt")
+            .add_header(DESTINATION, "/dav/c.txt", true)
+            .add_header(IF, format!("({})", token), true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("c.txt")).unwrap(),
+            "world"
+        );
+        let res = request("UNLOCK", "/dav/c.txt")
+            .add_header(LOCK_TOKEN, token.as_str(), true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+
+        let res = request("DELETE", "/dav/b").send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert!(!dir.path().join("b").exists());
+        let res = request("PROPFIND", "/dav/b").send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+        let res = TestClient::get("http://127.0.0.1/dav/c.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_failed_overwrite_keeps_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            root: dir.path().display().to_string(),
+            database_url: "sqlite::memory:".to_string(),
+            ..Default::default()
+        };
+        let service = Service::new(crate::web::create_router(&config).await.unwrap());
+        // 套接字文件无法打开读取，复制失败
+        let _socket = std::os::unix::net::UnixListener::bind(dir.path().join("sock")).unwrap();
+        std::fs::write(dir.path().join("dst.txt"), "original").unwrap();
+        let res = request("PROPPATCH", "/dav/dst.txt")
+            .body(r#"<D:propertyupdate xmlns:D="DAV:" xmlns:Z="urn:z"><D:set><D:prop><Z:color>red</Z:color></D:prop></D:set></D:propertyupdate>"#)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::MULTI_STATUS));
+
+        let res = request("COPY", "/dav/sock")
+            .add_header(DESTINATION, "/dav/dst.txt", true)
+            .add_header(OVERWRITE, "T", true)
+            .send(&service)
+            .await;
+        assert!(res.status_code.unwrap().is_server_error());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("dst.txt")).unwrap(),
+            "original"
+        );
+        let mut res = request("PROPFIND", "/dav/dst.txt")
+            .body(r#"<D:propfind xmlns:D="DAV:"><D:prop><Z:color xmlns:Z="urn:z"/></D:prop></D:propfind>"#)
+            .send(&service)
+            .await;
+        assert!(res.take_string().await.unwrap().contains(">red</P:color>"));
+        let mut res = TestClient::get("http://127.0.0.1/trash")
+            .send(&service)
+            .await;
+        let items: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(items.as_array().unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::web::fs::io_status;
+use crate::web::{precondition, stat};
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt, TryStreamExt, stream};
+use salvo::http::header::{
+    ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, HeaderValue, IF_MATCH,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, IF_UNMODIFIED_SINCE, LAST_MODIFIED, RANGE,
+};
+use salvo::http::{Method, StatusCode, StatusError};
+use salvo::{Request, Response};
+use std::fs::Metadata;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+/// 单次请求允许的最大区间数，超过时忽略Range返回完整内容
+const MAX_RANGES: usize = 64;
+/// 读取文件的缓冲区大小
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// 字节区间，`end` 包含在内
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+/// Range头解析结果
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeSpec {
+    /// 语法无效或不支持的单位，按RFC 9110应忽略
+    Ignore,
+    /// 所有区间都无法满足
+    Unsatisfiable,
+    Ranges(Vec<ByteRange>),
+}
+
+/// 解析 `Range: bytes=...`
+pub fn parse_range(header: &str, total: u64) -> RangeSpec {
+    let Some(specs) = header.trim().strip_prefix("bytes=") else {
+        return RangeSpec::Ignore;
+    };
+    let mut ranges = Vec::new();
+    for spec in specs.split(',') {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            continue;
+        }
+        let Some((start, end)) = spec.split_once('-') else {
+            return RangeSpec::Ignore;
+        };
+        let (start, end) = (start.trim(), end.trim());
+        let range = if start.is_empty() {
+            // 后缀区间: -N
+            let Ok(suffix) = end.parse::<u64>() else {
+                return RangeSpec::Ignore;
+            };
+            if suffix == 0 || total == 0 {
+                continue;
+            }
+            ByteRange {
+                start: total.saturating_sub(suffix),
+                end: total - 1,
+            }
+        } else {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeSpec::Ignore;
+            };
+            let end = if end.is_empty() {
+                u64::MAX
+            } else {
+                match end.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return RangeSpec::Ignore,
+                }
+            };
+            if start >= total {
+                continue;
+            }
+            ByteRange {
+                start,
+                end: end.min(total - 1),
+            }
+        };
+        ranges.push(range);
+    }
+    if ranges.is_empty() {
+        RangeSpec::Unsatisfiable
+    } else if ranges.len() > MAX_RANGES {
+        RangeSpec::Ignore
+    } else {
+        RangeSpec::Ranges(ranges)
+    }
+}
+
+/// 秒级精度的修改时间，HTTP日期只精确到秒
+fn modified_secs(metadata: &Metadata) -> Option<SystemTime> {
+    let mtime = metadata.modified().ok()?;
+    let secs = mtime.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn header_date(req: &Request, name: salvo::http::header::HeaderName) -> Option<SystemTime> {
+    req.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+}
+
+/// If-Range 是否与当前表示匹配
+fn if_range_matches(req: &Request, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    let Some(value) = req.headers().get(IF_RANGE).and_then(|v| v.to_str().ok()) else {
+        return true;
+    };
+    let value = value.trim();
+    if value.starts_with('"') || value.starts_with("W/") {
+        // If-Range 要求强比较
+        value == etag
+    } else {
+        match (httpdate::parse_http_date(value), last_modified) {
+            (Ok(date), Some(lm)) => date == lm,
+            _ => false,
+        }
+    }
+}
+
+/// 发送文件，支持条件请求和单/多区间请求
+pub async fn send_file(
+    path: &Path,
+    metadata: &Metadata,
+    req: &Request,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let total = metadata.len();
+    let etag = precondition::etag(metadata);
+    let last_modified = modified_secs(metadata);
+    let content_type = stat::sniff_mime(path).await;
+    // HEAD 请求只返回头部
+    let head = req.method() == Method::HEAD;
+
+    let headers = res.headers_mut();
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Ok(v) = HeaderValue::from_str(&etag) {
+        headers.insert(ETAG, v);
+    }
+    if let Some(lm) = last_modified
+        && let Ok(v) = HeaderValue::from_str(&httpdate::fmt_http_date(lm))
+    {
+        headers.insert(LAST_MODIFIED, v);
+    }
+
+    // 前置条件，顺序遵循 RFC 9110 13.2.2
+    if !precondition::if_match(req.headers(), Some(metadata)) {
+        return Err(StatusError::precondition_failed());
+    }
+    if !req.headers().contains_key(IF_MATCH)
+        && let (Some(since), Some(lm)) = (header_date(req, IF_UNMODIFIED_SINCE), last_modified)
+        && lm > since
+    {
+        return Err(StatusError::precondition_failed());
+    }
+    if !precondition::if_none_match(req.headers(), Some(metadata)) {
+        res.status_code(StatusCode::NOT_MODIFIED);
+        return Ok(());
+    }
+    if !req.headers().contains_key(IF_NONE_MATCH)
+        && let (Some(since), Some(lm)) = (header_date(req, IF_MODIFIED_SINCE), last_modified)
+        && lm <= since
+    {
+        res.status_code(StatusCode::NOT_MODIFIED);
+        return Ok(());
+    }
+
+    let spec = match req.headers().get(RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range) if if_range_matches(req, &etag, last_modified) => parse_range(range, total),
+        _ => RangeSpec::Ignore,
+    };
+
+    match spec {
+        RangeSpec::Ignore => {
+            set_header(res, CONTENT_TYPE, &content_type);
+            set_header(res, CONTENT_LENGTH, &total.to_string());
+            res.status_code(StatusCode::OK);
+            if head {
+                return Ok(());
+            }
+            let file = tokio::fs::File::open(path).await.map_err(io_status)?;
+            res.stream(ReaderStream::with_capacity(file, BUFFER_SIZE));
+        }
+        RangeSpec::Unsatisfiable => {
+            set_header(res, CONTENT_RANGE, &format!("bytes */{}", total));
+            res.status_code(StatusCode::RANGE_NOT_SATISFIABLE);
+        }
+        RangeSpec::Ranges(ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            set_header(res, CONTENT_TYPE, &content_type);
+            set_header(res, CONTENT_LENGTH, &range.len().to_string());
+            set_header(res, CONTENT_RANGE, &range.content_range(total));
+            res.status_code(StatusCode::PARTIAL_CONTENT);
+            if head {
+                return Ok(());
+            }
+            let reader = open_range(path.to_path_buf(), range)
+                .await
+                .map_err(io_status)?;
+            res.stream(ReaderStream::with_capacity(reader, BUFFER_SIZE));
+        }
+        RangeSpec::Ranges(ranges) => {
+            let boundary = uuid::Uuid::now_v7().simple().to_string();
+            let (length, body) =
+                multipart_body(path.to_path_buf(), &ranges, total, &content_type, &boundary);
+            set_header(
+                res,
+                CONTENT_TYPE,
+                &format!("multipart/byteranges; boundary={}", boundary),
+            );
+            set_header(res, CONTENT_LENGTH, &length.to_string());
+            res.status_code(StatusCode::PARTIAL_CONTENT);
+            if !head {
+                res.stream(body);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn set_header(res: &mut Response, name: salvo::http::header::HeaderName, value: &str) {
+    if let Ok(v) = HeaderValue::from_str(value) {
+        res.headers_mut().insert(name, v);
+    }
+}
+
+/// 打开文件并定位到区间起点
+async fn open_range(
+    path: PathBuf,
+    range: ByteRange,
+) -> io::Result<tokio::io::Take<tokio::fs::File>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(range.start)).await?;
+    Ok(file.take(range.len()))
+}
+
+/// 构造 multipart/byteranges 响应体，返回总长度和数据流
+fn multipart_body(
+    path: PathBuf,
+    ranges: &[ByteRange],
+    total: u64,
+    content_type: &str,
+    boundary: &str,
+) -> (u64, impl Stream<Item = io::Result<Bytes>> + Send + 'static) {
+    enum Part {
+        Text(Bytes),
+        File(ByteRange),
+    }
+
+    let mut parts = Vec::with_capacity(ranges.len() * 2 + 1);
+    let mut length = 0u64;
+    for range in ranges {
+        let head = format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            boundary,
+            content_type,
+            range.content_range(total)
+        );
+        length += head.len() as u64 + range.len();
+        parts.push(Part::Text(Bytes::from(head)));
+        parts.push(Part::File(*range));
+    }
+    let tail = format!("\r\n--{}--\r\n", boundary);
+    length += tail.len() as u64;
+    parts.push(Part::Text(Bytes::from(tail)));
+
+    let body = stream::iter(parts).flat_map(move |part| match part {
+        Part::Text(bytes) => stream::once(async move { Ok(bytes) }).boxed(),
+        Part::File(range) => stream::once(open_range(path.clone(), range))
+            .map_ok(|reader| ReaderStream::with_capacity(reader, BUFFER_SIZE))
+            .try_flatten()
+            .boxed(),
+    });
+    (length, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            parse_range("bytes=0-9", 100),
+            RangeSpec::Ranges(vec![r(0, 9)])
+        );
+        assert_eq!(
+            parse_range("bytes=90-", 100),
+            RangeSpec::Ranges(vec![r(90, 99)])
+        );
+        assert_eq!(
+            parse_range("bytes=-10", 100),
+            RangeSpec::Ranges(vec![r(90, 99)])
+        );
+        assert_eq!(
+            parse_range("bytes=0-1000", 100),
+            RangeSpec::Ranges(vec![r(0, 99)])
+        );
+        assert_eq!(
+            parse_range("bytes=0-0, 5-9 ,-1", 100),
+            RangeSpec::Ranges(vec![r(0, 0), r(5, 9), r(99, 99)])
+        );
+        assert_eq!(parse_range("bytes=100-", 100), RangeSpec::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 100), RangeSpec::Unsatisfiable);
+        assert_eq!(parse_range("bytes=9-0", 100), RangeSpec::Ignore);
+        assert_eq!(parse_range("items=0-9", 100), RangeSpec::Ignore);
+        assert_eq!(parse_range("bytes=a-b", 100), RangeSpec::Ignore);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::sandbox::Sandbox;
+use crate::sandbox::extract::{
+    self, EntryReport, EntryStatus, EntryType, ExtractError, Format, Limits,
+};
+use crate::sandbox::ops::Conflict;
+use crate::web::fs::{io_status, request_path, sandbox};
+use crate::web::locks;
+use futures_util::StreamExt;
+use salvo::http::{StatusCode, StatusError};
+use salvo::prelude::Json;
+use salvo::{Depot, Request, Response, handler};
+use serde::Serialize;
+use std::io;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+/// 暂存上传归档的内部目录
+pub const STAGING_DIR: &str = ".staging";
+
+/// 单个条目的解压结果
+#[derive(Serialize, Debug)]
+pub struct EntryResult {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub kind: EntryType,
+    pub size: u64,
+    pub status: EntryStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 解压响应
+#[derive(Serialize, Debug)]
+pub struct ExtractResult {
+    pub path: String,
+    pub extracted: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub entries: Vec<EntryResult>,
+}
+
+impl ExtractResult {
+    fn new(sandbox: &Sandbox, path: String, report: Vec<EntryReport>) -> Self {
+        let count = |status| report.iter().filter(|e| e.status == status).count();
+        ExtractResult {
+            path,
+            extracted: count(EntryStatus::Extracted),
+            skipped: count(EntryStatus::Skipped),
+            failed: count(EntryStatus::Failed),
+            entries: report
+                .into_iter()
+                .map(|e| EntryResult {
+                    name: e.name,
+                    path: e.path.and_then(|p| sandbox.relative(&p)),
+                    kind: e.kind,
+                    size: e.size,
+                    status: e.status,
+                    error: e.error,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<ExtractError> for StatusError {
+    fn from(e: ExtractError) -> Self {
+        match e {
+            ExtractError::Unsupported => StatusError::unsupported_media_type().brief(e.to_string()),
+            ExtractError::Corrupt(_) => StatusError::bad_request().brief(e.to_string()),
+            ExtractError::TooManyEntries(_) | ExtractError::TooLarge(_) => {
+                StatusError::payload_too_large().brief(e.to_string())
+            }
+            ExtractError::Io(e) => io_status(e),
+        }
+    }
+}
+
+/// 从Depot中取出解压限制
+fn limits(depot: &Depot) -> Result<Limits, StatusError> {
+    depot
+        .obtain::<Limits>()
+        .copied()
+        .map_err(|_| StatusError::internal_server_error().brief("解压限制未配置"))
+}
+
+/// 把请求体写入暂存文件，超过 `max_bytes` 时返回413
+async fn stage_body(req: &mut Request, path: &Path, max_bytes: u64) -> Result<(), StatusError> {
+    let mut file = tokio::fs::File::create(path).await.map_err(io_status)?;
+    let mut received = 0u64;
+    let mut body = req.take_body();
+    while let Some(frame) = body.next().await {
+        let frame = frame.map_err(|e| StatusError::bad_request().brief(e.to_string()))?;
+        if let Ok(data) = frame.into_data() {
+            received += data.len() as u64;
+            if received > max_bytes {
+                return Err(StatusError::payload_too_large()
+                    .brief(format!("上传大小超过限制: {} 字节", max_bytes)));
+            }
+            file.write_all(&data).await.map_err(io_status)?;
+        }
+    }
+    file.flush().await.map_err(io_status)
+}
+
+/// 是否请求解压: `?extract=true`
+pub fn wants_extract(req: &Request) -> bool {
+    req.query::<bool>("extract").unwrap_or(false)
+}
+
+/// POST ?extract=true: 上传 zip / tar / tar.gz / tar.zst 归档并解压到目录
+///
+/// 请求体先暂存到内部目录，格式由 `?format=` 指定或按魔数识别。
+/// 返回每个条目的结果，部分失败时返回207；超出限制时返回413并删除本次新建的文件。
+#[handler]
+pub async fn extract_upload(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let limits = limits(depot)?;
+    let rel = request_path(req);
+    let dir = sandbox.resolve(&rel)?;
+    locks::require(depot, req, &sandbox, &dir, true).await?;
+    let conflict = req.query::<Conflict>("conflict").unwrap_or_default();
+    let mkdirs = req.query::<bool>("mkdirs").unwrap_or(false);
+    let format = match req.queries().contains_key("format") {
+        true => Some(
+            req.query::<Format>("format")
+                .ok_or_else(|| StatusError::bad_request().brief("不支持的归档格式"))?,
+        ),
+        false => None,
+    };
+
+    match tokio::fs::metadata(&dir).await {
+        Ok(m) if m.is_dir() => {}
+        Ok(_) => return Err(StatusError::conflict().brief("目标不是目录")),
+        Err(_) if mkdirs => tokio::fs::create_dir_all(&dir).await.map_err(io_status)?,
+        Err(_) => return Err(StatusError::not_found().brief(format!("目录不存在: {}", rel))),
+    }
+
+    // 暂存请求体，zip 需要随机读取中央目录
+    let staging = sandbox.internal_dir(STAGING_DIR).map_err(io_status)?;
+    let archive = staging.join(uuid::Uuid::now_v7().to_string());
+    let staged = stage_body(req, &archive, limits.max_bytes).await;
+    if staged.is_err() {
+        let _ = tokio::fs::remove_file(&archive).await;
+    }
+    staged?;
+
+    let dest = sandbox.relative(&dir).unwrap_or_default();
+    let task_sandbox = sandbox.clone();
+    let task_archive = archive.clone();
+    let task_dest = dest.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        extract::extract(
+            &task_sandbox,
+            &task_archive,
+            format,
+            &task_dest,
+            limits,
+            conflict,
+        )
+    })
+    .await;
+    if let Err(e) = tokio::fs::remove_file(&archive).await
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        log::warn!("删除暂存文件失败 {}: {}", archive.display(), e);
+    }
+    let report =
+        result.map_err(|e| StatusError::internal_server_error().brief(e.to_string()))??;
+
+    let result = ExtractResult::new(&sandbox, dest, report);
+    log::info!(
+        "解压到 {}: {} 个条目, {} 个失败",
+        dir.display(),
+        result.entries.len(),
+        result.failed
+    );
+    if result.failed == 0 {
+        res.status_code(StatusCode::OK);
+    } else {
+        res.status_code(StatusCode::MULTI_STATUS);
+    }
+    res.render(Json(result));
+    Ok(())
+}
@@ -0,0 +1,1379 @@
+use crate::db::Db;
+use crate::digest::Algorithm;
+use crate::sandbox::{AtomicFile, Sandbox, SandboxError};
+use crate::web::patch::{file_lock, release_lock};
+use crate::web::{checksum, download, listing, locks, mounts, precondition, versions};
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use salvo::http::header::{CONTENT_LENGTH, ETAG, HeaderValue};
+use salvo::http::{Method, StatusCode, StatusError};
+use salvo::{Depot, Request, Response, handler};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+impl From<SandboxError> for StatusError {
+    fn from(e: SandboxError) -> Self {
+        match e {
+            SandboxError::Forbidden(_) => StatusError::forbidden().brief(e.to_string()),
+            SandboxError::NotFound(_) => StatusError::not_found().brief(e.to_string()),
+            SandboxError::Io(e) => io_status(e),
+        }
+    }
+}
+
+/// 将IO错误映射为HTTP状态
+pub(crate) fn io_status(e: io::Error) -> StatusError {
+    match e.kind() {
+        io::ErrorKind::NotFound => StatusError::not_found().brief(e.to_string()),
+        io::ErrorKind::PermissionDenied => StatusError::forbidden().brief(e.to_string()),
+        _ => {
+            log::error!("IO错误: {}", e);
+            StatusError::internal_server_error().brief(e.to_string())
+        }
+    }
+}
+
+/// 从Depot中取出沙箱
+pub(crate) fn sandbox(depot: &Depot) -> Result<Arc<Sandbox>, StatusError> {
+    depot
+        .obtain::<Arc<Sandbox>>()
+        .cloned()
+        .map_err(|_| StatusError::internal_server_error().brief("沙箱未配置"))
+}
+
+/// 从Depot中取出数据库
+pub(crate) fn db(depot: &Depot) -> Result<Db, StatusError> {
+    depot
+        .obtain::<Db>()
+        .cloned()
+        .map_err(|_| StatusError::internal_server_error().brief("数据库未配置"))
+}
+
+/// 将数据库错误映射为HTTP状态
+pub(crate) fn db_status(e: sqlx::Error) -> StatusError {
+    log::error!("数据库错误: {}", e);
+    StatusError::internal_server_error().brief("数据库错误")
+}
+
+/// 客户端标识，目前为对端地址
+pub(crate) fn client_addr(req: &Request) -> String {
+    match req.remote_addr().clone().into_std() {
+        Some(addr) => addr.ip().to_string(),
+        None => req.remote_addr().to_string(),
+    }
+}
+
+/// 请求中的相对路径
+pub(crate) fn request_path(req: &Request) -> String {
+    req.param::<String>("path").unwrap_or_default()
+}
+
+/// 请求头中的 Content-Length
+pub(crate) fn content_length(req: &Request) -> Option<u64> {
+    req.headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+}
+
+/// 读取文件，路径为目录时返回目录列表；HEAD 只返回头部
+#[handler]
+pub async fn read_file(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let path = sandbox.resolve_existing(&rel)?;
+    let metadata = tokio::fs::metadata(&path).await.map_err(io_status)?;
+    if metadata.is_dir() {
+        if req.method() == Method::HEAD {
+            res.status_code(StatusCode::OK);
+            return Ok(());
+        }
+        return listing::list_dir(&sandbox, &path, req, res).await;
+    }
+    if !metadata.is_file() {
+        return Err(StatusError::not_found().brief(format!("不是文件: {}", rel)));
+    }
+    log::debug!("读取文件: {}", path.display());
+    download::send_file(&path, &metadata, req, res).await?;
+    checksum::digest_headers(req, depot, res, &sandbox, &path, &metadata).await;
+    Ok(())
+}
+
+/// 写入文件
+///
+/// 请求体流式写入同目录临时文件，fsync 后重命名到目标位置。
+/// 支持 `If-Match` / `If-None-Match: *` 前置条件和 `?mkdirs=true` 自动创建父目录。
+/// 请求带 `Content-Digest` 时在提交前校验，不匹配返回400且不改动目标文件。
+/// 替换已有文件时按版本策略保留旧内容。超过挂载点的文件大小限制时返回413。
+#[handler]
+pub async fn write_file(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let path = sandbox.resolve(&rel)?;
+    if path == sandbox.root() {
+        return Err(StatusError::method_not_allowed().brief("不能写入根目录"));
+    }
+    locks::require(depot, req, &sandbox, &path, false).await?;
+
+    let current = tokio::fs::metadata(&path).await.ok();
+    if current.as_ref().is_some_and(|m| m.is_dir()) {
+        return Err(StatusError::conflict().brief(format!("目标是目录: {}", rel)));
+    }
+    precondition::check_write(req.headers(), current.as_ref())?;
+    mounts::check_file_size(depot, content_length(req).unwrap_or_default())?;
+
+    let mkdirs = req.query::<bool>("mkdirs").unwrap_or(false);
+    prepare_parent(&path, mkdirs).await?;
+
+    let verifier = checksum::UploadVerifier::new(checksum::expected_digests(req.headers())?);
+    let body = req
+        .take_body()
+        .try_filter_map(|frame| async move { Ok(frame.into_data().ok()) })
+        .map_err(|e| StatusError::bad_request().brief(e.to_string()));
+    let digests = store(
+        req,
+        depot,
+        &sandbox,
+        &path,
+        current.is_some(),
+        verifier,
+        body,
+    )
+    .await?;
+
+    checksum::remember_upload(depot, Some(res), &sandbox, &path, &digests).await;
+    set_etag(&path, res).await;
+    if current.is_some() {
+        res.status_code(StatusCode::NO_CONTENT);
+    } else {
+        res.status_code(StatusCode::CREATED);
+    }
+    Ok(())
+}
+
+/// 把数据流原子写入 `path`，返回校验过的摘要
+///
+/// 内容先写入同目录临时文件，边写边检查挂载点的大小限制并计算 `verifier` 中的摘要，
+/// 校验通过后才提交。`If-Match` / `If-None-Match` 在提交前按目标的最新状态再检查一次，
+/// 不满足返回412；`replace` 为真时提交前按版本策略保留旧内容。调用方负责检查锁，
+/// 并应在读取请求体前先检查前置条件以便尽早拒绝。
+pub(crate) async fn store<S>(
+    req: &Request,
+    depot: &Depot,
+    sandbox: &Sandbox,
+    path: &Path,
+    replace: bool,
+    mut verifier: checksum::UploadVerifier,
+    body: S,
+) -> Result<Vec<(Algorithm, Vec<u8>)>, StatusError>
+where
+    S: Stream<Item = Result<Bytes, StatusError>>,
+{
+    let create_only = precondition::create_only(req.headers());
+    let mut file = AtomicFile::create(path).await.map_err(io_status)?;
+    let mut size = 0u64;
+    let mut body = std::pin::pin!(body);
+    while let Some(data) = body.next().await {
+        let data = data?;
+        size += data.len() as u64;
+        mounts::check_file_size(depot, size)?;
+        verifier.update(&data);
+        file.write(&data).await.map_err(io_status)?;
+    }
+    let digests = verifier
+        .verify()
+        .map_err(|e| StatusError::bad_request().brief(e))?;
+    let client = client_addr(req);
+    let lock = file_lock(path);
+    let committed = async {
+        let _guard = lock.lock().await;
+        // 读取请求体期间目标可能已被改写，提交前按最新状态重新检查前置条件
+        let current = tokio::fs::metadata(path).await.ok();
+        precondition::check_write(req.headers(), current.as_ref())?;
+        if create_only {
+            return file.commit_new().await.map_err(|e| {
+                if e.kind() == io::ErrorKind::AlreadyExists {
+                    StatusError::precondition_failed().brief("目标已存在")
+                } else {
+                    io_status(e)
+                }
+            });
+        }
+        // 提交会整体替换目标，旧内容以硬链接保存为版本
+        let saved = match replace {
+            true => versions::snapshot(depot, sandbox, path, true, client).await?,
+            false => None,
+        };
+        let committed = file.commit().await.map_err(io_status);
+        if committed.is_err()
+            && let Some(saved) = saved
+        {
+            versions::discard(depot, sandbox, &saved).await;
+        }
+        committed
+    }
+    .await;
+    release_lock(path, lock);
+    let written = committed?;
+    log::info!("写入文件: {} ({} 字节)", path.display(), written);
+    Ok(digests)
+}
+
+/// 确保父目录存在，`mkdirs` 为真时自动创建
+pub(crate) async fn prepare_parent(path: &Path, mkdirs: bool) -> Result<(), StatusError> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| StatusError::bad_request().brief("无效路径"))?;
+    match tokio::fs::metadata(parent).await {
+        Ok(m) if m.is_dir() => Ok(()),
+        Ok(_) => Err(StatusError::conflict().brief("父路径不是目录")),
+        Err(_) if mkdirs => tokio::fs::create_dir_all(parent).await.map_err(io_status),
+        Err(_) => Err(StatusError::conflict().brief("父目录不存在")),
+    }
+}
+
+/// 设置响应的ETag头
+pub(crate) async fn set_etag(path: &Path, res: &mut Response) {
+    if let Ok(metadata) = tokio::fs::metadata(path).await
+        && let Ok(value) = HeaderValue::from_str(&precondition::etag(&metadata))
+    {
+        res.headers_mut().insert(ETAG, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lock::LockManager;
+    use crate::sandbox::Sandbox;
+    use crate::sandbox::extract::Limits;
+    use crate::web::versions::VersionPolicy;
+    use salvo::prelude::*;
+    use salvo::test::{ResponseExt, TestClient};
+    use std::sync::Arc;
+
+    async fn service(sandbox: Sandbox) -> Service {
+        let db = crate::db::memory_db().await;
+        let router = Router::new()
+            .hoop(
+                affix_state::inject(Arc::new(sandbox))
+                    .inject(db)
+                    .inject(Arc::new(VersionPolicy::parse("tmp/**=off;**=3").unwrap()))
+                    .inject(Arc::new(LockManager::memory()))
+                    .inject(Limits {
+                        max_entries: 100,
+                        max_bytes: 1 << 20,
+                        max_file_size: None,
+                    }),
+            )
+            .push(crate::web::fs_router())
+            .push(Router::with_path("batch").post(crate::web::batch::batch))
+            .push(crate::web::locks::router())
+            .push(crate::web::trash::router());
+        Service::new(router)
+    }
+
+    /// 收到信号后才产生数据的请求体
+    struct GatedBody(Option<(tokio::sync::oneshot::Receiver<()>, &'static str)>);
+
+    impl salvo::http::body::Body for GatedBody {
+        type Data = bytes::Bytes;
+        type Error = salvo::BoxedError;
+
+        fn poll_frame(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Option<Result<salvo::http::body::Frame<Self::Data>, Self::Error>>>
+        {
+            use std::future::Future;
+            let Some((gate, data)) = &mut self.0 else {
+                return std::task::Poll::Ready(None);
+            };
+            let data = *data;
+            std::task::ready!(std::pin::Pin::new(gate).poll(cx)).ok();
+            self.0 = None;
+            std::task::Poll::Ready(Some(Ok(salvo::http::body::Frame::data(data.into()))))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_put_if_match_race() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        std::fs::write(root.join("f.txt"), "v1").unwrap();
+        let etag = crate::web::precondition::etag(&std::fs::metadata(root.join("f.txt")).unwrap());
+        let service = Arc::new(service(sandbox).await);
+
+        // 前置条件通过、请求体尚未读完时，另一方改写了目标
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let request = TestClient::put("http://127.0.0.1/fs/f.txt")
+            .add_header("if-match", &etag, true)
+            .body(salvo::http::ReqBody::Boxed {
+                inner: Box::pin(GatedBody(Some((rx, "mine")))),
+                fusewire: None,
+            });
+        let task = tokio::spawn({
+            let service = service.clone();
+            async move { request.send(&*service).await.status_code }
+        });
+        while !std::fs::read_dir(&root)
+            .unwrap()
+            .flatten()
+            .any(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        std::fs::write(root.join("f.txt"), "theirs").unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(task.await.unwrap(), Some(StatusCode::PRECONDITION_FAILED));
+        assert_eq!(
+            std::fs::read_to_string(root.join("f.txt")).unwrap(),
+            "theirs"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        std::fs::write(sandbox.root().join("hello.txt"), "hello").unwrap();
+        let service = service(sandbox).await;
+
+        let mut res = TestClient::get("http://127.0.0.1/fs/hello.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert_eq!(res.take_string().await.unwrap(), "hello");
+
+        let res = TestClient::get("http://127.0.0.1/fs/missing.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+
+        let res = TestClient::get("http://127.0.0.1/fs/..%2F..%2Fetc%2Fpasswd")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::FORBIDDEN));
+    }
+
+    #[tokio::test]
+    async fn test_list_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        std::fs::create_dir(sandbox.root().join("sub")).unwrap();
+        std::fs::write(sandbox.root().join("sub/a.txt"), "a").unwrap();
+        std::fs::write(sandbox.root().join("sub/.secret"), "s").unwrap();
+        let service = service(sandbox).await;
+
+        let mut res = TestClient::get("http://127.0.0.1/fs/sub")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let listing: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(listing["path"], "sub");
+        assert_eq!(listing["total"], 1);
+        assert_eq!(listing["entries"][0]["name"], "a.txt");
+        assert_eq!(listing["entries"][0]["kind"], "file");
+
+        let mut res = TestClient::get("http://127.0.0.1/fs/sub?hidden=true&limit=1&offset=1")
+            .send(&service)
+            .await;
+        let listing: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(listing["total"], 2);
+        assert_eq!(listing["entries"][0]["name"], "a.txt");
+
+        let mut res = TestClient::get("http://127.0.0.1/fs/")
+            .add_header("accept", "text/html", true)
+            .send(&service)
+            .await;
+        let html = res.take_string().await.unwrap();
+        assert!(html.contains("href=\"/fs/sub/\""));
+    }
+
+    #[tokio::test]
+    async fn test_write_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        let service = service(sandbox).await;
+
+        let res = TestClient::put("http://127.0.0.1/fs/a/b.txt")
+            .text("one")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+
+        let res = TestClient::put("http://127.0.0.1/fs/a/b.txt?mkdirs=true")
+            .text("one")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        let etag = res
+            .headers()
+            .get("etag")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            std::fs::read_to_string(root.join("a/b.txt")).unwrap(),
+            "one"
+        );
+
+        let res = TestClient::put("http://127.0.0.1/fs/a/b.txt")
+            .add_header("if-none-match", "*", true)
+            .text("two")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::PRECONDITION_FAILED));
+
+        let res = TestClient::put("http://127.0.0.1/fs/a/b.txt")
+            .add_header("if-match", "\"stale\"", true)
+            .text("two")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::PRECONDITION_FAILED));
+
+        let res = TestClient::put("http://127.0.0.1/fs/a/b.txt")
+            .add_header("if-match", etag, true)
+            .text("two")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert_eq!(
+            std::fs::read_to_string(root.join("a/b.txt")).unwrap(),
+            "two"
+        );
+        assert_eq!(std::fs::read_dir(root.join("a")).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_download_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        std::fs::write(sandbox.root().join("digits.txt"), "0123456789").unwrap();
+        let service = service(sandbox).await;
+        let url = "http://127.0.0.1/fs/digits.txt";
+
+        let mut res = TestClient::get(url)
+            .add_header("range", "bytes=2-4", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::PARTIAL_CONTENT));
+        assert_eq!(res.headers()["content-range"], "bytes 2-4/10");
+        assert_eq!(res.take_string().await.unwrap(), "234");
+        let etag = res.headers()["etag"].to_str().unwrap().to_string();
+
+        let mut res = TestClient::get(url)
+            .add_header("range", "bytes=0-0,-2", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::PARTIAL_CONTENT));
+        let content_type = res.headers()["content-type"].to_str().unwrap().to_string();
+        assert!(content_type.starts_with("multipart/byteranges; boundary="));
+        let length: usize = res.headers()["content-length"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = res.take_string().await.unwrap();
+        assert_eq!(body.len(), length);
+        assert!(body.contains("Content-Range: bytes 0-0/10\r\n\r\n0\r\n"));
+        assert!(body.contains("Content-Range: bytes 8-9/10\r\n\r\n89\r\n"));
+
+        let res = TestClient::get(url)
+            .add_header("range", "bytes=20-", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::RANGE_NOT_SATISFIABLE));
+        assert_eq!(res.headers()["content-range"], "bytes */10");
+
+        let res = TestClient::get(url)
+            .add_header("if-none-match", &etag, true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_MODIFIED));
+
+        let mut res = TestClient::get(url)
+            .add_header("range", "bytes=2-4", true)
+            .add_header("if-range", "\"stale\"", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert_eq!(res.take_string().await.unwrap(), "0123456789");
+    }
+
+    #[tokio::test]
+    async fn test_upload_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        std::fs::write(root.join("a.txt"), "old").unwrap();
+        let service = service(sandbox).await;
+
+        let body = "--XYZ\r\n\
+            Content-Disposition: form-data; name=\"f1\"; filename=\"a.txt\"\r\n\r\n\
+            new\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"f2\"; filename=\"../../b.txt\"\r\n\r\n\
+            bbb\r\n\
+            --XYZ--\r\n";
+        let upload = |policy: &str| {
+            TestClient::post(format!(
+                "http://127.0.0.1/fs/up?mkdirs=true&overwrite={}",
+                policy
+            ))
+            .add_header("content-type", "multipart/form-data; boundary=XYZ", true)
+            .body(body)
+        };
+
+        let mut res = upload("fail").send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let results: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(results[0]["path"], "up/a.txt");
+        assert_eq!(results[1]["path"], "up/b.txt");
+        assert_eq!(results[1]["size"], 3);
+
+        let mut res = upload("fail").send(&service).await;
+        let results: serde_json::Value = res.take_json().await.unwrap();
+        assert!(results[0]["error"].is_string());
+
+        let mut res = upload("rename").send(&service).await;
+        let results: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(results[0]["path"], "up/a (1).txt");
+
+        upload("replace").send(&service).await;
+        assert_eq!(
+            std::fs::read_to_string(root.join("up/a.txt")).unwrap(),
+            "new"
+        );
+        assert_eq!(std::fs::read_dir(root.join("up")).unwrap().count(), 4);
+        assert_eq!(std::fs::read_to_string(root.join("a.txt")).unwrap(), "old");
+    }
+
+    #[tokio::test]
+    async fn test_upload_long_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        let service = service(sandbox).await;
+
+        // 255 字节的文件名加上临时文件前后缀也不能超出 NAME_MAX
+        let long = format!("{}.txt", "x".repeat(251));
+        let body = format!(
+            "--XYZ\r\n\
+            Content-Disposition: form-data; name=\"f1\"; filename=\"{}\"\r\n\r\n\
+            long\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"f2\"; filename=\"{}\"\r\n\r\n\
+            again\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"f3\"; filename=\"b.txt\"\r\n\r\n\
+            bbb\r\n\
+            --XYZ--\r\n",
+            long, long
+        );
+        let mut res = TestClient::post("http://127.0.0.1/fs/?overwrite=rename")
+            .add_header("content-type", "multipart/form-data; boundary=XYZ", true)
+            .body(body)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let results: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(results[0]["path"], long.as_str());
+        assert_eq!(std::fs::read_to_string(root.join(&long)).unwrap(), "long");
+        // 加序号后超长的名称只记为该文件的错误
+        assert!(results[1]["error"].is_string());
+        assert_eq!(results[2]["path"], "b.txt");
+        assert!(
+            !std::fs::read_dir(&root)
+                .unwrap()
+                .flatten()
+                .any(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_overwrite_to_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        let _socket = std::os::unix::net::UnixListener::bind(root.join("sock")).unwrap();
+        std::fs::write(root.join("a.txt"), "a").unwrap();
+        std::fs::write(root.join("b.txt"), "b").unwrap();
+        let service = service(sandbox).await;
+
+        // 复制失败时被覆盖的目标原样保留
+        let res = TestClient::post("http://127.0.0.1/fs/sock?copy=b.txt&conflict=overwrite")
+            .send(&service)
+            .await;
+        assert!(res.status_code.unwrap().is_server_error());
+        assert_eq!(std::fs::read_to_string(root.join("b.txt")).unwrap(), "b");
+        let mut res = TestClient::get("http://127.0.0.1/trash")
+            .send(&service)
+            .await;
+        let items: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(items.as_array().unwrap().len(), 0);
+
+        // 成功覆盖时旧目标进入回收站
+        let res = TestClient::post("http://127.0.0.1/fs/a.txt?move=b.txt&conflict=overwrite")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert_eq!(std::fs::read_to_string(root.join("b.txt")).unwrap(), "a");
+        let mut res = TestClient::get("http://127.0.0.1/trash")
+            .send(&service)
+            .await;
+        let items: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(items[0]["original_path"], "b.txt");
+    }
+
+    #[tokio::test]
+    async fn test_file_operations() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        let service = service(sandbox).await;
+
+        let res = TestClient::post("http://127.0.0.1/fs/a/b?mkdir")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert!(root.join("a/b").is_dir());
+        std::fs::write(root.join("a/b/f.txt"), "f").unwrap();
+
+        let res = TestClient::post("http://127.0.0.1/fs/a?copy=c")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert_eq!(
+            std::fs::read_to_string(root.join("c/b/f.txt")).unwrap(),
+            "f"
+        );
+
+        let res = TestClient::post("http://127.0.0.1/fs/a?copy=c")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+
+        let mut res = TestClient::post("http://127.0.0.1/fs/a?copy=c&conflict=skip")
+            .send(&service)
+            .await;
+        let result: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(result["skipped"], true);
+
+        let res = TestClient::post("http://127.0.0.1/fs/c/b/f.txt?move=../../etc/x")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::FORBIDDEN));
+
+        let res = TestClient::post("http://127.0.0.1/fs/c/b/f.txt?move=d/g.txt&mkdirs=true")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert!(root.join("d/g.txt").is_file());
+        assert!(!root.join("c/b/f.txt").exists());
+
+        let res = TestClient::delete("http://127.0.0.1/fs/a")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+        let res = TestClient::delete("http://127.0.0.1/fs/a?recursive=true")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert!(!root.join("a").exists());
+
+        let res = TestClient::delete("http://127.0.0.1/fs/")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::FORBIDDEN));
+    }
+
+    #[tokio::test]
+    async fn test_partial_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        std::fs::write(root.join("f.txt"), "hello world").unwrap();
+        let service = service(sandbox).await;
+
+        let res = TestClient::patch("http://127.0.0.1/fs/f.txt")
+            .add_header("content-range", "bytes 6-10/*", true)
+            .add_header("content-length", "5", true)
+            .body("WORLD")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert_eq!(
+            std::fs::read_to_string(root.join("f.txt")).unwrap(),
+            "hello WORLD"
+        );
+        let etag = res
+            .headers()
+            .get("etag")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let res = TestClient::post("http://127.0.0.1/fs/f.txt?append")
+            .add_header("if-match", &etag, true)
+            .body("!")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert_eq!(
+            std::fs::read_to_string(root.join("f.txt")).unwrap(),
+            "hello WORLD!"
+        );
+
+        // 旧ETag已失效
+        let res = TestClient::post("http://127.0.0.1/fs/f.txt?truncate=5")
+            .add_header("if-match", &etag, true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::PRECONDITION_FAILED));
+        let res = TestClient::post("http://127.0.0.1/fs/f.txt?truncate=5")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert_eq!(
+            std::fs::read_to_string(root.join("f.txt")).unwrap(),
+            "hello"
+        );
+
+        let res = TestClient::patch("http://127.0.0.1/fs/f.txt?offset=9")
+            .body("x")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::RANGE_NOT_SATISFIABLE));
+        // 长度不符时文件保持原样
+        let res = TestClient::patch("http://127.0.0.1/fs/f.txt")
+            .add_header("content-range", "bytes 0-1/*", true)
+            .add_header("content-length", "3", true)
+            .body("abc")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+        let res = TestClient::patch("http://127.0.0.1/fs/f.txt")
+            .add_header("content-range", "bytes 0-1/*", true)
+            .body("abc")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::LENGTH_REQUIRED));
+        let res = TestClient::patch("http://127.0.0.1/fs/f.txt")
+            .add_header("content-range", "bytes 0-1/*", true)
+            .add_header("content-length", "2", true)
+            .body("abc")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+        assert_eq!(
+            std::fs::read_to_string(root.join("f.txt")).unwrap(),
+            "hello"
+        );
+        let res = TestClient::patch("http://127.0.0.1/fs/missing.txt?offset=0")
+            .body("x")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+    }
+
+    /// 先产生一段数据再出错的请求体，模拟写到一半断开
+    struct BrokenBody(Option<&'static str>);
+
+    impl salvo::http::body::Body for BrokenBody {
+        type Data = bytes::Bytes;
+        type Error = salvo::BoxedError;
+
+        fn poll_frame(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Option<Result<salvo::http::body::Frame<Self::Data>, Self::Error>>>
+        {
+            std::task::Poll::Ready(Some(match self.0.take() {
+                Some(data) => Ok(salvo::http::body::Frame::data(data.into())),
+                None => Err("连接断开".into()),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial_write_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        std::fs::write(root.join("f.txt"), "hello").unwrap();
+        let service = service(sandbox).await;
+
+        // 覆盖一部分并越过文件末尾后失败
+        for (uri, data) in [
+            ("http://127.0.0.1/fs/f.txt?offset=3", "XYZW"),
+            ("http://127.0.0.1/fs/f.txt?offset=0", "ab"),
+            ("http://127.0.0.1/fs/f.txt?append", "tail"),
+        ] {
+            let builder = if uri.ends_with("append") {
+                TestClient::post(uri)
+            } else {
+                TestClient::patch(uri)
+            };
+            let res = builder
+                .body(salvo::http::ReqBody::Boxed {
+                    inner: Box::pin(BrokenBody(Some(data))),
+                    fusewire: None,
+                })
+                .send(&service)
+                .await;
+            assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+            assert_eq!(
+                std::fs::read_to_string(root.join("f.txt")).unwrap(),
+                "hello"
+            );
+        }
+        // 不留下保存原内容的临时文件
+        assert!(
+            !std::fs::read_dir(&root)
+                .unwrap()
+                .flatten()
+                .any(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_upload() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        let service = service(sandbox).await;
+
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "bin/app.txt", &b"hello"[..])
+            .unwrap();
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+
+        let mut res = TestClient::post("http://127.0.0.1/fs/artifacts?extract=true&mkdirs=true")
+            .bytes(archive.clone())
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let result: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(result["extracted"], 1);
+        assert_eq!(result["entries"][0]["path"], "artifacts/bin/app.txt");
+        assert_eq!(
+            std::fs::read_to_string(root.join("artifacts/bin/app.txt")).unwrap(),
+            "hello"
+        );
+
+        let res = TestClient::post("http://127.0.0.1/fs/artifacts?extract=true")
+            .bytes(archive)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::MULTI_STATUS));
+
+        let res = TestClient::post("http://127.0.0.1/fs/artifacts?extract=true")
+            .bytes(b"not an archive".to_vec())
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+        assert!(
+            std::fs::read_dir(root.join(".staging"))
+                .unwrap()
+                .next()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_head_and_stat() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        std::fs::write(root.join("image.bin"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let service = service(sandbox).await;
+
+        let mut res = TestClient::head("http://127.0.0.1/fs/image.bin")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert_eq!(res.content_type().unwrap().to_string(), "image/png");
+        assert_eq!(res.headers().get("content-length").unwrap(), "16");
+        assert!(res.headers().contains_key("etag"));
+        assert!(res.take_string().await.unwrap().is_empty());
+
+        let mut res = TestClient::get("http://127.0.0.1/fs/image.bin?stat")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let stat: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(stat["path"], "image.bin");
+        assert_eq!(stat["kind"], "file");
+        assert_eq!(stat["size"], 16);
+        assert_eq!(stat["mime"], "image/png");
+        assert!(stat["mtime"].is_string());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("image.bin", root.join("link")).unwrap();
+            let mut res = TestClient::get("http://127.0.0.1/fs/link?stat")
+                .send(&service)
+                .await;
+            let stat: serde_json::Value = res.take_json().await.unwrap();
+            assert_eq!(stat["kind"], "symlink");
+            assert_eq!(stat["symlink_target"], "image.bin");
+            assert!(stat["uid"].is_number());
+            assert!(stat["inode"].is_number());
+        }
+
+        let res = TestClient::get("http://127.0.0.1/fs/missing?stat")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn test_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path().join("root")).unwrap();
+        let root = sandbox.root().to_path_buf();
+        std::fs::write(root.join("hello.txt"), "hello world").unwrap();
+        let db = crate::db::test_db(dir.path()).await;
+        let router = Router::new()
+            .hoop(
+                affix_state::inject(Arc::new(sandbox))
+                    .inject(db)
+                    .inject(Arc::new(LockManager::memory())),
+            )
+            .push(crate::web::fs_router());
+        let service = Service::new(router);
+        let sha256 = "sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:";
+
+        let mut res = TestClient::get("http://127.0.0.1/fs/hello.txt?checksum=sha256,crc32c")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert_eq!(
+            res.headers().get("repr-digest").unwrap(),
+            &format!("{}, crc32c=:yZRlqg==:", sha256)
+        );
+        let result: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(result["checksums"][1]["hex"], "c99465aa");
+        assert_eq!(result["checksums"][0]["cached"], false);
+
+        let mut res = TestClient::get("http://127.0.0.1/fs/hello.txt?checksum")
+            .send(&service)
+            .await;
+        let result: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(result["checksums"][0]["algorithm"], "sha256");
+        assert_eq!(result["checksums"][0]["cached"], true);
+
+        let res = TestClient::get("http://127.0.0.1/fs/hello.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.headers().get("content-digest").unwrap(), sha256);
+        assert_eq!(res.headers().get("repr-digest").unwrap(), sha256);
+        // 未缓存的摘要不在下载时计算，后台算好后再下载才带上
+        let range = || {
+            TestClient::get("http://127.0.0.1/fs/hello.txt")
+                .add_header("range", "bytes=0-4", true)
+                .add_header("want-repr-digest", "md5=5", true)
+        };
+        let res = range().send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::PARTIAL_CONTENT));
+        assert!(!res.headers().contains_key("repr-digest"));
+        let mut res = range().send(&service).await;
+        for _ in 0..100 {
+            if res.headers().contains_key("repr-digest") {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            res = range().send(&service).await;
+        }
+        assert_eq!(
+            res.headers().get("repr-digest").unwrap(),
+            "md5=:XrY7u+Ae7tCTyyK7j1rNww==:"
+        );
+        assert!(!res.headers().contains_key("content-digest"));
+
+        // 摘要不匹配时不提交
+        let res = TestClient::put("http://127.0.0.1/fs/hello.txt")
+            .add_header("content-digest", sha256, true)
+            .body("goodbye")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+        assert_eq!(
+            std::fs::read_to_string(root.join("hello.txt")).unwrap(),
+            "hello world"
+        );
+        let res = TestClient::put("http://127.0.0.1/fs/copy.txt")
+            .add_header("content-digest", sha256, true)
+            .body("hello world")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        assert_eq!(res.headers().get("repr-digest").unwrap(), sha256);
+    }
+
+    #[tokio::test]
+    async fn test_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("docs/a.txt"), "old").unwrap();
+        let service = service(sandbox).await;
+
+        let mut res = TestClient::delete("http://127.0.0.1/fs/docs/a.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let result: serde_json::Value = res.take_json().await.unwrap();
+        let id = result["trash_id"].as_str().unwrap().to_string();
+        assert!(!root.join("docs/a.txt").exists());
+
+        // 回收站目录不能通过文件接口访问
+        let res = TestClient::get(format!("http://127.0.0.1/fs/.trash/{}", id))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::FORBIDDEN));
+
+        let mut res = TestClient::get("http://127.0.0.1/trash?path=docs")
+            .send(&service)
+            .await;
+        let items: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(items[0]["id"], id.as_str());
+        assert_eq!(items[0]["original_path"], "docs/a.txt");
+        assert_eq!(items[0]["size"], 3);
+        assert!(items[0]["deleted_by"].is_string());
+
+        std::fs::write(root.join("docs/a.txt"), "new").unwrap();
+        let url = format!("http://127.0.0.1/trash/{}", id);
+        let res = TestClient::post(&url).send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+        let res = TestClient::post(format!("{}?to=docs/b.txt", url))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert_eq!(
+            std::fs::read_to_string(root.join("docs/b.txt")).unwrap(),
+            "old"
+        );
+        let res = TestClient::get(&url).send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+
+        let mut res = TestClient::delete("http://127.0.0.1/fs/docs?recursive=true")
+            .send(&service)
+            .await;
+        let result: serde_json::Value = res.take_json().await.unwrap();
+        let url = format!(
+            "http://127.0.0.1/trash/{}",
+            result["trash_id"].as_str().unwrap()
+        );
+        let res = TestClient::delete(&url).send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert_eq!(std::fs::read_dir(root.join(".trash")).unwrap().count(), 0);
+
+        std::fs::write(root.join("c.txt"), "c").unwrap();
+        let res = TestClient::delete("http://127.0.0.1/fs/c.txt?permanent=true")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let mut res = TestClient::get("http://127.0.0.1/trash")
+            .send(&service)
+            .await;
+        let items: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(items.as_array().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        let service = service(sandbox).await;
+        let url = "http://127.0.0.1/fs/notes.txt";
+
+        for body in ["one", "two", "three"] {
+            TestClient::put(url).body(body).send(&service).await;
+        }
+        let res = TestClient::patch(format!("{}?offset=0", url))
+            .body("T")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert_eq!(
+            std::fs::read_to_string(root.join("notes.txt")).unwrap(),
+            "Three"
+        );
+        // 紧接着的原地写入不再复制整个文件
+        let res = TestClient::patch(format!("{}?offset=1", url))
+            .body("H")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+
+        let mut res = TestClient::get(format!("{}?versions", url))
+            .send(&service)
+            .await;
+        let versions: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(versions[0]["version"], 3);
+        assert_eq!(versions[0]["size"], 5);
+        assert_eq!(versions[1]["version"], 2);
+        assert_eq!(versions[1]["size"], 3);
+        assert!(versions.as_array().unwrap().len() <= 3);
+
+        let mut res = TestClient::get(format!("{}?version=2", url))
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "two");
+        let res = TestClient::get(format!("{}?version=9", url))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+
+        let res = TestClient::post(format!("{}?restore_version=2", url))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert_eq!(
+            std::fs::read_to_string(root.join("notes.txt")).unwrap(),
+            "two"
+        );
+        let mut res = TestClient::get(format!("{}?version=4", url))
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "THree");
+        assert_eq!(
+            std::fs::read_dir(root.join(".versions")).unwrap().count(),
+            3
+        );
+
+        // 规则为 off 的目录不保留版本
+        for body in ["a", "b"] {
+            TestClient::put("http://127.0.0.1/fs/tmp/x?mkdirs=true")
+                .body(body)
+                .send(&service)
+                .await;
+        }
+        let mut res = TestClient::get("http://127.0.0.1/fs/tmp/x?versions")
+            .send(&service)
+            .await;
+        let versions: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(versions.as_array().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_locks() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        std::fs::create_dir_all(root.join("docs/sub")).unwrap();
+        std::fs::write(root.join("docs/sub/a.txt"), "a").unwrap();
+        let service = service(sandbox).await;
+
+        let mut res = TestClient::post("http://127.0.0.1/locks/docs?depth=infinity&timeout=60")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        let lock: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(lock["depth"], "infinity");
+        let token = lock["token"].as_str().unwrap().to_string();
+        assert_eq!(
+            res.headers()["lock-token"].to_str().unwrap(),
+            format!("<{}>", token)
+        );
+
+        let res = TestClient::post("http://127.0.0.1/locks/docs/sub/a.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::LOCKED));
+
+        // 未出示令牌的写入、删除和移动都被拒绝
+        let res = TestClient::put("http://127.0.0.1/fs/docs/sub/a.txt")
+            .body("b")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::LOCKED));
+        let res = TestClient::delete("http://127.0.0.1/fs/docs?recursive=true")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::LOCKED));
+        let res = TestClient::post("http://127.0.0.1/fs/docs/sub/a.txt?move=b.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::LOCKED));
+
+        let res = TestClient::put("http://127.0.0.1/fs/docs/sub/a.txt")
+            .add_header("lock-token", format!("<{}>", token), true)
+            .body("b")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert_eq!(
+            std::fs::read_to_string(root.join("docs/sub/a.txt")).unwrap(),
+            "b"
+        );
+
+        let mut res = TestClient::get("http://127.0.0.1/locks/docs/sub")
+            .send(&service)
+            .await;
+        let locks: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(locks.as_array().unwrap().len(), 1);
+        // 列表不泄露令牌
+        assert_eq!(locks[0]["path"], "docs");
+        assert!(locks[0].get("token").is_none());
+
+        let mut res = TestClient::post("http://127.0.0.1/locks/docs?refresh&timeout=600")
+            .add_header("lock-token", &token, true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let lock: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(lock["timeout"], 600);
+        assert!(lock.get("token").is_none());
+
+        let res = TestClient::delete("http://127.0.0.1/locks/docs/sub")
+            .add_header("lock-token", &token, true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+        let res = TestClient::delete("http://127.0.0.1/locks/docs")
+            .add_header("lock-token", &token, true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+
+        let res = TestClient::post("http://127.0.0.1/fs/docs/sub/a.txt?move=b.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert!(root.join("b.txt").exists());
+
+        // 子路径上的锁同样阻止删除其父目录
+        let res = TestClient::post("http://127.0.0.1/locks/docs/sub/c.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        let res = TestClient::delete("http://127.0.0.1/fs/docs?recursive=true")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::LOCKED));
+    }
+
+    #[tokio::test]
+    async fn test_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        std::fs::write(root.join("keep.txt"), "keep").unwrap();
+        std::fs::write(root.join("old.txt"), "old").unwrap();
+        let service = service(sandbox).await;
+
+        // 非原子模式下失败的操作不影响其余操作
+        let mut res = TestClient::post("http://127.0.0.1/batch")
+            .json(&serde_json::json!([
+                {"op": "mkdir", "path": "a/b"},
+                {"op": "write", "path": "a/b/x.txt", "content": "x"},
+                {"op": "move", "from": "missing", "to": "y"},
+                {"op": "copy", "from": "a", "to": "c"},
+            ]))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::MULTI_STATUS));
+        let result: serde_json::Value = res.take_json().await.unwrap();
+        let statuses: Vec<_> = result["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["status"].as_u64().unwrap())
+            .collect();
+        assert_eq!(statuses, vec![200, 200, 404, 200]);
+        assert_eq!(
+            std::fs::read_to_string(root.join("c/b/x.txt")).unwrap(),
+            "x"
+        );
+
+        // 原子模式下后面的失败撤销前面的全部步骤
+        let mut res = TestClient::post("http://127.0.0.1/batch?atomic=true")
+            .json(&serde_json::json!([
+                {"op": "write", "path": "old.txt", "content": "bmV3", "base64": true},
+                {"op": "write", "path": "n/e/w.txt", "content": "w", "mkdirs": true},
+                {"op": "move", "from": "keep.txt", "to": "c/b/x.txt", "conflict": "overwrite"},
+                {"op": "delete", "path": "a", "recursive": true},
+                {"op": "delete", "path": "c", "recursive": true, "permanent": true},
+                {"op": "mkdir", "path": "old.txt"},
+                {"op": "mkdir", "path": "never"},
+            ]))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::MULTI_STATUS));
+        let result: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(result["committed"], false);
+        let results = result["results"].as_array().unwrap();
+        assert_eq!(results[0]["rolled_back"], true);
+        assert_eq!(results[5]["status"], 409);
+        assert_eq!(results[6]["status"], 424);
+        assert_eq!(
+            std::fs::read_to_string(root.join("old.txt")).unwrap(),
+            "old"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("keep.txt")).unwrap(),
+            "keep"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("c/b/x.txt")).unwrap(),
+            "x"
+        );
+        assert!(root.join("a/b/x.txt").exists());
+        assert!(!root.join("n").exists());
+        assert!(!root.join("never").exists());
+        assert_eq!(std::fs::read_dir(root.join(".staging")).unwrap().count(), 0);
+        let mut res = TestClient::get("http://127.0.0.1/trash")
+            .send(&service)
+            .await;
+        let trash: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(trash.as_array().unwrap().len(), 0);
+        let mut res = TestClient::get("http://127.0.0.1/fs/old.txt?versions")
+            .send(&service)
+            .await;
+        let versions: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(versions.as_array().unwrap().len(), 0);
+
+        let mut res = TestClient::post("http://127.0.0.1/batch?atomic=true")
+            .json(&serde_json::json!([
+                {"op": "write", "path": "old.txt", "content": "new"},
+                {"op": "delete", "path": "c", "recursive": true, "permanent": true},
+            ]))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let result: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(result["committed"], true);
+        assert_eq!(
+            std::fs::read_to_string(root.join("old.txt")).unwrap(),
+            "new"
+        );
+        assert!(!root.join("c").exists());
+        assert_eq!(std::fs::read_dir(root.join(".staging")).unwrap().count(), 0);
+    }
+}
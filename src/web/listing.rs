@@ -0,0 +1,339 @@
+use crate::sandbox::Sandbox;
+use crate::web::fs::io_status;
+use chrono::{DateTime, Utc};
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
+use salvo::http::StatusError;
+use salvo::prelude::{Json, Text};
+use salvo::{Request, Response};
+use serde::{Deserialize, Serialize};
+use std::fs::Metadata;
+use std::path::Path;
+
+/// URL路径段中需要编码的字符
+pub(crate) const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// 目录项类型
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Dir,
+    File,
+    Symlink,
+}
+
+impl EntryKind {
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            EntryKind::Symlink
+        } else if file_type.is_dir() {
+            EntryKind::Dir
+        } else {
+            EntryKind::File
+        }
+    }
+}
+
+/// 目录项
+#[derive(Serialize, Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub mtime: Option<DateTime<Utc>>,
+    pub mode: u32,
+}
+
+impl Entry {
+    pub fn new(name: String, metadata: &Metadata) -> Self {
+        Entry {
+            name,
+            kind: EntryKind::from_metadata(metadata),
+            size: metadata.len(),
+            mtime: metadata.modified().ok().map(DateTime::<Utc>::from),
+            mode: file_mode(metadata),
+        }
+    }
+}
+
+/// 排序字段
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Mtime,
+    Kind,
+}
+
+/// 排序方向
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// 列表查询参数
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ListQuery {
+    pub sort: SortKey,
+    pub order: SortOrder,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    pub hidden: bool,
+}
+
+impl Default for ListQuery {
+    fn default() -> Self {
+        ListQuery {
+            sort: SortKey::Name,
+            order: SortOrder::Asc,
+            offset: 0,
+            limit: None,
+            hidden: false,
+        }
+    }
+}
+
+/// 列表结果
+#[derive(Serialize, Debug)]
+struct Listing {
+    path: String,
+    total: usize,
+    offset: usize,
+    limit: Option<usize>,
+    entries: Vec<Entry>,
+}
+
+/// 文件权限位
+#[cfg(unix)]
+pub fn file_mode(metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+/// 文件权限位
+#[cfg(not(unix))]
+pub fn file_mode(metadata: &Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+/// 读取目录项，按查询参数过滤和排序
+pub async fn read_entries(
+    sandbox: &Sandbox,
+    dir: &Path,
+    query: &ListQuery,
+) -> std::io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut read_dir = tokio::fs::read_dir(dir).await?;
+    while let Some(item) = read_dir.next_entry().await? {
+        let name = item.file_name().to_string_lossy().into_owned();
+        if (!query.hidden && sandbox.is_hidden(&name)) || sandbox.is_excluded(&item.path()) {
+            continue;
+        }
+        match item.metadata().await {
+            Ok(metadata) => entries.push(Entry::new(name, &metadata)),
+            Err(e) => log::warn!("读取元数据失败 {}: {}", item.path().display(), e),
+        }
+    }
+
+    entries.sort_by(|a, b| {
+        let ord = match query.sort {
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Size => a.size.cmp(&b.size).then_with(|| a.name.cmp(&b.name)),
+            SortKey::Mtime => a.mtime.cmp(&b.mtime).then_with(|| a.name.cmp(&b.name)),
+            SortKey::Kind => a.kind.cmp(&b.kind).then_with(|| a.name.cmp(&b.name)),
+        };
+        match query.order {
+            SortOrder::Asc => ord,
+            SortOrder::Desc => ord.reverse(),
+        }
+    });
+    Ok(entries)
+}
+
+/// 列出目录，浏览器请求返回HTML，其余返回JSON
+pub async fn list_dir(
+    sandbox: &Sandbox,
+    dir: &Path,
+    req: &mut Request,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let query = req
+        .parse_queries::<ListQuery>()
+        .map_err(|e| StatusError::bad_request().brief(e.to_string()))?;
+    let entries = read_entries(sandbox, dir, &query)
+        .await
+        .map_err(io_status)?;
+    let total = entries.len();
+    let entries: Vec<Entry> = entries
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+    let path = sandbox.relative(dir).unwrap_or_default();
+
+    let wants_html = req
+        .accept()
+        .iter()
+        .any(|m| m.type_() == "text" && m.subtype() == "html");
+    if wants_html {
+        let prefix = match sandbox.mount() {
+            "" => String::new(),
+            mount => format!("/{}", mount),
+        };
+        res.render(Text::Html(render_html(&prefix, &path, &entries)));
+    } else {
+        res.render(Json(Listing {
+            path,
+            total,
+            offset: query.offset,
+            limit: query.limit,
+            entries,
+        }));
+    }
+    Ok(())
+}
+
+/// 生成目录索引页面，`prefix` 为挂载点的URL前缀
+fn render_html(prefix: &str, path: &str, entries: &[Entry]) -> String {
+    let base: String = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| format!("/{}", utf8_percent_encode(s, SEGMENT)))
+        .collect();
+    let title = format!("{}/{}", prefix, path);
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\">");
+    html.push_str(&format!(
+        "<title>Index of {}</title></head>\n",
+        escape_html(&title)
+    ));
+    html.push_str(&format!(
+        "<body>\n<h1>Index of {}</h1>\n",
+        escape_html(&title)
+    ));
+    html.push_str("<table>\n<tr><th>Name</th><th>Size</th><th>Modified</th></tr>\n");
+    if !path.is_empty() {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let suffix = if entry.kind == EntryKind::Dir {
+            "/"
+        } else {
+            ""
+        };
+        let href = format!(
+            "{}/fs{}/{}{}",
+            prefix,
+            base,
+            utf8_percent_encode(&entry.name, SEGMENT),
+            suffix
+        );
+        let mtime = entry
+            .mtime
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let size = if entry.kind == EntryKind::Dir {
+            "-".to_string()
+        } else {
+            entry.size.to_string()
+        };
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            escape_html(&href),
+            escape_html(&entry.name),
+            suffix,
+            size,
+            mtime
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// HTML转义
+pub fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        std::fs::write(sandbox.root().join("b.txt"), "b".repeat(65536)).unwrap();
+        std::fs::write(sandbox.root().join("a.txt"), "a").unwrap();
+        std::fs::write(sandbox.root().join(".hidden"), "").unwrap();
+        std::fs::create_dir(sandbox.root().join("c")).unwrap();
+
+        let query = ListQuery::default();
+        let entries = read_entries(&sandbox, sandbox.root(), &query)
+            .await
+            .unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["a.txt", "b.txt", "c"]);
+
+        let query = ListQuery {
+            sort: SortKey::Size,
+            order: SortOrder::Desc,
+            hidden: true,
+            ..Default::default()
+        };
+        let entries = read_entries(&sandbox, sandbox.root(), &query)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].name, "b.txt");
+    }
+
+    #[test]
+    fn test_render_html_escapes_names() {
+        let entries = vec![Entry {
+            name: "<a b>.txt".to_string(),
+            kind: EntryKind::File,
+            size: 1,
+            mtime: None,
+            mode: 0o644,
+        }];
+        let html = render_html("", "sub dir", &entries);
+        assert!(html.contains("href=\"/fs/sub%20dir/%3Ca%20b%3E.txt\""));
+        let html = render_html("/data", "", &entries);
+        assert!(html.contains("href=\"/data/fs/%3Ca%20b%3E.txt\""));
+        assert!(html.contains("&lt;a b&gt;.txt"));
+    }
+}
@@ -0,0 +1,288 @@
+use crate::lock::{Depth, Lock, LockError, LockManager, Scope, TOKEN_PREFIX};
+use crate::sandbox::Sandbox;
+use crate::web::fs::{client_addr, request_path, sandbox};
+use salvo::http::header::{HeaderName, HeaderValue};
+use salvo::http::{HeaderMap, StatusCode, StatusError};
+use salvo::prelude::Json;
+use salvo::{Depot, Request, Response, Router, handler};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+
+/// 出示锁令牌的请求头，可带尖括号，多个令牌以逗号分隔
+pub const LOCK_TOKEN: HeaderName = HeaderName::from_static("lock-token");
+/// 默认有效期（秒）
+pub(crate) const DEFAULT_TIMEOUT: u64 = 300;
+/// 最长有效期（秒）
+pub(crate) const MAX_TIMEOUT: u64 = 86400;
+
+/// 列表和刷新返回的锁，不含令牌：令牌只在加锁时交给持有者
+#[derive(Serialize, Debug)]
+struct LockInfo<'a> {
+    mount: &'a str,
+    path: &'a str,
+    depth: Depth,
+    scope: Scope,
+    owner: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner_xml: Option<&'a str>,
+    timeout: u64,
+    created_at: i64,
+    expires_at: i64,
+}
+
+impl<'a> From<&'a Lock> for LockInfo<'a> {
+    fn from(lock: &'a Lock) -> Self {
+        LockInfo {
+            mount: &lock.mount,
+            path: &lock.path,
+            depth: lock.depth,
+            scope: lock.scope,
+            owner: &lock.owner,
+            owner_xml: lock.owner_xml.as_deref(),
+            timeout: lock.timeout,
+            created_at: lock.created_at,
+            expires_at: lock.expires_at,
+        }
+    }
+}
+
+/// 从Depot中取出锁管理器
+pub(crate) fn locks(depot: &Depot) -> Result<Arc<LockManager>, StatusError> {
+    depot
+        .obtain::<Arc<LockManager>>()
+        .cloned()
+        .map_err(|_| StatusError::internal_server_error().brief("锁管理器未配置"))
+}
+
+/// 将锁错误映射为HTTP状态
+pub(crate) fn lock_status(e: LockError) -> StatusError {
+    match e {
+        LockError::Locked(_) => StatusError::locked().brief(e.to_string()),
+        LockError::NotFound(_) => StatusError::conflict().brief(e.to_string()),
+        LockError::Backend(_) => {
+            log::error!("{}", e);
+            StatusError::service_unavailable().brief("锁存储不可用")
+        }
+    }
+}
+
+/// 请求出示的锁令牌，来自 `Lock-Token` 以及 WebDAV 的 `If` 头
+pub fn presented_tokens(headers: &HeaderMap) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for value in headers.get_all(LOCK_TOKEN) {
+        let Ok(value) = value.to_str() else { continue };
+        tokens.extend(
+            value
+                .split(',')
+                .map(|t| t.trim().trim_start_matches('<').trim_end_matches('>'))
+                .filter(|t| !t.is_empty())
+                .map(str::to_string),
+        );
+    }
+    // `If: (<opaquelocktoken:...>)`，只取出其中的令牌，不解析条件；`Not <...>` 不算出示
+    for value in headers.get_all("if") {
+        let Ok(mut rest) = value.to_str() else {
+            continue;
+        };
+        while let Some(start) = rest.find(&format!("<{}", TOKEN_PREFIX)) {
+            let Some(len) = rest[start..].find('>') else {
+                break;
+            };
+            let before = rest[..start].trim_end();
+            let negated = before
+                .len()
+                .checked_sub(3)
+                .and_then(|i| before.get(i..))
+                .is_some_and(|word| word.eq_ignore_ascii_case("not"));
+            if !negated {
+                tokens.push(rest[start + 1..start + len].to_string());
+            }
+            rest = &rest[start + len..];
+        }
+    }
+    tokens
+}
+
+/// 修改 `path` 前检查锁：作用于它的锁的令牌都必须出示，`tree` 为真时还包括其子树中的锁
+pub(crate) async fn require(
+    depot: &Depot,
+    req: &Request,
+    sandbox: &Sandbox,
+    path: &Path,
+    tree: bool,
+) -> Result<(), StatusError> {
+    let rel = sandbox.relative(path).unwrap_or_default();
+    locks(depot)?
+        .check(
+            sandbox.mount(),
+            &rel,
+            tree,
+            &presented_tokens(req.headers()),
+        )
+        .await
+        .map_err(lock_status)
+}
+
+/// 挂载点名称和请求路径规范化后的相对路径
+fn lock_path(req: &Request, depot: &Depot) -> Result<(String, String), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let path = sandbox.resolve_nofollow(&request_path(req))?;
+    Ok((
+        sandbox.mount().to_string(),
+        sandbox.relative(&path).unwrap_or_default(),
+    ))
+}
+
+/// 查询令牌对应的锁，并确认它属于请求的路径
+async fn owned_lock(
+    locks: &LockManager,
+    token: &str,
+    mount: &str,
+    rel: &str,
+) -> Result<(), StatusError> {
+    let lock = locks.get(token).await.map_err(lock_status)?;
+    if lock.mount != mount || lock.path != rel {
+        return Err(StatusError::conflict().brief("令牌不属于该路径"));
+    }
+    Ok(())
+}
+
+fn timeout(req: &Request) -> u64 {
+    req.query::<u64>("timeout")
+        .unwrap_or(DEFAULT_TIMEOUT)
+        .clamp(1, MAX_TIMEOUT)
+}
+
+/// 请求中唯一的令牌
+fn token(req: &Request) -> Result<String, StatusError> {
+    let mut tokens = presented_tokens(req.headers());
+    match tokens.len() {
+        1 => Ok(tokens.remove(0)),
+        0 => Err(StatusError::bad_request().brief("缺少 Lock-Token")),
+        _ => Err(StatusError::bad_request().brief("只能出示一个 Lock-Token")),
+    }
+}
+
+/// GET /locks/{path}: 列出作用于该路径或其子路径的锁，不返回令牌
+#[handler]
+async fn list(req: &mut Request, depot: &mut Depot, res: &mut Response) -> Result<(), StatusError> {
+    let (mount, rel) = lock_path(req, depot)?;
+    let locks = locks(depot)?
+        .list(&mount, &rel)
+        .await
+        .map_err(lock_status)?;
+    res.render(Json(locks.iter().map(LockInfo::from).collect::<Vec<_>>()));
+    Ok(())
+}
+
+/// POST /locks/{path}: 加排他锁
+///
+/// `?depth=infinity` 锁定整个子树，默认只锁定路径本身；`?timeout=秒` 为有效期；
+/// `?owner=` 记录持有者，默认为客户端地址。路径可以尚不存在。
+#[handler]
+async fn acquire(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let (mount, rel) = lock_path(req, depot)?;
+    let depth = match req.query::<String>("depth").as_deref() {
+        None | Some("0") => Depth::Zero,
+        Some("infinity") => Depth::Infinity,
+        Some(other) => {
+            return Err(StatusError::bad_request().brief(format!("无效的 depth: {}", other)));
+        }
+    };
+    let owner = req
+        .query::<String>("owner")
+        .filter(|o| !o.is_empty())
+        .unwrap_or_else(|| client_addr(req));
+    let lock = locks(depot)?
+        .acquire(&mount, &rel, depth, &owner, timeout(req))
+        .await
+        .map_err(lock_status)?;
+    log::info!(
+        "加锁: /{} {:?} {} ({})",
+        lock.path,
+        lock.depth,
+        lock.token,
+        lock.owner
+    );
+
+    if let Ok(value) = HeaderValue::from_str(&format!("<{}>", lock.token)) {
+        res.headers_mut().insert(LOCK_TOKEN, value);
+    }
+    res.status_code(StatusCode::CREATED);
+    res.render(Json(lock));
+    Ok(())
+}
+
+/// POST /locks/{path}?refresh: 按 `?timeout=` 延长 `Lock-Token` 指定的锁
+#[handler]
+async fn refresh(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let (mount, rel) = lock_path(req, depot)?;
+    let token = token(req)?;
+    let locks = locks(depot)?;
+    owned_lock(&locks, &token, &mount, &rel).await?;
+    let lock = locks
+        .refresh(&token, timeout(req))
+        .await
+        .map_err(lock_status)?;
+    res.render(Json(LockInfo::from(&lock)));
+    Ok(())
+}
+
+/// DELETE /locks/{path}: 释放 `Lock-Token` 指定的锁
+#[handler]
+async fn release(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let (mount, rel) = lock_path(req, depot)?;
+    let token = token(req)?;
+    let locks = locks(depot)?;
+    owned_lock(&locks, &token, &mount, &rel).await?;
+    let lock = locks.release(&token).await.map_err(lock_status)?;
+    log::info!("解锁: /{} {}", lock.path, lock.token);
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// 锁路由
+pub fn router() -> Router {
+    Router::with_path("locks/{**path}")
+        .push(Router::with_filter_fn(super::has_query("refresh")).post(refresh))
+        .get(list)
+        .post(acquire)
+        .delete(release)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presented_tokens() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            LOCK_TOKEN,
+            HeaderValue::from_static("<opaquelocktoken:a>, b"),
+        );
+        headers.append(
+            "if",
+            HeaderValue::from_static(
+                "</x> (<opaquelocktoken:c>) (Not <opaquelocktoken:d>) (not\t<opaquelocktoken:e>)",
+            ),
+        );
+        assert_eq!(
+            presented_tokens(&headers),
+            vec!["opaquelocktoken:a", "b", "opaquelocktoken:c"]
+        );
+    }
+}
@@ -1,7 +1,41 @@
+use crate::cmd::ServerConfig;
+use crate::db::Db;
+use crate::lock::LockManager;
+use crate::sandbox;
+use crate::sandbox::extract::Limits;
+use crate::web::admin::{AdminClients, AdminToken};
+use crate::web::mounts::{Mount, MountTable, ReadOnly};
+use crate::web::versions::VersionPolicy;
+use salvo::affix_state;
 use salvo::prelude::{Json, Text};
+use salvo::routing::PathState;
 use salvo::{Depot, Request, Response, Router, handler};
+use std::sync::Arc;
 use std::time::Duration;
 
+mod admin;
+mod archive;
+mod batch;
+mod checksum;
+mod dav;
+mod download;
+mod extract;
+mod fs;
+mod listing;
+mod locks;
+mod mounts;
+mod ops;
+mod patch;
+mod precondition;
+mod s3;
+mod search;
+mod stat;
+mod trash;
+mod tus;
+mod upload;
+mod versions;
+mod watch;
+
 /// Web处理器
 #[handler]
 async fn index(res: &mut Response) {
@@ -11,13 +45,21 @@ async fn index(res: &mut Response) {
 }
 
 #[handler]
-async fn health_check(_req: &mut Request, _depot: &mut Depot, res: &mut Response) {
+async fn health_check(_req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let read_only = match (
+        depot.obtain::<Arc<ReadOnly>>(),
+        depot.obtain::<Arc<MountTable>>(),
+    ) {
+        (Ok(state), Ok(mounts)) => state.report(mounts),
+        _ => serde_json::Value::Null,
+    };
     let health_data = serde_json::json!({
         "status": "healthy",
         "timestamp": chrono::Utc::now().to_rfc3339(),
         "pid": std::process::id(),
         "platform": std::env::consts::OS,
-        "framework": "Salvo"
+        "framework": "Salvo",
+        "read_only": read_only
     });
     res.render(Json(health_data));
 }
@@ -34,10 +76,164 @@ async fn shutdown_handler(res: &mut Response) {
     });
 }
 
-/// 创建路由
-pub fn create_router() -> Router {
+/// 按查询参数是否存在匹配路由
+fn has_query(key: &'static str) -> impl Fn(&mut Request, &mut PathState) -> bool {
+    move |req, _| req.queries().contains_key(key)
+}
+
+/// 文件操作路由
+pub(crate) fn fs_router() -> Router {
+    Router::with_path("fs/{**path}")
+        .push(Router::with_filter_fn(has_query("stat")).get(stat::stat))
+        .push(Router::with_filter_fn(has_query("archive")).get(archive::download_dir))
+        .push(Router::with_filter_fn(has_query("checksum")).get(checksum::checksum))
+        .push(Router::with_filter_fn(has_query("versions")).get(versions::list_versions))
+        .push(
+            Router::with_filter_fn(has_query("version"))
+                .get(versions::download_version)
+                .head(versions::download_version),
+        )
+        .get(fs::read_file)
+        .head(fs::read_file)
+        .put(fs::write_file)
+        .patch(patch::patch_file)
+        .delete(ops::delete_path)
+        .push(Router::with_filter_fn(has_query("mkdir")).post(ops::mkdir))
+        .push(Router::with_filter_fn(has_query("move")).post(ops::move_path))
+        .push(Router::with_filter_fn(has_query("copy")).post(ops::copy_path))
+        .push(Router::with_filter_fn(has_query("append")).post(patch::append_file))
+        .push(Router::with_filter_fn(has_query("truncate")).post(patch::truncate_file))
+        .push(Router::with_filter_fn(has_query("restore_version")).post(versions::restore_version))
+        .push(
+            Router::with_filter_fn(|req, _| extract::wants_extract(req))
+                .post(extract::extract_upload),
+        )
+        .post(upload::upload_files)
+}
+
+/// 一个挂载点下的路由
+fn mount_router() -> Router {
     Router::new()
+        .hoop(mounts::guard)
+        .push(fs_router())
+        .push(Router::with_path("batch").post(batch::batch))
+        .push(Router::with_path("search").get(search::search))
+        .push(Router::with_path("watch/{**path}").get(watch::watch))
+        .push(locks::router())
+        .push(trash::router())
+        .push(tus::router())
+        .push(dav::router())
+}
+
+/// 创建路由
+///
+/// `ROOT` 为默认挂载点，路由位于 `/` 下；`MOUNTS` 中的每个挂载点位于 `/{name}` 下，
+/// 各自有独立的沙箱、只读标志和大小限制。
+pub async fn create_router(config: &ServerConfig) -> anyhow::Result<Router> {
+    let mut mounts = vec![Mount::new("", &config.root)];
+    mounts.extend(
+        Mount::parse_list(&config.mounts).map_err(|e| anyhow::anyhow!("挂载点配置错误: {}", e))?,
+    );
+    let mounts = MountTable(mounts.into_iter().map(Arc::new).collect());
+    let db = Db::connect(&config.database_url)
+        .await
+        .map_err(|e| anyhow::anyhow!("连接数据库失败 {}: {}", config.database_url, e))?;
+    let policy = Arc::new(
+        VersionPolicy::parse(&config.version_rules)
+            .map_err(|e| anyhow::anyhow!("版本规则配置错误: {}", e))?,
+    );
+    let read_only = Arc::new(
+        ReadOnly::parse(&config.read_only, &mounts)
+            .map_err(|e| anyhow::anyhow!("只读配置错误: {}", e))?,
+    );
+    let locks = if config.redis_url.is_empty() {
+        LockManager::memory()
+    } else {
+        LockManager::redis(&config.redis_url, config.redis_cluster)
+            .await
+            .map_err(|e| anyhow::anyhow!("连接Redis失败 {}: {}", config.redis_url, e))?
+    };
+    log::info!(
+        "锁存储: {}",
+        if locks.is_shared() {
+            "Redis"
+        } else {
+            "进程内"
+        }
+    );
+
+    let mut router = Router::new()
+        .hoop(
+            affix_state::inject(db.clone())
+                .inject(policy.clone())
+                .inject(Arc::new(locks))
+                .inject(Arc::new(mounts.clone()))
+                .inject(read_only.clone())
+                .inject(AdminToken(config.admin_token.clone()))
+                .inject(AdminClients::parse(&config.tls_admin_clients)),
+        )
         .get(index)
         .get(health_check)
         .post(shutdown_handler)
+        .push(Router::with_path("health").get(health_check))
+        .push(Router::with_path("mounts").get(mounts::list))
+        .push(admin::router());
+    let mut buckets = s3::Buckets::default();
+    for mount in &mounts.0 {
+        let sandbox = Arc::new(mount.sandbox().map_err(|e| anyhow::anyhow!(e))?);
+        log::info!(
+            "挂载点 {} -> {}{}",
+            mount.prefix(),
+            sandbox.root().display(),
+            if mount.read_only { " (只读)" } else { "" }
+        );
+        let recovered = sandbox::journal::recover(&sandbox.root().join(extract::STAGING_DIR));
+        if recovered > 0 {
+            log::warn!("已回滚 {} 个中断的批量操作", recovered);
+        }
+        versions::spawn_pruner(
+            sandbox.clone(),
+            db.clone(),
+            policy.clone(),
+            read_only.clone(),
+        );
+        trash::spawn_purger(
+            sandbox.clone(),
+            db.clone(),
+            Duration::from_secs(config.trash_retention_days * 24 * 3600),
+            read_only.clone(),
+        );
+
+        let limits = Limits {
+            max_entries: config.extract_max_entries,
+            max_bytes: config.extract_max_bytes,
+            max_file_size: mount.max_file_size,
+        };
+        // 默认挂载点在 S3 中的桶名由 S3_BUCKET 决定
+        let bucket = match mount.name.is_empty() {
+            true => config.s3_bucket.as_str(),
+            false if mount.name == config.s3_bucket => {
+                anyhow::bail!("挂载点 {} 与 S3_BUCKET 重名", mount.name)
+            }
+            false => mount.name.as_str(),
+        };
+        if !bucket.is_empty() {
+            buckets.0.push(s3::Bucket::new(
+                bucket,
+                sandbox.clone(),
+                mount.clone(),
+                limits,
+            ));
+        }
+
+        let state = affix_state::inject(sandbox)
+            .inject(mount.clone())
+            .inject(limits);
+        let scope = match mount.name.is_empty() {
+            true => Router::new(),
+            false => Router::with_path(&mount.name),
+        };
+        router = router.push(scope.hoop(state).push(mount_router()));
+    }
+    Ok(router.push(s3::router(buckets)))
 }
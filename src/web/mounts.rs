@@ -0,0 +1,473 @@
+use crate::db::now;
+use crate::sandbox::{Sandbox, SymlinkPolicy};
+use salvo::http::header::{ALLOW, HeaderValue, RETRY_AFTER};
+use salvo::http::{Method, StatusError};
+use salvo::prelude::Json;
+use salvo::{Depot, FlowCtrl, Request, Response, handler};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// 顶层路由占用的名称，不能用作挂载点
+const RESERVED_NAMES: [&str; 12] = [
+    "fs", "batch", "search", "watch", "locks", "trash", "tus", "mounts", "admin", "dav", "s3",
+    "health",
+];
+
+/// 只读挂载点允许的方法
+const READ_METHODS: &str = "GET, HEAD, OPTIONS";
+/// 维护期未指定时的 Retry-After（秒）
+pub const DEFAULT_RETRY_AFTER: u64 = 300;
+
+/// 一个挂载点：URL 前缀 `/{name}` 下的一棵目录树及其策略
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Mount {
+    /// 名称，默认挂载点为空串
+    pub name: String,
+    /// 目录，不对外公开
+    #[serde(skip)]
+    pub root: String,
+    pub read_only: bool,
+    /// 单个文件的最大字节数
+    pub max_file_size: Option<u64>,
+    /// 隐藏规则，匹配的路径既不列出也不能访问
+    pub hidden: Vec<String>,
+    pub symlinks: SymlinkPolicy,
+}
+
+impl Mount {
+    /// 可读写、没有额外限制的挂载点
+    pub fn new(name: &str, root: &str) -> Self {
+        Mount {
+            name: name.to_string(),
+            root: root.to_string(),
+            read_only: false,
+            max_file_size: None,
+            hidden: Vec::new(),
+            symlinks: SymlinkPolicy::Follow,
+        }
+    }
+
+    /// 解析 `name=path[,ro|rw][,max_size=字节][,hidden=glob|glob][,symlinks=follow|deny]`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, rest) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("缺少 `=`: {}", spec))?;
+        let name = name.trim();
+        if name.is_empty()
+            || name.starts_with('.')
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            return Err(format!("无效的挂载点名称: {}", name));
+        }
+        if RESERVED_NAMES.contains(&name) {
+            return Err(format!("挂载点名称与已有路由冲突: {}", name));
+        }
+        let mut options = rest.split(',').map(str::trim);
+        let root = options.next().unwrap_or_default();
+        if root.is_empty() {
+            return Err(format!("挂载点 {} 缺少目录", name));
+        }
+
+        let mut mount = Mount::new(name, root);
+        for option in options.filter(|o| !o.is_empty()) {
+            match option.split_once('=') {
+                None if option == "ro" => mount.read_only = true,
+                None if option == "rw" => mount.read_only = false,
+                Some(("max_size", n)) => {
+                    mount.max_file_size = Some(
+                        n.trim()
+                            .parse()
+                            .map_err(|_| format!("无效的 max_size: {}", n))?,
+                    );
+                }
+                Some(("hidden", patterns)) => {
+                    mount.hidden = patterns
+                        .split('|')
+                        .map(str::trim)
+                        .filter(|p| !p.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                }
+                Some(("symlinks", "follow")) => mount.symlinks = SymlinkPolicy::Follow,
+                Some(("symlinks", "deny")) => mount.symlinks = SymlinkPolicy::Deny,
+                _ => return Err(format!("挂载点 {} 的选项无效: {}", name, option)),
+            }
+        }
+        Ok(mount)
+    }
+
+    /// 解析以 `;` 分隔的多个挂载点
+    pub fn parse_list(spec: &str) -> Result<Vec<Self>, String> {
+        let mut mounts: Vec<Mount> = Vec::new();
+        for part in spec.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+            let mount = Mount::parse(part)?;
+            if mounts.iter().any(|m| m.name == mount.name) {
+                return Err(format!("挂载点重复: {}", mount.name));
+            }
+            mounts.push(mount);
+        }
+        Ok(mounts)
+    }
+
+    /// URL 前缀
+    pub fn prefix(&self) -> String {
+        format!("/{}", self.name)
+    }
+
+    /// 按挂载点策略创建沙箱
+    pub fn sandbox(&self) -> Result<Sandbox, String> {
+        Sandbox::new(&self.root)
+            .map_err(|e| format!("初始化挂载点目录失败 {}: {}", self.root, e))?
+            .with_mount(&self.name)
+            .with_hidden(&self.hidden)
+            .map(|sandbox| sandbox.with_symlinks(self.symlinks))
+    }
+
+    /// 挂载点支持的操作
+    fn capabilities(&self) -> Vec<&'static str> {
+        let mut capabilities = vec![
+            "read", "list", "archive", "checksum", "search", "watch", "versions", "dav",
+        ];
+        if !self.read_only {
+            capabilities.extend([
+                "write", "patch", "upload", "extract", "tus", "mkdir", "move", "copy", "delete",
+                "trash", "restore", "locks", "batch",
+            ]);
+        }
+        capabilities
+    }
+}
+
+/// 全部挂载点，第一个为默认挂载点
+#[derive(Debug, Clone)]
+pub struct MountTable(pub Vec<Arc<Mount>>);
+
+impl MountTable {
+    /// 按名称或URL前缀查找挂载点，`/` 为默认挂载点
+    pub fn find(&self, key: &str) -> Option<&Arc<Mount>> {
+        let name = key.trim_matches('/');
+        self.0.iter().find(|m| m.name == name)
+    }
+}
+
+/// 一段只读维护期
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Window {
+    pub reason: String,
+    pub since: i64,
+    /// 建议客户端重试的间隔（秒）
+    pub retry_after: u64,
+}
+
+impl Window {
+    pub fn new(reason: &str, retry_after: u64) -> Self {
+        Window {
+            reason: reason.to_string(),
+            since: now(),
+            retry_after,
+        }
+    }
+}
+
+/// 运行时的只读维护状态，对全部挂载点或单个挂载点生效
+///
+/// 维护期内修改请求返回503和 `Retry-After`，读取照常；后台清理任务也暂停。
+#[derive(Debug, Default)]
+pub struct ReadOnly {
+    global: RwLock<Option<Window>>,
+    /// 按挂载点名称
+    mounts: RwLock<HashMap<String, Window>>,
+}
+
+impl ReadOnly {
+    /// 按配置 `READ_ONLY` 初始化：`true` 为全部挂载点，否则为逗号分隔的挂载点名称或前缀
+    pub fn parse(spec: &str, mounts: &MountTable) -> Result<Self, String> {
+        let state = ReadOnly::default();
+        let window = Window::new("配置", DEFAULT_RETRY_AFTER);
+        match spec.trim() {
+            "" | "false" => {}
+            "true" | "*" => state.set(None, Some(window)),
+            names => {
+                for key in names.split(',').map(str::trim) {
+                    let mount = mounts
+                        .find(key)
+                        .ok_or_else(|| format!("挂载点不存在: {}", key))?;
+                    state.set(Some(&mount.name), Some(window.clone()));
+                }
+            }
+        }
+        Ok(state)
+    }
+
+    /// 开始或结束维护期，`mount` 为 `None` 时作用于全部挂载点
+    pub fn set(&self, mount: Option<&str>, window: Option<Window>) {
+        match mount {
+            None => *self.global.write().unwrap_or_else(|e| e.into_inner()) = window,
+            Some(name) => {
+                let mut mounts = self.mounts.write().unwrap_or_else(|e| e.into_inner());
+                match window {
+                    Some(window) => mounts.insert(name.to_string(), window),
+                    None => mounts.remove(name),
+                };
+            }
+        }
+    }
+
+    /// 挂载点当前所处的维护期，全局的优先
+    pub fn active(&self, mount: &str) -> Option<Window> {
+        if let Some(window) = self
+            .global
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+        {
+            return Some(window);
+        }
+        self.mounts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(mount)
+            .cloned()
+    }
+
+    /// 各挂载点的只读状态，供健康检查和管理接口报告
+    pub fn report(&self, mounts: &MountTable) -> serde_json::Value {
+        let global = self
+            .global
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let windows = self.mounts.read().unwrap_or_else(|e| e.into_inner());
+        let mounts: Vec<_> = mounts
+            .0
+            .iter()
+            .map(|mount| {
+                serde_json::json!({
+                    "prefix": mount.prefix(),
+                    "read_only": mount.read_only,
+                    "maintenance": windows.get(&mount.name),
+                })
+            })
+            .collect();
+        serde_json::json!({ "global": global, "mounts": mounts })
+    }
+}
+
+/// 从Depot中取出当前挂载点
+pub(crate) fn mount(depot: &Depot) -> Option<Arc<Mount>> {
+    depot.obtain::<Arc<Mount>>().ok().cloned()
+}
+
+/// 文件将达到 `size` 字节时检查挂载点的大小限制，超过返回413
+pub(crate) fn check_file_size(depot: &Depot, size: u64) -> Result<(), StatusError> {
+    match mount(depot).and_then(|m| m.max_file_size) {
+        Some(max) if size > max => {
+            Err(StatusError::payload_too_large().brief(format!("文件超过大小限制: {} 字节", max)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// 拒绝修改请求：配置为只读的挂载点返回405，处于维护期的返回503和 `Retry-After`
+///
+/// 只读的请求（包括 WebDAV 的 PROPFIND）不受影响。
+#[handler]
+pub async fn guard(req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    let Some(mount) = mount(depot) else {
+        return;
+    };
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        || req.method().as_str() == "PROPFIND"
+    {
+        return;
+    }
+    if mount.read_only {
+        res.headers_mut()
+            .insert(ALLOW, HeaderValue::from_static(READ_METHODS));
+        res.render(
+            StatusError::method_not_allowed().brief(format!("挂载点只读: {}", mount.prefix())),
+        );
+        ctrl.skip_rest();
+    } else if let Ok(state) = depot.obtain::<Arc<ReadOnly>>()
+        && let Some(window) = state.active(&mount.name)
+    {
+        res.headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(window.retry_after));
+        res.render(StatusError::service_unavailable().brief(format!(
+            "挂载点 {} 处于只读维护中: {}",
+            mount.prefix(),
+            window.reason
+        )));
+        ctrl.skip_rest();
+    }
+}
+
+/// 挂载点信息
+#[derive(Serialize, Debug)]
+struct MountInfo<'a> {
+    #[serde(flatten)]
+    mount: &'a Mount,
+    prefix: String,
+    capabilities: Vec<&'static str>,
+}
+
+/// GET /mounts: 列出挂载点及其能力
+#[handler]
+pub async fn list(depot: &mut Depot, res: &mut Response) -> Result<(), StatusError> {
+    let mounts = depot
+        .obtain::<Arc<MountTable>>()
+        .map_err(|_| StatusError::internal_server_error().brief("挂载点未配置"))?;
+    let infos: Vec<MountInfo> = mounts
+        .0
+        .iter()
+        .map(|mount| MountInfo {
+            mount,
+            prefix: mount.prefix(),
+            capabilities: mount.capabilities(),
+        })
+        .collect();
+    res.render(Json(infos));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::ServerConfig;
+    use salvo::http::StatusCode;
+    use salvo::prelude::Service;
+    use salvo::test::{ResponseExt, TestClient};
+
+    #[test]
+    fn test_parse_mounts() {
+        let mounts = Mount::parse_list(
+            "datasets=/srv/data,ro,hidden=*.bak|private/**,symlinks=deny; scratch=/tmp/scratch,rw,max_size=1024",
+        )
+        .unwrap();
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0].name, "datasets");
+        assert_eq!(mounts[0].root, "/srv/data");
+        assert!(mounts[0].read_only);
+        assert_eq!(mounts[0].hidden, ["*.bak", "private/**"]);
+        assert_eq!(mounts[0].symlinks, SymlinkPolicy::Deny);
+        assert_eq!(mounts[0].prefix(), "/datasets");
+        assert!(!mounts[1].read_only);
+        assert_eq!(mounts[1].max_file_size, Some(1024));
+        assert!(!mounts[0].capabilities().contains(&"write"));
+
+        assert!(Mount::parse_list("").unwrap().is_empty());
+        assert!(Mount::parse("fs=/x").is_err());
+        assert!(Mount::parse("health=/x").is_err());
+        assert!(Mount::parse("a/b=/x").is_err());
+        assert!(Mount::parse("a=").is_err());
+        assert!(Mount::parse("a=/x,max_size=big").is_err());
+        assert!(Mount::parse("a=/x,symlinks=maybe").is_err());
+        assert!(Mount::parse_list("a=/x;a=/y").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mount_routes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).display().to_string();
+        std::fs::create_dir_all(dir.path().join("data/private")).unwrap();
+        std::fs::write(dir.path().join("data/x.txt"), "x").unwrap();
+        std::fs::write(dir.path().join("data/old.bak"), "old").unwrap();
+        std::fs::write(dir.path().join("data/private/key"), "key").unwrap();
+        let config = ServerConfig {
+            root: path("main"),
+            mounts: format!(
+                "data={},ro,hidden=*.bak|private;scratch={},max_size=4,symlinks=deny",
+                path("data"),
+                path("scratch")
+            ),
+            database_url: "sqlite::memory:".to_string(),
+            ..Default::default()
+        };
+        let service = Service::new(crate::web::create_router(&config).await.unwrap());
+
+        let mut res = TestClient::get("http://127.0.0.1/mounts")
+            .send(&service)
+            .await;
+        let mounts: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(mounts[0]["prefix"], "/");
+        assert_eq!(mounts[1]["name"], "data");
+        assert_eq!(mounts[1]["read_only"], true);
+        assert_eq!(mounts[1]["hidden"][1], "private");
+        assert_eq!(mounts[2]["max_file_size"], 4);
+        assert_eq!(mounts[2]["symlinks"], "deny");
+        assert!(mounts[1].get("root").is_none());
+
+        // 只读挂载点可以读，不能写
+        let mut res = TestClient::get("http://127.0.0.1/data/fs/x.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "x");
+        let res = TestClient::put("http://127.0.0.1/data/fs/y.txt")
+            .body("y")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::METHOD_NOT_ALLOWED));
+        assert_eq!(res.headers().get(ALLOW).unwrap(), READ_METHODS);
+
+        // 隐藏的路径不列出也不能访问
+        let mut res = TestClient::get("http://127.0.0.1/data/fs/")
+            .send(&service)
+            .await;
+        let listing = res.take_string().await.unwrap();
+        assert!(listing.contains("x.txt"));
+        assert!(!listing.contains("old.bak") && !listing.contains("private"));
+        for hidden in ["old.bak", "private/key"] {
+            let res = TestClient::get(format!("http://127.0.0.1/data/fs/{}", hidden))
+                .send(&service)
+                .await;
+            assert_eq!(res.status_code, Some(StatusCode::FORBIDDEN));
+        }
+
+        // 大小限制，以及各挂载点的路径互不相干
+        let res = TestClient::put("http://127.0.0.1/scratch/fs/a.txt")
+            .body("12345")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::PAYLOAD_TOO_LARGE));
+        let res = TestClient::put("http://127.0.0.1/scratch/fs/a.txt")
+            .body("1234")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        assert!(dir.path().join("scratch/a.txt").exists());
+        let res = TestClient::get("http://127.0.0.1/fs/a.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+
+        // 回收站按挂载点区分
+        let res = TestClient::delete("http://127.0.0.1/scratch/fs/a.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let mut res = TestClient::get("http://127.0.0.1/scratch/trash")
+            .send(&service)
+            .await;
+        let items: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(items.as_array().unwrap().len(), 1);
+        let mut res = TestClient::get("http://127.0.0.1/trash")
+            .send(&service)
+            .await;
+        let items: serde_json::Value = res.take_json().await.unwrap();
+        assert!(items.as_array().unwrap().is_empty());
+
+        #[cfg(unix)]
+        {
+            std::fs::create_dir(dir.path().join("scratch/sub")).unwrap();
+            std::os::unix::fs::symlink("sub", dir.path().join("scratch/link")).unwrap();
+            let res = TestClient::put("http://127.0.0.1/scratch/fs/link/b.txt")
+                .body("b")
+                .send(&service)
+                .await;
+            assert_eq!(res.status_code, Some(StatusCode::FORBIDDEN));
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use crate::sandbox::Sandbox;
+use crate::sandbox::ops::{self, Conflict, OpError, Report};
+use crate::web::fs::{client_addr, db, io_status, prepare_parent, request_path, sandbox};
+use crate::web::{locks, precondition, trash, versions};
+use salvo::http::{StatusCode, StatusError};
+use salvo::prelude::Json;
+use salvo::{Depot, Request, Response, handler};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// 文件操作的响应
+#[derive(Serialize, Debug)]
+pub struct OpResult {
+    pub path: String,
+    pub skipped: bool,
+    pub errors: Vec<OpPathError>,
+    /// 移入回收站后的条目ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trash_id: Option<String>,
+}
+
+/// 以相对路径表示的单个错误
+#[derive(Serialize, Debug)]
+pub struct OpPathError {
+    pub path: String,
+    pub error: String,
+}
+
+impl From<OpError> for StatusError {
+    fn from(e: OpError) -> Self {
+        match e {
+            OpError::Exists | OpError::NotEmpty | OpError::IntoItself => {
+                StatusError::conflict().brief(e.to_string())
+            }
+            OpError::Io(e) => io_status(e),
+        }
+    }
+}
+
+impl OpResult {
+    pub fn new(sandbox: &Sandbox, path: &std::path::Path, report: Report) -> Self {
+        OpResult {
+            path: sandbox.relative(path).unwrap_or_default(),
+            skipped: report.skipped,
+            errors: report
+                .errors
+                .into_iter()
+                .map(|e| OpPathError {
+                    path: sandbox.relative(&e.path).unwrap_or_default(),
+                    error: e.error,
+                })
+                .collect(),
+            trash_id: None,
+        }
+    }
+}
+
+/// 输出操作结果，部分失败时返回207
+fn render(sandbox: &Sandbox, path: &std::path::Path, report: Report, res: &mut Response) {
+    render_result(OpResult::new(sandbox, path, report), res);
+}
+
+pub(crate) fn render_result(result: OpResult, res: &mut Response) {
+    if result.errors.is_empty() {
+        res.status_code(StatusCode::OK);
+    } else {
+        res.status_code(StatusCode::MULTI_STATUS);
+    }
+    res.render(Json(result));
+}
+
+/// 在阻塞线程中执行文件操作
+pub async fn run<F>(f: F) -> Result<Report, StatusError>
+where
+    F: FnOnce() -> Result<Report, OpError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| StatusError::internal_server_error().brief(e.to_string()))?
+        .map_err(StatusError::from)
+}
+
+/// 执行会写入 `dst` 的文件操作
+///
+/// `conflict` 为 overwrite 且目标已存在时，旧目标先按版本策略保存为版本并移入回收站，
+/// 再以 fail 策略执行操作；操作失败时清掉残留内容，把旧目标放回原处。
+pub(crate) async fn replace<F>(
+    depot: &Depot,
+    sandbox: &Sandbox,
+    dst: &Path,
+    conflict: Conflict,
+    client: String,
+    op: F,
+) -> Result<Report, StatusError>
+where
+    F: FnOnce(Conflict) -> Result<Report, OpError> + Send + 'static,
+{
+    if conflict != Conflict::Overwrite || tokio::fs::symlink_metadata(dst).await.is_err() {
+        return run(move || op(conflict)).await;
+    }
+    let db = db(depot)?;
+    let version = versions::snapshot(depot, sandbox, dst, true, client.clone()).await?;
+    let item = match trash::move_to_trash(sandbox, &db, dst, true, client).await {
+        Ok((item, _)) => item,
+        Err(e) => {
+            if let Some(version) = version {
+                versions::discard(depot, sandbox, &version).await;
+            }
+            return Err(e);
+        }
+    };
+    log::info!("覆盖，旧目标移入回收站: {} -> {}", dst.display(), item.id);
+
+    let result = run(move || op(Conflict::Fail)).await;
+    if result.is_err() {
+        // 源保持不变，清掉可能残留的部分内容后放回被覆盖的目标
+        let target = dst.to_path_buf();
+        if tokio::fs::symlink_metadata(dst).await.is_ok()
+            && let Err(e) = run(move || ops::remove(&target, true)).await
+        {
+            log::warn!("清理未完成的目标失败 {}: {}", dst.display(), e.brief);
+        }
+        if let Err(e) = trash::put_back(sandbox, &db, &item, dst).await {
+            log::error!("放回被覆盖的目标失败 {}: {}", item.id, e.brief);
+        }
+        if let Some(version) = version {
+            versions::discard(depot, sandbox, &version).await;
+        }
+    }
+    result
+}
+
+/// 解析请求路径，不允许操作根目录本身；符号链接按链接本身处理
+pub(crate) fn resolve_target(sandbox: &Sandbox, rel: &str) -> Result<PathBuf, StatusError> {
+    let path = sandbox.resolve_nofollow(rel)?;
+    if path == sandbox.root() {
+        return Err(StatusError::forbidden().brief("不能操作根目录"));
+    }
+    Ok(path)
+}
+
+fn conflict(req: &Request) -> Conflict {
+    req.query::<Conflict>("conflict").unwrap_or_default()
+}
+
+/// DELETE: 把文件或目录移入回收站，非空目录需 `?recursive=true`
+///
+/// `?permanent=true` 时直接删除，不经过回收站。
+#[handler]
+pub async fn delete_path(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let path = resolve_target(&sandbox, &rel)?;
+    locks::require(depot, req, &sandbox, &path, true).await?;
+    let metadata = tokio::fs::symlink_metadata(&path)
+        .await
+        .map_err(io_status)?;
+    precondition::check_write(req.headers(), Some(&metadata))?;
+    let recursive = req.query::<bool>("recursive").unwrap_or(false);
+
+    if !req.query::<bool>("permanent").unwrap_or(false) {
+        let db = db(depot)?;
+        let (item, report) =
+            trash::move_to_trash(&sandbox, &db, &path, recursive, client_addr(req)).await?;
+        log::info!("移入回收站: {} -> {}", path.display(), item.id);
+        render_result(
+            OpResult {
+                trash_id: Some(item.id),
+                ..OpResult::new(&sandbox, &path, report)
+            },
+            res,
+        );
+        return Ok(());
+    }
+
+    let target = path.clone();
+    let report = run(move || ops::remove(&target, recursive)).await?;
+    log::info!("删除: {}", path.display());
+    render(&sandbox, &path, report, res);
+    Ok(())
+}
+
+/// POST ?mkdir: 创建目录及其父目录
+#[handler]
+pub async fn mkdir(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let path = resolve_target(&sandbox, &rel)?;
+    locks::require(depot, req, &sandbox, &path, false).await?;
+    let conflict = conflict(req);
+
+    let target = path.clone();
+    let report = run(move || ops::mkdir(&target, conflict)).await?;
+    log::info!("创建目录: {}", path.display());
+    render(&sandbox, &path, report, res);
+    Ok(())
+}
+
+/// 解析源路径和 `?move=` / `?copy=` 指定的目标路径
+async fn source_and_destination(
+    req: &Request,
+    sandbox: &Sandbox,
+    key: &str,
+) -> Result<(PathBuf, PathBuf), StatusError> {
+    let src = resolve_target(sandbox, &request_path(req))?;
+    if tokio::fs::symlink_metadata(&src).await.is_err() {
+        return Err(StatusError::not_found().brief(format!("路径不存在: {}", request_path(req))));
+    }
+    let dst = req
+        .query::<String>(key)
+        .filter(|d| !d.is_empty())
+        .ok_or_else(|| StatusError::bad_request().brief(format!("缺少目标路径: {}", key)))?;
+    let dst = resolve_target(sandbox, &dst)?;
+    let mkdirs = req.query::<bool>("mkdirs").unwrap_or(false);
+    prepare_parent(&dst, mkdirs).await?;
+    Ok((src, dst))
+}
+
+/// POST ?move=目标: 移动或重命名
+#[handler]
+pub async fn move_path(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let (src, dst) = source_and_destination(req, &sandbox, "move").await?;
+    locks::require(depot, req, &sandbox, &src, true).await?;
+    locks::require(depot, req, &sandbox, &dst, true).await?;
+    let conflict = conflict(req);
+
+    let from = src.clone();
+    let report = replace(depot, &sandbox, &dst, conflict, client_addr(req), {
+        let to = dst.clone();
+        move |conflict| ops::rename(&from, &to, conflict)
+    })
+    .await?;
+    log::info!("移动: {} -> {}", src.display(), dst.display());
+    render(&sandbox, &dst, report, res);
+    Ok(())
+}
+
+/// POST ?copy=目标: 服务器端复制文件或目录树
+#[handler]
+pub async fn copy_path(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let (src, dst) = source_and_destination(req, &sandbox, "copy").await?;
+    locks::require(depot, req, &sandbox, &dst, true).await?;
+    let conflict = conflict(req);
+
+    let from = src.clone();
+    let report = replace(depot, &sandbox, &dst, conflict, client_addr(req), {
+        let to = dst.clone();
+        move |conflict| ops::copy(&from, &to, conflict)
+    })
+    .await?;
+    log::info!("复制: {} -> {}", src.display(), dst.display());
+    render(&sandbox, &dst, report, res);
+    Ok(())
+}
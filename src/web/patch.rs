@@ -0,0 +1,366 @@
+use crate::sandbox::temp_name;
+use crate::web::fs::{client_addr, content_length, io_status, request_path, sandbox, set_etag};
+use crate::web::{locks, mounts, precondition, versions};
+use futures_util::StreamExt;
+use lazy_static::lazy_static;
+use salvo::http::header::CONTENT_RANGE;
+use salvo::http::{StatusCode, StatusError};
+use salvo::{Depot, Request, Response, handler};
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+lazy_static! {
+    /// 每个文件一把锁，使前置条件检查和原地写入不被同进程的其他写入打断
+    static ref FILE_LOCKS: Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>> =
+        Mutex::new(HashMap::new());
+}
+
+pub(crate) fn file_lock(path: &Path) -> Arc<tokio::sync::Mutex<()>> {
+    let mut locks = FILE_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    locks.entry(path.to_path_buf()).or_default().clone()
+}
+
+/// 没有其他等待者时移除锁
+pub(crate) fn release_lock(path: &Path, lock: Arc<tokio::sync::Mutex<()>>) {
+    let mut locks = FILE_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    // 表中一份，调用方一份
+    if Arc::strong_count(&lock) <= 2 {
+        locks.remove(path);
+    }
+}
+
+/// `Content-Range: bytes start-end/total`，`end` 和 `total` 可省略为 `*`
+#[derive(Debug, PartialEq, Eq)]
+pub struct ContentRange {
+    pub start: u64,
+    pub end: Option<u64>,
+    pub total: Option<u64>,
+}
+
+/// 解析请求中的 Content-Range
+pub fn parse_content_range(value: &str) -> Option<ContentRange> {
+    let spec = value.trim().strip_prefix("bytes")?.trim_start();
+    let (range, total) = spec.split_once('/')?;
+    let total = match total.trim() {
+        "*" => None,
+        t => Some(t.parse().ok()?),
+    };
+    let (start, end) = match range.trim().split_once('-') {
+        Some((start, end)) => (start.trim().parse().ok()?, Some(end.trim().parse().ok()?)),
+        None => (range.trim().parse().ok()?, None),
+    };
+    if end.is_some_and(|end| end < start) || total.is_some_and(|t| end.unwrap_or(start) >= t) {
+        return None;
+    }
+    Some(ContentRange { start, end, total })
+}
+
+/// 解析已存在的普通文件并检查前置条件
+async fn existing_file(req: &Request, path: &Path) -> Result<Metadata, StatusError> {
+    let metadata = tokio::fs::metadata(path).await.map_err(io_status)?;
+    if !metadata.is_file() {
+        return Err(StatusError::conflict().brief("目标不是文件"));
+    }
+    precondition::check_write(req.headers(), Some(&metadata))?;
+    Ok(metadata)
+}
+
+/// 从 `offset` 起写入请求体，返回写入的字节数；写入位置超过挂载点的文件大小限制时返回413
+///
+/// 给出 `len` 时请求体必须恰好这么长。被覆盖的原内容先存入同目录的临时文件，
+/// 任何一步失败都据此写回并截回原长度，文件保持写入前的样子；额外开销与写入量成正比，与文件大小无关。
+async fn write_body(
+    req: &mut Request,
+    depot: &Depot,
+    path: &Path,
+    offset: SeekFrom,
+    len: Option<u64>,
+) -> Result<u64, StatusError> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .await
+        .map_err(io_status)?;
+    let original = file.metadata().await.map_err(io_status)?.len();
+    let start = file.seek(offset).await.map_err(io_status)?;
+    let backup = path.with_file_name(temp_name());
+    let mut saved: Option<tokio::fs::File> = None;
+    let mut written = 0u64;
+    let result = async {
+        let mut body = req.take_body();
+        while let Some(frame) = body.next().await {
+            let frame = frame.map_err(|e| StatusError::bad_request().brief(e.to_string()))?;
+            let Ok(data) = frame.into_data() else {
+                continue;
+            };
+            // 超出区间的部分不落盘
+            if len.is_some_and(|len| written + data.len() as u64 > len) {
+                return Err(StatusError::bad_request().brief("请求体长度超出 Content-Range"));
+            }
+            let pos = start + written;
+            mounts::check_file_size(depot, pos + data.len() as u64)?;
+            if pos < original {
+                let mut old = vec![0; (original - pos).min(data.len() as u64) as usize];
+                file.read_exact(&mut old).await.map_err(io_status)?;
+                let saved = match &mut saved {
+                    Some(saved) => saved,
+                    None => saved.insert(
+                        tokio::fs::OpenOptions::new()
+                            .read(true)
+                            .write(true)
+                            .create_new(true)
+                            .open(&backup)
+                            .await
+                            .map_err(io_status)?,
+                    ),
+                };
+                saved.write_all(&old).await.map_err(io_status)?;
+                file.seek(SeekFrom::Start(pos)).await.map_err(io_status)?;
+            }
+            file.write_all(&data).await.map_err(io_status)?;
+            written += data.len() as u64;
+        }
+        if let Some(len) = len
+            && written != len
+        {
+            return Err(StatusError::bad_request()
+                .brief(format!("请求体长度 {} 与 Content-Range 不符", written)));
+        }
+        file.sync_data().await.map_err(io_status)
+    }
+    .await;
+    if result.is_err()
+        && let Err(e) = restore(&mut file, saved.as_mut(), start, original).await
+    {
+        log::error!("局部写入失败后恢复原内容失败: {}: {}", path.display(), e);
+    }
+    if saved.is_some() {
+        drop(saved);
+        tokio::fs::remove_file(&backup).await.ok();
+    }
+    result.map(|_| written)
+}
+
+/// 把保存的原内容写回 `start` 处并截回原长度
+async fn restore(
+    file: &mut tokio::fs::File,
+    saved: Option<&mut tokio::fs::File>,
+    start: u64,
+    len: u64,
+) -> std::io::Result<()> {
+    if let Some(saved) = saved {
+        saved.seek(SeekFrom::Start(0)).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+        tokio::io::copy(saved, file).await?;
+    }
+    file.set_len(len).await?;
+    file.sync_all().await
+}
+
+/// PATCH: 原地覆盖一段字节
+///
+/// 起点由 `Content-Range: bytes start-end/total` 或 `?offset=` 指定，不能超过当前文件长度。
+/// 给出 `end` 时必须带与区间一致的 Content-Length，写入前即可发现长度不符；给出 `total` 时写入后把文件调整为该长度。
+/// 按版本策略在写入前保存当前内容。写入中途失败（请求体出错、超出大小限制等）时文件恢复原样。
+#[handler]
+pub async fn patch_file(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let path = sandbox.resolve(&rel)?;
+    locks::require(depot, req, &sandbox, &path, false).await?;
+
+    let range = match req.headers().get(CONTENT_RANGE) {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(parse_content_range)
+            .ok_or_else(|| StatusError::bad_request().brief("无效的 Content-Range"))?,
+        None => ContentRange {
+            start: req
+                .query::<u64>("offset")
+                .ok_or_else(|| StatusError::bad_request().brief("需要 Content-Range 或 offset"))?,
+            end: None,
+            total: None,
+        },
+    };
+
+    // 在写入前发现长度不符或超出大小限制
+    let content_length = content_length(req);
+    if let Some(end) = range.end {
+        match content_length {
+            None => {
+                return Err(StatusError::length_required()
+                    .brief("带区间终点的 Content-Range 需要 Content-Length"));
+            }
+            Some(len) if len != end - range.start + 1 => {
+                return Err(StatusError::bad_request().brief("请求体长度与 Content-Range 不符"));
+            }
+            _ => {}
+        }
+    }
+    let end = range.start
+        + content_length
+            .or(range.end.map(|end| end - range.start + 1))
+            .unwrap_or_default();
+    mounts::check_file_size(depot, end.max(range.total.unwrap_or_default()))?;
+
+    let client = client_addr(req);
+    let lock = file_lock(&path);
+    let result = async {
+        let _guard = lock.lock().await;
+        let metadata = existing_file(req, &path).await?;
+        if range.start > metadata.len() {
+            return Err(StatusError::range_not_satisfiable()
+                .brief(format!("起点超出文件长度: {}", metadata.len())));
+        }
+        versions::snapshot(depot, &sandbox, &path, false, client).await?;
+        let written = write_body(
+            req,
+            depot,
+            &path,
+            SeekFrom::Start(range.start),
+            range.end.map(|end| end - range.start + 1),
+        )
+        .await?;
+        if let Some(total) = range.total {
+            resize(&path, total).await?;
+        }
+        log::info!(
+            "局部写入: {} @{} ({} 字节)",
+            path.display(),
+            range.start,
+            written
+        );
+        Ok(())
+    }
+    .await;
+    release_lock(&path, lock);
+    result?;
+
+    set_etag(&path, res).await;
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// POST ?append: 把请求体追加到文件末尾
+#[handler]
+pub async fn append_file(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let path = sandbox.resolve(&rel)?;
+    locks::require(depot, req, &sandbox, &path, false).await?;
+
+    let lock = file_lock(&path);
+    let result = async {
+        let _guard = lock.lock().await;
+        let metadata = existing_file(req, &path).await?;
+        mounts::check_file_size(
+            depot,
+            metadata.len() + content_length(req).unwrap_or_default(),
+        )?;
+        let written = write_body(req, depot, &path, SeekFrom::End(0), None).await?;
+        log::info!("追加写入: {} ({} 字节)", path.display(), written);
+        Ok::<_, StatusError>(())
+    }
+    .await;
+    release_lock(&path, lock);
+    result?;
+
+    set_etag(&path, res).await;
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+async fn resize(path: &Path, len: u64) -> Result<(), StatusError> {
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .await
+        .map_err(io_status)?;
+    file.set_len(len).await.map_err(io_status)?;
+    file.sync_all().await.map_err(io_status)
+}
+
+/// POST ?truncate=长度: 截断或以零扩展文件到指定长度，截断前保存版本
+#[handler]
+pub async fn truncate_file(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let path = sandbox.resolve(&rel)?;
+    let len = req
+        .query::<u64>("truncate")
+        .ok_or_else(|| StatusError::bad_request().brief("无效的长度"))?;
+    mounts::check_file_size(depot, len)?;
+    locks::require(depot, req, &sandbox, &path, false).await?;
+
+    let client = client_addr(req);
+    let lock = file_lock(&path);
+    let result = async {
+        let _guard = lock.lock().await;
+        existing_file(req, &path).await?;
+        versions::snapshot(depot, &sandbox, &path, false, client).await?;
+        resize(&path, len).await?;
+        log::info!("截断文件: {} -> {} 字节", path.display(), len);
+        Ok::<_, StatusError>(())
+    }
+    .await;
+    release_lock(&path, lock);
+    result?;
+
+    set_etag(&path, res).await;
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(
+            parse_content_range("bytes 0-4/10"),
+            Some(ContentRange {
+                start: 0,
+                end: Some(4),
+                total: Some(10)
+            })
+        );
+        assert_eq!(
+            parse_content_range("bytes 5-9/*"),
+            Some(ContentRange {
+                start: 5,
+                end: Some(9),
+                total: None
+            })
+        );
+        assert_eq!(
+            parse_content_range("bytes 7/*"),
+            Some(ContentRange {
+                start: 7,
+                end: None,
+                total: None
+            })
+        );
+        assert_eq!(parse_content_range("bytes 5-4/*"), None);
+        assert_eq!(parse_content_range("bytes 0-10/10"), None);
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+    }
+}
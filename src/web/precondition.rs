@@ -0,0 +1,126 @@
+use salvo::http::StatusError;
+use salvo::http::header::{HeaderMap, IF_MATCH, IF_NONE_MATCH};
+use std::fs::Metadata;
+use std::time::UNIX_EPOCH;
+
+/// 由 inode、大小和修改时间生成强ETag
+pub fn etag(metadata: &Metadata) -> String {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("\"{:x}-{:x}-{:x}\"", inode(metadata), metadata.len(), mtime)
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> u64 {
+    0
+}
+
+/// 解析 If-Match / If-None-Match 的值，`None` 表示 `*`
+fn parse_list(
+    headers: &HeaderMap,
+    name: impl salvo::http::header::AsHeaderName,
+) -> Option<Option<Vec<String>>> {
+    let values: Vec<&str> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    let tags: Vec<String> = values
+        .iter()
+        .flat_map(|v| v.split(','))
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    if tags.iter().any(|t| t == "*") {
+        Some(None)
+    } else {
+        Some(Some(tags))
+    }
+}
+
+/// 弱比较时去掉 `W/` 前缀
+fn weak(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+/// 检查 If-Match，`current` 为目标当前的元数据
+pub fn if_match(headers: &HeaderMap, current: Option<&Metadata>) -> bool {
+    match parse_list(headers, IF_MATCH) {
+        None => true,
+        Some(None) => current.is_some(),
+        Some(Some(tags)) => current
+            .map(etag)
+            .is_some_and(|e| tags.iter().any(|t| !t.starts_with("W/") && *t == e)),
+    }
+}
+
+/// 检查 If-None-Match，返回 `false` 表示条件不成立
+pub fn if_none_match(headers: &HeaderMap, current: Option<&Metadata>) -> bool {
+    match parse_list(headers, IF_NONE_MATCH) {
+        None => true,
+        Some(None) => current.is_none(),
+        Some(Some(tags)) => match current.map(etag) {
+            Some(e) => !tags.iter().any(|t| weak(t) == e),
+            None => true,
+        },
+    }
+}
+
+/// 请求是否要求目标必须不存在（`If-None-Match: *`）
+pub fn create_only(headers: &HeaderMap) -> bool {
+    matches!(parse_list(headers, IF_NONE_MATCH), Some(None))
+}
+
+/// 检查写操作的前置条件，不满足时返回412
+pub fn check_write(headers: &HeaderMap, current: Option<&Metadata>) -> Result<(), StatusError> {
+    if !if_match(headers, current) {
+        return Err(StatusError::precondition_failed().brief("If-Match 条件不满足"));
+    }
+    if !if_none_match(headers, current) {
+        return Err(StatusError::precondition_failed().brief("If-None-Match 条件不满足"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use salvo::http::header::HeaderValue;
+
+    #[test]
+    fn test_write_preconditions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, "a").unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        let tag = etag(&metadata);
+
+        let mut headers = HeaderMap::new();
+        assert!(check_write(&headers, Some(&metadata)).is_ok());
+
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(create_only(&headers));
+        assert!(check_write(&headers, None).is_ok());
+        assert!(check_write(&headers, Some(&metadata)).is_err());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_MATCH, HeaderValue::from_str(&tag).unwrap());
+        assert!(check_write(&headers, Some(&metadata)).is_ok());
+        assert!(check_write(&headers, None).is_err());
+        headers.insert(IF_MATCH, HeaderValue::from_static("\"other\", \"tags\""));
+        assert!(check_write(&headers, Some(&metadata)).is_err());
+    }
+}
@@ -0,0 +1,1778 @@
+//! S3 兼容接口：每个挂载点是一个桶，路径风格寻址 `/s3/{bucket}/{key}`
+//!
+//! 请求用 SigV4 认证，访问密钥保存在数据库中（见 `/admin/s3-keys`）。对象就是挂载点下的文件，
+//! 与 `/fs` 共用沙箱、锁、版本、回收站和大小限制；以 `/` 结尾的键表示目录。
+
+use crate::db::now;
+use crate::db::s3::{S3Part, S3Upload};
+use crate::digest::{Algorithm, hex};
+use crate::s3::chunked::{ChunkSigner, Decoder};
+use crate::s3::response::{self, Listing, Object, Part};
+use crate::s3::sigv4::{
+    STREAMING_PAYLOAD, STREAMING_PAYLOAD_TRAILER, STREAMING_UNSIGNED_TRAILER, Signature,
+    UNSIGNED_PAYLOAD, canonical_request, parse_date, sign, signing_key, string_to_sign,
+};
+use crate::s3::{request, uri_decode};
+use crate::sandbox::Sandbox;
+use crate::sandbox::extract::Limits;
+use crate::util::token_eq;
+use crate::web::checksum::UploadVerifier;
+use crate::web::fs::{
+    client_addr, content_length, db, db_status, io_status, prepare_parent, request_path, sandbox,
+    store,
+};
+use crate::web::mounts::Mount;
+use crate::web::ops::resolve_target;
+use crate::web::{fs, locks, mounts, precondition, trash};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use bytes::Bytes;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::stream::{self, BoxStream};
+use futures_util::{StreamExt, TryStreamExt};
+use salvo::affix_state;
+use salvo::http::header::{
+    AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, ETAG, HeaderName, HeaderValue,
+};
+use salvo::http::{Method, ResBody, StatusCode, StatusError};
+use salvo::{Depot, FlowCtrl, Handler, Request, Response, Router, handler};
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio_util::io::ReaderStream;
+
+/// 分段上传的临时目录
+pub const S3_DIR: &str = ".s3";
+/// ListObjects 一页最多返回的条目数
+const MAX_KEYS: usize = 1000;
+/// DeleteObjects 一次最多删除的键数
+const MAX_DELETE: usize = 1000;
+/// 分段编号的上限
+const MAX_PARTS: i64 = 10_000;
+/// XML 请求体的最大字节数
+const MAX_XML: usize = 1 << 20;
+/// 签名时间与服务器时间的最大偏差
+const MAX_SKEW: i64 = 15 * 60;
+/// 预签名 URL 的最长有效期（秒）
+const MAX_EXPIRES: i64 = 7 * 24 * 3600;
+/// ListBuckets 中的所有者
+const OWNER: &str = "fs-proxy";
+/// 空内容的 MD5，目录标记的 ETag
+const EMPTY_ETAG: &str = "\"d41d8cd98f00b204e9800998ecf8427e\"";
+const XML_CONTENT_TYPE: &str = "application/xml";
+
+const REQUEST_ID: HeaderName = HeaderName::from_static("x-amz-request-id");
+const COPY_SOURCE: &str = "x-amz-copy-source";
+
+/// 桶的子资源和列表参数，其余的查询参数按不支持的子资源处理
+const BUCKET_PARAMS: [&str; 12] = [
+    "list-type",
+    "prefix",
+    "delimiter",
+    "max-keys",
+    "continuation-token",
+    "start-after",
+    "marker",
+    "encoding-type",
+    "fetch-owner",
+    "location",
+    "delete",
+    "x-id",
+];
+/// 对象请求支持的查询参数
+const OBJECT_PARAMS: [&str; 5] = ["uploads", "uploadId", "partNumber", "versionId", "x-id"];
+
+/// 一个桶：挂载点及其沙箱和限制
+#[derive(Debug, Clone)]
+pub struct Bucket {
+    pub name: String,
+    pub sandbox: Arc<Sandbox>,
+    pub mount: Arc<Mount>,
+    pub limits: Limits,
+    pub created: DateTime<Utc>,
+}
+
+impl Bucket {
+    pub fn new(name: &str, sandbox: Arc<Sandbox>, mount: Arc<Mount>, limits: Limits) -> Self {
+        let created = std::fs::metadata(sandbox.root())
+            .and_then(|m| m.modified())
+            .map(DateTime::from)
+            .unwrap_or_default();
+        Bucket {
+            name: name.to_string(),
+            sandbox,
+            mount,
+            limits,
+            created,
+        }
+    }
+}
+
+/// 全部桶
+#[derive(Debug, Clone, Default)]
+pub struct Buckets(pub Vec<Bucket>);
+
+impl Buckets {
+    pub fn find(&self, name: &str) -> Option<&Bucket> {
+        self.0.iter().find(|b| b.name == name)
+    }
+}
+
+/// S3 错误码，放在 `StatusError::origin` 中
+struct Code(&'static str);
+
+/// 带 S3 错误码的错误
+fn s3_error(e: StatusError, code: &'static str) -> StatusError {
+    e.origin(Code(code))
+}
+
+/// 错误对应的 S3 错误码，没有指定时按状态码推断
+fn code(e: &StatusError) -> &'static str {
+    if let Some(Code(code)) = e.origin.as_ref().and_then(|o| o.downcast_ref::<Code>()) {
+        return code;
+    }
+    match e.code {
+        StatusCode::BAD_REQUEST => "InvalidRequest",
+        StatusCode::FORBIDDEN => "AccessDenied",
+        StatusCode::NOT_FOUND => "NoSuchKey",
+        StatusCode::METHOD_NOT_ALLOWED => "MethodNotAllowed",
+        StatusCode::CONFLICT | StatusCode::LOCKED => "OperationAborted",
+        StatusCode::LENGTH_REQUIRED => "MissingContentLength",
+        StatusCode::PRECONDITION_FAILED => "PreconditionFailed",
+        StatusCode::PAYLOAD_TOO_LARGE => "EntityTooLarge",
+        StatusCode::RANGE_NOT_SATISFIABLE => "InvalidRange",
+        StatusCode::NOT_IMPLEMENTED => "NotImplemented",
+        StatusCode::SERVICE_UNAVAILABLE => "ServiceUnavailable",
+        _ => "InternalError",
+    }
+}
+
+fn invalid_argument(message: impl Into<String>) -> StatusError {
+    s3_error(StatusError::bad_request().brief(message), "InvalidArgument")
+}
+
+fn access_denied(code: &'static str, message: impl Into<String>) -> StatusError {
+    s3_error(StatusError::forbidden().brief(message), code)
+}
+
+fn malformed(e: String) -> StatusError {
+    s3_error(
+        StatusError::bad_request().brief(format!("无效的 XML 请求体: {}", e)),
+        "MalformedXML",
+    )
+}
+
+fn no_such_upload(id: &str) -> StatusError {
+    s3_error(
+        StatusError::not_found().brief(format!("分段上传不存在: {}", id)),
+        "NoSuchUpload",
+    )
+}
+
+/// `store` 返回的400没有错误码时是摘要不匹配
+fn store_status(e: StatusError) -> StatusError {
+    match e.code == StatusCode::BAD_REQUEST && e.origin.is_none() {
+        true => s3_error(e, "BadDigest"),
+        false => e,
+    }
+}
+
+fn render_xml(res: &mut Response, body: String) {
+    res.status_code(StatusCode::OK);
+    res.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(XML_CONTENT_TYPE));
+    res.write_body(body).ok();
+}
+
+fn header<'a>(req: &'a Request, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+fn set_etag(res: &mut Response, etag: &str) {
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        res.headers_mut().insert(ETAG, value);
+    }
+}
+
+fn modified(metadata: &Metadata) -> DateTime<Utc> {
+    metadata.modified().map(DateTime::from).unwrap_or_default()
+}
+
+fn bucket_name(req: &Request) -> String {
+    req.param::<String>("bucket").unwrap_or_default()
+}
+
+/// 键以 `/` 结尾：目录
+fn is_dir_key(req: &Request) -> bool {
+    !request_path(req).is_empty() && req.uri().path().ends_with('/')
+}
+
+/// 查询参数，只做百分号解码（`+` 不视为空格），与签名时的解析一致
+struct Query(Vec<(String, String)>);
+
+impl Query {
+    fn parse(req: &Request) -> Self {
+        Query(
+            req.uri()
+                .query()
+                .unwrap_or_default()
+                .split('&')
+                .filter(|p| !p.is_empty())
+                .map(|p| match p.split_once('=') {
+                    Some((k, v)) => (uri_decode(k), uri_decode(v)),
+                    None => (uri_decode(p), String::new()),
+                })
+                .collect(),
+        )
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// 拒绝 `known` 以外的子资源（预签名参数和 `response-*` 除外）
+    fn check(&self, known: &[&str]) -> Result<(), StatusError> {
+        match self.0.iter().find(|(k, _)| {
+            !known.contains(&k.as_str()) && !k.starts_with("X-Amz-") && !k.starts_with("response-")
+        }) {
+            Some((k, _)) => Err(s3_error(
+                StatusError::not_implemented().brief(format!("不支持的子资源: {}", k)),
+                "NotImplemented",
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 请求体的校验方式，由 `x-amz-content-sha256` 决定
+#[derive(Debug, Clone)]
+enum Payload {
+    /// 不校验
+    Unsigned,
+    /// 整个请求体的 SHA-256
+    Sha256(Vec<u8>),
+    /// aws-chunked 编码，带签名时逐块校验
+    Chunked(Option<ChunkSigner>),
+}
+
+/// 把错误渲染为 S3 的 XML 错误响应，每个响应带 `x-amz-request-id`
+#[handler]
+async fn render_errors(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let request_id = uuid::Uuid::new_v4().simple().to_string();
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(REQUEST_ID, value);
+    }
+    ctrl.call_next(req, depot, res).await;
+    let ResBody::Error(e) = &res.body else {
+        return;
+    };
+    let body = response::error(code(e), &e.brief, req.uri().path(), &request_id);
+    res.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(XML_CONTENT_TYPE));
+    res.body = match *req.method() == Method::HEAD {
+        true => ResBody::None,
+        false => ResBody::Once(Bytes::from(body)),
+    };
+}
+
+/// 校验 SigV4 签名（`Authorization` 头或预签名 URL），通过后把请求体的校验方式放入 Depot
+#[handler]
+async fn authenticate(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    match verify(req, depot).await {
+        Ok(payload) => {
+            depot.inject(payload);
+        }
+        Err(e) => {
+            log::warn!("S3 认证失败 {}: {}", client_addr(req), e.brief);
+            res.render(e);
+            ctrl.skip_rest();
+        }
+    }
+}
+
+async fn verify(req: &Request, depot: &Depot) -> Result<Payload, StatusError> {
+    let mut query = Query::parse(req).0;
+    let (signature, amz_date, payload_hash) = match header(req, AUTHORIZATION.as_str()) {
+        Some(value) => {
+            let signature = Signature::from_header(value).map_err(|e| {
+                s3_error(
+                    StatusError::bad_request().brief(e),
+                    "AuthorizationHeaderMalformed",
+                )
+            })?;
+            let amz_date = header(req, "x-amz-date")
+                .ok_or_else(|| access_denied("AccessDenied", "缺少 x-amz-date"))?;
+            let payload_hash = header(req, "x-amz-content-sha256").ok_or_else(|| {
+                s3_error(
+                    StatusError::bad_request().brief("缺少 x-amz-content-sha256"),
+                    "InvalidRequest",
+                )
+            })?;
+            (signature, amz_date.to_string(), payload_hash.to_string())
+        }
+        None => {
+            let query_error = |e: String| {
+                s3_error(
+                    StatusError::bad_request().brief(e),
+                    "AuthorizationQueryParametersError",
+                )
+            };
+            let Some(signature) = Signature::from_query(&query).map_err(query_error)? else {
+                return Err(access_denied("AccessDenied", "请求没有签名"));
+            };
+            let get = |name: &str| {
+                query
+                    .iter()
+                    .find(|(k, _)| k == name)
+                    .map(|(_, v)| v.clone())
+            };
+            let amz_date =
+                get("X-Amz-Date").ok_or_else(|| query_error("缺少 X-Amz-Date".into()))?;
+            let expires = get("X-Amz-Expires")
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| (1..=MAX_EXPIRES).contains(v))
+                .ok_or_else(|| query_error("X-Amz-Expires 应为 1 到 604800 秒".into()))?;
+            let time = parse_date(&amz_date)
+                .ok_or_else(|| access_denied("AccessDenied", "无效的 X-Amz-Date"))?;
+            if Utc::now() > time + Duration::seconds(expires) {
+                return Err(access_denied("AccessDenied", "预签名 URL 已过期"));
+            }
+            query.retain(|(k, _)| k != "X-Amz-Signature");
+            (signature, amz_date, UNSIGNED_PAYLOAD.to_string())
+        }
+    };
+    if signature.scope.service != "s3" {
+        return Err(s3_error(
+            StatusError::bad_request().brief(format!("服务应为 s3: {}", signature.scope.service)),
+            "AuthorizationHeaderMalformed",
+        ));
+    }
+    let time =
+        parse_date(&amz_date).ok_or_else(|| access_denied("AccessDenied", "无效的 x-amz-date"))?;
+    if time.format("%Y%m%d").to_string() != signature.scope.date {
+        return Err(s3_error(
+            StatusError::bad_request().brief("凭证范围的日期与请求时间不符"),
+            "AuthorizationHeaderMalformed",
+        ));
+    }
+    // 预签名 URL 的签名时间可以早于现在，上面已检查有效期
+    let skew = (time - Utc::now()).num_seconds();
+    if skew > MAX_SKEW || (header(req, AUTHORIZATION.as_str()).is_some() && skew < -MAX_SKEW) {
+        return Err(access_denied(
+            "RequestTimeTooSkewed",
+            "请求时间与服务器时间相差过大",
+        ));
+    }
+
+    let secret = db(depot)?
+        .s3_secret(&signature.access_key)
+        .await
+        .map_err(db_status)?
+        .ok_or_else(|| {
+            access_denied(
+                "InvalidAccessKeyId",
+                format!("访问密钥不存在: {}", signature.access_key),
+            )
+        })?;
+    let headers: Vec<(String, String)> = signature
+        .signed_headers
+        .iter()
+        .map(|name| {
+            let value = match name.as_str() {
+                // HTTP/2 没有 Host 头，取 :authority
+                "host" => header(req, "host")
+                    .map(str::to_string)
+                    .or_else(|| req.uri().authority().map(|a| a.to_string()))
+                    .unwrap_or_default(),
+                _ => req
+                    .headers()
+                    .get_all(name.as_str())
+                    .iter()
+                    .filter_map(|v| v.to_str().ok())
+                    .map(str::trim)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            };
+            (name.clone(), value)
+        })
+        .collect();
+    let canonical = canonical_request(
+        req.method().as_str(),
+        req.uri().path(),
+        &query,
+        &headers,
+        &payload_hash,
+    );
+    let key = signing_key(&secret, &signature.scope);
+    let expected = sign(
+        &key,
+        &string_to_sign(&amz_date, &signature.scope, &canonical),
+    );
+    if !token_eq(expected.as_bytes(), signature.signature.as_bytes()) {
+        log::debug!("S3 规范请求:\n{}", canonical);
+        return Err(access_denied(
+            "SignatureDoesNotMatch",
+            "签名不匹配，请检查访问密钥和签名方法",
+        ));
+    }
+
+    match payload_hash.as_str() {
+        UNSIGNED_PAYLOAD => Ok(Payload::Unsigned),
+        STREAMING_PAYLOAD | STREAMING_PAYLOAD_TRAILER => Ok(Payload::Chunked(Some(ChunkSigner {
+            key,
+            amz_date,
+            scope: signature.scope.to_string(),
+            previous: signature.signature,
+        }))),
+        STREAMING_UNSIGNED_TRAILER => Ok(Payload::Chunked(None)),
+        hash => crate::digest::from_hex(hash)
+            .filter(|h| h.len() == 32)
+            .map(Payload::Sha256)
+            .ok_or_else(|| invalid_argument(format!("无效的 x-amz-content-sha256: {}", hash))),
+    }
+}
+
+/// 客户端给出的请求体摘要：签名中的 SHA-256、`Content-MD5` 和 `x-amz-checksum-*`
+fn expected_digests(
+    req: &Request,
+    depot: &Depot,
+) -> Result<Vec<(Algorithm, Vec<u8>)>, StatusError> {
+    let mut expected = Vec::new();
+    if let Ok(Payload::Sha256(hash)) = depot.obtain::<Payload>() {
+        expected.push((Algorithm::Sha256, hash.clone()));
+    }
+    for (name, algorithm) in [
+        ("content-md5", Algorithm::Md5),
+        ("x-amz-checksum-sha256", Algorithm::Sha256),
+        ("x-amz-checksum-sha1", Algorithm::Sha1),
+        ("x-amz-checksum-crc32c", Algorithm::Crc32c),
+    ] {
+        if let Some(value) = header(req, name) {
+            let digest = STANDARD.decode(value.trim()).map_err(|_| {
+                s3_error(
+                    StatusError::bad_request().brief(format!("无效的 {}", name)),
+                    "InvalidDigest",
+                )
+            })?;
+            expected.push((algorithm, digest));
+        }
+    }
+    Ok(expected)
+}
+
+/// 对象内容的长度：aws-chunked 编码时为 `x-amz-decoded-content-length`
+fn object_length(req: &Request) -> Option<u64> {
+    match header(req, "x-amz-decoded-content-length") {
+        Some(value) => value.trim().parse().ok(),
+        None => content_length(req),
+    }
+}
+
+/// 请求体的数据流，aws-chunked 编码时边读边解码
+fn body(req: &mut Request, depot: &Depot) -> BoxStream<'static, Result<Bytes, StatusError>> {
+    let frames = req
+        .take_body()
+        .try_filter_map(|frame| async move { Ok(frame.into_data().ok()) })
+        .map_err(|e| {
+            s3_error(
+                StatusError::bad_request().brief(e.to_string()),
+                "IncompleteBody",
+            )
+        })
+        .boxed();
+    let Ok(Payload::Chunked(signer)) = depot.obtain::<Payload>() else {
+        return frames;
+    };
+    let chunk_error = |e: String| {
+        s3_error(
+            StatusError::bad_request().brief(format!("无效的 aws-chunked 请求体: {}", e)),
+            "IncompleteBody",
+        )
+    };
+    stream::unfold(
+        Some((frames, Decoder::new(signer.clone()))),
+        move |state| async move {
+            let (mut frames, mut decoder) = state?;
+            loop {
+                let decoded = match frames.next().await {
+                    Some(Ok(data)) => match decoder.push(&data) {
+                        Ok(out) if out.is_empty() => continue,
+                        decoded => decoded,
+                    },
+                    Some(Err(e)) => return Some((Err(e), None)),
+                    None => match decoder.finish() {
+                        Ok(()) => return None,
+                        Err(e) => Err(e),
+                    },
+                };
+                return Some(match decoded {
+                    Ok(out) => (Ok(out), Some((frames, decoder))),
+                    Err(e) => (Err(chunk_error(e)), None),
+                });
+            }
+        },
+    )
+    .map_ok(|out| stream::iter(out.into_iter().map(Ok)))
+    .try_flatten()
+    .boxed()
+}
+
+/// 读取 XML 请求体并校验客户端给出的摘要
+async fn read_xml(req: &mut Request, depot: &Depot) -> Result<Vec<u8>, StatusError> {
+    let mut verifier = UploadVerifier::new(expected_digests(req, depot)?);
+    let mut body = body(req, depot);
+    let mut data = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        if data.len() + chunk.len() > MAX_XML {
+            return Err(s3_error(
+                StatusError::payload_too_large().brief("XML 请求体过大"),
+                "MaxMessageLengthExceeded",
+            ));
+        }
+        verifier.update(&chunk);
+        data.extend_from_slice(&chunk);
+    }
+    verifier
+        .verify()
+        .map_err(|e| s3_error(StatusError::bad_request().brief(e), "BadDigest"))?;
+    Ok(data)
+}
+
+fn buckets(depot: &Depot) -> Result<Arc<Buckets>, StatusError> {
+    depot
+        .obtain::<Arc<Buckets>>()
+        .cloned()
+        .map_err(|_| StatusError::internal_server_error().brief("S3 桶未配置"))
+}
+
+/// 按路径中的桶名选择挂载点，之后的处理与挂载点下的路由相同
+#[handler]
+async fn select(req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    let name = bucket_name(req);
+    let bucket = buckets(depot)
+        .ok()
+        .and_then(|buckets| buckets.find(&name).cloned());
+    match bucket {
+        Some(bucket) => {
+            depot.inject(bucket.sandbox);
+            depot.inject(bucket.mount);
+            depot.inject(bucket.limits);
+        }
+        None => {
+            res.render(s3_error(
+                StatusError::not_found().brief(format!("桶不存在: {}", name)),
+                "NoSuchBucket",
+            ));
+            ctrl.skip_rest();
+        }
+    }
+}
+
+/// GET /s3: ListBuckets
+#[handler]
+async fn list_buckets(depot: &mut Depot, res: &mut Response) -> Result<(), StatusError> {
+    let buckets: Vec<(String, DateTime<Utc>)> = buckets(depot)?
+        .0
+        .iter()
+        .map(|b| (b.name.clone(), b.created))
+        .collect();
+    render_xml(res, response::list_buckets(OWNER, &buckets));
+    Ok(())
+}
+
+/// 桶和对象的请求，按方法、子资源和键分派
+#[handler]
+async fn dispatch(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) -> Result<(), StatusError> {
+    let query = Query::parse(req);
+    if request_path(req).is_empty() {
+        query.check(&BUCKET_PARAMS)?;
+        return match req.method().clone() {
+            Method::GET if query.has("location") => {
+                render_xml(res, response::location());
+                Ok(())
+            }
+            Method::GET => list_objects(req, depot, res, &query).await,
+            Method::HEAD => {
+                res.status_code(StatusCode::OK);
+                Ok(())
+            }
+            Method::POST if query.has("delete") => delete_objects(req, depot, res).await,
+            Method::PUT => Err(s3_error(
+                StatusError::conflict().brief("桶已存在"),
+                "BucketAlreadyOwnedByYou",
+            )),
+            _ => Err(s3_error(
+                StatusError::not_implemented().brief("桶由挂载点配置决定，不能创建或删除"),
+                "NotImplemented",
+            )),
+        };
+    }
+    query.check(&OBJECT_PARAMS)?;
+    let upload_id = query.get("uploadId").map(str::to_string);
+    match (req.method().clone(), upload_id) {
+        (Method::POST, None) if query.has("uploads") => {
+            create_multipart_upload(req, depot, res).await
+        }
+        (Method::POST, Some(id)) => complete_multipart_upload(req, depot, res, &id).await,
+        (Method::PUT, Some(id)) => upload_part(req, depot, res, &id, &query).await,
+        (Method::GET, Some(id)) => list_parts(req, depot, res, &id).await,
+        (Method::DELETE, Some(id)) => abort_multipart_upload(req, depot, res, &id).await,
+        (Method::GET | Method::HEAD, None) => get_object(req, depot, res, ctrl, &query).await,
+        (Method::PUT, None) if header(req, COPY_SOURCE).is_some() => {
+            copy_object(req, depot, res).await
+        }
+        (Method::PUT, None) => put_object(req, depot, res).await,
+        (Method::DELETE, None) => delete_object(req, depot, res).await,
+        _ => Err(StatusError::method_not_allowed()),
+    }
+}
+
+/// 一页列表
+#[derive(Debug, Default)]
+struct Page {
+    objects: Vec<Object>,
+    prefixes: Vec<String>,
+    /// 截断时最后返回的键或公共前缀
+    next: Option<String>,
+}
+
+/// 收集 `dir`（键前缀为 `dir_key`）下名称以 `name_prefix` 开头的对象
+///
+/// `recursive` 为假时子目录作为 `子目录/` 返回而不展开；展开时没有可列出内容的目录作为目录标记返回。
+/// 符号链接按挂载点的策略解析，只列出指向文件的链接。
+fn collect(
+    sandbox: &Sandbox,
+    dir: &Path,
+    dir_key: &str,
+    name_prefix: &str,
+    recursive: bool,
+    after: &str,
+    out: &mut Vec<(String, Metadata)>,
+) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        let path = entry.path();
+        if !name.starts_with(name_prefix) || sandbox.is_excluded(&path) {
+            continue;
+        }
+        let key = format!("{}{}", dir_key, name);
+        let Ok(mut metadata) = std::fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if metadata.is_symlink() {
+            match sandbox
+                .resolve(&key)
+                .ok()
+                .and_then(|p| std::fs::metadata(p).ok())
+            {
+                Some(m) if m.is_file() => metadata = m,
+                _ => continue,
+            }
+        }
+        if metadata.is_dir() {
+            let dir_key = format!("{}/", key);
+            if !recursive {
+                out.push((dir_key, metadata));
+                continue;
+            }
+            // 子树中的键都以 `dir_key` 开头，都不大于 `after` 时不必展开
+            if after > dir_key.as_str() && !after.starts_with(&dir_key) {
+                continue;
+            }
+            let before = out.len();
+            collect(sandbox, &path, &dir_key, "", true, after, out);
+            if out.len() == before {
+                out.push((dir_key, metadata));
+            }
+        } else if metadata.is_file() {
+            out.push((key, metadata));
+        }
+    }
+}
+
+/// 列出键大于 `after` 的一页，`delimiter` 之后的部分合并为公共前缀
+fn list(
+    sandbox: &Sandbox,
+    prefix: &str,
+    delimiter: Option<&str>,
+    after: &str,
+    max_keys: usize,
+) -> Page {
+    // 从前缀中最后一个 `/` 所在的目录开始
+    let dir_key = prefix.rfind('/').map_or("", |i| &prefix[..=i]);
+    let Ok(dir) = sandbox.resolve(dir_key) else {
+        return Page::default();
+    };
+    let mut keys = Vec::new();
+    collect(
+        sandbox,
+        &dir,
+        dir_key,
+        &prefix[dir_key.len()..],
+        delimiter != Some("/"),
+        after,
+        &mut keys,
+    );
+    keys.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut page = Page::default();
+    let mut last = None;
+    let mut count = 0;
+    for (key, metadata) in keys {
+        if key.as_str() <= after {
+            continue;
+        }
+        let common = delimiter.and_then(|d| {
+            key[prefix.len()..]
+                .find(d)
+                .map(|i| key[..prefix.len() + i + d.len()].to_string())
+        });
+        if let Some(common) = &common
+            && (common.as_str() <= after || page.prefixes.last() == Some(common))
+        {
+            continue;
+        }
+        if count == max_keys {
+            page.next = last;
+            break;
+        }
+        count += 1;
+        match common {
+            Some(common) => {
+                page.prefixes.push(common.clone());
+                last = Some(common);
+            }
+            None => {
+                let directory = key.ends_with('/');
+                page.objects.push(Object {
+                    key: key.clone(),
+                    last_modified: modified(&metadata),
+                    etag: match directory {
+                        true => EMPTY_ETAG.to_string(),
+                        false => precondition::etag(&metadata),
+                    },
+                    size: if directory { 0 } else { metadata.len() },
+                });
+                last = Some(key);
+            }
+        }
+    }
+    page
+}
+
+/// GET /s3/{bucket}: ListObjectsV2（`list-type=2`）或 ListObjects
+async fn list_objects(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+    query: &Query,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let v2 = query.get("list-type") == Some("2");
+    let prefix = query.get("prefix").unwrap_or_default().to_string();
+    let delimiter = query
+        .get("delimiter")
+        .filter(|d| !d.is_empty())
+        .map(str::to_string);
+    let max_keys = match query.get("max-keys") {
+        Some(value) => value
+            .parse::<usize>()
+            .map_err(|_| invalid_argument(format!("无效的 max-keys: {}", value)))?
+            .min(MAX_KEYS),
+        None => MAX_KEYS,
+    };
+    let encode = match query.get("encoding-type") {
+        None => false,
+        Some("url") => true,
+        Some(other) => return Err(invalid_argument(format!("无效的 encoding-type: {}", other))),
+    };
+    let start_after = match v2 {
+        true => query.get("start-after"),
+        false => query.get("marker"),
+    }
+    .filter(|s| !s.is_empty())
+    .map(str::to_string);
+    let continuation_token = query
+        .get("continuation-token")
+        .filter(|_| v2)
+        .map(str::to_string);
+    // 续页令牌是上一页最后一个键的 base64
+    let after = match &continuation_token {
+        Some(token) => URL_SAFE_NO_PAD
+            .decode(token)
+            .ok()
+            .and_then(|k| String::from_utf8(k).ok())
+            .ok_or_else(|| invalid_argument("无效的 continuation-token"))?,
+        None => start_after.clone().unwrap_or_default(),
+    };
+
+    let task = (prefix.clone(), delimiter.clone());
+    let page = tokio::task::spawn_blocking(move || {
+        list(&sandbox, &task.0, task.1.as_deref(), &after, max_keys)
+    })
+    .await
+    .map_err(|e| StatusError::internal_server_error().brief(e.to_string()))?;
+    let listing = Listing {
+        bucket: bucket_name(req),
+        prefix,
+        delimiter,
+        max_keys,
+        encode,
+        v2,
+        start_after,
+        continuation_token,
+        next: page.next.map(|key| match v2 {
+            true => URL_SAFE_NO_PAD.encode(key),
+            false => key,
+        }),
+        objects: page.objects,
+        prefixes: page.prefixes,
+    };
+    render_xml(res, listing.to_xml());
+    Ok(())
+}
+
+/// GetObject / HeadObject：文件交给 `/fs` 的读取处理，以 `/` 结尾的键读取目录标记
+async fn get_object(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+    query: &Query,
+) -> Result<(), StatusError> {
+    if query.get("versionId").is_some_and(|v| v != "null") {
+        return Err(s3_error(
+            StatusError::not_found().brief("只有当前版本"),
+            "NoSuchVersion",
+        ));
+    }
+    if query.get("partNumber").is_some_and(|n| n != "1") {
+        return Err(s3_error(
+            StatusError::range_not_satisfiable().brief("对象只有一个分段"),
+            "InvalidPartNumber",
+        ));
+    }
+    let sandbox = sandbox(depot)?;
+    let key = request_path(req);
+    let path = sandbox.resolve_existing(&key)?;
+    let metadata = tokio::fs::metadata(&path).await.map_err(io_status)?;
+    if metadata.is_dir() != is_dir_key(req) {
+        return Err(StatusError::not_found().brief(format!("对象不存在: {}", key)));
+    }
+    if metadata.is_dir() {
+        res.headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(0));
+        set_etag(res, EMPTY_ETAG);
+        res.status_code(StatusCode::OK);
+        return Ok(());
+    }
+    fs::read_file.handle(req, depot, res, ctrl).await;
+    Ok(())
+}
+
+/// 写入对象前的检查：锁、前置条件、大小限制和父目录，返回目标的当前状态
+async fn prepare_write(
+    req: &Request,
+    depot: &Depot,
+    sandbox: &Sandbox,
+    path: &Path,
+    size: u64,
+) -> Result<Option<Metadata>, StatusError> {
+    if path == sandbox.root() {
+        return Err(invalid_argument("键不能为空"));
+    }
+    locks::require(depot, req, sandbox, path, false).await?;
+    let current = tokio::fs::metadata(path).await.ok();
+    if current.as_ref().is_some_and(|m| m.is_dir()) {
+        return Err(StatusError::conflict().brief("目标是目录"));
+    }
+    precondition::check_write(req.headers(), current.as_ref())?;
+    mounts::check_file_size(depot, size)?;
+    prepare_parent(path, true).await?;
+    Ok(current)
+}
+
+/// PutObject：原子写入文件，缺少的父目录自动创建；以 `/` 结尾的空对象创建目录
+async fn put_object(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let key = request_path(req);
+    let path = sandbox.resolve(&key)?;
+    if is_dir_key(req) {
+        if object_length(req).unwrap_or_default() > 0 {
+            return Err(invalid_argument("目录标记不能有内容"));
+        }
+        locks::require(depot, req, &sandbox, &path, false).await?;
+        if tokio::fs::metadata(&path).await.is_ok_and(|m| !m.is_dir()) {
+            return Err(StatusError::conflict().brief(format!("已存在同名文件: {}", key)));
+        }
+        tokio::fs::create_dir_all(&path).await.map_err(io_status)?;
+        log::info!("S3 创建目录: {}", path.display());
+        set_etag(res, EMPTY_ETAG);
+        res.status_code(StatusCode::OK);
+        return Ok(());
+    }
+
+    let current = prepare_write(
+        req,
+        depot,
+        &sandbox,
+        &path,
+        object_length(req).unwrap_or_default(),
+    )
+    .await?;
+    let verifier = UploadVerifier::new(expected_digests(req, depot)?);
+    let body = body(req, depot);
+    store(
+        req,
+        depot,
+        &sandbox,
+        &path,
+        current.is_some(),
+        verifier,
+        body,
+    )
+    .await
+    .map_err(store_status)?;
+    fs::set_etag(&path, res).await;
+    res.status_code(StatusCode::OK);
+    Ok(())
+}
+
+/// CopyObject：`x-amz-copy-source: /桶/键`，可以跨桶
+async fn copy_object(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let source = header(req, COPY_SOURCE).unwrap_or_default();
+    let source = uri_decode(source.split('?').next().unwrap_or_default());
+    let (bucket, key) = source
+        .trim_start_matches('/')
+        .split_once('/')
+        .filter(|(_, key)| !key.is_empty())
+        .ok_or_else(|| invalid_argument(format!("无效的 {}: {}", COPY_SOURCE, source)))?;
+    let buckets = buckets(depot)?;
+    let from = buckets.find(bucket).ok_or_else(|| {
+        s3_error(
+            StatusError::not_found().brief(format!("桶不存在: {}", bucket)),
+            "NoSuchBucket",
+        )
+    })?;
+    let src = from.sandbox.resolve_existing(key)?;
+    let metadata = tokio::fs::metadata(&src).await.map_err(io_status)?;
+    if !metadata.is_file() {
+        return Err(StatusError::not_found().brief(format!("对象不存在: {}", key)));
+    }
+
+    let sandbox = sandbox(depot)?;
+    let path = sandbox.resolve(&request_path(req))?;
+    let current = prepare_write(req, depot, &sandbox, &path, metadata.len()).await?;
+    let file = tokio::fs::File::open(&src).await.map_err(io_status)?;
+    store(
+        req,
+        depot,
+        &sandbox,
+        &path,
+        current.is_some(),
+        UploadVerifier::new(Vec::new()),
+        ReaderStream::new(file).map_err(io_status),
+    )
+    .await?;
+    log::info!("S3 复制对象: {} -> {}", src.display(), path.display());
+    let metadata = tokio::fs::metadata(&path).await.map_err(io_status)?;
+    render_xml(
+        res,
+        response::copy_object(&precondition::etag(&metadata), modified(&metadata)),
+    );
+    Ok(())
+}
+
+/// 删除一个键：文件移入回收站，目录标记只删除空目录；键不存在也算成功
+async fn delete_key(
+    req: &Request,
+    depot: &Depot,
+    sandbox: &Sandbox,
+    key: &str,
+) -> Result<(), StatusError> {
+    let path = match resolve_target(sandbox, key) {
+        Ok(path) => path,
+        Err(e) if e.code == StatusCode::NOT_FOUND => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let Ok(metadata) = tokio::fs::symlink_metadata(&path).await else {
+        return Ok(());
+    };
+    if metadata.is_dir() != key.ends_with('/') {
+        return Ok(());
+    }
+    locks::require(depot, req, sandbox, &path, false).await?;
+    if metadata.is_dir() {
+        // 非空目录仍由其中的对象隐含，保留
+        return match tokio::fs::remove_dir(&path).await {
+            Ok(()) => {
+                log::info!("S3 删除目录: {}", path.display());
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::DirectoryNotEmpty => Ok(()),
+            Err(e) => Err(io_status(e)),
+        };
+    }
+    let (item, _) =
+        trash::move_to_trash(sandbox, &db(depot)?, &path, false, client_addr(req)).await?;
+    log::info!("S3 删除对象: {} -> 回收站 {}", path.display(), item.id);
+    Ok(())
+}
+
+/// DeleteObject
+async fn delete_object(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let key = match is_dir_key(req) {
+        true => format!("{}/", request_path(req).trim_end_matches('/')),
+        false => request_path(req),
+    };
+    delete_key(req, depot, &sandbox, &key).await?;
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// DeleteObjects：逐个删除，失败的键在响应中单独报告
+async fn delete_objects(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let body = read_xml(req, depot).await?;
+    let delete = request::delete(&body).map_err(malformed)?;
+    if delete.keys.len() > MAX_DELETE {
+        return Err(malformed(format!("一次最多删除 {} 个键", MAX_DELETE)));
+    }
+    let sandbox = sandbox(depot)?;
+    let mut deleted = Vec::new();
+    let mut errors = Vec::new();
+    for key in delete.keys {
+        match delete_key(req, depot, &sandbox, &key).await {
+            Ok(()) => deleted.push(key),
+            Err(e) => errors.push((key, code(&e).to_string(), e.brief)),
+        }
+    }
+    if delete.quiet {
+        deleted.clear();
+    }
+    render_xml(res, response::delete_result(&deleted, &errors));
+    Ok(())
+}
+
+/// 分段上传的临时目录 `.s3/{id}`
+fn upload_dir(sandbox: &Sandbox, id: &str) -> Result<PathBuf, StatusError> {
+    Ok(sandbox.internal_dir(S3_DIR).map_err(io_status)?.join(id))
+}
+
+/// 查找请求的键上的分段上传
+async fn find_upload(
+    req: &Request,
+    depot: &Depot,
+    sandbox: &Sandbox,
+    id: &str,
+) -> Result<(S3Upload, PathBuf), StatusError> {
+    let path = sandbox.resolve(&request_path(req))?;
+    match db(depot)?
+        .get_s3_upload(sandbox.mount(), id)
+        .await
+        .map_err(db_status)?
+    {
+        Some(upload) if sandbox.relative(&path).as_ref() == Some(&upload.key) => Ok((upload, path)),
+        _ => Err(no_such_upload(id)),
+    }
+}
+
+/// CreateMultipartUpload：分段保存在 `.s3/{id}` 下，完成时拼接成目标文件
+async fn create_multipart_upload(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let key = request_path(req);
+    let path = sandbox.resolve(&key)?;
+    if is_dir_key(req) || path == sandbox.root() {
+        return Err(invalid_argument(format!("无效的键: {}", key)));
+    }
+    let upload = S3Upload {
+        id: uuid::Uuid::now_v7().simple().to_string(),
+        mount: sandbox.mount().to_string(),
+        key: sandbox.relative(&path).unwrap_or_default(),
+        created_at: now(),
+    };
+    tokio::fs::create_dir_all(upload_dir(&sandbox, &upload.id)?)
+        .await
+        .map_err(io_status)?;
+    db(depot)?
+        .insert_s3_upload(&upload)
+        .await
+        .map_err(db_status)?;
+    log::info!("S3 开始分段上传 {}: {}", upload.id, path.display());
+    render_xml(
+        res,
+        response::initiate_multipart_upload(&bucket_name(req), &key, &upload.id),
+    );
+    Ok(())
+}
+
+/// UploadPart：分段的 ETag 是其内容的 MD5
+async fn upload_part(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+    id: &str,
+    query: &Query,
+) -> Result<(), StatusError> {
+    let number = query
+        .get("partNumber")
+        .and_then(|n| n.parse::<i64>().ok())
+        .filter(|n| (1..=MAX_PARTS).contains(n))
+        .ok_or_else(|| invalid_argument(format!("partNumber 应为 1 到 {}", MAX_PARTS)))?;
+    if header(req, COPY_SOURCE).is_some() {
+        return Err(s3_error(
+            StatusError::not_implemented().brief("不支持 UploadPartCopy"),
+            "NotImplemented",
+        ));
+    }
+    let sandbox = sandbox(depot)?;
+    find_upload(req, depot, &sandbox, id).await?;
+    mounts::check_file_size(depot, object_length(req).unwrap_or_default())?;
+
+    let path = upload_dir(&sandbox, id)?.join(number.to_string());
+    let verifier = UploadVerifier::with_extra(expected_digests(req, depot)?, &[Algorithm::Md5]);
+    let body = body(req, depot);
+    let digests = store(req, depot, &sandbox, &path, false, verifier, body)
+        .await
+        .map_err(store_status)?;
+    let etag = digests
+        .iter()
+        .find(|(algorithm, _)| *algorithm == Algorithm::Md5)
+        .map(|(_, digest)| hex(digest))
+        .unwrap_or_default();
+    let size = tokio::fs::metadata(&path).await.map_err(io_status)?.len();
+    db(depot)?
+        .put_s3_part(
+            id,
+            &S3Part {
+                part_number: number,
+                etag: etag.clone(),
+                size: size as i64,
+                uploaded_at: now(),
+            },
+        )
+        .await
+        .map_err(db_status)?;
+    set_etag(res, &format!("\"{}\"", etag));
+    res.status_code(StatusCode::OK);
+    Ok(())
+}
+
+/// ListParts
+async fn list_parts(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+    id: &str,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    find_upload(req, depot, &sandbox, id).await?;
+    let parts: Vec<Part> = db(depot)?
+        .list_s3_parts(id)
+        .await
+        .map_err(db_status)?
+        .into_iter()
+        .map(|p| Part {
+            number: p.part_number,
+            etag: format!("\"{}\"", p.etag),
+            size: p.size,
+            last_modified: DateTime::from_timestamp(p.uploaded_at, 0).unwrap_or_default(),
+        })
+        .collect();
+    render_xml(
+        res,
+        response::list_parts(&bucket_name(req), &request_path(req), id, &parts),
+    );
+    Ok(())
+}
+
+/// 删除分段上传的临时目录和记录
+async fn discard_upload(sandbox: &Sandbox, depot: &Depot, id: &str) -> Result<(), StatusError> {
+    match tokio::fs::remove_dir_all(upload_dir(sandbox, id)?).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("删除分段上传目录失败 {}: {}", id, e),
+    }
+    db(depot)?.delete_s3_upload(id).await.map_err(db_status)
+}
+
+/// AbortMultipartUpload
+async fn abort_multipart_upload(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+    id: &str,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    find_upload(req, depot, &sandbox, id).await?;
+    discard_upload(&sandbox, depot, id).await?;
+    log::info!("S3 放弃分段上传: {}", id);
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// CompleteMultipartUpload：按请求中的顺序拼接分段，原子写入目标文件
+async fn complete_multipart_upload(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+    id: &str,
+) -> Result<(), StatusError> {
+    let body = read_xml(req, depot).await?;
+    let requested = request::complete_multipart_upload(&body).map_err(malformed)?;
+    let sandbox = sandbox(depot)?;
+    let (upload, path) = find_upload(req, depot, &sandbox, id).await?;
+    let parts = db(depot)?.list_s3_parts(id).await.map_err(db_status)?;
+    let dir = upload_dir(&sandbox, id)?;
+    let mut files = Vec::new();
+    let mut size = 0u64;
+    let mut previous = 0;
+    for (number, etag) in &requested {
+        if *number <= previous {
+            return Err(s3_error(
+                StatusError::bad_request().brief("分段编号必须递增"),
+                "InvalidPartOrder",
+            ));
+        }
+        previous = *number;
+        let part = parts
+            .iter()
+            .find(|p| p.part_number == *number && p.etag == *etag)
+            .ok_or_else(|| {
+                s3_error(
+                    StatusError::bad_request()
+                        .brief(format!("分段 {} 不存在或 ETag 不匹配", number)),
+                    "InvalidPart",
+                )
+            })?;
+        size += part.size as u64;
+        files.push(dir.join(number.to_string()));
+    }
+
+    let current = prepare_write(req, depot, &sandbox, &path, size).await?;
+    let body = stream::iter(files)
+        .then(tokio::fs::File::open)
+        .map_ok(ReaderStream::new)
+        .try_flatten()
+        .map_err(io_status);
+    store(
+        req,
+        depot,
+        &sandbox,
+        &path,
+        current.is_some(),
+        UploadVerifier::new(Vec::new()),
+        body,
+    )
+    .await?;
+    discard_upload(&sandbox, depot, id).await?;
+    log::info!(
+        "S3 完成分段上传 {}: {} ({} 个分段)",
+        id,
+        path.display(),
+        requested.len()
+    );
+    let metadata = tokio::fs::metadata(&path).await.map_err(io_status)?;
+    render_xml(
+        res,
+        response::complete_multipart_upload(
+            req.uri().path(),
+            &bucket_name(req),
+            &upload.key,
+            &precondition::etag(&metadata),
+        ),
+    );
+    Ok(())
+}
+
+/// S3 路由，`buckets` 为可访问的挂载点
+pub fn router(buckets: Buckets) -> Router {
+    Router::with_path("s3")
+        .hoop(affix_state::inject(Arc::new(buckets)))
+        .hoop(render_errors)
+        .hoop(authenticate)
+        .get(list_buckets)
+        .push(
+            Router::with_path("{bucket}/{**path}")
+                .hoop(select)
+                .hoop(mounts::guard)
+                .goal(dispatch),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::ServerConfig;
+    use crate::s3::sigv4::{ALGORITHM, Scope, sha256_hex};
+    use md5::{Digest, Md5};
+    use salvo::prelude::Service;
+    use salvo::test::{RequestBuilder, ResponseExt, TestClient};
+
+    struct Client {
+        service: Service,
+        access_key: String,
+        secret: String,
+    }
+
+    impl Client {
+        /// 用 SigV4 签名的请求，`headers` 的名称为小写
+        fn request(
+            &self,
+            method: &str,
+            path: &str,
+            headers: &[(&str, &str)],
+            body: &[u8],
+        ) -> RequestBuilder {
+            let now = Utc::now();
+            let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+            let scope = Scope {
+                date: now.format("%Y%m%d").to_string(),
+                region: "us-east-1".into(),
+                service: "s3".into(),
+            };
+            let payload_hash = headers
+                .iter()
+                .find(|(k, _)| *k == "x-amz-content-sha256")
+                .map(|(_, v)| v.to_string())
+                .unwrap_or_else(|| sha256_hex(body));
+            let mut signed: Vec<(String, String)> = vec![
+                ("host".into(), "127.0.0.1".into()),
+                ("x-amz-content-sha256".into(), payload_hash.clone()),
+                ("x-amz-date".into(), amz_date.clone()),
+            ];
+            for (k, v) in headers {
+                if *k != "x-amz-content-sha256" {
+                    signed.push((k.to_string(), v.to_string()));
+                }
+            }
+            signed.sort();
+            let (raw_path, raw_query) = path.split_once('?').unwrap_or((path, ""));
+            let query: Vec<(String, String)> = raw_query
+                .split('&')
+                .filter(|p| !p.is_empty())
+                .map(|p| {
+                    let (k, v) = p.split_once('=').unwrap_or((p, ""));
+                    (uri_decode(k), uri_decode(v))
+                })
+                .collect();
+            let canonical = canonical_request(method, raw_path, &query, &signed, &payload_hash);
+            let signature = sign(
+                &signing_key(&self.secret, &scope),
+                &string_to_sign(&amz_date, &scope, &canonical),
+            );
+            let authorization = format!(
+                "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+                ALGORITHM,
+                self.access_key,
+                scope,
+                signed
+                    .iter()
+                    .map(|(k, _)| k.as_str())
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                signature
+            );
+            let mut builder = RequestBuilder::new(
+                format!("http://127.0.0.1{}", path),
+                Method::from_bytes(method.as_bytes()).unwrap(),
+            );
+            for (k, v) in &signed {
+                builder =
+                    builder.add_header(HeaderName::from_bytes(k.as_bytes()).unwrap(), v, true);
+            }
+            builder
+                .add_header(AUTHORIZATION, authorization, true)
+                .body(body.to_vec())
+        }
+
+        async fn send(
+            &self,
+            method: &str,
+            path: &str,
+            headers: &[(&str, &str)],
+            body: &[u8],
+        ) -> Response {
+            self.request(method, path, headers, body)
+                .send(&self.service)
+                .await
+        }
+    }
+
+    fn between<'a>(text: &'a str, start: &str, end: &str) -> &'a str {
+        let from = text.find(start).unwrap() + start.len();
+        &text[from..from + text[from..].find(end).unwrap()]
+    }
+
+    #[tokio::test]
+    async fn test_s3() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            root: dir.path().display().to_string(),
+            database_url: "sqlite::memory:".to_string(),
+            admin_token: "secret".to_string(),
+            s3_bucket: "data".to_string(),
+            ..Default::default()
+        };
+        let service = Service::new(crate::web::create_router(&config).await.unwrap());
+        let mut res = TestClient::post("http://127.0.0.1/admin/s3-keys?description=test")
+            .add_header(AUTHORIZATION, "Bearer secret", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        let key: serde_json::Value = res.take_json().await.unwrap();
+        let mut res = TestClient::get("http://127.0.0.1/admin/s3-keys")
+            .add_header(AUTHORIZATION, "Bearer secret", true)
+            .send(&service)
+            .await;
+        let keys: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(keys[0]["access_key"], key["access_key"]);
+        assert_eq!(keys[0]["description"], "test");
+        assert!(keys[0].get("secret_key").is_none());
+        let mut client = Client {
+            service,
+            access_key: key["access_key"].as_str().unwrap().to_string(),
+            secret: key["secret_key"].as_str().unwrap().to_string(),
+        };
+
+        // 没有签名或签名错误
+        let mut res = TestClient::get("http://127.0.0.1/s3")
+            .send(&client.service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::FORBIDDEN));
+        assert!(res.headers().contains_key(REQUEST_ID));
+        let body = res.take_string().await.unwrap();
+        assert!(body.contains(
+            "<Code>AccessDenied</Code><Message>请求没有签名</Message><Resource>/s3</Resource>"
+        ));
+        let secret = std::mem::replace(&mut client.secret, "wrong".into());
+        let mut res = client.send("GET", "/s3", &[], b"").await;
+        assert!(
+            res.take_string()
+                .await
+                .unwrap()
+                .contains("<Code>SignatureDoesNotMatch</Code>")
+        );
+        client.secret = secret;
+
+        let mut res = client.send("GET", "/s3", &[], b"").await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert!(
+            res.take_string()
+                .await
+                .unwrap()
+                .contains("<Bucket><Name>data</Name>")
+        );
+        let mut res = client.send("GET", "/s3/nope/x", &[], b"").await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+        assert!(
+            res.take_string()
+                .await
+                .unwrap()
+                .contains("<Code>NoSuchBucket</Code>")
+        );
+        let res = client.send("HEAD", "/s3/data", &[], b"").await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+
+        // PutObject 创建父目录，校验 Content-MD5
+        let res = client
+            .send("PUT", "/s3/data/a%20b/c.txt", &[], b"hello")
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert!(res.headers().contains_key(ETAG));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("a b/c.txt")).unwrap(),
+            "hello"
+        );
+        let mut res = client
+            .send(
+                "PUT",
+                "/s3/data/d.txt",
+                &[("content-md5", "1B2M2Y8AsgTpgAmY7PhCfg==")],
+                b"x",
+            )
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+        assert!(
+            res.take_string()
+                .await
+                .unwrap()
+                .contains("<Code>BadDigest</Code>")
+        );
+        assert!(!dir.path().join("d.txt").exists());
+        // aws-chunked，分块不签名
+        let res = client
+            .send(
+                "PUT",
+                "/s3/data/d.txt",
+                &[
+                    ("x-amz-content-sha256", STREAMING_UNSIGNED_TRAILER),
+                    ("x-amz-decoded-content-length", "5"),
+                ],
+                b"3\r\nwor\r\n2\r\nld\r\n0\r\n\r\n",
+            )
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let res = client.send("PUT", "/s3/data/e/", &[], b"").await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert!(dir.path().join("e").is_dir());
+
+        let mut res = client.send("GET", "/s3/data/d.txt", &[], b"").await;
+        assert_eq!(res.take_string().await.unwrap(), "world");
+        let mut res = client
+            .send("GET", "/s3/data/d.txt", &[("range", "bytes=1-2")], b"")
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "or");
+        let mut res = client.send("GET", "/s3/data/missing", &[], b"").await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+        assert!(
+            res.take_string()
+                .await
+                .unwrap()
+                .contains("<Code>NoSuchKey</Code>")
+        );
+        let mut res = client.send("HEAD", "/s3/data/missing", &[], b"").await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+        assert_eq!(res.take_string().await.unwrap(), "");
+        let mut res = client.send("GET", "/s3/data?acl", &[], b"").await;
+        assert!(
+            res.take_string()
+                .await
+                .unwrap()
+                .contains("<Code>NotImplemented</Code>")
+        );
+
+        // 列表：按层级和递归，分页
+        let mut res = client
+            .send("GET", "/s3/data?list-type=2&delimiter=%2F", &[], b"")
+            .await;
+        let body = res.take_string().await.unwrap();
+        assert!(body.contains("<Key>d.txt</Key>"));
+        assert!(body.contains("<CommonPrefixes><Prefix>a b/</Prefix></CommonPrefixes><CommonPrefixes><Prefix>e/</Prefix></CommonPrefixes>"));
+        let mut res = client
+            .send("GET", "/s3/data?list-type=2&max-keys=2", &[], b"")
+            .await;
+        let body = res.take_string().await.unwrap();
+        assert!(body.contains("<IsTruncated>true</IsTruncated>"));
+        assert!(body.contains("<Key>a b/c.txt</Key>"));
+        assert!(body.contains("<Key>d.txt</Key>"));
+        let token = between(&body, "<NextContinuationToken>", "<");
+        let mut res = client
+            .send(
+                "GET",
+                &format!(
+                    "/s3/data?list-type=2&max-keys=2&continuation-token={}",
+                    token
+                ),
+                &[],
+                b"",
+            )
+            .await;
+        let body = res.take_string().await.unwrap();
+        assert!(body.contains("<IsTruncated>false</IsTruncated>"));
+        assert!(body.contains("<Key>e/</Key><LastModified>"));
+        assert!(!body.contains("d.txt"));
+
+        // CopyObject 和分段上传
+        let mut res = client
+            .send(
+                "PUT",
+                "/s3/data/f/copy.txt",
+                &[("x-amz-copy-source", "/data/a%20b/c.txt")],
+                b"",
+            )
+            .await;
+        assert!(
+            res.take_string()
+                .await
+                .unwrap()
+                .contains("</CopyObjectResult>")
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("f/copy.txt")).unwrap(),
+            "hello"
+        );
+        let mut res = client
+            .send("POST", "/s3/data/big.bin?uploads", &[], b"")
+            .await;
+        let body = res.take_string().await.unwrap();
+        let id = between(&body, "<UploadId>", "<").to_string();
+        for (number, data) in [(2, "world"), (1, "hello ")] {
+            let res = client
+                .send(
+                    "PUT",
+                    &format!("/s3/data/big.bin?partNumber={}&uploadId={}", number, id),
+                    &[],
+                    data.as_bytes(),
+                )
+                .await;
+            assert_eq!(res.status_code, Some(StatusCode::OK));
+        }
+        let mut res = client
+            .send(
+                "GET",
+                &format!("/s3/data/big.bin?uploadId={}", id),
+                &[],
+                b"",
+            )
+            .await;
+        let body = res.take_string().await.unwrap();
+        assert!(body.contains("<PartNumber>1</PartNumber>"));
+        assert!(body.contains(&format!(
+            "<ETag>&quot;{}&quot;</ETag><Size>6</Size>",
+            hex(&Md5::digest(b"hello "))
+        )));
+        let complete = "<CompleteMultipartUpload>\
+            <Part><PartNumber>1</PartNumber><ETag>\"1a77fd7d4ba0d9c9d4f4dbbc2f5f2f8a\"</ETag></Part>\
+            </CompleteMultipartUpload>";
+        let mut res = client
+            .send(
+                "POST",
+                &format!("/s3/data/big.bin?uploadId={}", id),
+                &[],
+                complete.as_bytes(),
+            )
+            .await;
+        assert!(
+            res.take_string()
+                .await
+                .unwrap()
+                .contains("<Code>InvalidPart</Code>")
+        );
+        let complete = format!(
+            "<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>\"{}\"</ETag></Part>\
+             <Part><PartNumber>2</PartNumber><ETag>\"{}\"</ETag></Part></CompleteMultipartUpload>",
+            hex(&Md5::digest(b"hello ")),
+            hex(&Md5::digest(b"world")),
+        );
+        let mut res = client
+            .send(
+                "POST",
+                &format!("/s3/data/big.bin?uploadId={}", id),
+                &[],
+                complete.as_bytes(),
+            )
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert!(
+            res.take_string()
+                .await
+                .unwrap()
+                .contains("<Key>big.bin</Key>")
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("big.bin")).unwrap(),
+            "hello world"
+        );
+        assert!(!dir.path().join(S3_DIR).join(&id).exists());
+        let mut res = client
+            .send(
+                "DELETE",
+                &format!("/s3/data/big.bin?uploadId={}", id),
+                &[],
+                b"",
+            )
+            .await;
+        assert!(
+            res.take_string()
+                .await
+                .unwrap()
+                .contains("<Code>NoSuchUpload</Code>")
+        );
+
+        // 删除：文件进回收站，不存在的键也成功
+        let res = client.send("DELETE", "/s3/data/d.txt", &[], b"").await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert!(!dir.path().join("d.txt").exists());
+        let res = client.send("DELETE", "/s3/data/d.txt", &[], b"").await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        let delete = "<Delete><Quiet>false</Quiet><Object><Key>big.bin</Key></Object><Object><Key>e/</Key></Object></Delete>";
+        let mut res = client
+            .send("POST", "/s3/data?delete", &[], delete.as_bytes())
+            .await;
+        let body = res.take_string().await.unwrap();
+        assert!(
+            body.contains("<Deleted><Key>big.bin</Key></Deleted><Deleted><Key>e/</Key></Deleted>")
+        );
+        assert!(!dir.path().join("big.bin").exists());
+        assert!(!dir.path().join("e").exists());
+        let mut res = TestClient::get("http://127.0.0.1/trash")
+            .send(&client.service)
+            .await;
+        assert_eq!(
+            res.take_json::<serde_json::Value>()
+                .await
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // 吊销后密钥失效
+        let res = TestClient::delete(format!(
+            "http://127.0.0.1/admin/s3-keys/{}",
+            client.access_key
+        ))
+        .add_header(AUTHORIZATION, "Bearer secret", true)
+        .send(&client.service)
+        .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        let mut res = client.send("GET", "/s3", &[], b"").await;
+        assert!(
+            res.take_string()
+                .await
+                .unwrap()
+                .contains("<Code>InvalidAccessKeyId</Code>")
+        );
+    }
+}
@@ -0,0 +1,458 @@
+use crate::sandbox::Sandbox;
+use crate::web::fs::{io_status, sandbox};
+use crate::web::listing::{Entry, EntryKind};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use globset::GlobMatcher;
+use regex::bytes::Regex as BytesRegex;
+use regex::{Regex, RegexBuilder};
+use salvo::http::header::{CONTENT_TYPE, HeaderValue};
+use salvo::http::{StatusCode, StatusError};
+use salvo::{Depot, Request, Response, handler};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// 默认最大递归深度
+const DEFAULT_MAX_DEPTH: usize = 32;
+/// 默认时间预算（秒）
+const DEFAULT_TIMEOUT: u64 = 30;
+/// 时间预算上限（秒）
+const MAX_TIMEOUT: u64 = 300;
+/// 默认最多返回的结果数
+const DEFAULT_LIMIT: usize = 1000;
+/// 每个文件最多返回的匹配行数
+const MAX_LINE_MATCHES: usize = 10;
+/// 返回的匹配行最大字节数
+const MAX_LINE_LEN: usize = 256;
+/// 内容搜索时跳过超过此大小的文件
+const MAX_GREP_SIZE: u64 = 64 * 1024 * 1024;
+/// 判断二进制文件时检查的字节数
+const BINARY_PROBE_LEN: usize = 8192;
+/// 待发送结果的最大数量
+const CHANNEL_CAPACITY: usize = 64;
+
+/// 搜索参数
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SearchQuery {
+    /// 起始目录
+    pub path: String,
+    /// 文件名通配符，含 `/` 时匹配相对路径
+    pub glob: Option<String>,
+    /// 文件名正则
+    pub regex: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<EntryKind>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// 修改时间不早于
+    pub after: Option<DateTime<Utc>>,
+    /// 修改时间早于
+    pub before: Option<DateTime<Utc>>,
+    /// 内容正则，只搜索普通文件
+    pub content: Option<String>,
+    /// 忽略大小写
+    pub icase: bool,
+    pub max_depth: Option<usize>,
+    /// 时间预算（秒）
+    pub timeout: Option<u64>,
+    pub limit: Option<usize>,
+    pub hidden: bool,
+}
+
+/// 内容匹配的行
+#[derive(Serialize, Debug)]
+pub struct LineMatch {
+    pub line: u64,
+    pub text: String,
+}
+
+/// 一条搜索结果
+#[derive(Serialize, Debug)]
+pub struct Hit {
+    pub path: String,
+    #[serde(flatten)]
+    pub entry: Entry,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<Vec<LineMatch>>,
+}
+
+/// 搜索结束的原因
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Stop {
+    Done,
+    Timeout,
+    Limit,
+}
+
+/// 最后一行：统计信息
+#[derive(Serialize, Debug)]
+pub struct Summary {
+    pub done: bool,
+    pub stopped: Stop,
+    pub scanned: u64,
+    pub matched: usize,
+    /// 因深度限制未进入的目录数
+    pub depth_limited: u64,
+    pub elapsed_ms: u64,
+}
+
+/// 编译后的过滤条件
+struct Filter {
+    glob: Option<(GlobMatcher, bool)>,
+    regex: Option<Regex>,
+    kind: Option<EntryKind>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    content: Option<BytesRegex>,
+}
+
+impl Filter {
+    fn new(query: &SearchQuery) -> Result<Self, String> {
+        let glob = match &query.glob {
+            Some(pattern) => {
+                let glob = globset::GlobBuilder::new(pattern)
+                    .case_insensitive(query.icase)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| format!("无效的通配符: {}", e))?;
+                Some((glob.compile_matcher(), pattern.contains('/')))
+            }
+            None => None,
+        };
+        let regex = match &query.regex {
+            Some(pattern) => Some(
+                RegexBuilder::new(pattern)
+                    .case_insensitive(query.icase)
+                    .build()
+                    .map_err(|e| format!("无效的正则: {}", e))?,
+            ),
+            None => None,
+        };
+        let content = match &query.content {
+            Some(pattern) => Some(
+                regex::bytes::RegexBuilder::new(pattern)
+                    .case_insensitive(query.icase)
+                    .build()
+                    .map_err(|e| format!("无效的内容正则: {}", e))?,
+            ),
+            None => None,
+        };
+        Ok(Filter {
+            glob,
+            regex,
+            kind: query.kind,
+            min_size: query.min_size,
+            max_size: query.max_size,
+            after: query.after,
+            before: query.before,
+            content,
+        })
+    }
+
+    /// 按名称和元数据过滤
+    fn matches(&self, rel: &str, entry: &Entry) -> bool {
+        if let Some((glob, full_path)) = &self.glob {
+            let target = if *full_path { rel } else { entry.name.as_str() };
+            if !glob.is_match(target) {
+                return false;
+            }
+        }
+        if self
+            .regex
+            .as_ref()
+            .is_some_and(|r| !r.is_match(&entry.name))
+            || self.kind.is_some_and(|k| k != entry.kind)
+            || self.min_size.is_some_and(|s| entry.size < s)
+            || self.max_size.is_some_and(|s| entry.size > s)
+        {
+            return false;
+        }
+        match (entry.mtime, self.after, self.before) {
+            (None, None, None) => true,
+            (None, _, _) => false,
+            (Some(mtime), after, before) => {
+                after.is_none_or(|a| mtime >= a) && before.is_none_or(|b| mtime < b)
+            }
+        }
+    }
+}
+
+/// 按行搜索文件内容，跳过二进制文件
+fn grep(path: &Path, regex: &BytesRegex, deadline: Instant) -> io::Result<Vec<LineMatch>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    if reader
+        .fill_buf()?
+        .iter()
+        .take(BINARY_PROBE_LEN)
+        .any(|b| *b == 0)
+    {
+        return Ok(Vec::new());
+    }
+    let mut matches = Vec::new();
+    let mut line = Vec::new();
+    let mut number = 0u64;
+    loop {
+        line.clear();
+        // 单行最长读取 1MB，避免超长行占用内存
+        if (&mut reader).take(1 << 20).read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        number += 1;
+        if regex.is_match(&line) {
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches(['\r', '\n']);
+            let mut end = text.len().min(MAX_LINE_LEN);
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            matches.push(LineMatch {
+                line: number,
+                text: text[..end].to_string(),
+            });
+            if matches.len() >= MAX_LINE_MATCHES {
+                break;
+            }
+        }
+        if number.is_multiple_of(1024) && Instant::now() >= deadline {
+            break;
+        }
+    }
+    Ok(matches)
+}
+
+/// 遍历状态
+struct Walker<'a> {
+    sandbox: &'a Sandbox,
+    filter: Filter,
+    hidden: bool,
+    max_depth: usize,
+    limit: usize,
+    deadline: Instant,
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    scanned: u64,
+    matched: usize,
+    depth_limited: u64,
+}
+
+impl Walker<'_> {
+    fn send<T: Serialize>(&self, value: &T) -> bool {
+        let mut line = serde_json::to_vec(value).unwrap_or_default();
+        line.push(b'\n');
+        self.tx.blocking_send(Ok(Bytes::from(line))).is_ok()
+    }
+
+    /// 递归遍历，返回 `Some` 表示需要提前结束
+    fn walk(&mut self, dir: &Path, prefix: &str, depth: usize) -> Option<Stop> {
+        let mut entries = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(Result::ok).collect::<Vec<_>>(),
+            Err(e) => {
+                log::debug!("搜索跳过目录 {}: {}", dir.display(), e);
+                return None;
+            }
+        };
+        entries.sort_by_key(|e| e.file_name());
+        for item in entries {
+            if self.tx.is_closed() {
+                return Some(Stop::Done);
+            }
+            if Instant::now() >= self.deadline {
+                return Some(Stop::Timeout);
+            }
+            let name = item.file_name().to_string_lossy().into_owned();
+            let path = item.path();
+            if (!self.hidden && self.sandbox.is_hidden(&name)) || self.sandbox.is_excluded(&path) {
+                continue;
+            }
+            let Ok(metadata) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            self.scanned += 1;
+            let rel = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            let entry = Entry::new(name, &metadata);
+            let is_dir = entry.kind == EntryKind::Dir;
+
+            if self.filter.matches(&rel, &entry) {
+                let matches = match &self.filter.content {
+                    Some(_) if entry.kind != EntryKind::File || entry.size > MAX_GREP_SIZE => None,
+                    Some(regex) => match grep(&path, regex, self.deadline) {
+                        Ok(found) if !found.is_empty() => Some(found),
+                        _ => None,
+                    },
+                    None => Some(Vec::new()),
+                };
+                if let Some(matches) = matches {
+                    let hit = Hit {
+                        path: self.sandbox.relative(&path).unwrap_or(rel.clone()),
+                        entry,
+                        matches: self.filter.content.is_some().then_some(matches),
+                    };
+                    if !self.send(&hit) {
+                        return Some(Stop::Done);
+                    }
+                    self.matched += 1;
+                    if self.matched >= self.limit {
+                        return Some(Stop::Limit);
+                    }
+                }
+            }
+
+            if is_dir {
+                if depth >= self.max_depth {
+                    self.depth_limited += 1;
+                } else if let Some(stop) = self.walk(&path, &rel, depth + 1) {
+                    return Some(stop);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// GET /search: 递归搜索，结果以NDJSON流式返回
+///
+/// 每行一个结果，最后一行是统计信息。不跟随符号链接，隐藏文件规则与目录列表相同。
+/// 超过深度、时间或数量限制时停止，客户端断开后也会尽快停止遍历。
+#[handler]
+pub async fn search(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let query = req
+        .parse_queries::<SearchQuery>()
+        .map_err(|e| StatusError::bad_request().brief(e.to_string()))?;
+    let filter = Filter::new(&query).map_err(|e| StatusError::bad_request().brief(e))?;
+    let dir = sandbox.resolve_existing(&query.path)?;
+    let metadata = tokio::fs::metadata(&dir).await.map_err(io_status)?;
+    if !metadata.is_dir() {
+        return Err(StatusError::bad_request().brief(format!("不是目录: {}", query.path)));
+    }
+
+    let timeout = Duration::from_secs(query.timeout.unwrap_or(DEFAULT_TIMEOUT).min(MAX_TIMEOUT));
+    let max_depth = query.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).max(1);
+    let hidden = query.hidden;
+    log::debug!("搜索: {} {:?}", dir.display(), query);
+
+    let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(CHANNEL_CAPACITY);
+    let sandbox = Arc::clone(&sandbox);
+    tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        let prefix = sandbox.relative(&dir).unwrap_or_default();
+        let mut walker = Walker {
+            sandbox: &sandbox,
+            filter,
+            hidden,
+            max_depth,
+            limit,
+            deadline: started + timeout,
+            tx,
+            scanned: 0,
+            matched: 0,
+            depth_limited: 0,
+        };
+        let stopped = walker.walk(&dir, &prefix, 1).unwrap_or(Stop::Done);
+        if walker.tx.is_closed() {
+            log::debug!("客户端已断开，停止搜索: {}", dir.display());
+            return;
+        }
+        let summary = Summary {
+            done: true,
+            stopped,
+            scanned: walker.scanned,
+            matched: walker.matched,
+            depth_limited: walker.depth_limited,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        };
+        walker.send(&summary);
+    });
+
+    res.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    res.status_code(StatusCode::OK);
+    res.stream(futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use salvo::prelude::*;
+    use salvo::test::{ResponseExt, TestClient};
+
+    async fn run(service: &Service, query: &str) -> Vec<serde_json::Value> {
+        let mut res = TestClient::get(format!("http://127.0.0.1/search?{}", query))
+            .send(service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        res.take_string()
+            .await
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let root = sandbox.root().to_path_buf();
+        fs::create_dir_all(root.join("src/deep/er")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {\n    todo!()\n}\n").unwrap();
+        fs::write(root.join("src/lib.rs"), "pub fn x() {}\n").unwrap();
+        fs::write(root.join("src/deep/er/mod.rs"), "// TODO later\n").unwrap();
+        fs::write(root.join("src/.hidden.rs"), "todo").unwrap();
+        fs::write(root.join("notes.txt"), vec![b'x'; 2048]).unwrap();
+        let router = Router::new()
+            .hoop(salvo::affix_state::inject(Arc::new(sandbox)))
+            .push(Router::with_path("search").get(super::search));
+        let service = Service::new(router);
+
+        let lines = run(&service, "glob=*.rs").await;
+        let paths: Vec<_> = lines.iter().filter_map(|l| l["path"].as_str()).collect();
+        assert_eq!(paths, ["src/deep/er/mod.rs", "src/lib.rs", "src/main.rs"]);
+        let summary = lines.last().unwrap();
+        assert_eq!(summary["done"], true);
+        assert_eq!(summary["matched"], 3);
+
+        let lines = run(&service, "path=src&content=todo&icase=true").await;
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["path"], "src/deep/er/mod.rs");
+        assert_eq!(lines[1]["matches"][0]["line"], 2);
+
+        let lines = run(&service, "type=file&min_size=1000").await;
+        assert_eq!(lines[0]["path"], "notes.txt");
+
+        let lines = run(&service, "glob=*.rs&max_depth=2").await;
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2]["depth_limited"], 1);
+
+        let lines = run(&service, "regex=%5E(lib|main)%5C.rs%24&limit=1").await;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["stopped"], "limit");
+
+        let res = TestClient::get("http://127.0.0.1/search?regex=(")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::web::fs::{io_status, request_path, sandbox};
+use crate::web::listing::{EntryKind, file_mode};
+use crate::web::precondition;
+use chrono::{DateTime, Utc};
+use salvo::http::StatusError;
+use salvo::prelude::Json;
+use salvo::{Depot, Request, Response, handler};
+use serde::Serialize;
+use std::fs::Metadata;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+/// 嗅探MIME时读取的字节数
+const SNIFF_LEN: usize = 8192;
+
+/// 根据文件头的魔数判断MIME类型，无法识别时按扩展名猜测
+pub async fn sniff_mime(path: &Path) -> String {
+    let mut buf = vec![0u8; SNIFF_LEN];
+    let sniffed = match tokio::fs::File::open(path).await {
+        Ok(mut file) => {
+            let mut len = 0;
+            while len < SNIFF_LEN {
+                match file.read(&mut buf[len..]).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => len += n,
+                }
+            }
+            infer::get(&buf[..len]).map(|t| t.mime_type().to_string())
+        }
+        Err(_) => None,
+    };
+    sniffed.unwrap_or_else(|| {
+        mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .to_string()
+    })
+}
+
+/// 文件的扩展元数据
+#[derive(Serialize, Debug)]
+pub struct Stat {
+    pub path: String,
+    pub name: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub mode: u32,
+    pub permissions: String,
+    pub atime: Option<DateTime<Utc>>,
+    pub mtime: Option<DateTime<Utc>>,
+    pub ctime: Option<DateTime<Utc>>,
+    pub birth: Option<DateTime<Utc>>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub inode: Option<u64>,
+    pub nlink: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    pub etag: String,
+}
+
+/// `rwxr-xr-x` 形式的权限字符串
+fn permissions_string(mode: u32) -> String {
+    let mut s = String::with_capacity(9);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    s
+}
+
+/// 填充 Unix 平台特有的字段
+#[cfg(unix)]
+fn fill_unix(info: &mut Stat, metadata: &Metadata) {
+    use std::os::unix::fs::MetadataExt;
+    info.ctime = DateTime::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32);
+    info.uid = Some(metadata.uid());
+    info.gid = Some(metadata.gid());
+    info.inode = Some(metadata.ino());
+    info.nlink = Some(metadata.nlink());
+}
+
+impl Stat {
+    pub async fn new(rel: String, path: &Path, metadata: &Metadata) -> Self {
+        let kind = EntryKind::from_metadata(metadata);
+        let mode = file_mode(metadata);
+        let symlink_target = if kind == EntryKind::Symlink {
+            tokio::fs::read_link(path)
+                .await
+                .ok()
+                .map(|t| t.to_string_lossy().into_owned())
+        } else {
+            None
+        };
+        let mime = if kind == EntryKind::File {
+            Some(sniff_mime(path).await)
+        } else {
+            None
+        };
+        let mut info = Stat {
+            name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            path: rel,
+            kind,
+            size: metadata.len(),
+            mode,
+            permissions: permissions_string(mode),
+            atime: metadata.accessed().ok().map(DateTime::<Utc>::from),
+            mtime: metadata.modified().ok().map(DateTime::<Utc>::from),
+            ctime: None,
+            birth: metadata.created().ok().map(DateTime::<Utc>::from),
+            uid: None,
+            gid: None,
+            inode: None,
+            nlink: None,
+            symlink_target,
+            mime,
+            etag: precondition::etag(metadata),
+        };
+        #[cfg(unix)]
+        fill_unix(&mut info, metadata);
+        info
+    }
+}
+
+/// GET ?stat: 以JSON返回扩展元数据，不跟随最后一级符号链接
+#[handler]
+pub async fn stat(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let path = sandbox.resolve_nofollow(&rel)?;
+    let metadata = tokio::fs::symlink_metadata(&path)
+        .await
+        .map_err(io_status)?;
+    let rel = sandbox.relative(&path).unwrap_or_default();
+    res.render(Json(Stat::new(rel, &path, &metadata).await));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permissions_string() {
+        assert_eq!(permissions_string(0o755), "rwxr-xr-x");
+        assert_eq!(permissions_string(0o640), "rw-r-----");
+    }
+
+    #[tokio::test]
+    async fn test_sniff_mime() {
+        let dir = tempfile::tempdir().unwrap();
+        // PNG 文件头，扩展名故意写错
+        let png = dir.path().join("image.txt");
+        std::fs::write(&png, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        assert_eq!(sniff_mime(&png).await, "image/png");
+        let text = dir.path().join("notes.txt");
+        std::fs::write(&text, "hello").unwrap();
+        assert_eq!(sniff_mime(&text).await, "text/plain");
+    }
+}
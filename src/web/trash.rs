@@ -0,0 +1,334 @@
+use crate::db::trash::TrashItem;
+use crate::db::{Db, now};
+use crate::sandbox::Sandbox;
+use crate::sandbox::ops::{self, Conflict, OpError, Report};
+use crate::web::fs::{client_addr, db, db_status, io_status, sandbox};
+use crate::web::locks;
+use crate::web::mounts::ReadOnly;
+use crate::web::ops::{OpResult, render_result, replace, run};
+use salvo::http::{StatusCode, StatusError};
+use salvo::prelude::Json;
+use salvo::{Depot, Request, Response, Router, handler};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 回收站目录
+pub const TRASH_DIR: &str = ".trash";
+/// 后台清理的间隔
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// 条目类型和总字节数（不跟随符号链接）
+fn measure(path: &Path) -> io::Result<(&'static str, u64)> {
+    fn tree_size(dir: &Path) -> u64 {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return 0;
+        };
+        entries
+            .flatten()
+            .map(|entry| match entry.file_type() {
+                Ok(t) if t.is_dir() => tree_size(&entry.path()),
+                Ok(_) => entry.metadata().map(|m| m.len()).unwrap_or_default(),
+                Err(_) => 0,
+            })
+            .sum()
+    }
+
+    let metadata = fs::symlink_metadata(path)?;
+    Ok(if metadata.is_dir() {
+        ("dir", tree_size(path))
+    } else if metadata.is_symlink() {
+        ("symlink", 0)
+    } else {
+        ("file", metadata.len())
+    })
+}
+
+pub(crate) fn trash_path(sandbox: &Sandbox, id: &str) -> Result<PathBuf, StatusError> {
+    Ok(sandbox.internal_dir(TRASH_DIR).map_err(io_status)?.join(id))
+}
+
+/// 把文件或目录移入回收站并记录，目录非空时需 `recursive`
+pub async fn move_to_trash(
+    sandbox: &Sandbox,
+    db: &Db,
+    path: &Path,
+    recursive: bool,
+    deleted_by: String,
+) -> Result<(TrashItem, Report), StatusError> {
+    let task_path = path.to_path_buf();
+    let (kind, size) = tokio::task::spawn_blocking(move || {
+        let measured = measure(&task_path)?;
+        if measured.0 == "dir" && !recursive && fs::read_dir(&task_path)?.next().is_some() {
+            return Err(OpError::NotEmpty);
+        }
+        Ok(measured)
+    })
+    .await
+    .map_err(|e| StatusError::internal_server_error().brief(e.to_string()))??;
+
+    let item = TrashItem {
+        id: uuid::Uuid::now_v7().simple().to_string(),
+        mount: sandbox.mount().to_string(),
+        original_path: sandbox.relative(path).unwrap_or_default(),
+        kind: kind.to_string(),
+        size: size as i64,
+        deleted_by,
+        deleted_at: now(),
+    };
+    // 先记录再移动，进程中断时最多留下一条指向不存在内容的记录
+    db.insert_trash_item(&item).await.map_err(db_status)?;
+    let src = path.to_path_buf();
+    let dst = trash_path(sandbox, &item.id)?;
+    match run(move || ops::rename(&src, &dst, Conflict::Fail)).await {
+        Ok(report) => Ok((item, report)),
+        Err(e) => {
+            if let Err(e) = db.delete_trash_item(&item.id).await {
+                log::warn!("删除回收站记录失败 {}: {}", item.id, e);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// 把刚移入回收站的条目放回原处并删除记录，用于撤销失败的覆盖
+pub(crate) async fn put_back(
+    sandbox: &Sandbox,
+    db: &Db,
+    item: &TrashItem,
+    path: &Path,
+) -> Result<(), StatusError> {
+    let src = trash_path(sandbox, &item.id)?;
+    let dst = path.to_path_buf();
+    run(move || ops::rename(&src, &dst, Conflict::Fail)).await?;
+    db.delete_trash_item(&item.id).await.map_err(db_status)?;
+    log::info!("回收站条目 {} 已放回 {}", item.id, path.display());
+    Ok(())
+}
+
+/// 彻底删除回收站条目
+async fn purge_item(sandbox: &Sandbox, db: &Db, item: &TrashItem) -> Result<(), StatusError> {
+    let path = trash_path(sandbox, &item.id)?;
+    let report = match run(move || ops::remove(&path, true)).await {
+        Ok(report) => report,
+        Err(e) if e.code == StatusCode::NOT_FOUND => Report::default(),
+        Err(e) => return Err(e),
+    };
+    if let Some(e) = report.errors.first() {
+        return Err(StatusError::internal_server_error().brief(e.error.clone()));
+    }
+    db.delete_trash_item(&item.id).await.map_err(db_status)?;
+    log::info!("清除回收站条目 {} ({})", item.id, item.original_path);
+    Ok(())
+}
+
+/// 清除删除时间早于 `retention` 之前的条目，返回清除的数量
+pub async fn purge_expired(sandbox: &Sandbox, db: &Db, retention: Duration) -> usize {
+    let before = now() - retention.as_secs() as i64;
+    let items = match db.expired_trash_items(sandbox.mount(), before).await {
+        Ok(items) => items,
+        Err(e) => {
+            log::error!("查询过期回收站条目失败: {}", e);
+            return 0;
+        }
+    };
+    let mut purged = 0;
+    for item in items {
+        match purge_item(sandbox, db, &item).await {
+            Ok(()) => purged += 1,
+            Err(e) => log::warn!("清除回收站条目失败 {}: {:?}", item.id, e.brief),
+        }
+    }
+    purged
+}
+
+/// 启动后台任务，定期清除超过保留期的条目；保留期为0时不清除，挂载点维护期间暂停
+pub fn spawn_purger(sandbox: Arc<Sandbox>, db: Db, retention: Duration, read_only: Arc<ReadOnly>) {
+    if retention.is_zero() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if read_only.active(sandbox.mount()).is_some() {
+                continue;
+            }
+            let purged = purge_expired(&sandbox, &db, retention).await;
+            if purged > 0 {
+                log::info!("回收站清理: {} 个条目", purged);
+            }
+        }
+    });
+}
+
+async fn find(sandbox: &Sandbox, db: &Db, req: &Request) -> Result<TrashItem, StatusError> {
+    let id = req.param::<String>("id").unwrap_or_default();
+    db.get_trash_item(sandbox.mount(), &id)
+        .await
+        .map_err(db_status)?
+        .ok_or_else(|| StatusError::not_found().brief(format!("回收站条目不存在: {}", id)))
+}
+
+/// GET /trash: 列出回收站，`?path=` 只列出该路径下删除的条目
+#[handler]
+async fn list(req: &mut Request, depot: &mut Depot, res: &mut Response) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let prefix = req.query::<String>("path").unwrap_or_default();
+    let prefix = prefix.trim_matches('/');
+    let items = db
+        .list_trash_items(sandbox.mount(), prefix)
+        .await
+        .map_err(db_status)?;
+    res.render(Json(items));
+    Ok(())
+}
+
+/// GET /trash/{id}: 查询单个条目
+#[handler]
+async fn show(req: &mut Request, depot: &mut Depot, res: &mut Response) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    res.render(Json(find(&sandbox, &db, req).await?));
+    Ok(())
+}
+
+/// POST /trash/{id}: 恢复到原路径或 `?to=` 指定的路径
+///
+/// 目标已存在时按 `?conflict=overwrite|skip|fail` 处理，缺失的父目录自动创建。
+#[handler]
+async fn restore(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let item = find(&sandbox, &db, req).await?;
+    let conflict = req.query::<Conflict>("conflict").unwrap_or_default();
+    let rel = req
+        .query::<String>("to")
+        .unwrap_or_else(|| item.original_path.clone());
+    let dst = sandbox.resolve_nofollow(&rel)?;
+    if dst == sandbox.root() {
+        return Err(StatusError::forbidden().brief("不能恢复到根目录"));
+    }
+    locks::require(depot, req, &sandbox, &dst, true).await?;
+    let src = trash_path(&sandbox, &item.id)?;
+    if tokio::fs::symlink_metadata(&src).await.is_err() {
+        return Err(StatusError::gone().brief(format!("回收站内容已丢失: {}", item.id)));
+    }
+    if let Some(parent) = dst.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(io_status)?;
+    }
+
+    let report = replace(depot, &sandbox, &dst, conflict, client_addr(req), {
+        let target = dst.clone();
+        move |conflict| ops::rename(&src, &target, conflict)
+    })
+    .await?;
+    if !report.skipped && report.errors.is_empty() {
+        db.delete_trash_item(&item.id).await.map_err(db_status)?;
+        log::info!("恢复回收站条目 {} -> {}", item.id, dst.display());
+    }
+    render_result(OpResult::new(&sandbox, &dst, report), res);
+    Ok(())
+}
+
+/// DELETE /trash/{id}: 彻底删除一个条目
+#[handler]
+async fn purge(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let item = find(&sandbox, &db, req).await?;
+    purge_item(&sandbox, &db, &item).await?;
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// DELETE /trash: 清空回收站，`?older_than=秒` 只清除更早删除的条目
+#[handler]
+async fn purge_all(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let before = match req.query::<i64>("older_than") {
+        Some(secs) => now() - secs,
+        None => i64::MAX,
+    };
+    let items = db
+        .expired_trash_items(sandbox.mount(), before)
+        .await
+        .map_err(db_status)?;
+    let mut purged = 0;
+    for item in &items {
+        purge_item(&sandbox, &db, item).await?;
+        purged += 1;
+    }
+    res.render(Json(serde_json::json!({ "purged": purged })));
+    Ok(())
+}
+
+/// 回收站路由
+pub fn router() -> Router {
+    Router::with_path("trash").get(list).delete(purge_all).push(
+        Router::with_path("{id}")
+            .get(show)
+            .post(restore)
+            .delete(purge),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_purge_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path().join("root")).unwrap();
+        let root = sandbox.root().to_path_buf();
+        let db = crate::db::test_db(dir.path()).await;
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::write(root.join("a/b/f.txt"), "12345").unwrap();
+
+        let (item, report) =
+            move_to_trash(&sandbox, &db, &root.join("a"), true, "test".to_string())
+                .await
+                .unwrap();
+        assert!(report.errors.is_empty());
+        assert_eq!(item.kind, "dir");
+        assert_eq!(item.size, 5);
+        assert!(!root.join("a").exists());
+        assert!(root.join(TRASH_DIR).join(&item.id).join("b/f.txt").exists());
+
+        assert_eq!(
+            purge_expired(&sandbox, &db, Duration::from_secs(3600)).await,
+            0
+        );
+        let old = TrashItem {
+            id: "old".to_string(),
+            deleted_at: now() - 7200,
+            ..item.clone()
+        };
+        db.insert_trash_item(&old).await.unwrap();
+        std::fs::write(root.join(TRASH_DIR).join("old"), "x").unwrap();
+        assert_eq!(
+            purge_expired(&sandbox, &db, Duration::from_secs(3600)).await,
+            1
+        );
+        assert!(!root.join(TRASH_DIR).join("old").exists());
+        assert!(db.get_trash_item("", &item.id).await.unwrap().is_some());
+        assert!(db.get_trash_item("data", &item.id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,540 @@
+use crate::db::tus::TusUpload;
+use crate::db::{Db, now};
+use crate::digest::{Algorithm, Hasher};
+use crate::sandbox::Sandbox;
+use crate::web::fs::{db, db_status, io_status, sandbox};
+use crate::web::{locks, mounts};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures_util::StreamExt;
+use lazy_static::lazy_static;
+use salvo::http::header::{CACHE_CONTROL, CONTENT_TYPE, HeaderName, HeaderValue, LOCATION};
+use salvo::http::{StatusCode, StatusError};
+use salvo::{Depot, FlowCtrl, Request, Response, Router, handler};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+/// 支持的 tus 协议版本
+const TUS_VERSION: &str = "1.0.0";
+/// 支持的扩展
+const TUS_EXTENSIONS: &str = "creation,termination,checksum";
+/// 支持的校验算法
+const TUS_CHECKSUM_ALGORITHMS: &str = "sha1,sha256,md5,blake3,crc32c";
+/// 暂存未完成上传的内部目录
+pub const TUS_DIR: &str = ".tus";
+/// 校验失败状态码（tus checksum 扩展）
+const CHECKSUM_MISMATCH: u16 = 460;
+
+lazy_static! {
+    /// 每个上传一把锁，防止并发 PATCH 同一个上传
+    static ref UPLOAD_LOCKS: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>> =
+        Mutex::new(HashMap::new());
+}
+
+fn upload_lock(id: &str) -> Arc<tokio::sync::Mutex<()>> {
+    let mut locks = UPLOAD_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    locks.entry(id.to_string()).or_default().clone()
+}
+
+/// 没有其他等待者时移除锁
+fn release_lock(id: &str, lock: Arc<tokio::sync::Mutex<()>>) {
+    let mut locks = UPLOAD_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    // 表中一份，调用方一份
+    if Arc::strong_count(&lock) <= 2 {
+        locks.remove(id);
+    }
+}
+
+fn header<'a>(req: &'a Request, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+fn set_header(res: &mut Response, name: &'static str, value: &str) {
+    if let Ok(v) = HeaderValue::from_str(value) {
+        res.headers_mut().insert(HeaderName::from_static(name), v);
+    }
+}
+
+/// 解析 Upload-Metadata: `key base64value,key2 base64value2,flag`
+pub fn parse_metadata(value: &str) -> Result<HashMap<String, String>, String> {
+    let mut map = HashMap::new();
+    for pair in value.split(',') {
+        let pair = pair.trim();
+        if pair.is_empty() {
+            continue;
+        }
+        let mut parts = pair.splitn(2, ' ');
+        let key = parts.next().unwrap_or_default().to_string();
+        let value = match parts.next() {
+            Some(encoded) => {
+                let bytes = STANDARD
+                    .decode(encoded.trim())
+                    .map_err(|e| format!("Upload-Metadata 解码失败 {}: {}", key, e))?;
+                String::from_utf8(bytes).map_err(|_| format!("Upload-Metadata 非UTF-8: {}", key))?
+            }
+            None => String::new(),
+        };
+        map.insert(key, value);
+    }
+    Ok(map)
+}
+
+/// 所有 tus 请求共用：校验 Tus-Resumable 并在响应中回写
+#[handler]
+async fn tus_resumable(req: &mut Request, res: &mut Response, ctrl: &mut FlowCtrl) {
+    set_header(res, "tus-resumable", TUS_VERSION);
+    if req.method() != salvo::http::Method::OPTIONS
+        && header(req, "tus-resumable") != Some(TUS_VERSION)
+    {
+        set_header(res, "tus-version", TUS_VERSION);
+        res.status_code(StatusCode::PRECONDITION_FAILED);
+        ctrl.skip_rest();
+    }
+}
+
+/// OPTIONS: 报告服务器能力，挂载点有文件大小限制时一并报告
+#[handler]
+async fn options(depot: &mut Depot, res: &mut Response) {
+    set_header(res, "tus-version", TUS_VERSION);
+    set_header(res, "tus-extension", TUS_EXTENSIONS);
+    set_header(res, "tus-checksum-algorithm", TUS_CHECKSUM_ALGORITHMS);
+    if let Some(max) = mounts::mount(depot).and_then(|m| m.max_file_size) {
+        set_header(res, "tus-max-size", &max.to_string());
+    }
+    res.status_code(StatusCode::NO_CONTENT);
+}
+
+/// POST: 创建上传
+///
+/// 目标路径取自 Upload-Metadata 的 `path`，没有时使用 `filename` 放在根目录下。
+#[handler]
+async fn create(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+
+    if header(req, "upload-defer-length").is_some() {
+        return Err(StatusError::bad_request().brief("不支持 Upload-Defer-Length"));
+    }
+    let length = header(req, "upload-length")
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|v| *v >= 0)
+        .ok_or_else(|| StatusError::bad_request().brief("缺少或无效的 Upload-Length"))?;
+    mounts::check_file_size(depot, length as u64)?;
+    let raw_metadata = header(req, "upload-metadata")
+        .unwrap_or_default()
+        .to_string();
+    let metadata =
+        parse_metadata(&raw_metadata).map_err(|e| StatusError::bad_request().brief(e))?;
+    let target = metadata
+        .get("path")
+        .or_else(|| metadata.get("filename"))
+        .filter(|p| !p.is_empty())
+        .ok_or_else(|| StatusError::bad_request().brief("Upload-Metadata 缺少 path 或 filename"))?;
+
+    let target = sandbox.resolve(target)?;
+    if target == sandbox.root() || target.is_dir() {
+        return Err(StatusError::conflict().brief("目标是目录"));
+    }
+    locks::require(depot, req, &sandbox, &target, false).await?;
+    let target_path = sandbox.relative(&target).unwrap_or_default();
+
+    let id = uuid::Uuid::now_v7().simple().to_string();
+    let dir = sandbox.internal_dir(TUS_DIR).map_err(io_status)?;
+    tokio::fs::File::create(dir.join(&id))
+        .await
+        .map_err(io_status)?;
+
+    let ts = now();
+    let upload = TusUpload {
+        id: id.clone(),
+        mount: sandbox.mount().to_string(),
+        target_path,
+        upload_length: length,
+        upload_offset: 0,
+        metadata: raw_metadata,
+        created_at: ts,
+        updated_at: ts,
+    };
+    db.insert_tus_upload(&upload).await.map_err(db_status)?;
+    log::info!(
+        "创建tus上传 {} -> {} ({} 字节)",
+        id,
+        upload.target_path,
+        length
+    );
+
+    // 空文件直接完成
+    if length == 0 {
+        finish(&sandbox, &db, &upload).await?;
+    }
+
+    let location = format!("{}/{}", req.uri().path().trim_end_matches('/'), id);
+    if let Ok(v) = HeaderValue::from_str(&location) {
+        res.headers_mut().insert(LOCATION, v);
+    }
+    res.status_code(StatusCode::CREATED);
+    Ok(())
+}
+
+/// 查询上传记录，不存在时返回404
+async fn find(sandbox: &Sandbox, db: &Db, req: &Request) -> Result<TusUpload, StatusError> {
+    let id = req.param::<String>("id").unwrap_or_default();
+    db.get_tus_upload(sandbox.mount(), &id)
+        .await
+        .map_err(db_status)?
+        .ok_or_else(|| StatusError::not_found().brief(format!("上传不存在: {}", id)))
+}
+
+/// HEAD: 查询上传进度
+#[handler]
+async fn head(req: &mut Request, depot: &mut Depot, res: &mut Response) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let upload = find(&sandbox, &db, req).await?;
+    set_header(res, "upload-offset", &upload.upload_offset.to_string());
+    set_header(res, "upload-length", &upload.upload_length.to_string());
+    if !upload.metadata.is_empty() {
+        set_header(res, "upload-metadata", &upload.metadata);
+    }
+    res.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// PATCH: 从指定偏移量追加数据
+#[handler]
+async fn patch(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if content_type != "application/offset+octet-stream" {
+        return Err(StatusError::unsupported_media_type()
+            .brief("Content-Type 必须为 application/offset+octet-stream"));
+    }
+    let offset = header(req, "upload-offset")
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .ok_or_else(|| StatusError::bad_request().brief("缺少或无效的 Upload-Offset"))?;
+    let checksum = match header(req, "upload-checksum") {
+        Some(value) => {
+            let (algorithm, expected) = value
+                .trim()
+                .split_once(' ')
+                .ok_or_else(|| StatusError::bad_request().brief("无效的 Upload-Checksum"))?;
+            let algorithm = algorithm
+                .parse::<Algorithm>()
+                .map_err(|e| StatusError::bad_request().brief(e))?;
+            let expected = STANDARD
+                .decode(expected.trim())
+                .map_err(|_| StatusError::bad_request().brief("无效的 Upload-Checksum"))?;
+            Some((algorithm.hasher(), expected))
+        }
+        None => None,
+    };
+
+    // 先确认上传存在，未知的 id 不会在锁表中留下条目
+    let id = find(&sandbox, &db, req).await?.id;
+    let lock = upload_lock(&id);
+    let result = receive(req, depot, res, &lock, offset, checksum).await;
+    release_lock(&id, lock);
+    result
+}
+
+/// 持有上传的锁接收一段数据，锁内重新读取上传记录
+async fn receive(
+    req: &mut Request,
+    depot: &Depot,
+    res: &mut Response,
+    lock: &tokio::sync::Mutex<()>,
+    offset: i64,
+    checksum: Option<(Hasher, Vec<u8>)>,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let _guard = lock.lock().await;
+    let upload = find(&sandbox, &db, req).await?;
+    let target = sandbox.resolve(&upload.target_path)?;
+    locks::require(depot, req, &sandbox, &target, false).await?;
+    if offset != upload.upload_offset {
+        return Err(StatusError::conflict().brief(format!(
+            "Upload-Offset 不匹配: 当前 {}",
+            upload.upload_offset
+        )));
+    }
+
+    let part = sandbox
+        .internal_dir(TUS_DIR)
+        .map_err(io_status)?
+        .join(&upload.id);
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(&part)
+        .await
+        .map_err(io_status)?;
+    // 截掉上次中断可能残留的未确认数据
+    file.set_len(offset as u64).await.map_err(io_status)?;
+    file.seek(std::io::SeekFrom::Start(offset as u64))
+        .await
+        .map_err(io_status)?;
+
+    let mut checksum = checksum;
+    let mut received: i64 = 0;
+    let mut body = req.take_body();
+    let mut failure = None;
+    while let Some(frame) = body.next().await {
+        let data = match frame {
+            Ok(frame) => match frame.into_data() {
+                Ok(data) => data,
+                Err(_) => continue,
+            },
+            Err(e) => {
+                failure = Some(StatusError::bad_request().brief(e.to_string()));
+                break;
+            }
+        };
+        if offset + received + data.len() as i64 > upload.upload_length {
+            failure = Some(StatusError::payload_too_large().brief("超出 Upload-Length"));
+            break;
+        }
+        if let Err(e) = file.write_all(&data).await {
+            failure = Some(io_status(e));
+            break;
+        }
+        if let Some((hasher, _)) = checksum.as_mut() {
+            hasher.update(&data);
+        }
+        received += data.len() as i64;
+    }
+
+    // 校验失败或带校验的请求中断时丢弃本次数据
+    let mismatch = match checksum {
+        Some((hasher, expected)) => failure.is_some() || hasher.finalize() != expected,
+        None => false,
+    };
+    if mismatch || (failure.is_some() && received == 0) {
+        file.set_len(offset as u64).await.map_err(io_status)?;
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        res.status_code(StatusCode::from_u16(CHECKSUM_MISMATCH).unwrap_or(StatusCode::BAD_REQUEST));
+        return Ok(());
+    }
+
+    file.sync_all().await.map_err(io_status)?;
+    drop(file);
+    let new_offset = offset + received;
+    db.update_tus_offset(&upload.id, new_offset)
+        .await
+        .map_err(db_status)?;
+    if let Some(e) = failure {
+        return Err(e);
+    }
+
+    if new_offset == upload.upload_length {
+        finish(&sandbox, &db, &upload).await?;
+    }
+    set_header(res, "upload-offset", &new_offset.to_string());
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// 上传完成，原子移动到目标位置并删除记录
+async fn finish(
+    sandbox: &crate::sandbox::Sandbox,
+    db: &Db,
+    upload: &TusUpload,
+) -> Result<(), StatusError> {
+    let part = sandbox
+        .internal_dir(TUS_DIR)
+        .map_err(io_status)?
+        .join(&upload.id);
+    let target = sandbox.resolve(&upload.target_path)?;
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(io_status)?;
+    }
+    tokio::fs::rename(&part, &target).await.map_err(io_status)?;
+    db.delete_tus_upload(&upload.id).await.map_err(db_status)?;
+    log::info!("tus上传完成 {} -> {}", upload.id, target.display());
+    Ok(())
+}
+
+/// DELETE: 终止上传并删除已接收的数据
+#[handler]
+async fn terminate(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let id = find(&sandbox, &db, req).await?.id;
+    let lock = upload_lock(&id);
+    let result = async {
+        let _guard = lock.lock().await;
+        let upload = find(&sandbox, &db, req).await?;
+        remove(&sandbox, &db, &upload).await
+    }
+    .await;
+    release_lock(&id, lock);
+    result?;
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+/// 删除已接收的数据和上传记录
+async fn remove(sandbox: &Sandbox, db: &Db, upload: &TusUpload) -> Result<(), StatusError> {
+    let part = sandbox
+        .internal_dir(TUS_DIR)
+        .map_err(io_status)?
+        .join(&upload.id);
+    match tokio::fs::remove_file(&part).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(io_status(e)),
+    }
+    db.delete_tus_upload(&upload.id).await.map_err(db_status)?;
+    log::info!("终止tus上传 {}", upload.id);
+    Ok(())
+}
+
+/// tus 路由
+pub fn router() -> Router {
+    Router::with_path("tus")
+        .hoop(tus_resumable)
+        .options(options)
+        .post(create)
+        .push(
+            Router::with_path("{id}")
+                .options(options)
+                .head(head)
+                .patch(patch)
+                .delete(terminate),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandbox::Sandbox;
+    use salvo::prelude::*;
+    use salvo::test::TestClient;
+
+    #[test]
+    fn test_parse_metadata() {
+        let map = parse_metadata("filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==,is_confidential")
+            .unwrap();
+        assert_eq!(map["filename"], "world_domination_plan.pdf");
+        assert_eq!(map["is_confidential"], "");
+        assert!(parse_metadata("filename !!!").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tus_upload() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path().join("root")).unwrap();
+        let root = sandbox.root().to_path_buf();
+        let db = crate::db::test_db(dir.path()).await;
+        let router = Router::new()
+            .hoop(
+                affix_state::inject(Arc::new(sandbox))
+                    .inject(db)
+                    .inject(Arc::new(crate::lock::LockManager::memory())),
+            )
+            .push(router());
+        let service = Service::new(router);
+
+        let res = TestClient::post("http://127.0.0.1/tus")
+            .add_header("upload-length", "10", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::PRECONDITION_FAILED));
+
+        let path = STANDARD.encode("sub/data.bin");
+        let res = TestClient::post("http://127.0.0.1/tus")
+            .add_header("tus-resumable", TUS_VERSION, true)
+            .add_header("upload-length", "10", true)
+            .add_header("upload-metadata", format!("path {}", path), true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        let location = res.headers()["location"].to_str().unwrap().to_string();
+        let url = format!("http://127.0.0.1{}", location);
+
+        let send_chunk = |offset: &str, body: &'static str| {
+            TestClient::patch(&url)
+                .add_header("tus-resumable", TUS_VERSION, true)
+                .add_header("content-type", "application/offset+octet-stream", true)
+                .add_header("upload-offset", offset, true)
+                .body(body)
+        };
+
+        let res = send_chunk("0", "01234").send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert_eq!(res.headers()["upload-offset"], "5");
+
+        let res = send_chunk("0", "01234").send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+
+        // 未知的 id 不在锁表中留下条目，释放后的锁也被移除
+        for method in ["PATCH", "DELETE"] {
+            let res = salvo::test::RequestBuilder::new(
+                "http://127.0.0.1/tus/unknown",
+                salvo::http::Method::from_bytes(method.as_bytes()).unwrap(),
+            )
+            .add_header("tus-resumable", TUS_VERSION, true)
+            .add_header("content-type", "application/offset+octet-stream", true)
+            .add_header("upload-offset", "0", true)
+            .send(&service)
+            .await;
+            assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+        }
+        assert!(UPLOAD_LOCKS.lock().unwrap().is_empty());
+
+        // sha1("56789") 的错误值
+        let res = send_chunk("5", "56789")
+            .add_header("upload-checksum", "sha1 AAAAAAAAAAAAAAAAAAAAAAAAAAA=", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code.map(|s| s.as_u16()), Some(CHECKSUM_MISMATCH));
+
+        let res = TestClient::head(&url)
+            .add_header("tus-resumable", TUS_VERSION, true)
+            .send(&service)
+            .await;
+        assert_eq!(res.headers()["upload-offset"], "5");
+        assert_eq!(res.headers()["upload-length"], "10");
+
+        let mut hasher = Algorithm::Sha1.hasher();
+        hasher.update(b"56789");
+        let checksum = format!("sha1 {}", STANDARD.encode(hasher.finalize()));
+        let res = send_chunk("5", "56789")
+            .add_header("upload-checksum", checksum, true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+        assert_eq!(
+            std::fs::read_to_string(root.join("sub/data.bin")).unwrap(),
+            "0123456789"
+        );
+
+        let res = TestClient::head(&url)
+            .add_header("tus-resumable", TUS_VERSION, true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::sandbox::AtomicFile;
+use crate::web::fs::{io_status, request_path, sandbox};
+use crate::web::{checksum, locks, mounts};
+use futures_util::TryStreamExt;
+use salvo::http::StatusError;
+use salvo::http::header::CONTENT_TYPE;
+use salvo::prelude::Json;
+use salvo::{Depot, Request, Response, handler};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 文件名最大字节数
+const MAX_NAME_LEN: usize = 255;
+
+/// 目标已存在时的处理策略
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverwritePolicy {
+    /// 报错，不覆盖
+    #[default]
+    Fail,
+    /// 覆盖
+    Replace,
+    /// 自动重命名为 `name (1).ext`
+    Rename,
+}
+
+/// 单个文件的上传结果
+#[derive(Serialize, Debug, Default)]
+pub struct UploadResult {
+    pub field: String,
+    pub filename: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 清理客户端提供的文件名，只保留最后一段并去掉危险字符
+pub fn sanitize_filename(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = name
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+        .collect();
+    let cleaned = cleaned
+        .trim()
+        .trim_start_matches('.')
+        .trim_end_matches(['.', ' ']);
+    if cleaned.is_empty() {
+        return None;
+    }
+    let mut end = cleaned.len().min(MAX_NAME_LEN);
+    while !cleaned.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(cleaned[..end].to_string())
+}
+
+/// 生成第 `n` 个候选文件名: `name (n).ext`
+fn numbered_name(name: &str, n: usize) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{} ({}).{}", stem, n, ext),
+        _ => format!("{} ({})", name, n),
+    }
+}
+
+/// 按策略提交临时文件，返回最终路径
+async fn commit(
+    dir: &Path,
+    name: &str,
+    mut file: AtomicFile,
+    policy: OverwritePolicy,
+) -> io::Result<PathBuf> {
+    let target = dir.join(name);
+    match policy {
+        OverwritePolicy::Replace => {
+            file.commit().await?;
+            Ok(target)
+        }
+        OverwritePolicy::Fail => {
+            file.commit_new().await?;
+            Ok(target)
+        }
+        OverwritePolicy::Rename => {
+            let mut candidate = target;
+            for n in 1..10_000 {
+                file = match file.try_commit_new().await? {
+                    Ok(_) => return Ok(candidate),
+                    Err(file) => file,
+                };
+                candidate = dir.join(numbered_name(name, n));
+                file.set_target(candidate.clone());
+            }
+            Err(io::Error::other("无可用文件名"))
+        }
+    }
+}
+
+/// 以 multipart/form-data 上传多个文件到目录
+///
+/// 每个文件部分流式写入目标目录中的临时文件，完成后按 `overwrite` 策略提交。
+/// 隐藏的文件名、超过挂载点大小限制的文件和写入失败都记为该文件的错误。
+#[handler]
+pub async fn upload_files(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let dir = sandbox.resolve(&rel)?;
+    locks::require(depot, req, &sandbox, &dir, false).await?;
+    let policy = req
+        .query::<OverwritePolicy>("overwrite")
+        .unwrap_or_default();
+    let mkdirs = req.query::<bool>("mkdirs").unwrap_or(false);
+
+    let boundary = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|ct| multer::parse_boundary(ct).ok())
+        .ok_or_else(|| StatusError::unsupported_media_type().brief("需要 multipart/form-data"))?;
+
+    match tokio::fs::metadata(&dir).await {
+        Ok(m) if m.is_dir() => {}
+        Ok(_) => return Err(StatusError::conflict().brief("目标不是目录")),
+        Err(_) if mkdirs => tokio::fs::create_dir_all(&dir).await.map_err(io_status)?,
+        Err(_) => return Err(StatusError::not_found().brief(format!("目录不存在: {}", rel))),
+    }
+
+    let body = req
+        .take_body()
+        .try_filter_map(|frame| async move { Ok(frame.into_data().ok()) });
+    let mut multipart = multer::Multipart::new(body, boundary);
+
+    let mut results = Vec::new();
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return Err(StatusError::bad_request().brief(e.to_string())),
+        };
+        let Some(raw_name) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+        let mut result = UploadResult {
+            field: field.name().unwrap_or_default().to_string(),
+            filename: raw_name.clone(),
+            ..Default::default()
+        };
+        let Some(name) = sanitize_filename(&raw_name) else {
+            result.error = Some("非法文件名".to_string());
+            results.push(result);
+            continue;
+        };
+        if sandbox.is_excluded(&dir.join(&name)) {
+            result.error = Some(format!("禁止访问: {}", name));
+            results.push(result);
+            continue;
+        }
+
+        let mut verifier = match checksum::expected_digests(field.headers()) {
+            Ok(expected) => checksum::UploadVerifier::new(expected),
+            Err(e) => {
+                result.error = Some(e.brief);
+                results.push(result);
+                continue;
+            }
+        };
+        // 单个文件的IO错误只记在该文件上，已保存的文件和后续部分不受影响
+        let mut file = match AtomicFile::create(&dir.join(&name)).await {
+            Ok(file) => file,
+            Err(e) => {
+                result.error = Some(e.to_string());
+                results.push(result);
+                continue;
+            }
+        };
+        let failed = loop {
+            match field.chunk().await {
+                Ok(Some(chunk)) => {
+                    result.size += chunk.len() as u64;
+                    if let Err(e) = mounts::check_file_size(depot, result.size) {
+                        break Some(e.brief);
+                    }
+                    verifier.update(&chunk);
+                    if let Err(e) = file.write(&chunk).await {
+                        break Some(e.to_string());
+                    }
+                }
+                Ok(None) => break None,
+                Err(e) => return Err(StatusError::bad_request().brief(e.to_string())),
+            }
+        };
+        if failed.is_some() {
+            result.error = failed;
+            results.push(result);
+            continue;
+        }
+
+        let digests = match verifier.verify() {
+            Ok(digests) => digests,
+            Err(e) => {
+                result.error = Some(e);
+                results.push(result);
+                continue;
+            }
+        };
+
+        match commit(&dir, &name, file, policy).await {
+            Ok(path) => {
+                log::info!("上传文件: {} ({} 字节)", path.display(), result.size);
+                checksum::remember_upload(depot, None, &sandbox, &path, &digests).await;
+                result.path = sandbox.relative(&path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                result.error = Some(format!("文件已存在: {}", name));
+            }
+            Err(e) => result.error = Some(e.to_string()),
+        }
+        results.push(result);
+    }
+
+    res.render(Json(results));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("a.txt").unwrap(), "a.txt");
+        assert_eq!(sanitize_filename("../../etc/passwd").unwrap(), "passwd");
+        assert_eq!(sanitize_filename("C:\\Users\\x\\b.txt").unwrap(), "b.txt");
+        assert_eq!(sanitize_filename(".bashrc").unwrap(), "bashrc");
+        assert_eq!(sanitize_filename("a<b>?.txt").unwrap(), "ab.txt");
+        assert!(sanitize_filename("..").is_none());
+        assert!(sanitize_filename("dir/").is_none());
+        assert_eq!(sanitize_filename(&"x".repeat(300)).unwrap().len(), 255);
+    }
+
+    #[test]
+    fn test_numbered_name() {
+        assert_eq!(numbered_name("a.txt", 1), "a (1).txt");
+        assert_eq!(numbered_name("archive", 2), "archive (2)");
+    }
+}
@@ -0,0 +1,426 @@
+use crate::db::versions::FileVersion;
+use crate::db::{Db, now};
+use crate::sandbox::{AtomicFile, Sandbox};
+use crate::web::fs::{client_addr, db, db_status, io_status, request_path, sandbox, set_etag};
+use crate::web::mounts::ReadOnly;
+use crate::web::patch::{file_lock, release_lock};
+use crate::web::{download, locks, precondition};
+use globset::{GlobBuilder, GlobMatcher};
+use lazy_static::lazy_static;
+use salvo::http::{StatusCode, StatusError};
+use salvo::prelude::Json;
+use salvo::{Depot, Request, Response, handler};
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+
+/// 历史版本目录
+pub const VERSIONS_DIR: &str = ".versions";
+/// 后台按保留期清理的间隔
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+/// 恢复版本时的读取缓冲区大小
+const BUFFER_SIZE: usize = 64 * 1024;
+/// 原地写入（PATCH、截断）复制版本的最短间隔
+const IN_PLACE_WINDOW: Duration = Duration::from_secs(600);
+
+lazy_static! {
+    static ref IN_PLACE_COPIES: Mutex<HashMap<PathBuf, (u64, Instant)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// 版本保留规则，数量和时间同时给出时两者都要满足
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Retention {
+    /// 最多保留的版本数
+    pub max_count: Option<usize>,
+    /// 最长保留时间
+    pub max_age: Option<Duration>,
+}
+
+impl Retention {
+    /// 解析 `10`、`30d`、`10,30d`
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut retention = Retention::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let invalid = || format!("无效的版本保留规则: {}", spec);
+            match part.strip_suffix('d') {
+                Some(days) => {
+                    let days = days.parse::<u64>().map_err(|_| invalid())?;
+                    retention.max_age = Some(Duration::from_secs(days * 24 * 3600));
+                }
+                None => retention.max_count = Some(part.parse().map_err(|_| invalid())?),
+            }
+        }
+        Ok(retention)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: GlobMatcher,
+    /// `None` 表示不保留版本
+    retention: Option<Retention>,
+}
+
+/// 版本策略：按通配符匹配文件的相对路径，第一条匹配的规则生效，没有匹配时不保留版本
+#[derive(Debug, Clone, Default)]
+pub struct VersionPolicy {
+    rules: Vec<Rule>,
+}
+
+impl VersionPolicy {
+    /// 解析 `docs/**=20;*.log=off;**=10,30d`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for rule in spec.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+            let (pattern, retention) = rule
+                .split_once('=')
+                .ok_or_else(|| format!("无效的版本规则: {}", rule))?;
+            let glob = GlobBuilder::new(pattern.trim())
+                .literal_separator(true)
+                .build()
+                .map_err(|e| format!("无效的通配符 {}: {}", pattern, e))?
+                .compile_matcher();
+            let retention = match retention.trim() {
+                "off" | "0" => None,
+                spec => Some(Retention::parse(spec)?),
+            };
+            rules.push(Rule { glob, retention });
+        }
+        Ok(VersionPolicy { rules })
+    }
+
+    /// 文件适用的保留规则，`None` 表示不保留版本
+    pub fn retention(&self, path: &str) -> Option<Retention> {
+        self.rules
+            .iter()
+            .find(|rule| rule.glob.is_match(path))
+            .and_then(|rule| rule.retention)
+    }
+}
+
+fn version_path(sandbox: &Sandbox, id: &str) -> Result<PathBuf, StatusError> {
+    Ok(sandbox
+        .internal_dir(VERSIONS_DIR)
+        .map_err(io_status)?
+        .join(id))
+}
+
+/// 原地写入的文件最近一次被复制为版本的时间，按 inode 区分整体替换前后的文件
+fn copied_recently(path: &Path, metadata: &Metadata) -> bool {
+    let copies = IN_PLACE_COPIES.lock().unwrap_or_else(|e| e.into_inner());
+    copies
+        .get(path)
+        .is_some_and(|(ino, at)| *ino == metadata.ino() && at.elapsed() < IN_PLACE_WINDOW)
+}
+
+fn remember_copy(path: &Path, metadata: &Metadata) {
+    let mut copies = IN_PLACE_COPIES.lock().unwrap_or_else(|e| e.into_inner());
+    copies.retain(|_, (_, at)| at.elapsed() < IN_PLACE_WINDOW);
+    copies.insert(path.to_path_buf(), (metadata.ino(), Instant::now()));
+}
+
+/// 覆盖文件前把当前内容保存为新版本
+///
+/// `link` 为真时以硬链接保存（目标随后会被整体替换），否则复制（目标将被原地修改）。
+/// 复制要读写整个文件，因此同一文件的原地写入在 [`IN_PLACE_WINDOW`] 内只复制一次，窗口内的中间状态不保留。
+/// 未配置版本策略、规则不要求保留或跳过复制时返回 `None`。调用方需持有该文件的写锁。
+pub async fn snapshot(
+    depot: &Depot,
+    sandbox: &Sandbox,
+    path: &Path,
+    link: bool,
+    created_by: String,
+) -> Result<Option<FileVersion>, StatusError> {
+    let (Ok(policy), Ok(db)) = (depot.obtain::<Arc<VersionPolicy>>(), depot.obtain::<Db>()) else {
+        return Ok(None);
+    };
+    let rel = sandbox.relative(path).unwrap_or_default();
+    let Some(retention) = policy.retention(&rel) else {
+        return Ok(None);
+    };
+    let metadata = match tokio::fs::metadata(path).await {
+        Ok(m) if m.is_file() => m,
+        _ => return Ok(None),
+    };
+
+    if !link && copied_recently(path, &metadata) {
+        return Ok(None);
+    }
+
+    let id = uuid::Uuid::now_v7().simple().to_string();
+    let dst = version_path(sandbox, &id)?;
+    let saved = match link {
+        true => match tokio::fs::hard_link(path, &dst).await {
+            Ok(()) => Ok(()),
+            Err(_) => tokio::fs::copy(path, &dst).await.map(|_| ()),
+        },
+        false => tokio::fs::copy(path, &dst).await.map(|_| ()),
+    };
+    saved.map_err(io_status)?;
+    if !link {
+        remember_copy(path, &metadata);
+    }
+
+    let version = FileVersion {
+        id,
+        mount: sandbox.mount().to_string(),
+        version: db
+            .next_file_version(sandbox.mount(), &rel)
+            .await
+            .map_err(db_status)?,
+        path: rel,
+        size: metadata.len() as i64,
+        created_by,
+        created_at: now(),
+    };
+    if let Err(e) = db.insert_file_version(&version).await {
+        let _ = tokio::fs::remove_file(&dst).await;
+        return Err(db_status(e));
+    }
+    log::info!("保存版本: {} v{}", version.path, version.version);
+    prune(sandbox, db, &version.path, retention).await;
+    Ok(Some(version))
+}
+
+/// 撤销刚保存的版本，用于覆盖失败时
+pub async fn discard(depot: &Depot, sandbox: &Sandbox, version: &FileVersion) {
+    if let Ok(db) = depot.obtain::<Db>() {
+        remove_version(sandbox, db, version).await;
+    }
+}
+
+async fn remove_version(sandbox: &Sandbox, db: &Db, version: &FileVersion) -> bool {
+    if let Ok(path) = version_path(sandbox, &version.id)
+        && let Err(e) = tokio::fs::remove_file(&path).await
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        log::warn!("删除版本文件失败 {}: {}", path.display(), e);
+        return false;
+    }
+    match db.delete_file_version(&version.id).await {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("删除版本记录失败 {}: {}", version.id, e);
+            false
+        }
+    }
+}
+
+/// 按保留规则删除多余或过期的版本，返回删除的数量
+async fn prune(sandbox: &Sandbox, db: &Db, rel: &str, retention: Retention) -> usize {
+    let versions = match db.list_file_versions(sandbox.mount(), rel).await {
+        Ok(versions) => versions,
+        Err(e) => {
+            log::warn!("查询版本失败 {}: {}", rel, e);
+            return 0;
+        }
+    };
+    let oldest = retention
+        .max_age
+        .map(|age| now() - age.as_secs() as i64)
+        .unwrap_or(i64::MIN);
+    let mut pruned = 0;
+    for (i, version) in versions.iter().enumerate() {
+        let excess = retention.max_count.is_some_and(|max| i >= max);
+        if (excess || version.created_at < oldest) && remove_version(sandbox, db, version).await {
+            pruned += 1;
+        }
+    }
+    pruned
+}
+
+/// 启动后台任务，定期按保留期清理所有文件的版本；挂载点维护期间暂停
+pub fn spawn_pruner(
+    sandbox: Arc<Sandbox>,
+    db: Db,
+    policy: Arc<VersionPolicy>,
+    read_only: Arc<ReadOnly>,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if read_only.active(sandbox.mount()).is_some() {
+                continue;
+            }
+            let paths = match db.versioned_paths(sandbox.mount()).await {
+                Ok(paths) => paths,
+                Err(e) => {
+                    log::error!("查询版本路径失败: {}", e);
+                    continue;
+                }
+            };
+            let mut pruned = 0;
+            for path in paths {
+                if let Some(retention) = policy.retention(&path) {
+                    pruned += prune(&sandbox, &db, &path, retention).await;
+                }
+            }
+            if pruned > 0 {
+                log::info!("版本清理: {} 个版本", pruned);
+            }
+        }
+    });
+}
+
+async fn find(
+    sandbox: &Sandbox,
+    db: &Db,
+    rel: &str,
+    version: i64,
+) -> Result<FileVersion, StatusError> {
+    db.get_file_version(sandbox.mount(), rel, version)
+        .await
+        .map_err(db_status)?
+        .ok_or_else(|| StatusError::not_found().brief(format!("版本不存在: {} v{}", rel, version)))
+}
+
+/// 请求路径对应的相对路径，文件本身可以已被删除
+fn file_rel(sandbox: &Sandbox, req: &Request) -> Result<String, StatusError> {
+    let path = sandbox.resolve(&request_path(req))?;
+    if path == sandbox.root() {
+        return Err(StatusError::bad_request().brief("根目录没有版本"));
+    }
+    Ok(sandbox.relative(&path).unwrap_or_default())
+}
+
+/// GET ?versions: 按版本号倒序列出历史版本
+#[handler]
+pub async fn list_versions(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let rel = file_rel(&sandbox, req)?;
+    let versions = db
+        .list_file_versions(sandbox.mount(), &rel)
+        .await
+        .map_err(db_status)?;
+    res.render(Json(versions));
+    Ok(())
+}
+
+/// GET ?version=N: 下载指定版本，支持区间和条件请求
+#[handler]
+pub async fn download_version(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let rel = file_rel(&sandbox, req)?;
+    let number = req
+        .query::<i64>("version")
+        .ok_or_else(|| StatusError::bad_request().brief("无效的版本号"))?;
+    let version = find(&sandbox, &db, &rel, number).await?;
+    let path = version_path(&sandbox, &version.id)?;
+    let metadata = tokio::fs::metadata(&path)
+        .await
+        .map_err(|_| StatusError::gone().brief(format!("版本内容已丢失: {} v{}", rel, number)))?;
+    download::send_file(&path, &metadata, req, res).await
+}
+
+/// POST ?restore_version=N: 用指定版本替换当前文件
+///
+/// 当前内容先保存为新版本；文件已被删除时直接恢复。支持 `If-Match` 前置条件。
+#[handler]
+pub async fn restore_version(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let db = db(depot)?;
+    let rel = file_rel(&sandbox, req)?;
+    let path = sandbox.resolve(&rel)?;
+    locks::require(depot, req, &sandbox, &path, false).await?;
+    let number = req
+        .query::<i64>("restore_version")
+        .ok_or_else(|| StatusError::bad_request().brief("无效的版本号"))?;
+    let version = find(&sandbox, &db, &rel, number).await?;
+    let source = version_path(&sandbox, &version.id)?;
+    let client = client_addr(req);
+
+    let lock = file_lock(&path);
+    let result = async {
+        let _guard = lock.lock().await;
+        let current = tokio::fs::metadata(&path).await.ok();
+        if current.as_ref().is_some_and(|m| !m.is_file()) {
+            return Err(StatusError::conflict().brief("目标不是文件"));
+        }
+        precondition::check_write(req.headers(), current.as_ref())?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(io_status)?;
+        }
+
+        // 先完整复制到临时文件，再保存当前版本并替换
+        let mut reader = tokio::fs::File::open(&source).await.map_err(|_| {
+            StatusError::gone().brief(format!("版本内容已丢失: {} v{}", rel, number))
+        })?;
+        let mut file = AtomicFile::create(&path).await.map_err(io_status)?;
+        let mut buf = vec![0u8; BUFFER_SIZE];
+        loop {
+            let n = reader.read(&mut buf).await.map_err(io_status)?;
+            if n == 0 {
+                break;
+            }
+            file.write(&buf[..n]).await.map_err(io_status)?;
+        }
+        let saved = match current {
+            Some(_) => snapshot(depot, &sandbox, &path, true, client).await?,
+            None => None,
+        };
+        if let Err(e) = file.commit().await {
+            if let Some(saved) = saved {
+                discard(depot, &sandbox, &saved).await;
+            }
+            return Err(io_status(e));
+        }
+        log::info!("恢复版本: {} v{}", rel, number);
+        Ok(())
+    }
+    .await;
+    release_lock(&path, lock);
+    result?;
+
+    set_etag(&path, res).await;
+    res.status_code(StatusCode::NO_CONTENT);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_policy() {
+        let policy = VersionPolicy::parse("docs/**=20; *.log=off; **=10,30d").unwrap();
+        assert_eq!(
+            policy.retention("docs/a/b.txt"),
+            Some(Retention {
+                max_count: Some(20),
+                max_age: None
+            })
+        );
+        assert_eq!(policy.retention("app.log"), None);
+        assert_eq!(
+            policy.retention("logs/app.log"),
+            Some(Retention {
+                max_count: Some(10),
+                max_age: Some(Duration::from_secs(30 * 24 * 3600))
+            })
+        );
+        assert_eq!(VersionPolicy::default().retention("a.txt"), None);
+        assert!(VersionPolicy::parse("**").is_err());
+        assert!(VersionPolicy::parse("**=x").is_err());
+    }
+}
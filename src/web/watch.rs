@@ -0,0 +1,400 @@
+use crate::sandbox::{RESERVED_DIRS, Sandbox};
+use crate::web::fs::{io_status, request_path, sandbox};
+use chrono::{DateTime, Utc};
+use notify_debouncer_full::notify::event::{ModifyKind, RenameMode};
+use notify_debouncer_full::notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{
+    DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache, new_debouncer,
+};
+use salvo::http::StatusError;
+use salvo::http::header::UPGRADE;
+use salvo::sse::{SseEvent, SseKeepAlive};
+use salvo::websocket::{Message, WebSocket, WebSocketUpgrade};
+use salvo::{Depot, Request, Response, handler};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// 默认去抖时间（毫秒）
+const DEFAULT_DEBOUNCE_MS: u64 = 500;
+/// 去抖时间下限（毫秒）
+const MIN_DEBOUNCE_MS: u64 = 50;
+/// 去抖时间上限（毫秒）
+const MAX_DEBOUNCE_MS: u64 = 10_000;
+/// 待推送事件的最大数量，客户端跟不上时丢弃并推送 rescan
+const CHANNEL_CAPACITY: usize = 256;
+
+/// 订阅参数
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct WatchQuery {
+    /// 是否监听子目录
+    pub recursive: bool,
+    /// 是否推送隐藏文件的事件
+    pub hidden: bool,
+    /// 去抖时间（毫秒）
+    pub debounce_ms: u64,
+}
+
+impl Default for WatchQuery {
+    fn default() -> Self {
+        WatchQuery {
+            recursive: true,
+            hidden: false,
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+        }
+    }
+}
+
+/// 事件类型
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchKind {
+    Create,
+    Modify,
+    Delete,
+    Rename,
+    /// 事件丢失，客户端应重新列出目录
+    Rescan,
+    Error,
+}
+
+/// 推送给客户端的事件
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct WatchEvent {
+    pub kind: WatchKind,
+    /// 相对于根目录的路径
+    pub path: String,
+    /// 重命名前的路径
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub time: DateTime<Utc>,
+}
+
+impl WatchEvent {
+    fn new(kind: WatchKind, path: String) -> Self {
+        WatchEvent {
+            kind,
+            path,
+            from: None,
+            message: None,
+            time: Utc::now(),
+        }
+    }
+}
+
+/// 把文件系统事件转换为对客户端可见的事件
+struct Translator {
+    sandbox: Arc<Sandbox>,
+    /// 订阅的目录，rescan 事件使用
+    prefix: String,
+    hidden: bool,
+}
+
+impl Translator {
+    /// 可见的相对路径，内部目录、匹配隐藏规则的路径和（未要求时）隐藏文件返回 `None`
+    fn visible(&self, path: &Path) -> Option<String> {
+        let rel = self.sandbox.relative(path)?;
+        if rel.is_empty() {
+            return None;
+        }
+        let mut parts = rel.split('/');
+        if parts
+            .clone()
+            .next()
+            .is_some_and(|first| RESERVED_DIRS.contains(&first))
+            || self.sandbox.is_excluded(path)
+        {
+            return None;
+        }
+        if !self.hidden && parts.any(|p| self.sandbox.is_hidden(p)) {
+            return None;
+        }
+        Some(rel)
+    }
+
+    fn rescan(&self) -> WatchEvent {
+        WatchEvent::new(WatchKind::Rescan, self.prefix.clone())
+    }
+
+    fn translate(&self, event: &DebouncedEvent) -> Option<WatchEvent> {
+        if event.need_rescan() {
+            return Some(self.rescan());
+        }
+        let first = event.paths.first()?;
+        let translated = match event.kind {
+            EventKind::Create(_) => WatchEvent::new(WatchKind::Create, self.visible(first)?),
+            EventKind::Remove(_) => WatchEvent::new(WatchKind::Delete, self.visible(first)?),
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                let to = event.paths.get(1)?;
+                // 在可见与隐藏之间重命名时，只有一侧对客户端可见
+                match (self.visible(first), self.visible(to)) {
+                    (Some(from), Some(to)) => WatchEvent {
+                        from: Some(from),
+                        ..WatchEvent::new(WatchKind::Rename, to)
+                    },
+                    (None, Some(to)) => WatchEvent::new(WatchKind::Create, to),
+                    (Some(from), None) => WatchEvent::new(WatchKind::Delete, from),
+                    (None, None) => return None,
+                }
+            }
+            // 移出或移入订阅范围
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                WatchEvent::new(WatchKind::Delete, self.visible(first)?)
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                WatchEvent::new(WatchKind::Create, self.visible(first)?)
+            }
+            // 无法配对的重命名，按当前是否存在判断
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                let kind = match first.symlink_metadata() {
+                    Ok(_) => WatchKind::Create,
+                    Err(_) => WatchKind::Delete,
+                };
+                WatchEvent::new(kind, self.visible(first)?)
+            }
+            EventKind::Modify(_) => WatchEvent::new(WatchKind::Modify, self.visible(first)?),
+            EventKind::Access(_) | EventKind::Any | EventKind::Other => return None,
+        };
+        Some(translated)
+    }
+}
+
+/// 一个订阅，丢弃时停止监听
+struct Subscription {
+    rx: mpsc::Receiver<WatchEvent>,
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+}
+
+impl Subscription {
+    async fn recv(&mut self) -> Option<WatchEvent> {
+        self.rx.recv().await
+    }
+}
+
+/// 开始监听目录
+fn subscribe(
+    sandbox: Arc<Sandbox>,
+    dir: &Path,
+    query: &WatchQuery,
+) -> Result<Subscription, StatusError> {
+    let translator = Translator {
+        prefix: sandbox.relative(dir).unwrap_or_default(),
+        sandbox,
+        hidden: query.hidden,
+    };
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let mut overflowed = false;
+    let handler = move |result: DebounceEventResult| {
+        let events: Vec<WatchEvent> = match result {
+            Ok(events) => events
+                .iter()
+                .filter_map(|e| translator.translate(e))
+                .collect(),
+            Err(errors) => errors
+                .into_iter()
+                .map(|e| WatchEvent {
+                    message: Some(e.to_string()),
+                    ..WatchEvent::new(WatchKind::Error, translator.prefix.clone())
+                })
+                .collect(),
+        };
+        for event in events {
+            // 不阻塞去抖线程：队列满时丢弃，腾出空间后补发一次 rescan
+            if overflowed {
+                match tx.try_send(translator.rescan()) {
+                    Ok(()) => overflowed = false,
+                    Err(_) => return,
+                }
+            }
+            match tx.try_send(event) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(_)) => overflowed = true,
+                Err(mpsc::error::TrySendError::Closed(_)) => return,
+            }
+        }
+    };
+
+    let debounce = query.debounce_ms.clamp(MIN_DEBOUNCE_MS, MAX_DEBOUNCE_MS);
+    let mut debouncer = new_debouncer(Duration::from_millis(debounce), None, handler)
+        .map_err(|e| StatusError::internal_server_error().brief(e.to_string()))?;
+    let mode = match query.recursive {
+        true => RecursiveMode::Recursive,
+        false => RecursiveMode::NonRecursive,
+    };
+    debouncer
+        .watch(dir, mode)
+        .map_err(|e| StatusError::internal_server_error().brief(format!("监听失败: {}", e)))?;
+    Ok(Subscription {
+        rx,
+        _debouncer: debouncer,
+    })
+}
+
+/// 通过 WebSocket 推送事件，客户端关闭连接时结束
+async fn push_websocket(mut ws: WebSocket, mut subscription: Subscription) {
+    loop {
+        tokio::select! {
+            event = subscription.recv() => {
+                let Some(event) = event else { break };
+                let Ok(text) = serde_json::to_string(&event) else { continue };
+                if ws.send(Message::text(text)).await.is_err() {
+                    break;
+                }
+            }
+            msg = ws.recv() => match msg {
+                Some(Ok(msg)) if !msg.is_close() => {}
+                _ => break,
+            },
+        }
+    }
+}
+
+/// GET /watch/路径: 订阅目录下的变更
+///
+/// 带 `Upgrade: websocket` 时以 WebSocket 文本帧推送 JSON 事件，否则以 SSE 推送。
+/// 事件路径相对于根目录，内部目录和隐藏文件（除非 `hidden=true`）不会推送。
+#[handler]
+pub async fn watch(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), StatusError> {
+    let sandbox = sandbox(depot)?;
+    let rel = request_path(req);
+    let dir = sandbox.resolve_existing(&rel)?;
+    let query = req
+        .parse_queries::<WatchQuery>()
+        .map_err(|e| StatusError::bad_request().brief(e.to_string()))?;
+    let metadata = tokio::fs::metadata(&dir).await.map_err(io_status)?;
+    if !metadata.is_dir() {
+        return Err(StatusError::bad_request().brief(format!("不是目录: {}", rel)));
+    }
+
+    let subscription = subscribe(sandbox, &dir, &query)?;
+    log::info!("开始监听: {} {:?}", dir.display(), query);
+
+    if req.headers().contains_key(UPGRADE) {
+        return WebSocketUpgrade::new()
+            .upgrade(req, res, move |ws| async move {
+                push_websocket(ws, subscription).await;
+                log::info!("停止监听: {}", dir.display());
+            })
+            .await;
+    }
+
+    let events = futures_util::stream::unfold(subscription, |mut subscription| async move {
+        let event = subscription.recv().await?;
+        let sse = SseEvent::default()
+            .name(serde_json::to_value(event.kind).ok()?.as_str()?.to_string())
+            .json(&event)
+            .ok()?;
+        Some((Ok::<_, Infallible>(sse), subscription))
+    });
+    SseKeepAlive::new(events).stream(res);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify_debouncer_full::notify::Event;
+    use notify_debouncer_full::notify::event::{CreateKind, RemoveKind};
+    use std::time::Instant;
+
+    fn debounced(kind: EventKind, paths: &[&Path]) -> DebouncedEvent {
+        let mut event = Event::new(kind);
+        for path in paths {
+            event = event.add_path(path.to_path_buf());
+        }
+        DebouncedEvent::new(event, Instant::now())
+    }
+
+    #[test]
+    fn test_translate() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Arc::new(Sandbox::new(dir.path()).unwrap());
+        let root = sandbox.root().to_path_buf();
+        let translator = Translator {
+            sandbox,
+            prefix: String::new(),
+            hidden: false,
+        };
+
+        let event = translator
+            .translate(&debounced(
+                EventKind::Create(CreateKind::File),
+                &[&root.join("a/b.txt")],
+            ))
+            .unwrap();
+        assert_eq!(event.kind, WatchKind::Create);
+        assert_eq!(event.path, "a/b.txt");
+
+        // 隐藏文件与内部目录
+        for path in [".hidden", "a/.git/config", ".tus/x"] {
+            let event = debounced(EventKind::Remove(RemoveKind::File), &[&root.join(path)]);
+            assert!(translator.translate(&event).is_none(), "{}", path);
+        }
+
+        let rename = EventKind::Modify(ModifyKind::Name(RenameMode::Both));
+        let event = translator
+            .translate(&debounced(
+                rename,
+                &[&root.join("a.txt"), &root.join("b.txt")],
+            ))
+            .unwrap();
+        assert_eq!(event.kind, WatchKind::Rename);
+        assert_eq!(event.from.as_deref(), Some("a.txt"));
+        assert_eq!(event.path, "b.txt");
+
+        // 从隐藏文件重命名为可见文件
+        let event = translator
+            .translate(&debounced(
+                rename,
+                &[&root.join(".a.swp"), &root.join("a.txt")],
+            ))
+            .unwrap();
+        assert_eq!(event.kind, WatchKind::Create);
+        assert_eq!(event.path, "a.txt");
+        let event = translator
+            .translate(&debounced(
+                rename,
+                &[&root.join("a.txt"), &root.join(".a.txt")],
+            ))
+            .unwrap();
+        assert_eq!(event.kind, WatchKind::Delete);
+        assert_eq!(event.path, "a.txt");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Arc::new(Sandbox::new(dir.path()).unwrap());
+        let root = sandbox.root().to_path_buf();
+        std::fs::create_dir(root.join("sub")).unwrap();
+        let query = WatchQuery {
+            debounce_ms: 50,
+            ..Default::default()
+        };
+        let mut subscription = subscribe(sandbox, &root, &query).unwrap();
+
+        std::fs::write(root.join(".hidden"), "x").unwrap();
+        std::fs::write(root.join("sub/new.txt"), "hello").unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let event = subscription.recv().await.unwrap();
+                if event.kind == WatchKind::Create {
+                    return event;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(event.path, "sub/new.txt");
+    }
+}